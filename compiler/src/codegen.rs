@@ -59,7 +59,8 @@ use std::io::{self, stdin, stdout, Write};
 use std::path::Path;
 
 use super::util::{
-    self, async_on, def_async_fn, fq_grpc, pub_async_fn, to_camel_case, to_snake_case, MethodType,
+    self, async_on, def_async_fn, fq_grpc, pub_async_fn, stream_mod, to_camel_case, to_snake_case,
+    MethodType,
 };
 
 struct MethodGen<'a> {
@@ -160,17 +161,56 @@ impl<'a> MethodGen<'a> {
     }
 
     fn write_handler_impl(&self, w: &mut CodeWriter) {
-        w.block(format!("impl ::ttrpc::MethodHandler for {}Method {{", self.struct_name()), "}",
-        |w| {
-            w.block("fn handler(&self, ctx: ::ttrpc::TtrpcContext, req: ::ttrpc::Request) -> ::ttrpc::Result<()> {", "}",
-            |w| {
-                w.write_line(format!("::ttrpc::request_handler!(self, ctx, req, {}, {}, {});",
+        match self.method_type().0 {
+            MethodType::Unary => {
+                w.block(format!("impl ::ttrpc::MethodHandler for {}Method {{", self.struct_name()), "}",
+                |w| {
+                    w.block("fn handler(&self, ctx: ::ttrpc::TtrpcContext, req: ::ttrpc::Request) -> ::ttrpc::Result<()> {", "}",
+                    |w| {
+                        w.write_line(format!("::ttrpc::request_handler!(self, ctx, req, {}, {}, {});",
+                                                proto_path_to_rust_mod(self.root_scope.find_message(self.proto.input_type()).fd.name()),
+                                                self.root_scope.find_message(self.proto.input_type()).rust_name(),
+                                                self.name()));
+                        w.write_line("Ok(())");
+                    });
+                });
+            }
+            // only receive
+            MethodType::ClientStreaming => {
+                w.block(format!("impl ::ttrpc::StreamHandler for {}Method {{", self.struct_name()), "}",
+                |w| {
+                    w.block("fn handler(&self, ctx: ::ttrpc::TtrpcContext, inner: ::ttrpc::sync::StreamInner) -> ::ttrpc::Result<Option<::ttrpc::Response>> {", "}",
+                        |w| {
+                            w.write_line(format!("::ttrpc::client_streaming_handler!(self, ctx, inner, {});",
+                                        self.name()));
+                    });
+                });
+            }
+            // only send
+            MethodType::ServerStreaming => {
+                w.block(format!("impl ::ttrpc::StreamHandler for {}Method {{", self.struct_name()), "}",
+                |w| {
+                    w.block("fn handler(&self, ctx: ::ttrpc::TtrpcContext, mut inner: ::ttrpc::sync::StreamInner) -> ::ttrpc::Result<Option<::ttrpc::Response>> {", "}",
+                        |w| {
+                            w.write_line(format!("::ttrpc::server_streaming_handler!(self, ctx, inner, {}, {}, {});",
                                         proto_path_to_rust_mod(self.root_scope.find_message(self.proto.input_type()).fd.name()),
                                         self.root_scope.find_message(self.proto.input_type()).rust_name(),
                                         self.name()));
-                w.write_line("Ok(())");
-            });
-        });
+                    });
+                });
+            }
+            // receive and send
+            MethodType::Duplex => {
+                w.block(format!("impl ::ttrpc::StreamHandler for {}Method {{", self.struct_name()), "}",
+                |w| {
+                    w.block("fn handler(&self, ctx: ::ttrpc::TtrpcContext, inner: ::ttrpc::sync::StreamInner) -> ::ttrpc::Result<Option<::ttrpc::Response>> {", "}",
+                        |w| {
+                            w.write_line(format!("::ttrpc::duplex_streaming_handler!(self, ctx, inner, {});",
+                                        self.name()));
+                    });
+                });
+            }
+        }
     }
 
     fn write_handler_impl_async(&self, w: &mut CodeWriter) {
@@ -242,7 +282,10 @@ impl<'a> MethodGen<'a> {
             "{}(&self, ctx: ttrpc::context::Context) -> {}<{}<{}, {}>>",
             method_name,
             fq_grpc("Result"),
-            fq_grpc("r#async::ClientStreamSender"),
+            fq_grpc(&format!(
+                "{}::ClientStreamSender",
+                stream_mod(self.customize, "client")
+            )),
             self.input(),
             self.output()
         )
@@ -254,7 +297,10 @@ impl<'a> MethodGen<'a> {
             method_name,
             self.input(),
             fq_grpc("Result"),
-            fq_grpc("r#async::ClientStreamReceiver"),
+            fq_grpc(&format!(
+                "{}::ClientStreamReceiver",
+                stream_mod(self.customize, "client")
+            )),
             self.output()
         )
     }
@@ -264,7 +310,10 @@ impl<'a> MethodGen<'a> {
             "{}(&self, ctx: ttrpc::context::Context) -> {}<{}<{}, {}>>",
             method_name,
             fq_grpc("Result"),
-            fq_grpc("r#async::ClientStream"),
+            fq_grpc(&format!(
+                "{}::ClientStream",
+                stream_mod(self.customize, "client")
+            )),
             self.input(),
             self.output()
         )
@@ -272,18 +321,54 @@ impl<'a> MethodGen<'a> {
 
     fn write_client(&self, w: &mut CodeWriter) {
         let method_name = self.name();
-        if let MethodType::Unary = self.method_type().0 {
-            w.pub_fn(self.unary(&method_name), |w| {
-                w.write_line(format!("let mut cres = {}::new();", self.output()));
-                w.write_line(format!(
-                    "::ttrpc::client_request!(self, ctx, req, \"{}.{}\", \"{}\", cres);",
-                    self.package_name,
-                    self.service_name,
-                    &self.proto.name(),
-                ));
-                w.write_line("Ok(cres)");
-            });
-        }
+        match self.method_type().0 {
+            // Unary RPC
+            MethodType::Unary => {
+                w.pub_fn(self.unary(&method_name), |w| {
+                    w.write_line(format!("let mut cres = {}::new();", self.output()));
+                    w.write_line(format!(
+                        "::ttrpc::client_request!(self, ctx, req, \"{}.{}\", \"{}\", cres);",
+                        self.package_name,
+                        self.service_name,
+                        &self.proto.name(),
+                    ));
+                    w.write_line("Ok(cres)");
+                });
+            }
+            // Client Streaming RPC
+            MethodType::ClientStreaming => {
+                w.pub_fn(self.client_streaming(&method_name), |w| {
+                    w.write_line(format!(
+                        "::ttrpc::client_stream_send!(self, ctx, \"{}.{}\", \"{}\");",
+                        self.package_name,
+                        self.service_name,
+                        &self.proto.name(),
+                    ));
+                });
+            }
+            // Server Streaming RPC
+            MethodType::ServerStreaming => {
+                w.pub_fn(self.server_streaming(&method_name), |w| {
+                    w.write_line(format!(
+                        "::ttrpc::client_stream_receive!(self, ctx, req, \"{}.{}\", \"{}\");",
+                        self.package_name,
+                        self.service_name,
+                        &self.proto.name(),
+                    ));
+                });
+            }
+            // Bidirectional streaming RPC
+            MethodType::Duplex => {
+                w.pub_fn(self.duplex_streaming(&method_name), |w| {
+                    w.write_line(format!(
+                        "::ttrpc::client_stream!(self, ctx, \"{}.{}\", \"{}\");",
+                        self.package_name,
+                        self.service_name,
+                        &self.proto.name(),
+                    ));
+                });
+            }
+        };
     }
 
     fn write_async_client(&self, w: &mut CodeWriter) {
@@ -338,19 +423,24 @@ impl<'a> MethodGen<'a> {
     }
 
     fn write_service(&self, w: &mut CodeWriter) {
+        let stream_mod = stream_mod(self.customize, "server");
         let (_req, req_type, resp_type) = match self.method_type().0 {
             MethodType::Unary => ("req", self.input(), self.output()),
             MethodType::ClientStreaming => (
                 "stream",
-                format!("::ttrpc::r#async::ServerStreamReceiver<{}>", self.input()),
+                format!(
+                    "::ttrpc::{}::ServerStreamReceiver<{}>",
+                    stream_mod,
+                    self.input()
+                ),
                 self.output(),
             ),
             MethodType::ServerStreaming => (
                 "req",
                 format!(
-                    "{}, _: {}<{}>",
+                    "{}, _: ::ttrpc::{}::ServerStreamSender<{}>",
                     self.input(),
-                    "::ttrpc::r#async::ServerStreamSender",
+                    stream_mod,
                     self.output()
                 ),
                 "()".to_string(),
@@ -358,8 +448,8 @@ impl<'a> MethodGen<'a> {
             MethodType::Duplex => (
                 "stream",
                 format!(
-                    "{}<{}, {}>",
-                    "::ttrpc::r#async::ServerStream",
+                    "::ttrpc::{}::ServerStream<{}, {}>",
+                    stream_mod,
                     self.output(),
                     self.input(),
                 ),
@@ -393,17 +483,27 @@ impl<'a> MethodGen<'a> {
     }
 
     fn write_bind(&self, w: &mut CodeWriter) {
-        let method_handler_name = "::ttrpc::MethodHandler";
-
-        let s = format!(
-            "methods.insert(\"/{}.{}/{}\".to_string(),
+        let s = if matches!(self.method_type().0, MethodType::Unary) {
+            format!(
+                "methods.insert(\"/{}.{}/{}\".to_string(),
                     Box::new({}Method{{service: service.clone()}}) as Box<dyn {} + Send + Sync>);",
-            self.package_name,
-            self.service_name,
-            self.proto.name(),
-            self.struct_name(),
-            method_handler_name,
-        );
+                self.package_name,
+                self.service_name,
+                self.proto.name(),
+                self.struct_name(),
+                "::ttrpc::MethodHandler",
+            )
+        } else {
+            format!(
+                "streams.insert(\"/{}.{}/{}\".to_string(),
+                    Arc::new({}Method{{service: service.clone()}}) as Arc<dyn {} + Send + Sync>);",
+                self.package_name,
+                self.service_name,
+                self.proto.name(),
+                self.struct_name(),
+                "::ttrpc::StreamHandler",
+            )
+        };
         w.write_line(&s);
     }
 
@@ -434,6 +534,7 @@ struct ServiceGen<'a> {
     methods: Vec<MethodGen<'a>>,
     customize: &'a Customize,
     package_name: String,
+    file_descriptors: Vec<Vec<u8>>,
 }
 
 impl<'a> ServiceGen<'a> {
@@ -457,11 +558,18 @@ impl<'a> ServiceGen<'a> {
             })
             .collect();
 
+        let file_descriptors = if customize.gen_file_descriptors {
+            util::file_descriptors(root_scope.file_descriptors, file.name())
+        } else {
+            Vec::new()
+        };
+
         ServiceGen {
             proto,
             methods,
             customize,
             package_name: file.package().to_string(),
+            file_descriptors,
         }
     }
 
@@ -564,14 +672,15 @@ impl<'a> ServiceGen<'a> {
 
     fn write_sync_server_create(&self, w: &mut CodeWriter) {
         let method_handler_name = "::ttrpc::MethodHandler";
+        let has_normal_method = self.has_normal_method();
         let s = format!(
-            "create_{}(service: Arc<dyn {} + Send + Sync>) -> HashMap<String, Box<dyn {} + Send + Sync>>",
+            "create_{}({}: Arc<dyn {} + Send + Sync>) -> HashMap<String, Box<dyn {} + Send + Sync>>",
             to_snake_case(&self.service_name()),
+            if has_normal_method { "service" } else { "_service" },
             self.service_name(),
             method_handler_name,
         );
 
-        let has_normal_method = self.has_normal_method();
         w.pub_fn(&s, |w| {
             if has_normal_method {
                 w.write_line("let mut methods = HashMap::new();");
@@ -579,12 +688,38 @@ impl<'a> ServiceGen<'a> {
                 w.write_line("let methods = HashMap::new();");
             }
             for method in &self.methods[0..self.methods.len()] {
-                w.write_line("");
-                method.write_bind(w);
+                if matches!(method.method_type().0, MethodType::Unary) {
+                    w.write_line("");
+                    method.write_bind(w);
+                }
             }
             w.write_line("");
             w.write_line("methods");
         });
+
+        if !self.has_stream_method() {
+            return;
+        }
+
+        // Streaming methods are registered by `Server::register_stream_service`.
+        w.write_line("");
+        let s = format!(
+            "create_{}_streams(service: Arc<dyn {} + Send + Sync>) -> HashMap<String, Arc<dyn {} + Send + Sync>>",
+            to_snake_case(&self.service_name()),
+            self.service_name(),
+            "::ttrpc::StreamHandler",
+        );
+        w.pub_fn(&s, |w| {
+            w.write_line("let mut streams = HashMap::new();");
+            for method in &self.methods[0..self.methods.len()] {
+                if !matches!(method.method_type().0, MethodType::Unary) {
+                    w.write_line("");
+                    method.write_bind(w);
+                }
+            }
+            w.write_line("");
+            w.write_line("streams");
+        });
     }
 
     fn write_async_server_create(&self, w: &mut CodeWriter) {
@@ -639,6 +774,10 @@ impl<'a> ServiceGen<'a> {
         self.write_method_handlers(w);
         w.write_line("");
         self.write_server(w);
+        if self.customize.gen_file_descriptors {
+            w.write_line("");
+            util::write_file_descriptors(w, &self.service_name(), &self.file_descriptors);
+        }
     }
 }
 
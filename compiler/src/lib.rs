@@ -24,6 +24,7 @@
 //!- [Programmatic Generation](https://github.com/containerd/ttrpc-rust#2-generate-programmatically) uses ttrpc-compiler as a rust crate
 
 pub mod codegen;
+pub mod parse;
 pub mod prost_codegen;
 mod util;
 
@@ -38,4 +39,6 @@ pub struct Customize {
     pub async_server: bool,
     /// Gen mod rs in mod.rs
     pub gen_mod: bool,
+    /// Embed the descriptors of the files declaring the services, for the reflection service.
+    pub gen_file_descriptors: bool,
 }
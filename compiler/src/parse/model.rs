@@ -3,8 +3,8 @@
 //! This crate can be seen as a rust transcription of the
 //! [descriptor.proto](https://github.com/google/protobuf/blob/master/src/google/protobuf/descriptor.proto) file
 
-use crate::parser::{Loc, Parser, ParserErrorWithLocation};
-use crate::str_lit::StrLit;
+use super::parser::{Loc, Parser, ParserErrorWithLocation};
+use super::str_lit::StrLit;
 use protobuf_support::lexer::float;
 
 /// Protobox syntax
@@ -3,8 +3,8 @@ use std::fmt;
 use std::num::ParseIntError;
 use std::str;
 
-use crate::model::*;
-use crate::str_lit::*;
+use super::model::*;
+use super::str_lit::*;
 use protobuf_support::lexer::float;
 
 const FIRST_LINE: u32 = 1;
@@ -1,4 +1,4 @@
-use crate::parser::{Lexer, Loc, ParserError};
+use super::parser::{Lexer, Loc, ParserError};
 
 #[derive(Debug)]
 pub enum StrLitDecodeError {
@@ -11,58 +11,77 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::util::{fq_grpc, to_snake_case, MethodType};
-use derive_new::new;
-use prost::Message;
-use prost_build::{protoc, protoc_include, Config, Method, Service, ServiceGenerator};
+//! ttrpc code generation on top of prost.
+//!
+//! Services are written next to the prost messages of their package. The
+//! generated code needs `prost` and `ttrpc` with its `prost` feature, plus
+//! `async-trait` when async code is generated.
+
+use super::util::{
+    async_on, def_async_fn, file_descriptors, fq_grpc, pub_async_fn, stream_mod, to_camel_case,
+    to_snake_case, write_file_descriptors, writer::CodeWriter, MethodType,
+};
+use crate::parse::parse_and_typecheck;
+use crate::Customize;
+use prost::Message as _;
+use prost_build::{Config, Method, Service, ServiceGenerator};
 use prost_types::FileDescriptorSet;
-use std::io::{Error, ErrorKind, Read};
+use protobuf::descriptor::FileDescriptorProto;
+use protobuf::Message as _;
+use std::io;
+use std::io::{Error, ErrorKind};
 use std::path::Path;
-use std::{fs, io, process::Command};
 
 /// Returns the names of all packages compiled.
+///
+/// The `.proto` files are parsed without `protoc`, so the files they import
+/// (e.g. `google/protobuf/empty.proto`) must be found in `includes`.
 pub fn compile_protos<P>(protos: &[P], includes: &[P], out_dir: &str) -> io::Result<Vec<String>>
 where
     P: AsRef<Path>,
 {
-    let mut prost_config = Config::new();
-    prost_config.service_generator(Box::new(Generator));
-    prost_config.out_dir(out_dir);
-
-    // Create a file descriptor set for the protocol files.
-    let tmp = tempfile::Builder::new().prefix("prost-build").tempdir()?;
-    let descriptor_set = tmp.path().join("prost-descriptor-set");
-
-    let mut cmd = Command::new(protoc());
-    cmd.arg("--include_imports")
-        .arg("--include_source_info")
-        .arg("-o")
-        .arg(&descriptor_set);
-
-    for include in includes {
-        cmd.arg("-I").arg(include.as_ref());
-    }
-
-    // Set the protoc include after the user includes in case the user wants to
-    // override one of the built-in .protos.
-    cmd.arg("-I").arg(protoc_include());
+    compile_protos_with_customize(protos, includes, out_dir, &Customize::default())
+}
 
-    for proto in protos {
-        cmd.arg(proto.as_ref());
-    }
+/// Like [`compile_protos`], with the ttrpc code customized by `customize`.
+pub fn compile_protos_with_customize<P>(
+    protos: &[P],
+    includes: &[P],
+    out_dir: &str,
+    customize: &Customize,
+) -> io::Result<Vec<String>>
+where
+    P: AsRef<Path>,
+{
+    let protos: Vec<&Path> = protos.iter().map(|p| p.as_ref()).collect();
+    let includes: Vec<&Path> = includes.iter().map(|p| p.as_ref()).collect();
+    let p = parse_and_typecheck(&includes, &protos)?;
+    compile_file_descriptors(&p.file_descriptors, out_dir, customize)
+}
 
-    let output = cmd.output()?;
-    if !output.status.success() {
-        return Err(Error::new(
-            ErrorKind::Other,
-            format!("protoc failed: {}", String::from_utf8_lossy(&output.stderr)),
-        ));
+/// Like [`compile_protos_with_customize`], from the descriptors of the files
+/// to compile and of all the files they import.
+///
+/// Returns the names of all packages compiled.
+pub fn compile_file_descriptors(
+    files: &[FileDescriptorProto],
+    out_dir: &str,
+    customize: &Customize,
+) -> io::Result<Vec<String>> {
+    let mut files = files.to_vec();
+    // Generate the files of a package in a stable order.
+    files.sort_by(|a, b| a.name().cmp(b.name()));
+
+    let mut descriptor_set = FileDescriptorSet::default();
+    for file in &files {
+        let buf = file
+            .write_to_bytes()
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        descriptor_set
+            .file
+            .push(prost_types::FileDescriptorProto::decode(&*buf)?);
     }
 
-    let mut buf = Vec::new();
-    fs::File::open(descriptor_set)?.read_to_end(&mut buf)?;
-    let descriptor_set = FileDescriptorSet::decode(&*buf)?;
-
     // Get the package names from the descriptor set.
     let mut packages: Vec<_> = descriptor_set
         .file
@@ -72,83 +91,45 @@ where
     packages.sort();
     packages.dedup();
 
-    // FIXME(https://github.com/danburkert/prost/pull/155)
-    // Unfortunately we have to forget the above work and use `compile_protos` to
-    // actually generate the Rust code.
-    prost_config.compile_protos(protos, includes)?;
+    if !customize.gen_file_descriptors {
+        files.clear();
+    }
+    let mut prost_config = Config::new();
+    prost_config.out_dir(out_dir);
+    prost_config.service_generator(Box::new(Generator {
+        customize: customize.clone(),
+        files,
+    }));
+    prost_config.compile_fds(descriptor_set)?;
 
     Ok(packages)
 }
 
-struct Generator;
+struct Generator {
+    customize: Customize,
+    // The descriptors of the compiled files and their imports, to embed.
+    files: Vec<FileDescriptorProto>,
+}
 
 impl ServiceGenerator for Generator {
     fn generate(&mut self, service: Service, buf: &mut String) {
-        generate_methods(&service, buf);
-        generate_client(&service, buf);
-        generate_server(&service, buf);
-    }
-}
-
-fn generate_methods(service: &Service, buf: &mut String) {
-    let service_path = if service.package.is_empty() {
-        format!("/{}", service.proto_name)
-    } else {
-        format!("/{}.{}", service.package, service.proto_name)
-    };
-
-    for method in &service.methods {
-        generate_method(&service.name, &service_path, method, buf);
+        let mut w = CodeWriter::new();
+        ServiceGen::new(&service, &self.customize).write(&mut w);
+        if self.customize.gen_file_descriptors {
+            let file = self.files.iter().find(|f| {
+                f.package() == service.package
+                    && f.service.iter().any(|s| s.name() == service.proto_name)
+            });
+            let descriptors = file
+                .map(|f| file_descriptors(&self.files, f.name()))
+                .unwrap_or_default();
+            w.write_line("");
+            write_file_descriptors(&mut w, &service.name, &descriptors);
+        }
+        buf.push_str(&w.take_code());
     }
 }
 
-fn const_method_name(service_name: &str, method: &Method) -> String {
-    format!(
-        "METHOD_{}_{}",
-        to_snake_case(service_name).to_uppercase(),
-        method.name.to_uppercase()
-    )
-}
-
-fn generate_method(service_name: &str, service_path: &str, method: &Method, buf: &mut String) {
-    let name = const_method_name(service_name, method);
-    let ty = format!(
-        "{}<{}, {}>",
-        fq_grpc("Method"),
-        method.input_type,
-        method.output_type
-    );
-
-    buf.push_str("const ");
-    buf.push_str(&name);
-    buf.push_str(": ");
-    buf.push_str(&ty);
-    buf.push_str(" = ");
-    generate_method_body(service_path, method, buf);
-}
-
-fn generate_method_body(service_path: &str, method: &Method, buf: &mut String) {
-    let ty = fq_grpc(&MethodType::from_method(method).to_string());
-    let pr_mar = format!(
-        "{} {{ ser: {}, de: {} }}",
-        fq_grpc("Marshaller"),
-        fq_grpc("pr_ser"),
-        fq_grpc("pr_de")
-    );
-
-    buf.push_str(&fq_grpc("Method"));
-    buf.push('{');
-    generate_field_init("ty", &ty, buf);
-    generate_field_init(
-        "name",
-        &format!("\"{}/{}\"", service_path, method.proto_name),
-        buf,
-    );
-    generate_field_init("req_mar", &pr_mar, buf);
-    generate_field_init("resp_mar", &pr_mar, buf);
-    buf.push_str("};\n");
-}
-
 // TODO share this code with protobuf codegen
 impl MethodType {
     fn from_method(method: &Method) -> MethodType {
@@ -161,387 +142,627 @@ impl MethodType {
     }
 }
 
-fn generate_field_init(name: &str, value: &str, buf: &mut String) {
-    buf.push_str(name);
-    buf.push_str(": ");
-    buf.push_str(value);
-    buf.push_str(", ");
-}
+// The streams encode the prost messages with it instead of their `Codec`.
+const PROST_ENCODING: &str = "::ttrpc::proto::ProstEncoding";
 
-fn generate_client(service: &Service, buf: &mut String) {
-    let client_name = format!("{}Client", service.name);
-    buf.push_str("#[derive(Clone)]\n");
-    buf.push_str("pub struct ");
-    buf.push_str(&client_name);
-    buf.push_str(" { client: ::grpcio::Client }\n");
-
-    buf.push_str("impl ");
-    buf.push_str(&client_name);
-    buf.push_str(" {\n");
-    generate_ctor(&client_name, buf);
-    generate_client_methods(service, buf);
-    generate_spawn(buf);
-    buf.push_str("}\n")
+struct MethodGen<'a> {
+    proto: &'a Method,
+    service_path: String,
+    service_name: String,
+    customize: &'a Customize,
 }
 
-fn generate_ctor(client_name: &str, buf: &mut String) {
-    buf.push_str("pub fn new(channel: ::grpcio::Channel) -> Self { ");
-    buf.push_str(client_name);
-    buf.push_str(" { client: ::grpcio::Client::new(channel) }");
-    buf.push_str("}\n");
-}
+impl<'a> MethodGen<'a> {
+    fn new(
+        proto: &'a Method,
+        service_path: String,
+        service_name: String,
+        customize: &'a Customize,
+    ) -> MethodGen<'a> {
+        MethodGen {
+            proto,
+            service_path,
+            service_name,
+            customize,
+        }
+    }
 
-fn generate_client_methods(service: &Service, buf: &mut String) {
-    for method in &service.methods {
-        generate_client_method(&service.name, method, buf);
+    fn input(&self) -> &str {
+        &self.proto.input_type
     }
-}
 
-fn generate_client_method(service_name: &str, method: &Method, buf: &mut String) {
-    let name = &format!(
-        "METHOD_{}_{}",
-        to_snake_case(service_name).to_uppercase(),
-        method.name.to_uppercase()
-    );
-    match MethodType::from_method(method) {
-        MethodType::Unary => {
-            ClientMethod::new(
-                &method.name,
-                true,
-                Some(&method.input_type),
-                false,
-                vec![&method.output_type],
-                "unary_call",
-                name,
-            )
-            .generate(buf);
-            ClientMethod::new(
-                &method.name,
-                false,
-                Some(&method.input_type),
-                false,
-                vec![&method.output_type],
-                "unary_call",
-                name,
-            )
-            .generate(buf);
-            ClientMethod::new(
-                &method.name,
-                true,
-                Some(&method.input_type),
-                true,
-                vec![&format!(
-                    "{}<{}>",
-                    fq_grpc("ClientUnaryReceiver"),
-                    method.output_type
-                )],
-                "unary_call",
-                name,
-            )
-            .generate(buf);
-            ClientMethod::new(
-                &method.name,
-                false,
-                Some(&method.input_type),
-                true,
-                vec![&format!(
-                    "{}<{}>",
-                    fq_grpc("ClientUnaryReceiver"),
-                    method.output_type
-                )],
-                "unary_call",
-                name,
-            )
-            .generate(buf);
+    fn output(&self) -> &str {
+        &self.proto.output_type
+    }
+
+    fn method_type(&self) -> MethodType {
+        MethodType::from_method(self.proto)
+    }
+
+    fn name(&self) -> &str {
+        &self.proto.name
+    }
+
+    // Services of a package share one module, so the handler is prefixed
+    // with the service name.
+    fn struct_name(&self) -> String {
+        format!(
+            "{}{}Method",
+            self.service_name,
+            to_camel_case(&self.proto.proto_name)
+        )
+    }
+
+    fn write_handler(&self, w: &mut CodeWriter) {
+        w.block(format!("struct {} {{", self.struct_name()), "}", |w| {
+            w.write_line(format!(
+                "service: ::std::sync::Arc<dyn {} + Send + Sync>,",
+                self.service_name
+            ));
+        });
+        w.write_line("");
+        if async_on(self.customize, "server") {
+            self.write_handler_impl_async(w)
+        } else {
+            self.write_handler_impl(w)
         }
-        MethodType::ClientStreaming => {
-            ClientMethod::new(
-                &method.name,
-                true,
-                None,
-                false,
-                vec![
-                    &format!("{}<{}>", fq_grpc("ClientCStreamSender"), method.input_type),
-                    &format!(
-                        "{}<{}>",
-                        fq_grpc("ClientCStreamReceiver"),
-                        method.output_type
-                    ),
-                ],
-                "client_streaming",
-                name,
-            )
-            .generate(buf);
-            ClientMethod::new(
-                &method.name,
-                false,
-                None,
-                false,
-                vec![
-                    &format!("{}<{}>", fq_grpc("ClientCStreamSender"), method.input_type),
-                    &format!(
-                        "{}<{}>",
-                        fq_grpc("ClientCStreamReceiver"),
-                        method.output_type
-                    ),
-                ],
-                "client_streaming",
-                name,
+    }
+
+    fn write_handler_impl(&self, w: &mut CodeWriter) {
+        match self.method_type() {
+            MethodType::Unary => {
+                w.block(format!("impl ::ttrpc::MethodHandler for {} {{", self.struct_name()), "}",
+                |w| {
+                    w.block("fn handler(&self, ctx: ::ttrpc::TtrpcContext, req: ::ttrpc::Request) -> ::ttrpc::Result<()> {", "}",
+                    |w| {
+                        w.write_line(format!("::ttrpc::prost_request_handler!(self, ctx, req, {}, {});",
+                                                self.input(),
+                                                self.name()));
+                        w.write_line("Ok(())");
+                    });
+                });
+            }
+            // only receive
+            MethodType::ClientStreaming => {
+                w.block(format!("impl ::ttrpc::StreamHandler for {} {{", self.struct_name()), "}",
+                |w| {
+                    w.block("fn handler(&self, ctx: ::ttrpc::TtrpcContext, inner: ::ttrpc::sync::StreamInner) -> ::ttrpc::Result<Option<::ttrpc::Response>> {", "}",
+                        |w| {
+                            w.write_line(format!("::ttrpc::prost_client_streaming_handler!(self, ctx, inner, {});",
+                                        self.name()));
+                    });
+                });
+            }
+            // only send
+            MethodType::ServerStreaming => {
+                w.block(format!("impl ::ttrpc::StreamHandler for {} {{", self.struct_name()), "}",
+                |w| {
+                    w.block("fn handler(&self, ctx: ::ttrpc::TtrpcContext, mut inner: ::ttrpc::sync::StreamInner) -> ::ttrpc::Result<Option<::ttrpc::Response>> {", "}",
+                        |w| {
+                            w.write_line(format!("::ttrpc::prost_server_streaming_handler!(self, ctx, inner, {}, {});",
+                                        self.input(),
+                                        self.name()));
+                    });
+                });
+            }
+            // receive and send
+            MethodType::Duplex => {
+                w.block(format!("impl ::ttrpc::StreamHandler for {} {{", self.struct_name()), "}",
+                |w| {
+                    w.block("fn handler(&self, ctx: ::ttrpc::TtrpcContext, inner: ::ttrpc::sync::StreamInner) -> ::ttrpc::Result<Option<::ttrpc::Response>> {", "}",
+                        |w| {
+                            w.write_line(format!("::ttrpc::duplex_streaming_handler!(self, ctx, inner, {});",
+                                        self.name()));
+                    });
+                });
+            }
+        }
+    }
+
+    fn write_handler_impl_async(&self, w: &mut CodeWriter) {
+        w.write_line("#[::async_trait::async_trait]");
+        match self.method_type() {
+            MethodType::Unary => {
+                w.block(format!("impl ::ttrpc::r#async::MethodHandler for {} {{", self.struct_name()), "}",
+                |w| {
+                    w.block("async fn handler(&self, ctx: ::ttrpc::r#async::TtrpcContext, req: ::ttrpc::Request) -> ::ttrpc::Result<::ttrpc::Response> {", "}",
+                        |w| {
+                            w.write_line(format!("::ttrpc::async_prost_request_handler!(self, ctx, req, {}, {});",
+                                        self.input(),
+                                        self.name()));
+                    });
+            });
+            }
+            // only receive
+            MethodType::ClientStreaming => {
+                w.block(format!("impl ::ttrpc::r#async::StreamHandler for {} {{", self.struct_name()), "}",
+                |w| {
+                    w.block("async fn handler(&self, ctx: ::ttrpc::r#async::TtrpcContext, inner: ::ttrpc::r#async::StreamInner) -> ::ttrpc::Result<Option<::ttrpc::Response>> {", "}",
+                        |w| {
+                            w.write_line(format!("::ttrpc::async_prost_client_streaming_handler!(self, ctx, inner, {});",
+                                        self.name()));
+                    });
+            });
+            }
+            // only send
+            MethodType::ServerStreaming => {
+                w.block(format!("impl ::ttrpc::r#async::StreamHandler for {} {{", self.struct_name()), "}",
+                |w| {
+                    w.block("async fn handler(&self, ctx: ::ttrpc::r#async::TtrpcContext, mut inner: ::ttrpc::r#async::StreamInner) -> ::ttrpc::Result<Option<::ttrpc::Response>> {", "}",
+                        |w| {
+                            w.write_line(format!("::ttrpc::async_prost_server_streaming_handler!(self, ctx, inner, {}, {});",
+                                        self.input(),
+                                        self.name()));
+                    });
+            });
+            }
+            // receive and send
+            MethodType::Duplex => {
+                w.block(format!("impl ::ttrpc::r#async::StreamHandler for {} {{", self.struct_name()), "}",
+                |w| {
+                    w.block("async fn handler(&self, ctx: ::ttrpc::r#async::TtrpcContext, inner: ::ttrpc::r#async::StreamInner) -> ::ttrpc::Result<Option<::ttrpc::Response>> {", "}",
+                        |w| {
+                            w.write_line(format!("::ttrpc::async_duplex_streamimg_handler!(self, ctx, inner, {});",
+                                        self.name()));
+                    });
+            });
+            }
+        }
+    }
+
+    // Method signatures
+    fn unary(&self, method_name: &str) -> String {
+        format!(
+            "{}(&self, ctx: ::ttrpc::context::Context, req: &{}) -> {}<{}>",
+            method_name,
+            self.input(),
+            fq_grpc("Result"),
+            self.output()
+        )
+    }
+
+    fn client_streaming(&self, method_name: &str) -> String {
+        format!(
+            "{}(&self, ctx: ::ttrpc::context::Context) -> {}<{}<{}, {}, {}>>",
+            method_name,
+            fq_grpc("Result"),
+            fq_grpc(&format!(
+                "{}::ClientStreamSender",
+                stream_mod(self.customize, "client")
+            )),
+            self.input(),
+            self.output(),
+            PROST_ENCODING
+        )
+    }
+
+    fn server_streaming(&self, method_name: &str) -> String {
+        format!(
+            "{}(&self, ctx: ::ttrpc::context::Context, req: &{}) -> {}<{}<{}, {}>>",
+            method_name,
+            self.input(),
+            fq_grpc("Result"),
+            fq_grpc(&format!(
+                "{}::ClientStreamReceiver",
+                stream_mod(self.customize, "client")
+            )),
+            self.output(),
+            PROST_ENCODING
+        )
+    }
+
+    fn duplex_streaming(&self, method_name: &str) -> String {
+        format!(
+            "{}(&self, ctx: ::ttrpc::context::Context) -> {}<{}<{}, {}, {}>>",
+            method_name,
+            fq_grpc("Result"),
+            fq_grpc(&format!(
+                "{}::ClientStream",
+                stream_mod(self.customize, "client")
+            )),
+            self.input(),
+            self.output(),
+            PROST_ENCODING
+        )
+    }
+
+    fn write_client(&self, w: &mut CodeWriter) {
+        let method_name = self.name();
+        match self.method_type() {
+            // Unary RPC
+            MethodType::Unary => {
+                w.pub_fn(self.unary(method_name), |w| {
+                    w.write_line(format!(
+                        "::ttrpc::prost_client_request!(self, ctx, req, \"{}\", \"{}\", {});",
+                        self.service_path,
+                        &self.proto.proto_name,
+                        self.output(),
+                    ));
+                });
+            }
+            // Client Streaming RPC
+            MethodType::ClientStreaming => {
+                w.pub_fn(self.client_streaming(method_name), |w| {
+                    w.write_line(format!(
+                        "::ttrpc::client_stream_send!(self, ctx, \"{}\", \"{}\");",
+                        self.service_path, &self.proto.proto_name,
+                    ));
+                });
+            }
+            // Server Streaming RPC
+            MethodType::ServerStreaming => {
+                w.pub_fn(self.server_streaming(method_name), |w| {
+                    w.write_line(format!(
+                        "::ttrpc::prost_client_stream_receive!(self, ctx, req, \"{}\", \"{}\");",
+                        self.service_path, &self.proto.proto_name,
+                    ));
+                });
+            }
+            // Bidirectional streaming RPC
+            MethodType::Duplex => {
+                w.pub_fn(self.duplex_streaming(method_name), |w| {
+                    w.write_line(format!(
+                        "::ttrpc::client_stream!(self, ctx, \"{}\", \"{}\");",
+                        self.service_path, &self.proto.proto_name,
+                    ));
+                });
+            }
+        };
+    }
+
+    fn write_async_client(&self, w: &mut CodeWriter) {
+        let method_name = self.name();
+        match self.method_type() {
+            // Unary RPC
+            MethodType::Unary => {
+                pub_async_fn(w, &self.unary(method_name), |w| {
+                    w.write_line(format!(
+                        "::ttrpc::async_prost_client_request!(self, ctx, req, \"{}\", \"{}\", {});",
+                        self.service_path,
+                        &self.proto.proto_name,
+                        self.output(),
+                    ));
+                });
+            }
+            // Client Streaming RPC
+            MethodType::ClientStreaming => {
+                pub_async_fn(w, &self.client_streaming(method_name), |w| {
+                    w.write_line(format!(
+                        "::ttrpc::async_client_stream_send!(self, ctx, \"{}\", \"{}\");",
+                        self.service_path, &self.proto.proto_name,
+                    ));
+                });
+            }
+            // Server Streaming RPC
+            MethodType::ServerStreaming => {
+                pub_async_fn(w, &self.server_streaming(method_name), |w| {
+                    w.write_line(format!(
+                        "::ttrpc::async_prost_client_stream_receive!(self, ctx, req, \"{}\", \"{}\");",
+                        self.service_path,
+                        &self.proto.proto_name,
+                    ));
+                });
+            }
+            // Bidirectional streaming RPC
+            MethodType::Duplex => {
+                pub_async_fn(w, &self.duplex_streaming(method_name), |w| {
+                    w.write_line(format!(
+                        "::ttrpc::async_client_stream!(self, ctx, \"{}\", \"{}\");",
+                        self.service_path, &self.proto.proto_name,
+                    ));
+                });
+            }
+        };
+    }
+
+    fn write_service(&self, w: &mut CodeWriter) {
+        let stream_mod = stream_mod(self.customize, "server");
+        let (req_type, resp_type) = match self.method_type() {
+            MethodType::Unary => (self.input().to_string(), self.output().to_string()),
+            MethodType::ClientStreaming => (
+                format!(
+                    "::ttrpc::{}::ServerStreamReceiver<{}, {}>",
+                    stream_mod,
+                    self.input(),
+                    PROST_ENCODING
+                ),
+                self.output().to_string(),
+            ),
+            MethodType::ServerStreaming => (
+                format!(
+                    "{}, _: ::ttrpc::{}::ServerStreamSender<{}, {}>",
+                    self.input(),
+                    stream_mod,
+                    self.output(),
+                    PROST_ENCODING
+                ),
+                "()".to_string(),
+            ),
+            MethodType::Duplex => (
+                format!(
+                    "::ttrpc::{}::ServerStream<{}, {}, {}>",
+                    stream_mod,
+                    self.output(),
+                    self.input(),
+                    PROST_ENCODING
+                ),
+                "()".to_string(),
+            ),
+        };
+
+        let get_sig = |context_name| {
+            format!(
+                "{}(&self, _ctx: &{}, _: {}) -> ::ttrpc::Result<{}>",
+                self.name(),
+                fq_grpc(context_name),
+                req_type,
+                resp_type,
             )
-            .generate(buf);
+        };
+
+        let cb = |w: &mut CodeWriter| {
+            w.write_line(format!("Err(::ttrpc::Error::RpcStatus(::ttrpc::get_status(::ttrpc::Code::NOT_FOUND, \"/{}/{} is not supported\".to_string())))",
+            self.service_path, self.proto.proto_name,));
+        };
+
+        if async_on(self.customize, "server") {
+            let sig = get_sig("r#async::TtrpcContext");
+            def_async_fn(w, &sig, cb);
+        } else {
+            let sig = get_sig("TtrpcContext");
+            w.def_fn(&sig, cb);
         }
-        MethodType::ServerStreaming => {
-            ClientMethod::new(
-                &method.name,
-                true,
-                Some(&method.input_type),
-                false,
-                vec![&format!(
-                    "{}<{}>",
-                    fq_grpc("ClientSStreamReceiver"),
-                    method.output_type
-                )],
-                "server_streaming",
-                name,
+    }
+
+    fn write_bind(&self, w: &mut CodeWriter) {
+        let s = if matches!(self.method_type(), MethodType::Unary) {
+            format!(
+                "methods.insert(\"/{}/{}\".to_string(),
+                    Box::new({}{{service: service.clone()}}) as Box<dyn ::ttrpc::MethodHandler + Send + Sync>);",
+                self.service_path,
+                self.proto.proto_name,
+                self.struct_name(),
             )
-            .generate(buf);
-            ClientMethod::new(
-                &method.name,
-                false,
-                Some(&method.input_type),
-                false,
-                vec![&format!(
-                    "{}<{}>",
-                    fq_grpc("ClientSStreamReceiver"),
-                    method.output_type
-                )],
-                "server_streaming",
-                name,
+        } else {
+            format!(
+                "streams.insert(\"/{}/{}\".to_string(),
+                    ::std::sync::Arc::new({}{{service: service.clone()}}) as ::std::sync::Arc<dyn ::ttrpc::StreamHandler + Send + Sync>);",
+                self.service_path,
+                self.proto.proto_name,
+                self.struct_name(),
             )
-            .generate(buf);
-        }
-        MethodType::Duplex => {
-            ClientMethod::new(
-                &method.name,
-                true,
-                None,
-                false,
-                vec![
-                    &format!("{}<{}>", fq_grpc("ClientDuplexSender"), method.input_type),
-                    &format!(
-                        "{}<{}>",
-                        fq_grpc("ClientDuplexReceiver"),
-                        method.output_type
-                    ),
-                ],
-                "duplex_streaming",
-                name,
+        };
+        w.write_line(&s);
+    }
+
+    fn write_async_bind(&self, w: &mut CodeWriter) {
+        let s = if matches!(self.method_type(), MethodType::Unary) {
+            format!(
+                "methods.insert(\"{}\".to_string(),
+                    Box::new({}{{service: service.clone()}}) as {});",
+                self.proto.proto_name,
+                self.struct_name(),
+                "Box<dyn ::ttrpc::r#async::MethodHandler + Send + Sync>"
             )
-            .generate(buf);
-            ClientMethod::new(
-                &method.name,
-                false,
-                None,
-                false,
-                vec![
-                    &format!("{}<{}>", fq_grpc("ClientDuplexSender"), method.input_type),
-                    &format!(
-                        "{}<{}>",
-                        fq_grpc("ClientDuplexReceiver"),
-                        method.output_type
-                    ),
-                ],
-                "duplex_streaming",
-                name,
+        } else {
+            format!(
+                "streams.insert(\"{}\".to_string(),
+                    ::std::sync::Arc::new({}{{service: service.clone()}}) as {});",
+                self.proto.proto_name,
+                self.struct_name(),
+                "::std::sync::Arc<dyn ::ttrpc::r#async::StreamHandler + Send + Sync>"
             )
-            .generate(buf);
-        }
+        };
+        w.write_line(&s);
     }
 }
 
-#[derive(new)]
-struct ClientMethod<'a> {
-    method_name: &'a str,
-    opt: bool,
-    request: Option<&'a str>,
-    r#async: bool,
-    result_types: Vec<&'a str>,
-    inner_method_name: &'a str,
-    data_name: &'a str,
+struct ServiceGen<'a> {
+    proto: &'a Service,
+    service_path: String,
+    methods: Vec<MethodGen<'a>>,
+    customize: &'a Customize,
 }
 
-impl<'a> ClientMethod<'a> {
-    fn generate(&self, buf: &mut String) {
-        buf.push_str("pub fn ");
+impl<'a> ServiceGen<'a> {
+    fn new(proto: &'a Service, customize: &'a Customize) -> ServiceGen<'a> {
+        let service_path = if proto.package.is_empty() {
+            proto.proto_name.clone()
+        } else {
+            format!("{}.{}", proto.package, proto.proto_name)
+        };
 
-        buf.push_str(self.method_name);
-        if self.r#async {
-            buf.push_str("_async");
-        }
-        if self.opt {
-            buf.push_str("_opt");
+        let methods = proto
+            .methods
+            .iter()
+            .map(|m| MethodGen::new(m, service_path.clone(), proto.name.clone(), customize))
+            .collect();
+
+        ServiceGen {
+            proto,
+            service_path,
+            methods,
+            customize,
         }
+    }
 
-        buf.push_str("(&self");
-        if let Some(req) = self.request {
-            buf.push_str(", req: &");
-            buf.push_str(req);
-        }
-        if self.opt {
-            buf.push_str(", opt: ");
-            buf.push_str(&fq_grpc("CallOption"));
-        }
-        buf.push_str(") -> ");
+    fn service_name(&self) -> &str {
+        &self.proto.name
+    }
 
-        buf.push_str(&fq_grpc("Result"));
-        buf.push('<');
-        if self.result_types.len() != 1 {
-            buf.push('(');
-        }
-        for rt in &self.result_types {
-            buf.push_str(rt);
-            buf.push(',');
-        }
-        if self.result_types.len() != 1 {
-            buf.push(')');
-        }
-        buf.push_str("> { ");
-        if self.opt {
-            self.generate_inner_body(buf);
-        } else {
-            self.generate_opt_body(buf);
-        }
-        buf.push_str(" }\n");
+    fn service_path(&self) -> &str {
+        &self.service_path
     }
 
-    // Method delegates to the `_opt` version of the method.
-    fn generate_opt_body(&self, buf: &mut String) {
-        buf.push_str("self.");
-        buf.push_str(self.method_name);
-        if self.r#async {
-            buf.push_str("_async");
-        }
-        buf.push_str("_opt(");
-        if self.request.is_some() {
-            buf.push_str("req, ");
-        }
-        buf.push_str(&fq_grpc("CallOption::default()"));
-        buf.push(')');
+    fn client_name(&self) -> String {
+        format!("{}Client", self.service_name())
+    }
+
+    fn has_stream_method(&self) -> bool {
+        self.methods
+            .iter()
+            .any(|method| !matches!(method.method_type(), MethodType::Unary))
+    }
+
+    fn has_normal_method(&self) -> bool {
+        self.methods
+            .iter()
+            .any(|method| matches!(method.method_type(), MethodType::Unary))
+    }
+
+    fn write_client(&self, w: &mut CodeWriter) {
+        let client_type = if async_on(self.customize, "client") {
+            "::ttrpc::r#async::Client"
+        } else {
+            "::ttrpc::Client"
+        };
+
+        w.write_line("#[derive(Clone)]");
+        w.pub_struct(self.client_name(), |w| {
+            w.field_decl("client", client_type);
+        });
+
+        w.write_line("");
+
+        w.impl_self_block(self.client_name(), |w| {
+            w.pub_fn(format!("new(client: {}) -> Self", client_type), |w| {
+                w.expr_block(self.client_name(), |w| {
+                    w.write_line("client,");
+                });
+            });
+
+            for method in self.methods.iter() {
+                w.write_line("");
+                if async_on(self.customize, "client") {
+                    method.write_async_client(w);
+                } else {
+                    method.write_client(w);
+                }
+            }
+        });
     }
 
-    // Method delegates to the inner client.
-    fn generate_inner_body(&self, buf: &mut String) {
-        buf.push_str("self.client.");
-        buf.push_str(self.inner_method_name);
-        if self.r#async {
-            buf.push_str("_async");
+    fn write_server(&self, w: &mut CodeWriter) {
+        let mut trait_name = self.service_name().to_string();
+        if async_on(self.customize, "server") {
+            w.write_line("#[::async_trait::async_trait]");
+            trait_name = format!("{}: Sync", self.service_name());
         }
-        buf.push_str("(&");
-        buf.push_str(self.data_name);
-        if self.request.is_some() {
-            buf.push_str(", req");
+
+        w.pub_trait(&trait_name, |w| {
+            for method in self.methods.iter() {
+                method.write_service(w);
+            }
+        });
+
+        w.write_line("");
+        if async_on(self.customize, "server") {
+            self.write_async_server_create(w);
+        } else {
+            self.write_sync_server_create(w);
         }
-        buf.push_str(", opt)");
     }
-}
 
-fn generate_spawn(buf: &mut String) {
-    buf.push_str(
-        "pub fn spawn<F>(&self, f: F) \
-         where F: ::futures::Future<Item = (), Error = ()> + Send + 'static {\
-         self.client.spawn(f)\
-         }\n",
-    );
-}
+    fn write_sync_server_create(&self, w: &mut CodeWriter) {
+        let has_normal_method = self.has_normal_method();
+        let s = format!(
+            "create_{}({}: ::std::sync::Arc<dyn {} + Send + Sync>) -> ::std::collections::HashMap<String, Box<dyn ::ttrpc::MethodHandler + Send + Sync>>",
+            to_snake_case(self.service_name()),
+            if has_normal_method { "service" } else { "_service" },
+            self.service_name(),
+        );
+
+        w.pub_fn(&s, |w| {
+            if has_normal_method {
+                w.write_line("let mut methods = ::std::collections::HashMap::new();");
+            } else {
+                w.write_line("let methods = ::std::collections::HashMap::new();");
+            }
+            for method in self.methods.iter() {
+                if matches!(method.method_type(), MethodType::Unary) {
+                    w.write_line("");
+                    method.write_bind(w);
+                }
+            }
+            w.write_line("");
+            w.write_line("methods");
+        });
 
-fn generate_server(service: &Service, buf: &mut String) {
-    buf.push_str("pub trait ");
-    buf.push_str(&service.name);
-    buf.push_str(" {\n");
-    generate_server_methods(service, buf);
-    buf.push_str("}\n");
-
-    buf.push_str("pub fn create_");
-    buf.push_str(&to_snake_case(&service.name));
-    buf.push_str("<S: ");
-    buf.push_str(&service.name);
-    buf.push_str(" + Send + Clone + 'static>(s: S) -> ");
-    buf.push_str(&fq_grpc("Service"));
-    buf.push_str(" {\n");
-    buf.push_str("let mut builder = ::grpcio::ServiceBuilder::new();\n");
-
-    for method in &service.methods[0..service.methods.len() - 1] {
-        buf.push_str("let mut instance = s.clone();\n");
-        generate_method_bind(&service.name, method, buf);
-    }
-
-    buf.push_str("let mut instance = s;\n");
-    generate_method_bind(
-        &service.name,
-        &service.methods[service.methods.len() - 1],
-        buf,
-    );
-
-    buf.push_str("builder.build()\n");
-    buf.push_str("}\n");
-}
+        if !self.has_stream_method() {
+            return;
+        }
 
-fn generate_server_methods(service: &Service, buf: &mut String) {
-    for method in &service.methods {
-        let method_type = MethodType::from_method(method);
-        let request_arg = match method_type {
-            MethodType::Unary | MethodType::ServerStreaming => {
-                format!("req: {}", method.input_type)
+        // Streaming methods are registered by `Server::register_stream_service`.
+        w.write_line("");
+        let s = format!(
+            "create_{}_streams(service: ::std::sync::Arc<dyn {} + Send + Sync>) -> ::std::collections::HashMap<String, ::std::sync::Arc<dyn ::ttrpc::StreamHandler + Send + Sync>>",
+            to_snake_case(self.service_name()),
+            self.service_name(),
+        );
+        w.pub_fn(&s, |w| {
+            w.write_line("let mut streams = ::std::collections::HashMap::new();");
+            for method in self.methods.iter() {
+                if !matches!(method.method_type(), MethodType::Unary) {
+                    w.write_line("");
+                    method.write_bind(w);
+                }
             }
-            MethodType::ClientStreaming | MethodType::Duplex => format!(
-                "stream: {}<{}>",
-                fq_grpc("RequestStream"),
-                method.input_type
-            ),
-        };
-        let response_type = match method_type {
-            MethodType::Unary => "UnarySink",
-            MethodType::ClientStreaming => "ClientStreamingSink",
-            MethodType::ServerStreaming => "ServerStreamingSink",
-            MethodType::Duplex => "DuplexSink",
-        };
-        generate_server_method(method, &request_arg, response_type, buf);
+            w.write_line("");
+            w.write_line("streams");
+        });
     }
-}
 
-fn generate_server_method(
-    method: &Method,
-    request_arg: &str,
-    response_type: &str,
-    buf: &mut String,
-) {
-    buf.push_str("fn ");
-    buf.push_str(&method.name);
-    buf.push_str("(&mut self, ctx: ");
-    buf.push_str(&fq_grpc("RpcContext"));
-    buf.push_str(", ");
-    buf.push_str(request_arg);
-    buf.push_str(", sink: ");
-    buf.push_str(&fq_grpc(response_type));
-    buf.push('<');
-    buf.push_str(&method.output_type);
-    buf.push('>');
-    buf.push_str(");\n");
-}
+    fn write_async_server_create(&self, w: &mut CodeWriter) {
+        let s = format!(
+            "create_{}(service: ::std::sync::Arc<dyn {} + Send + Sync>) -> ::std::collections::HashMap<String, {}>",
+            to_snake_case(self.service_name()),
+            self.service_name(),
+            "::ttrpc::r#async::Service"
+        );
+
+        let has_stream_method = self.has_stream_method();
+        let has_normal_method = self.has_normal_method();
+        w.pub_fn(&s, |w| {
+            w.write_line("let mut ret = ::std::collections::HashMap::new();");
+            if has_normal_method {
+                w.write_line("let mut methods = ::std::collections::HashMap::new();");
+            } else {
+                w.write_line("let methods = ::std::collections::HashMap::new();");
+            }
+            if has_stream_method {
+                w.write_line("let mut streams = ::std::collections::HashMap::new();");
+            } else {
+                w.write_line("let streams = ::std::collections::HashMap::new();");
+            }
+            for method in self.methods.iter() {
+                w.write_line("");
+                method.write_async_bind(w);
+            }
+            w.write_line("");
+            w.write_line(format!(
+                "ret.insert(\"{}\".to_string(), {});",
+                self.service_path(),
+                "::ttrpc::r#async::Service{ methods, streams }"
+            ));
+            w.write_line("ret");
+        });
+    }
+
+    fn write_method_handlers(&self, w: &mut CodeWriter) {
+        for (i, method) in self.methods.iter().enumerate() {
+            if i != 0 {
+                w.write_line("");
+            }
 
-fn generate_method_bind(service_name: &str, method: &Method, buf: &mut String) {
-    let add_name = match MethodType::from_method(method) {
-        MethodType::Unary => "add_unary_handler",
-        MethodType::ClientStreaming => "add_client_streaming_handler",
-        MethodType::ServerStreaming => "add_server_streaming_handler",
-        MethodType::Duplex => "add_duplex_streaming_handler",
-    };
-
-    buf.push_str("builder = builder.");
-    buf.push_str(add_name);
-    buf.push_str("(&");
-    buf.push_str(&const_method_name(service_name, method));
-    buf.push_str(", move |ctx, req, resp| instance.");
-    buf.push_str(&method.name);
-    buf.push_str("(ctx, req, resp));\n");
+            method.write_handler(w);
+        }
+    }
+
+    fn write(&self, w: &mut CodeWriter) {
+        self.write_client(w);
+        w.write_line("");
+        self.write_method_handlers(w);
+        w.write_line("");
+        self.write_server(w);
+    }
 }
@@ -13,9 +13,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
 use std::fmt;
 use std::str;
 
+use protobuf::descriptor::FileDescriptorProto;
+use protobuf::Message;
+
 pub mod scope;
 pub mod writer;
 
@@ -110,6 +114,15 @@ pub fn async_on(customize: &crate::Customize, r#type: &str) -> bool {
     }
 }
 
+/// Module of the ttrpc stream types used by the `type` side.
+pub fn stream_mod(customize: &crate::Customize, r#type: &str) -> &'static str {
+    if async_on(customize, r#type) {
+        "r#async"
+    } else {
+        "sync"
+    }
+}
+
 pub fn async_fn_block<F>(w: &mut CodeWriter, public: bool, sig: &str, cb: F)
 where
     F: Fn(&mut CodeWriter),
@@ -144,6 +157,77 @@ pub fn proto_path_to_rust_mod(path: &str) -> String {
         .to_string()
 }
 
+/// Serialize the descriptor of the file `name` of `files`, after the ones of the files it
+/// imports, transitively. The source info is left out.
+pub fn file_descriptors(files: &[FileDescriptorProto], name: &str) -> Vec<Vec<u8>> {
+    fn add<'a>(
+        files: &HashMap<&str, &'a FileDescriptorProto>,
+        name: &str,
+        added: &mut Vec<&'a FileDescriptorProto>,
+    ) {
+        let file = match files.get(name) {
+            Some(file) => *file,
+            None => return,
+        };
+        if added.iter().any(|f| f.name() == name) {
+            return;
+        }
+        for dependency in &file.dependency {
+            add(files, dependency, added);
+        }
+        added.push(file);
+    }
+
+    let files: HashMap<&str, &FileDescriptorProto> = files.iter().map(|f| (f.name(), f)).collect();
+    let mut added = Vec::new();
+    add(&files, name, &mut added);
+    added
+        .into_iter()
+        .map(|file| {
+            let mut file = file.clone();
+            file.source_code_info.clear();
+            file.write_to_bytes().unwrap()
+        })
+        .collect()
+}
+
+/// Write the descriptors of the service `service_name` as `<SERVICE_NAME>_FILE_DESCRIPTORS`.
+pub fn write_file_descriptors(w: &mut CodeWriter, service_name: &str, descriptors: &[Vec<u8>]) {
+    w.write_line(format!(
+        "/// The serialized `FileDescriptorProto`s of `{}`, the imported files come first.",
+        service_name
+    ));
+    w.block(
+        format!(
+            "pub const {}_FILE_DESCRIPTORS: &[&[u8]] = &[",
+            to_snake_case(service_name).to_uppercase()
+        ),
+        "];",
+        |w| {
+            for descriptor in descriptors {
+                w.write_line("b\"\\");
+                for chunk in descriptor.chunks(32) {
+                    w.write_line(format!("{}\\", escape_bytes(chunk)));
+                }
+                w.write_line("\",");
+            }
+        },
+    );
+}
+
+// Escape `bytes` for a byte string literal split over lines, so whitespace is escaped too.
+fn escape_bytes(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len() * 4);
+    for &b in bytes {
+        match b {
+            b'"' | b'\\' => escaped.push_str(&format!("\\{}", b as char)),
+            0x21..=0x7e => escaped.push(b as char),
+            _ => escaped.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    escaped
+}
+
 pub enum MethodType {
     Unary,
     ClientStreaming,
@@ -210,4 +294,39 @@ mod test {
             assert_eq!(res, exp);
         }
     }
+
+    #[test]
+    fn test_file_descriptors() {
+        use protobuf::descriptor::FileDescriptorProto;
+        use protobuf::Message;
+
+        let file = |name: &str, dependencies: &[&str]| {
+            let mut file = FileDescriptorProto::new();
+            file.set_name(name.to_string());
+            file.dependency = dependencies.iter().map(|d| d.to_string()).collect();
+            file
+        };
+        let files = [
+            file("service.proto", &["types.proto", "empty.proto"]),
+            file("types.proto", &["empty.proto"]),
+            file("empty.proto", &[]),
+            file("other.proto", &[]),
+        ];
+
+        let names: Vec<String> = super::file_descriptors(&files, "service.proto")
+            .iter()
+            .map(|d| {
+                FileDescriptorProto::parse_from_bytes(d)
+                    .unwrap()
+                    .name()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(names, ["empty.proto", "types.proto", "service.proto"]);
+    }
+
+    #[test]
+    fn test_escape_bytes() {
+        assert_eq!(super::escape_bytes(b"a \"\\\n\xff"), r#"a\x20\"\\\x0a\xff"#);
+    }
 }
@@ -37,7 +37,7 @@ async fn main() {
 
         assert_eq!(
             resp,
-            Err(ttrpc::Error::Others(
+            Err(ttrpc::Error::Timeout(
                 "Receive packet timeout Elapsed(())".into()
             ))
         );
@@ -116,9 +116,9 @@ async fn main() {
 
 fn default_ctx() -> Context {
     let mut ctx = context::with_timeout(0);
-    ctx.add("key-1".to_string(), "value-1-1".to_string());
-    ctx.add("key-1".to_string(), "value-1-2".to_string());
-    ctx.set("key-2".to_string(), vec!["value-2".to_string()]);
+    ctx.metadata.append("key-1", "value-1-1").unwrap();
+    ctx.metadata.append("key-1", "value-1-2").unwrap();
+    ctx.metadata.append("key-2", "value-2").unwrap();
 
     ctx
 }
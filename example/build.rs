@@ -12,14 +12,17 @@ use ttrpc_codegen::{Codegen, Customize, ProtobufCustomize};
 fn main() {
     fs::create_dir_all("protocols/sync").unwrap();
     fs::create_dir_all("protocols/asynchronous").unwrap();
+    fs::create_dir_all("protocols/prost").unwrap();
+    fs::create_dir_all("protocols/prost_sync").unwrap();
 
-    let mut protos = vec![
+    let protos = vec![
         "protocols/protos/github.com/gogo/protobuf/gogoproto/gogo.proto",
         "protocols/protos/github.com/kata-containers/agent/pkg/types/types.proto",
         "protocols/protos/agent.proto",
         "protocols/protos/health.proto",
         "protocols/protos/google/protobuf/empty.proto",
         "protocols/protos/oci.proto",
+        "protocols/protos/streaming.proto",
     ];
     let protobuf_customized = ProtobufCustomize::default().gen_mod_rs(true);
 
@@ -36,9 +39,6 @@ fn main() {
         .run()
         .expect("Gen sync code failed.");
 
-    // Only async support stream currently.
-    protos.push("protocols/protos/streaming.proto");
-
     Codegen::new()
         .out_dir("protocols/asynchronous")
         .inputs(&protos)
@@ -53,6 +53,26 @@ fn main() {
         .run()
         .expect("Gen async code failed.");
 
+    Codegen::new()
+        .out_dir("protocols/prost")
+        .input("protocols/protos/streaming.proto")
+        .include("protocols/protos")
+        .prost()
+        .customize(Customize {
+            async_all: true,
+            ..Default::default()
+        })
+        .run()
+        .expect("Gen prost code failed.");
+
+    Codegen::new()
+        .out_dir("protocols/prost_sync")
+        .input("protocols/protos/streaming.proto")
+        .include("protocols/protos")
+        .prost()
+        .run()
+        .expect("Gen sync prost code failed.");
+
     // There is a message named 'Box' in oci.proto
     // so there is a struct named 'Box', we should replace Box<Self> to ::std::boxed::Box<Self>
     // to avoid the conflict.
@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+mod protocols;
+mod utils;
+
+use protocols::prost::{EchoPayload, Part, StreamingClient, Sum};
+use ttrpc::context;
+use ttrpc::r#async::Client;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    simple_logging::log_to_stderr(log::LevelFilter::Info);
+
+    let c = Client::connect(utils::SOCK_ADDR).await.unwrap();
+    let sc = StreamingClient::new(c);
+
+    echo_request(&sc).await;
+    echo_stream(&sc).await;
+    sum_stream(&sc).await;
+    divide_stream(&sc).await;
+
+    println!("***** Prost test is OK! *****");
+}
+
+async fn echo_request(cli: &StreamingClient) {
+    let echo = EchoPayload {
+        seq: 1,
+        msg: "Echo Me".to_string(),
+    };
+    let resp = cli.echo(context::with_timeout(0), &echo).await.unwrap();
+    assert_eq!(resp.msg, echo.msg);
+    assert_eq!(resp.seq, echo.seq + 1);
+}
+
+async fn echo_stream(cli: &StreamingClient) {
+    let mut stream = cli.echo_stream(context::with_timeout(0)).await.unwrap();
+
+    for i in 0..10 {
+        let echo = EchoPayload {
+            seq: i,
+            msg: format!("{}: Echo in a stream", i),
+        };
+        stream.send(&echo).await.unwrap();
+        let resp = stream.recv().await.unwrap();
+        assert_eq!(resp.msg, echo.msg);
+        assert_eq!(resp.seq, echo.seq + 1);
+    }
+    stream.close_send().await.unwrap();
+    let ret = stream.recv().await;
+    assert!(matches!(ret, Err(ttrpc::Error::Eof)));
+}
+
+async fn sum_stream(cli: &StreamingClient) {
+    let mut stream = cli.sum_stream(context::with_timeout(0)).await.unwrap();
+
+    let mut sum = Sum::default();
+    for add in -10..=10 {
+        stream.send(&Part { add }).await.unwrap();
+        sum.sum += add;
+        sum.num += 1;
+    }
+
+    let ssum = stream.close_and_recv().await.unwrap();
+    assert_eq!(ssum, sum);
+}
+
+async fn divide_stream(cli: &StreamingClient) {
+    let expected = Sum { sum: 392, num: 4 };
+    let mut stream = cli
+        .divide_stream(context::with_timeout(0), &expected)
+        .await
+        .unwrap();
+
+    let mut actual = Sum::default();
+    while let Some(part) = stream.recv().await.unwrap() {
+        actual.sum += part.add;
+        actual.num += 1;
+    }
+    assert_eq!(actual, expected);
+}
@@ -0,0 +1,91 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+mod protocols;
+mod utils;
+
+use std::sync::Arc;
+
+use log::{info, LevelFilter};
+
+use protocols::prost::{create_streaming, EchoPayload, Part, Streaming, Sum};
+use ttrpc::asynchronous::Server;
+use ttrpc::proto::ProstEncoding;
+
+use async_trait::async_trait;
+
+struct StreamingService;
+
+#[async_trait]
+impl Streaming for StreamingService {
+    async fn echo(
+        &self,
+        _ctx: &::ttrpc::r#async::TtrpcContext,
+        mut e: EchoPayload,
+    ) -> ::ttrpc::Result<EchoPayload> {
+        e.seq += 1;
+        Ok(e)
+    }
+
+    async fn echo_stream(
+        &self,
+        _ctx: &::ttrpc::r#async::TtrpcContext,
+        mut s: ::ttrpc::r#async::ServerStream<EchoPayload, EchoPayload, ProstEncoding>,
+    ) -> ::ttrpc::Result<()> {
+        while let Some(mut e) = s.recv().await? {
+            e.seq += 1;
+            s.send(&e).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn sum_stream(
+        &self,
+        _ctx: &::ttrpc::r#async::TtrpcContext,
+        mut s: ::ttrpc::r#async::ServerStreamReceiver<Part, ProstEncoding>,
+    ) -> ::ttrpc::Result<Sum> {
+        let mut sum = Sum::default();
+        while let Some(part) = s.recv().await? {
+            sum.sum += part.add;
+            sum.num += 1;
+        }
+
+        Ok(sum)
+    }
+
+    async fn divide_stream(
+        &self,
+        _ctx: &::ttrpc::r#async::TtrpcContext,
+        sum: Sum,
+        s: ::ttrpc::r#async::ServerStreamSender<Part, ProstEncoding>,
+    ) -> ::ttrpc::Result<()> {
+        for i in 0..sum.num {
+            let add = if i == 0 { sum.sum } else { 0 };
+            s.send(&Part { add }).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    simple_logging::log_to_stderr(LevelFilter::Info);
+    let service = create_streaming(Arc::new(StreamingService {}));
+    utils::remove_if_sock_exist(utils::SOCK_ADDR).unwrap();
+
+    let mut server = Server::new()
+        .bind(utils::SOCK_ADDR)
+        .unwrap()
+        .register_service(service);
+
+    server.start().await.unwrap();
+
+    tokio::select! {
+        _ = utils::hangup() => {}
+        _ = utils::interrupt() => {}
+    };
+    info!("shutdown");
+    server.shutdown().await.unwrap();
+}
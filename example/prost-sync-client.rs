@@ -0,0 +1,79 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+mod protocols;
+mod utils;
+
+use protocols::prost_sync::{EchoPayload, Part, StreamingClient, Sum};
+use ttrpc::context;
+use ttrpc::Client;
+
+fn main() {
+    simple_logging::log_to_stderr(log::LevelFilter::Info);
+
+    let c = Client::connect(utils::SOCK_ADDR).unwrap();
+    let sc = StreamingClient::new(c);
+
+    echo_request(&sc);
+    echo_stream(&sc);
+    sum_stream(&sc);
+    divide_stream(&sc);
+
+    println!("***** Sync prost test is OK! *****");
+}
+
+fn echo_request(cli: &StreamingClient) {
+    let echo = EchoPayload {
+        seq: 1,
+        msg: "Echo Me".to_string(),
+    };
+    let resp = cli.echo(context::with_timeout(0), &echo).unwrap();
+    assert_eq!(resp.msg, echo.msg);
+    assert_eq!(resp.seq, echo.seq + 1);
+}
+
+fn echo_stream(cli: &StreamingClient) {
+    let mut stream = cli.echo_stream(context::with_timeout(0)).unwrap();
+
+    for i in 0..10 {
+        let echo = EchoPayload {
+            seq: i,
+            msg: format!("{}: Echo in a stream", i),
+        };
+        stream.send(&echo).unwrap();
+        let resp = stream.recv().unwrap();
+        assert_eq!(resp.msg, echo.msg);
+        assert_eq!(resp.seq, echo.seq + 1);
+    }
+    stream.close_send().unwrap();
+    let ret = stream.recv();
+    assert!(matches!(ret, Err(ttrpc::Error::Eof)));
+}
+
+fn sum_stream(cli: &StreamingClient) {
+    let mut stream = cli.sum_stream(context::with_timeout(0)).unwrap();
+
+    let mut sum = Sum::default();
+    for add in -10..=10 {
+        stream.send(&Part { add }).unwrap();
+        sum.sum += add;
+        sum.num += 1;
+    }
+
+    let ssum = stream.close_and_recv().unwrap();
+    assert_eq!(ssum, sum);
+}
+
+fn divide_stream(cli: &StreamingClient) {
+    let expected = Sum { sum: 392, num: 4 };
+    let mut stream = cli
+        .divide_stream(context::with_timeout(0), &expected)
+        .unwrap();
+
+    let mut actual = Sum::default();
+    while let Some(part) = stream.recv().unwrap() {
+        actual.sum += part.add;
+        actual.num += 1;
+    }
+    assert_eq!(actual, expected);
+}
@@ -0,0 +1,97 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+mod protocols;
+mod utils;
+
+use std::{sync::Arc, thread};
+
+use log::LevelFilter;
+
+use protocols::prost_sync::{
+    create_streaming, create_streaming_streams, EchoPayload, Part, Streaming, Sum,
+};
+use ttrpc::proto::ProstEncoding;
+use ttrpc::Server;
+
+struct StreamingService;
+
+impl Streaming for StreamingService {
+    fn echo(
+        &self,
+        _ctx: &::ttrpc::TtrpcContext,
+        mut e: EchoPayload,
+    ) -> ::ttrpc::Result<EchoPayload> {
+        e.seq += 1;
+        Ok(e)
+    }
+
+    fn echo_stream(
+        &self,
+        _ctx: &::ttrpc::TtrpcContext,
+        mut s: ::ttrpc::sync::ServerStream<EchoPayload, EchoPayload, ProstEncoding>,
+    ) -> ::ttrpc::Result<()> {
+        while let Some(mut e) = s.recv()? {
+            e.seq += 1;
+            s.send(&e)?;
+        }
+
+        Ok(())
+    }
+
+    fn sum_stream(
+        &self,
+        _ctx: &::ttrpc::TtrpcContext,
+        mut s: ::ttrpc::sync::ServerStreamReceiver<Part, ProstEncoding>,
+    ) -> ::ttrpc::Result<Sum> {
+        let mut sum = Sum::default();
+        while let Some(part) = s.recv()? {
+            sum.sum += part.add;
+            sum.num += 1;
+        }
+
+        Ok(sum)
+    }
+
+    fn divide_stream(
+        &self,
+        _ctx: &::ttrpc::TtrpcContext,
+        sum: Sum,
+        s: ::ttrpc::sync::ServerStreamSender<Part, ProstEncoding>,
+    ) -> ::ttrpc::Result<()> {
+        for i in 0..sum.num {
+            let add = if i == 0 { sum.sum } else { 0 };
+            s.send(&Part { add })?;
+        }
+
+        Ok(())
+    }
+}
+
+fn main() {
+    simple_logging::log_to_stderr(LevelFilter::Info);
+    let service = Arc::new(StreamingService {});
+    let methods = create_streaming(service.clone());
+    let streams = create_streaming_streams(service);
+
+    utils::remove_if_sock_exist(utils::SOCK_ADDR).unwrap();
+    let mut server = Server::new()
+        .bind(utils::SOCK_ADDR)
+        .unwrap()
+        .register_service(methods)
+        .register_stream_service(streams);
+
+    server.start().unwrap();
+
+    // Hold the main thread until receiving signal SIGTERM
+    let (tx, rx) = std::sync::mpsc::channel();
+    thread::spawn(move || {
+        ctrlc::set_handler(move || {
+            tx.send(()).unwrap();
+        })
+        .expect("Error setting Ctrl-C handler");
+        println!("Server is running, press Ctrl + C to exit");
+    });
+
+    rx.recv().unwrap();
+}
@@ -4,3 +4,13 @@
 //
 pub mod asynchronous;
 pub mod sync;
+// Only the prost examples use these, unlike the rust-protobuf output they
+// carry no lint allowances of their own.
+#[allow(dead_code)]
+pub mod prost {
+    include!("prost/ttrpc.test.streaming.rs");
+}
+#[allow(dead_code)]
+pub mod prost_sync {
+    include!("prost_sync/ttrpc.test.streaming.rs");
+}
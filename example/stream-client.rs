@@ -0,0 +1,201 @@
+// Copyright 2022 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+mod protocols;
+mod utils;
+
+use std::thread;
+
+use protocols::sync::{empty, streaming, streaming_ttrpc};
+use ttrpc::context::{self, Context};
+use ttrpc::Client;
+
+fn main() {
+    simple_logging::log_to_stderr(log::LevelFilter::Info);
+
+    let c = Client::connect(utils::SOCK_ADDR).unwrap();
+    let sc = streaming_ttrpc::StreamingClient::new(c);
+
+    let tests: Vec<fn(streaming_ttrpc::StreamingClient)> = vec![
+        echo_request,
+        echo_stream,
+        sum_stream,
+        divide_stream,
+        echo_null,
+        echo_null_stream,
+        echo_default_value,
+        server_send_stream,
+    ];
+
+    let threads: Vec<_> = tests
+        .into_iter()
+        .map(|t| {
+            let sc = sc.clone();
+            thread::spawn(move || t(sc))
+        })
+        .collect();
+
+    for t in threads {
+        assert!(
+            t.join().is_ok(),
+            "stream test is failed because some error occurred"
+        );
+    }
+
+    println!("***** Sync Stream test is OK! *****");
+}
+
+fn default_ctx() -> Context {
+    let mut ctx = context::with_timeout(0);
+    ctx.metadata.append("key-1", "value-1-1").unwrap();
+    ctx.metadata.append("key-1", "value-1-2").unwrap();
+    ctx.metadata.append("key-2", "value-2").unwrap();
+
+    ctx
+}
+
+fn echo_request(cli: streaming_ttrpc::StreamingClient) {
+    let echo1 = streaming::EchoPayload {
+        seq: 1,
+        msg: "Echo Me".to_string(),
+        ..Default::default()
+    };
+    let resp = cli.echo(default_ctx(), &echo1).unwrap();
+    assert_eq!(resp.msg, echo1.msg);
+    assert_eq!(resp.seq, echo1.seq + 1);
+}
+
+fn echo_stream(cli: streaming_ttrpc::StreamingClient) {
+    let mut stream = cli.echo_stream(default_ctx()).unwrap();
+
+    let mut i = 0;
+    while i < 100 {
+        let echo = streaming::EchoPayload {
+            seq: i as u32,
+            msg: format!("{}: Echo in a stream", i),
+            ..Default::default()
+        };
+        stream.send(&echo).unwrap();
+        let resp = stream.recv().unwrap();
+        assert_eq!(resp.msg, echo.msg);
+        assert_eq!(resp.seq, echo.seq + 1);
+
+        i += 2;
+    }
+    stream.close_send().unwrap();
+    let ret = stream.recv();
+    assert!(matches!(ret, Err(ttrpc::Error::Eof)));
+}
+
+fn sum_stream(cli: streaming_ttrpc::StreamingClient) {
+    let mut stream = cli.sum_stream(default_ctx()).unwrap();
+
+    let mut sum = streaming::Sum::new();
+    stream.send(&streaming::Part::new()).unwrap();
+
+    sum.num += 1;
+    let mut i = -99i32;
+    while i <= 100 {
+        let addi = streaming::Part {
+            add: i,
+            ..Default::default()
+        };
+        stream.send(&addi).unwrap();
+        sum.sum += i;
+        sum.num += 1;
+
+        i += 1;
+    }
+    stream.send(&streaming::Part::new()).unwrap();
+    sum.num += 1;
+
+    let ssum = stream.close_and_recv().unwrap();
+    assert_eq!(ssum.sum, sum.sum);
+    assert_eq!(ssum.num, sum.num);
+}
+
+fn divide_stream(cli: streaming_ttrpc::StreamingClient) {
+    let expected = streaming::Sum {
+        sum: 392,
+        num: 4,
+        ..Default::default()
+    };
+    let mut stream = cli.divide_stream(default_ctx(), &expected).unwrap();
+
+    let mut actual = streaming::Sum::new();
+
+    while let Some(part) = stream.recv().unwrap() {
+        actual.sum += part.add;
+        actual.num += 1;
+    }
+    assert_eq!(actual.sum, expected.sum);
+    assert_eq!(actual.num, expected.num);
+}
+
+fn echo_null(cli: streaming_ttrpc::StreamingClient) {
+    let mut stream = cli.echo_null(default_ctx()).unwrap();
+
+    for i in 0..100 {
+        let echo = streaming::EchoPayload {
+            seq: i as u32,
+            msg: "non-empty empty".to_string(),
+            ..Default::default()
+        };
+        stream.send(&echo).unwrap();
+    }
+    let res = stream.close_and_recv().unwrap();
+    assert_eq!(res, empty::Empty::new());
+}
+
+fn echo_null_stream(cli: streaming_ttrpc::StreamingClient) {
+    let stream = cli.echo_null_stream(default_ctx()).unwrap();
+
+    let (tx, mut rx) = stream.split();
+
+    let t = thread::spawn(move || loop {
+        let ret = rx.recv();
+        if matches!(ret, Err(ttrpc::Error::Eof)) {
+            break;
+        }
+    });
+
+    for i in 0..100 {
+        let echo = streaming::EchoPayload {
+            seq: i as u32,
+            msg: "non-empty empty".to_string(),
+            ..Default::default()
+        };
+        tx.send(&echo).unwrap();
+    }
+
+    tx.close_send().unwrap();
+
+    t.join().unwrap();
+}
+
+fn echo_default_value(cli: streaming_ttrpc::StreamingClient) {
+    let mut stream = cli
+        .echo_default_value(default_ctx(), &Default::default())
+        .unwrap();
+
+    let received = stream.recv().unwrap().unwrap();
+
+    assert_eq!(received.seq, 0);
+    assert_eq!(received.msg, "");
+}
+
+fn server_send_stream(cli: streaming_ttrpc::StreamingClient) {
+    let mut stream = cli
+        .server_send_stream(default_ctx(), &Default::default())
+        .unwrap();
+
+    let mut seq = 0;
+    while let Some(received) = stream.recv().unwrap() {
+        assert_eq!(received.seq, seq);
+        assert_eq!(received.msg, "hello");
+        seq += 1;
+    }
+    assert_eq!(seq, 10);
+}
@@ -0,0 +1,208 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Listeners inherited with socket activation.
+//!
+//! systemd, and other managers following its protocol, pass listening sockets to a service from
+//! fd 3 on and tell about them with `LISTEN_PID`, `LISTEN_FDS` and `LISTEN_FDNAMES`. The servers
+//! take them with `add_activated_listeners`, or a listener at a time from [`listen_fds`].
+
+use std::env;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::sys::socket::{
+    getsockname, getsockopt, sockopt, AddressFamily, SockType, SockaddrLike, SockaddrStorage,
+};
+
+use crate::error::{Error, Result};
+
+/// The first file descriptor passed with socket activation.
+pub const LISTEN_FDS_START: RawFd = 3;
+
+/// A socket passed to the process.
+#[derive(Debug)]
+pub struct InheritedListener {
+    fd: OwnedFd,
+    name: Option<String>,
+    family: Option<AddressFamily>,
+    listening: bool,
+}
+
+impl InheritedListener {
+    pub(crate) fn new(fd: OwnedFd, name: Option<String>) -> Result<InheritedListener> {
+        // Not to leak into the processes spawned by the service.
+        fcntl(fd.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+        let family = getsockname::<SockaddrStorage>(fd.as_raw_fd())
+            .ok()
+            .and_then(|addr| addr.family());
+        let listening = family.is_some()
+            && getsockopt(fd.as_raw_fd(), sockopt::SockType)? == SockType::Stream
+            && getsockopt(fd.as_raw_fd(), sockopt::AcceptConn)?;
+        Ok(InheritedListener {
+            fd,
+            name,
+            family,
+            listening,
+        })
+    }
+
+    /// The name of the socket from `LISTEN_FDNAMES`, e.g. `FileDescriptorName=` of its unit.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The address family of the socket, `None` if it's not a socket.
+    pub fn family(&self) -> Option<AddressFamily> {
+        self.family
+    }
+
+    /// Check that a ttrpc server can accept connections from it, and take its descriptor.
+    pub(crate) fn into_listener(self) -> Result<(OwnedFd, AddressFamily)> {
+        match self.family {
+            Some(family) if self.listening && is_supported(family) => Ok((self.fd, family)),
+            family => Err(Error::Others(format!(
+                "inherited fd {} ({:?}) is not a listening stream socket of a supported family: {:?}",
+                self.fd.as_raw_fd(),
+                self.name,
+                family
+            ))),
+        }
+    }
+}
+
+impl AsFd for InheritedListener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for InheritedListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl From<InheritedListener> for OwnedFd {
+    fn from(listener: InheritedListener) -> OwnedFd {
+        listener.fd
+    }
+}
+
+impl IntoRawFd for InheritedListener {
+    fn into_raw_fd(self) -> RawFd {
+        self.fd.into_raw_fd()
+    }
+}
+
+fn is_supported(family: AddressFamily) -> bool {
+    match family {
+        AddressFamily::Unix => true,
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        AddressFamily::Vsock => true,
+        AddressFamily::Inet | AddressFamily::Inet6 => cfg!(feature = "tcp"),
+        _ => false,
+    }
+}
+
+/// Take the sockets passed to the process with socket activation, empty if there are none.
+///
+/// The environment variables are removed, so the sockets are taken once and the processes
+/// spawned by the service don't take them for theirs.
+pub fn listen_fds() -> Result<Vec<InheritedListener>> {
+    let fds = listen_fds_env()?;
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        env::remove_var(name);
+    }
+
+    fds.into_iter()
+        .map(|(fd, name)| {
+            // Safety: the descriptors are passed to this process for it to own them.
+            InheritedListener::new(unsafe { OwnedFd::from_raw_fd(fd) }, name)
+        })
+        .collect()
+}
+
+// Get the descriptors passed to this process and their names, without taking them.
+pub(crate) fn listen_fds_env() -> Result<Vec<(RawFd, Option<String>)>> {
+    parse_env(
+        env::var("LISTEN_PID").ok().as_deref(),
+        env::var("LISTEN_FDS").ok().as_deref(),
+        env::var("LISTEN_FDNAMES").ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+// Get the descriptors passed to the process `pid` and their names.
+fn parse_env(
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    listen_fdnames: Option<&str>,
+    pid: u32,
+) -> Result<Vec<(RawFd, Option<String>)>> {
+    // They are meant for another process, e.g. the parent of this one.
+    if listen_pid.and_then(|p| p.parse::<u32>().ok()) != Some(pid) {
+        return Ok(Vec::new());
+    }
+    let count: RawFd = match listen_fds {
+        Some(count) => count
+            .parse()
+            .map_err(|e| Error::Others(format!("LISTEN_FDS {count:?} is invalid: {e}")))?,
+        None => return Ok(Vec::new()),
+    };
+    if !(0..=RawFd::MAX - LISTEN_FDS_START).contains(&count) {
+        return Err(Error::Others(format!("LISTEN_FDS {count} is invalid")));
+    }
+
+    let mut names = listen_fdnames.map(|names| names.split(':'));
+    Ok((LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            let name = names.as_mut().and_then(|names| names.next());
+            (fd, name.filter(|n| !n.is_empty()).map(str::to_string))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::{UnixDatagram, UnixListener};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_env() {
+        assert_eq!(
+            parse_env(Some("42"), Some("2"), Some("ttrpc:"), 42).unwrap(),
+            [(3, Some("ttrpc".to_string())), (4, None)]
+        );
+        assert_eq!(
+            parse_env(Some("42"), Some("1"), None, 42).unwrap(),
+            [(3, None)]
+        );
+        // Meant for another process.
+        assert!(parse_env(Some("41"), Some("1"), None, 42)
+            .unwrap()
+            .is_empty());
+        assert!(parse_env(None, Some("1"), None, 42).unwrap().is_empty());
+        assert!(parse_env(Some("42"), Some("-1"), None, 42).is_err());
+        assert!(parse_env(Some("42"), Some("x"), None, 42).is_err());
+    }
+
+    #[test]
+    fn test_check_listener() {
+        let dir = std::env::temp_dir().join(format!("ttrpc-activation-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let listener = UnixListener::bind(dir.join("stream")).unwrap();
+        let listener = InheritedListener::new(listener.into(), Some("ttrpc".to_string())).unwrap();
+        assert_eq!(listener.name(), Some("ttrpc"));
+        assert_eq!(listener.family(), Some(AddressFamily::Unix));
+        assert!(listener.into_listener().is_ok());
+
+        let socket = UnixDatagram::bind(dir.join("dgram")).unwrap();
+        let socket = InheritedListener::new(socket.into(), None).unwrap();
+        assert!(socket.into_listener().is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
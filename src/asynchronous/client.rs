@@ -6,41 +6,187 @@
 
 use std::collections::HashMap;
 use std::convert::TryInto;
+use std::future::Future;
 #[cfg(unix)]
-use std::os::unix::io::RawFd;
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::{Arc, Mutex};
+use std::os::unix::io::{OwnedFd, RawFd};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::Duration;
 
 use async_trait::async_trait;
-use tokio::{self, sync::mpsc, task};
+use futures::future::FutureExt as _;
+use tokio::{
+    self, select,
+    sync::{mpsc, watch},
+    task,
+    time::{sleep, timeout},
+};
 
-use crate::error::{get_rpc_status, Error, Result};
+use crate::error::{get_rpc_status, Error, Result, Source};
+#[cfg(unix)]
+use crate::fd::FdTable;
 use crate::proto::{
     Code, Codec, GenMessage, Message, MessageHeader, Request, Response, FLAG_NO_DATA,
-    FLAG_REMOTE_CLOSED, FLAG_REMOTE_OPEN, MESSAGE_TYPE_DATA, MESSAGE_TYPE_RESPONSE,
+    FLAG_REMOTE_CLOSED, FLAG_REMOTE_OPEN, MESSAGE_LENGTH_MAX, MESSAGE_TYPE_DATA,
+    MESSAGE_TYPE_RESPONSE,
 };
 use crate::r#async::connection::*;
 use crate::r#async::shutdown;
 use crate::r#async::stream::{
-    Kind, MessageReceiver, MessageSender, ResultReceiver, ResultSender, StreamInner,
+    Kind, MessageReceiver, MessageSender, ResultReceiver, ResultSender, StreamGuard, StreamInner,
 };
+use crate::r#async::{ClientInterceptor, ClientNext};
 
 use super::stream::SendingMessage;
 use super::transport::Socket;
 
+/// The state of the connection of a [`Client`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Connecting to the server.
+    Connecting,
+    /// Connected, calls are sent.
+    Ready,
+    /// The last connection failed, waiting for the backoff to connect again.
+    TransientFailure,
+    /// The connection is closed for good.
+    Shutdown,
+}
+
+/// The exponential backoff between the connection attempts of a reconnecting [`Client`].
+///
+/// A `multiplier` which is negative, NaN or makes the delay overflow gives `max`.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// The delay after the first failure, bounded by `max` too.
+    pub initial: Duration,
+    /// The upper bound of the delay.
+    pub max: Duration,
+    /// The factor the delay grows by after each failed attempt.
+    pub multiplier: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl Backoff {
+    // The delay after the first failure.
+    fn first_delay(&self) -> Duration {
+        self.initial.min(self.max)
+    }
+
+    // The delay following `delay`.
+    fn next_delay(&self, delay: Duration) -> Duration {
+        Duration::try_from_secs_f64(delay.as_secs_f64() * self.multiplier)
+            .map_or(self.max, |delay| delay.min(self.max))
+    }
+}
+
+// The sending side of a connection.
+#[derive(Clone)]
+struct Link {
+    req_tx: MessageSender,
+    streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
+    #[cfg(unix)]
+    fds: Arc<FdTable>,
+}
+
+impl Link {
+    // Start a connection over `socket`, the returned future runs it until it's closed.
+    fn start(
+        socket: Socket,
+        max_recv_message_size: Arc<AtomicUsize>,
+    ) -> (Link, impl Future<Output = ()>) {
+        let (req_tx, rx): (MessageSender, MessageReceiver) = mpsc::channel(100);
+        let streams = Arc::new(Mutex::new(HashMap::new()));
+        #[cfg(unix)]
+        let fds = socket.fds();
+        let delegate = ClientBuilder {
+            rx: Some(rx),
+            streams: streams.clone(),
+            max_recv_message_size,
+            #[cfg(unix)]
+            fds: fds.clone(),
+        };
+
+        let conn = Connection::new(socket, delegate);
+        let run = async move {
+            conn.run()
+                .await
+                .map_err(|e| trace!("connection run error. {}", e))
+                .ok();
+        };
+        let link = Link {
+            req_tx,
+            streams,
+            #[cfg(unix)]
+            fds,
+        };
+        (link, run)
+    }
+
+    // A link whose calls fail as the connection is closed.
+    fn closed() -> Link {
+        Link {
+            req_tx: mpsc::channel(1).0,
+            streams: Arc::new(Mutex::new(HashMap::new())),
+            #[cfg(unix)]
+            fds: Arc::default(),
+        }
+    }
+}
+
 /// A ttrpc Client (async).
+///
+/// Clones share the connection, with its message size limits. The interceptors, waiting for
+/// ready and cancelling calls are options of each clone instead, which it passes on to the
+/// clones made from it.
 #[derive(Clone)]
 pub struct Client {
-    req_tx: MessageSender,
+    link: Arc<RwLock<Link>>,
+    state: watch::Receiver<ConnectionState>,
+    wait_for_ready: bool,
+    #[cfg(feature = "cancel")]
+    cancel_calls: bool,
     next_stream_id: Arc<AtomicU32>,
-    streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
+    max_send_message_size: Arc<AtomicUsize>,
+    max_recv_message_size: Arc<AtomicUsize>,
+    interceptors: Arc<Vec<Arc<dyn ClientInterceptor + Send + Sync>>>,
 }
 
 impl Client {
     pub async fn connect(sockaddr: &str) -> Result<Client> {
         let socket = Socket::connect(sockaddr)
             .await
-            .map_err(err_to_others_err!(e, "Socket::connect error "))?;
+            .map_err(err_to_io_err!(e, "Socket::connect error "))?;
+        Ok(Self::new(socket))
+    }
+
+    /// Connect to `sockaddr` of the form `tls://host:port`, the connection is secured with
+    /// `config` and the certificate of the server must be valid for `host`.
+    #[cfg(feature = "tls")]
+    pub async fn connect_tls(
+        sockaddr: &str,
+        config: Arc<super::transport::tls::rustls::ClientConfig>,
+    ) -> Result<Client> {
+        let addr = sockaddr
+            .strip_prefix("tls://")
+            .ok_or_else(|| Error::Others(format!("Scheme of {sockaddr:?} is not tls")))?;
+        let host = addr
+            .rsplit_once(':')
+            .map_or(addr, |(host, _)| host)
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let socket = Socket::connect_tls(addr, config, host)
+            .await
+            .map_err(err_to_io_err!(e, "Socket::connect_tls error "))?;
         Ok(Self::new(socket))
     }
 
@@ -54,42 +200,228 @@ impl Client {
 
     /// Initialize a new [`Client`].
     pub fn new(stream: Socket) -> Client {
-        let (req_tx, rx): (MessageSender, MessageReceiver) = mpsc::channel(100);
-
-        let req_map = Arc::new(Mutex::new(HashMap::new()));
-        let delegate = ClientBuilder {
-            rx: Some(rx),
-            streams: req_map.clone(),
-        };
+        let max_recv_message_size = Arc::new(AtomicUsize::new(MESSAGE_LENGTH_MAX));
+        let (link, conn) = Link::start(stream, max_recv_message_size.clone());
+        let (state_tx, state) = watch::channel(ConnectionState::Ready);
 
-        let conn = Connection::new(stream, delegate);
         // Long-running receiver task
-        tokio::spawn(async move { conn.run().await });
+        tokio::spawn(async move {
+            conn.await;
+            state_tx.send_replace(ConnectionState::Shutdown);
+        });
+
+        Self::with_link(Arc::new(RwLock::new(link)), state, max_recv_message_size)
+    }
+
+    /// Initialize a [`Client`] connecting to `sockaddr` again whenever its connection is lost.
+    ///
+    /// The client connects in the background, failed attempts are retried after `backoff`.
+    /// Calls in flight when the connection is lost fail with its error and are not retried,
+    /// the server may have handled them. Calls made while the client isn't
+    /// [`Ready`](ConnectionState::Ready) fail as well unless
+    /// [`set_wait_for_ready`](Client::set_wait_for_ready) is enabled.
+    pub fn reconnecting(sockaddr: &str, backoff: Backoff) -> Client {
+        let max_recv_message_size = Arc::new(AtomicUsize::new(MESSAGE_LENGTH_MAX));
+        let link = Arc::new(RwLock::new(Link::closed()));
+        let (state_tx, state) = watch::channel(ConnectionState::Connecting);
 
+        tokio::spawn(reconnect(
+            sockaddr.to_string(),
+            backoff,
+            Arc::downgrade(&link),
+            max_recv_message_size.clone(),
+            state_tx,
+        ));
+
+        Self::with_link(link, state, max_recv_message_size)
+    }
+
+    fn with_link(
+        link: Arc<RwLock<Link>>,
+        state: watch::Receiver<ConnectionState>,
+        max_recv_message_size: Arc<AtomicUsize>,
+    ) -> Client {
         Client {
-            req_tx,
+            link,
+            state,
+            wait_for_ready: false,
+            #[cfg(feature = "cancel")]
+            cancel_calls: false,
             next_stream_id: Arc::new(AtomicU32::new(1)),
-            streams: req_map,
+            max_send_message_size: Arc::new(AtomicUsize::new(MESSAGE_LENGTH_MAX)),
+            max_recv_message_size,
+            interceptors: Arc::new(Vec::new()),
         }
     }
 
+    /// The current state of the connection.
+    pub fn state(&self) -> ConnectionState {
+        *self.state.borrow()
+    }
+
+    /// Watch the state of the connection.
+    pub fn watch_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.clone()
+    }
+
+    /// Let calls wait for the connection to be ready instead of failing.
+    ///
+    /// The wait is bounded by the timeout of the call. Unlike the message size limits, it
+    /// applies to this [`Client`] and the clones made from it afterwards only, so it can be
+    /// chosen per generated client, e.g. `HealthClient::new(c.clone().set_wait_for_ready(true))`.
+    pub fn set_wait_for_ready(mut self, wait_for_ready: bool) -> Client {
+        self.wait_for_ready = wait_for_ready;
+        self
+    }
+
+    /// Tell the server when a call is given up, so that it stops handling it.
+    ///
+    /// A call is given up when it times out or its future is dropped, and a stream when its
+    /// receiver is dropped before the server has closed it. The cancellation is an extension
+    /// of the protocol flagged [`FLAG_CANCEL`](crate::proto::FLAG_CANCEL): only enable it
+    /// against async servers of this crate built with the `cancel` feature. Other servers, like
+    /// the sync one or the Go implementation, answer it with an `INVALID_ARGUMENT` status,
+    /// which the client drops. Like [`set_wait_for_ready`](Client::set_wait_for_ready), it applies to this
+    /// [`Client`] and the clones made from it afterwards.
+    #[cfg(feature = "cancel")]
+    pub fn set_cancel_calls(mut self, cancel_calls: bool) -> Client {
+        self.cancel_calls = cancel_calls;
+        self
+    }
+
+    // Get the link to send a call on, after waiting for it to be ready if asked to.
+    async fn link(&self, timeout_nano: i64) -> Result<Link> {
+        if self.wait_for_ready {
+            let mut state = self.state.clone();
+            let ready =
+                state.wait_for(|s| matches!(s, ConnectionState::Ready | ConnectionState::Shutdown));
+            if timeout_nano == 0 {
+                ready.await.ok();
+            } else {
+                timeout(Duration::from_nanos(timeout_nano as u64), ready)
+                    .await
+                    .map_err(|e| Error::Timeout(format!("Wait for ready timeout {e:?}")))?
+                    .ok();
+            }
+        }
+        match self.state() {
+            ConnectionState::Ready => Ok(self.link.read().unwrap().clone()),
+            state => Err(Error::Closed(format!("Connection is not ready: {state:?}"))),
+        }
+    }
+
+    /// Set the maximum length of a message sent on the connection.
+    ///
+    /// The limit is the connection's: it applies to every clone of this [`Client`], those
+    /// made before included.
+    pub fn set_connection_max_send_message_size(&self, size: usize) {
+        self.max_send_message_size.store(size, Ordering::Relaxed);
+    }
+
+    /// Set the maximum length of a message received on the connection.
+    ///
+    /// The limit is the connection's: it applies to every clone of this [`Client`], those
+    /// made before included.
+    pub fn set_connection_max_recv_message_size(&self, size: usize) {
+        self.max_recv_message_size.store(size, Ordering::Relaxed);
+    }
+
+    /// Add an interceptor wrapping the requests and streams of the client.
+    ///
+    /// Interceptors run in the order they are added, the first one is the outermost. The
+    /// interceptor applies to this [`Client`] and the clones made from it afterwards, e.g. by
+    /// generated clients, not to the clones made before.
+    pub fn add_interceptor(
+        mut self,
+        interceptor: Arc<dyn ClientInterceptor + Send + Sync>,
+    ) -> Client {
+        Arc::make_mut(&mut self.interceptors).push(interceptor);
+        self
+    }
+
+    fn max_send_message_size(&self) -> usize {
+        self.max_send_message_size.load(Ordering::Relaxed)
+    }
+
     /// Requsts a unary request and returns with response.
     pub async fn request(&self, req: Request) -> Result<Response> {
+        #[cfg(unix)]
+        let mut fds = Vec::new();
+        ClientNext::new(
+            &self.interceptors,
+            Box::new(|req| {
+                self.do_request(
+                    req,
+                    #[cfg(unix)]
+                    &mut fds,
+                )
+                .boxed()
+            }),
+        )
+        .run(req)
+        .await
+    }
+
+    /// Send `req` along with file descriptors, and return the response along with the file
+    /// descriptors sent back by the server.
+    ///
+    /// File descriptors can only be passed over unix sockets.
+    #[cfg(unix)]
+    pub async fn request_with_fds(
+        &self,
+        req: Request,
+        mut fds: Vec<OwnedFd>,
+    ) -> Result<(Response, Vec<OwnedFd>)> {
+        let res = ClientNext::new(
+            &self.interceptors,
+            Box::new(|req| self.do_request(req, &mut fds).boxed()),
+        )
+        .run(req)
+        .await?;
+        Ok((res, fds))
+    }
+
+    // On unix, `fds` are sent with the request and replaced by the ones received with the
+    // response.
+    async fn do_request(
+        &self,
+        req: Request,
+        #[cfg(unix)] fds: &mut Vec<OwnedFd>,
+    ) -> Result<Response> {
         let timeout_nano = req.timeout_nano;
+        let link = self.link(timeout_nano).await?;
         let stream_id = self.next_stream_id.fetch_add(2, Ordering::Relaxed);
 
-        let msg: GenMessage = Message::new_request(stream_id, req)?
-            .try_into()
-            .map_err(|e: protobuf::Error| Error::Others(e.to_string()))?;
+        let msg: GenMessage =
+            Message::new_request_with_limit(stream_id, req, self.max_send_message_size())?
+                .try_into()
+                .map_err(|e: protobuf::Error| Error::Codec {
+                    msg: e.to_string(),
+                    source: Some(Source::new(e)),
+                })?;
 
         let (tx, mut rx): (ResultSender, ResultReceiver) = mpsc::channel(100);
 
-        self.streams
+        link.streams
             .lock()
-            .map_err(|_| Error::Others("Failed to acquire lock on streams".to_string()))?
+            .map_err(|_| Error::Poisoned("Failed to acquire lock on streams".to_string()))?
             .insert(stream_id, tx);
+        // The call is forgotten, and cancelled if asked to, if it times out or its future is
+        // dropped.
+        let _guard = StreamGuard::new(
+            stream_id,
+            Kind::Client,
+            link.req_tx.clone(),
+            link.streams.clone(),
+        );
+        #[cfg(feature = "cancel")]
+        let _guard = _guard.with_cancel(self.cancel_calls);
+        #[cfg(unix)]
+        let _guard = _guard.with_fds(link.fds.clone());
+        #[cfg(unix)]
+        link.fds.attach(stream_id, std::mem::take(fds))?;
 
-        self.req_tx
+        link.req_tx
             .send(SendingMessage::new(msg))
             .await
             .map_err(|_| Error::LocalClosed)?;
@@ -102,14 +434,18 @@ impl Client {
                 rx.recv(),
             )
             .await
-            .map_err(|e| Error::Others(format!("Receive packet timeout {e:?}")))?
+            .map_err(|e| Error::Timeout(format!("Receive packet timeout {e:?}")))?
             .ok_or_else(|| Error::RemoteClosed)?
         };
 
+        #[cfg(unix)]
+        {
+            *fds = link.fds.take_received(stream_id);
+        }
         let msg = result?;
 
         let res = Response::decode(msg.payload)
-            .map_err(err_to_others_err!(e, "Unpack response error "))?;
+            .map_err(err_to_codec_err!(e, "Unpack response error "))?;
 
         let status = res.status();
         if status.code() != Code::OK {
@@ -126,12 +462,34 @@ impl Client {
         streaming_client: bool,
         streaming_server: bool,
     ) -> Result<StreamInner> {
+        ClientNext::new(
+            &self.interceptors,
+            Box::new(move |req| {
+                self.do_new_stream(req, streaming_client, streaming_server)
+                    .boxed()
+            }),
+        )
+        .run(req)
+        .await
+    }
+
+    async fn do_new_stream(
+        &self,
+        req: Request,
+        streaming_client: bool,
+        streaming_server: bool,
+    ) -> Result<StreamInner> {
+        let link = self.link(req.timeout_nano).await?;
         let stream_id = self.next_stream_id.fetch_add(2, Ordering::Relaxed);
         let is_req_payload_empty = req.payload.is_empty();
 
-        let mut msg: GenMessage = Message::new_request(stream_id, req)?
-            .try_into()
-            .map_err(|e: protobuf::Error| Error::Others(e.to_string()))?;
+        let mut msg: GenMessage =
+            Message::new_request_with_limit(stream_id, req, self.max_send_message_size())?
+                .try_into()
+                .map_err(|e: protobuf::Error| Error::Codec {
+                    msg: e.to_string(),
+                    source: Some(Source::new(e)),
+                })?;
 
         if streaming_client {
             if !is_req_payload_empty {
@@ -146,32 +504,88 @@ impl Client {
         }
 
         let (tx, rx): (ResultSender, ResultReceiver) = mpsc::channel(100);
-        self.streams
+        link.streams
             .lock()
-            .map_err(|_| Error::Others("Failed to acquire lock on streams".to_string()))?
+            .map_err(|_| Error::Poisoned("Failed to acquire lock on streams".to_string()))?
             .insert(stream_id, tx);
-
-        self.req_tx
-            .send(SendingMessage::new(msg))
-            .await
-            .map_err(|e| Error::Others(format!("Send packet to sender error {e:?}")))?;
-
-        Ok(StreamInner::new(
+        // Built first to remove the stream if the request can't be sent.
+        let inner = StreamInner::new(
             stream_id,
-            self.req_tx.clone(),
+            link.req_tx.clone(),
             rx,
             streaming_client,
             streaming_server,
             Kind::Client,
-            self.streams.clone(),
-        ))
+            link.streams,
+        )
+        .with_max_send_message_size(self.max_send_message_size());
+        #[cfg(feature = "cancel")]
+        let inner = inner.with_cancel(self.cancel_calls);
+        #[cfg(unix)]
+        let inner = inner.with_fds(link.fds.clone());
+
+        link.req_tx
+            .send(SendingMessage::new(msg))
+            .await
+            .map_err(|e| Error::Closed(format!("Send packet to sender error {e:?}")))?;
+
+        Ok(inner)
     }
 }
 
+// Keep `link` connected to `sockaddr` as long as a client uses it.
+async fn reconnect(
+    sockaddr: String,
+    backoff: Backoff,
+    link: Weak<RwLock<Link>>,
+    max_recv_message_size: Arc<AtomicUsize>,
+    state_tx: watch::Sender<ConnectionState>,
+) {
+    let mut delay = backoff.first_delay();
+    loop {
+        state_tx.send_replace(ConnectionState::Connecting);
+        let socket = select! {
+            socket = Socket::connect(&sockaddr) => socket,
+            _ = state_tx.closed() => break,
+        };
+        match socket {
+            Ok(socket) => {
+                let Some(link) = link.upgrade() else {
+                    break;
+                };
+                let (new_link, conn) = Link::start(socket, max_recv_message_size.clone());
+                *link.write().unwrap() = new_link;
+                drop(link);
+
+                state_tx.send_replace(ConnectionState::Ready);
+                delay = backoff.first_delay();
+                // The connection also ends once all the clients are dropped.
+                conn.await;
+                debug!("connection to {} is lost", sockaddr);
+            }
+            Err(e) => debug!("connect to {} got error {:?}", sockaddr, e),
+        }
+
+        if link.strong_count() == 0 {
+            break;
+        }
+        state_tx.send_replace(ConnectionState::TransientFailure);
+        select! {
+            _ = sleep(delay) => {}
+            _ = state_tx.closed() => break,
+        }
+        delay = backoff.next_delay(delay);
+    }
+    state_tx.send_replace(ConnectionState::Shutdown);
+}
+
 #[derive(Debug)]
 struct ClientBuilder {
     rx: Option<MessageReceiver>,
     streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
+    max_recv_message_size: Arc<AtomicUsize>,
+    #[cfg(unix)]
+    fds: Arc<FdTable>,
 }
 
 impl Builder for ClientBuilder {
@@ -184,6 +598,9 @@ impl Builder for ClientBuilder {
             ClientReader {
                 shutdown_waiter: waiter,
                 streams: self.streams.clone(),
+                max_recv_message_size: self.max_recv_message_size.clone(),
+                #[cfg(unix)]
+                fds: self.fds.clone(),
             },
             ClientWriter {
                 rx: self.rx.take().unwrap(),
@@ -286,6 +703,9 @@ async fn get_resp_tx(
 struct ClientReader {
     streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
     shutdown_waiter: shutdown::Waiter,
+    max_recv_message_size: Arc<AtomicUsize>,
+    #[cfg(unix)]
+    fds: Arc<FdTable>,
 }
 
 #[async_trait]
@@ -312,27 +732,440 @@ impl ReaderDelegate for ClientReader {
 
     async fn exit(&self) {}
 
+    // The messages are passed on before the next one is read, so that those
+    // of a stream are kept in order.
     async fn handle_err(&self, header: MessageHeader, e: Error) {
-        let req_map = self.streams.clone();
-        tokio::spawn(async move {
-            if let Some(resp_tx) = get_resp_tx(req_map, &header).await {
-                resp_tx
-                    .send(Err(e))
-                    .await
-                    .unwrap_or_else(|_e| error!("The request has returned"));
-            }
-        });
+        if let Some(resp_tx) = get_resp_tx(self.streams.clone(), &header).await {
+            resp_tx
+                .send(Err(e))
+                .await
+                .unwrap_or_else(|_e| error!("The request has returned"));
+        }
     }
 
     async fn handle_msg(&self, msg: GenMessage) {
-        let req_map = self.streams.clone();
-        tokio::spawn(async move {
-            if let Some(resp_tx) = get_resp_tx(req_map, &msg.header).await {
-                resp_tx
-                    .send(Ok(msg))
-                    .await
-                    .unwrap_or_else(|_e| error!("The request has returned"));
-            }
+        if let Some(resp_tx) = get_resp_tx(self.streams.clone(), &msg.header).await {
+            resp_tx
+                .send(Ok(msg))
+                .await
+                .unwrap_or_else(|_e| error!("The request has returned"));
+        } else {
+            // The call is over, e.g. it timed out, nobody takes the descriptors sent with it.
+            #[cfg(unix)]
+            self.fds.forget(msg.header.stream_id);
+        }
+    }
+
+    fn max_recv_message_size(&self) -> usize {
+        self.max_recv_message_size.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::r#async::{MethodHandler, Server, Service, TtrpcContext};
+
+    // Tells when the handler of a call is dropped.
+    #[cfg(feature = "cancel")]
+    struct Dropped(mpsc::Sender<()>);
+
+    #[cfg(feature = "cancel")]
+    impl Drop for Dropped {
+        fn drop(&mut self) {
+            self.0.try_send(()).ok();
+        }
+    }
+
+    #[cfg(feature = "cancel")]
+    struct Hang(mpsc::Sender<()>);
+
+    #[cfg(feature = "cancel")]
+    #[async_trait]
+    impl MethodHandler for Hang {
+        async fn handler(&self, _ctx: TtrpcContext, _req: Request) -> Result<Response> {
+            let _dropped = Dropped(self.0.clone());
+            std::future::pending().await
+        }
+    }
+
+    #[cfg(feature = "cancel")]
+    #[async_trait]
+    impl crate::r#async::StreamHandler for Hang {
+        async fn handler(
+            &self,
+            _ctx: TtrpcContext,
+            inner: StreamInner,
+        ) -> Result<Option<Response>> {
+            let _dropped = Dropped(self.0.clone());
+            inner.send(b"first".to_vec()).await?;
+            std::future::pending().await
+        }
+    }
+
+    // Answers with a file descriptor once the calls are given up.
+    struct LateFd;
+
+    #[async_trait]
+    impl MethodHandler for LateFd {
+        async fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<Response> {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            let (rx, _tx) = nix::unistd::pipe().unwrap();
+            // Safety: the descriptor was just created by pipe.
+            let rx = unsafe { <OwnedFd as std::os::fd::FromRawFd>::from_raw_fd(rx) };
+            ctx.fds.send(vec![rx])?;
+            Ok(Response::new())
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+            multiplier: 3.0,
+        };
+        assert_eq!(backoff.first_delay(), backoff.initial);
+        let delay = backoff.next_delay(backoff.initial);
+        assert_eq!(delay, Duration::from_millis(300));
+        assert_eq!(backoff.next_delay(delay * 2), backoff.max);
+
+        for multiplier in [-1.0, f64::NAN, f64::INFINITY, f64::MAX] {
+            let backoff = Backoff {
+                multiplier,
+                ..backoff.clone()
+            };
+            assert_eq!(backoff.next_delay(backoff.initial), backoff.max);
+        }
+
+        let backoff = Backoff {
+            initial: Duration::from_secs(5),
+            ..backoff
+        };
+        assert_eq!(backoff.first_delay(), backoff.max);
+    }
+
+    // Answers at once.
+    struct Pong;
+
+    #[async_trait]
+    impl MethodHandler for Pong {
+        async fn handler(&self, _ctx: TtrpcContext, _req: Request) -> Result<Response> {
+            Ok(Response::new())
+        }
+    }
+
+    async fn start_pong(addr: &str) -> Server {
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("Pong".to_string(), Box::new(Pong));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test.Pong".to_string(), service)]));
+        server.start().await.unwrap();
+        server
+    }
+
+    #[tokio::test]
+    async fn test_reconnecting() {
+        let addr = r"unix://@/tmp/ttrpc-client-unit-test-reconnect";
+        let req = Request {
+            service: "test.Pong".to_string(),
+            method: "Pong".to_string(),
+            timeout_nano: Duration::from_secs(5).as_nanos() as i64,
+            ..Default::default()
+        };
+        let backoff = Backoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let client = Client::reconnecting(addr, backoff);
+        let mut state = client.watch_state();
+
+        // Calls fail fast until the server is up, unless they wait for ready.
+        let err = client.request(req.clone()).await.unwrap_err();
+        assert!(matches!(err, Error::Closed(_)), "{:?}", err);
+        assert_eq!(err.code(), Code::UNAVAILABLE);
+        let mut short = req.clone();
+        short.timeout_nano = Duration::from_millis(10).as_nanos() as i64;
+        let err = client
+            .clone()
+            .set_wait_for_ready(true)
+            .request(short)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Timeout(_)), "{:?}", err);
+        let waiting = tokio::spawn({
+            let client = client.clone().set_wait_for_ready(true);
+            let req = req.clone();
+            async move { client.request(req).await }
         });
+        sleep(Duration::from_millis(100)).await;
+
+        let mut server = start_pong(addr).await;
+        waiting.await.unwrap().unwrap();
+        assert_eq!(client.state(), ConnectionState::Ready);
+
+        // The connection is lost and made again.
+        server.shutdown().await.unwrap();
+        drop(server);
+        state
+            .wait_for(|s| *s != ConnectionState::Ready)
+            .await
+            .unwrap();
+
+        let mut server = start_pong(addr).await;
+        state
+            .wait_for(|s| *s == ConnectionState::Ready)
+            .await
+            .unwrap();
+        client.request(req).await.unwrap();
+
+        server.shutdown().await.unwrap();
+    }
+
+    fn active_streams(client: &Client) -> usize {
+        client.link.read().unwrap().streams.lock().unwrap().len()
+    }
+
+    #[cfg(feature = "cancel")]
+    #[tokio::test]
+    async fn test_cancel() {
+        let addr = r"unix://@/tmp/ttrpc-client-unit-test-cancel";
+        let (tx, mut rx) = mpsc::channel(1);
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("Hang".to_string(), Box::new(Hang(tx.clone())));
+        let mut streams: HashMap<String, Arc<dyn crate::r#async::StreamHandler + Send + Sync>> =
+            HashMap::new();
+        streams.insert("Watch".to_string(), Arc::new(Hang(tx)));
+        let service = Service { methods, streams };
+
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test.Cancel".to_string(), service)]));
+        server.start().await.unwrap();
+        let client = Client::connect(addr).await.unwrap().set_cancel_calls(true);
+
+        let mut req = Request {
+            service: "test.Cancel".to_string(),
+            method: "Hang".to_string(),
+            ..Default::default()
+        };
+
+        // The call times out.
+        req.timeout_nano = Duration::from_millis(100).as_nanos() as i64;
+        match client.request(req.clone()).await {
+            Err(Error::Timeout(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert_eq!(active_streams(&client), 0);
+        timeout(Duration::from_secs(3), rx.recv()).await.unwrap();
+
+        // The call is dropped, the server stops handling it while the connection is kept.
+        req.timeout_nano = 0;
+        assert!(
+            timeout(Duration::from_millis(100), client.request(req.clone()))
+                .await
+                .is_err()
+        );
+        assert_eq!(active_streams(&client), 0);
+        timeout(Duration::from_secs(3), rx.recv()).await.unwrap();
+
+        // The receiver of a stream is dropped before the server closes it.
+        req.method = "Watch".to_string();
+        let mut stream = client.new_stream(req, false, true).await.unwrap();
+        assert_eq!(stream.recv().await.unwrap(), b"first");
+        assert_eq!(active_streams(&client), 1);
+        drop(stream);
+        assert_eq!(active_streams(&client), 0);
+        timeout(Duration::from_secs(3), rx.recv()).await.unwrap();
+
+        assert_eq!(client.state(), ConnectionState::Ready);
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_forget_fds() {
+        let addr = r"unix://@/tmp/ttrpc-client-unit-test-forget-fds";
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("LateFd".to_string(), Box::new(LateFd));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test.Fds".to_string(), service)]));
+        server.start().await.unwrap();
+        let client = Client::connect(addr).await.unwrap();
+
+        let req = Request {
+            service: "test.Fds".to_string(),
+            method: "LateFd".to_string(),
+            ..Default::default()
+        };
+        // The server would give up on a call with a timeout, drop it instead.
+        assert!(timeout(
+            Duration::from_millis(50),
+            client.request_with_fds(req, Vec::new())
+        )
+        .await
+        .is_err());
+
+        // The descriptor of the late response is closed.
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(active_streams(&client), 0);
+        assert!(client.link.read().unwrap().fds.is_empty());
+
+        server.shutdown().await.unwrap();
+    }
+
+    // Answers with the token of the call, if any.
+    struct TokenEcho;
+
+    #[async_trait]
+    impl MethodHandler for TokenEcho {
+        async fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<Response> {
+            let mut res = Response::new();
+            res.payload = ctx.metadata.get("token").unwrap_or_default().into();
+            Ok(res)
+        }
+    }
+
+    struct Token;
+
+    #[async_trait]
+    impl ClientInterceptor for Token {
+        async fn intercept(
+            &self,
+            mut req: Request,
+            next: ClientNext<'_, Response>,
+        ) -> Result<Response> {
+            req.metadata.push(crate::proto::KeyValue {
+                key: "token".to_string(),
+                value: "secret".to_string(),
+                ..Default::default()
+            });
+            next.run(req).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interceptor() {
+        let addr = r"unix://@/tmp/ttrpc-client-unit-test-interceptor";
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("TokenEcho".to_string(), Box::new(TokenEcho));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test.Token".to_string(), service)]));
+        server.start().await.unwrap();
+
+        // The clones made afterwards have the interceptor, those made before don't.
+        let client = Client::connect(addr).await.unwrap();
+        let earlier = client.clone();
+        let client = client.add_interceptor(Arc::new(Token));
+        let req = Request {
+            service: "test.Token".to_string(),
+            method: "TokenEcho".to_string(),
+            ..Default::default()
+        };
+        let res = client.clone().request(req.clone()).await.unwrap();
+        assert_eq!(res.payload, b"secret");
+        let res = earlier.request(req).await.unwrap();
+        assert!(res.payload.is_empty());
+
+        server.shutdown().await.unwrap();
+    }
+
+    // Echo the content of the received pipe with a pipe.
+    struct PipeEcho;
+
+    #[async_trait]
+    impl MethodHandler for PipeEcho {
+        async fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<Response> {
+            let content = ctx
+                .fds
+                .take()
+                .into_iter()
+                .map(read_pipe)
+                .collect::<String>();
+            ctx.fds.send(vec![pipe_with(&content)])?;
+            Ok(Response::new())
+        }
+    }
+
+    fn pipe_with(content: &str) -> OwnedFd {
+        use std::io::Write as _;
+        use std::os::fd::FromRawFd as _;
+
+        let (rx, tx) = nix::unistd::pipe().unwrap();
+        // Safety: the descriptors were just created by pipe.
+        let (rx, tx) = unsafe { (OwnedFd::from_raw_fd(rx), OwnedFd::from_raw_fd(tx)) };
+        std::fs::File::from(tx)
+            .write_all(content.as_bytes())
+            .unwrap();
+        rx
+    }
+
+    fn read_pipe(fd: OwnedFd) -> String {
+        use std::io::Read as _;
+
+        let mut content = String::new();
+        std::fs::File::from(fd)
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[tokio::test]
+    async fn test_pass_fds() {
+        let addr = r"unix://@/tmp/ttrpc-client-unit-test-fds";
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("PipeEcho".to_string(), Box::new(PipeEcho));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test.Fds".to_string(), service)]));
+        server.start().await.unwrap();
+
+        let client = Client::connect(addr).await.unwrap();
+        let req = Request {
+            service: "test.Fds".to_string(),
+            method: "PipeEcho".to_string(),
+            ..Default::default()
+        };
+        let (_, fds) = client
+            .request_with_fds(req.clone(), vec![pipe_with("ping"), pipe_with("pong")])
+            .await
+            .unwrap();
+        let content: String = fds.into_iter().map(read_pipe).collect();
+        assert_eq!(content, "pingpong");
+
+        // The descriptors only go with the call they are attached to.
+        let (_, fds) = client.request_with_fds(req, Vec::new()).await.unwrap();
+        assert_eq!(fds.into_iter().map(read_pipe).collect::<String>(), "");
+
+        server.shutdown().await.unwrap();
     }
 }
@@ -4,12 +4,17 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
+#[cfg(unix)]
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use log::{error, trace};
 use tokio::io::split;
 use tokio::{io::ReadHalf, select, task};
 
 use crate::error::Error;
+#[cfg(unix)]
+use crate::fd::FdTable;
 use crate::proto::{GenMessage, GenMessageError, MessageHeader};
 
 use super::{stream::SendingMessage, transport::Socket};
@@ -35,12 +40,16 @@ pub trait ReaderDelegate {
     async fn exit(&self);
     async fn handle_msg(&self, msg: GenMessage);
     async fn handle_err(&self, header: MessageHeader, e: Error);
+    /// The maximum length of a message which can be received.
+    fn max_recv_message_size(&self) -> usize;
 }
 
 pub struct Connection<B: Builder> {
     reader: ReadHalf<Socket>,
     writer_task: task::JoinHandle<()>,
     reader_delegate: B::Reader,
+    #[cfg(unix)]
+    fds: Arc<FdTable>,
 }
 
 impl<B> Connection<B>
@@ -50,14 +59,20 @@ where
     B::Writer: WriterDelegate + Send + Sync + 'static,
 {
     pub fn new(conn: Socket, mut builder: B) -> Self {
+        #[cfg(unix)]
+        let fds = conn.fds();
         let (reader, mut writer) = split(conn);
 
         let (reader_delegate, mut writer_delegate) = builder.build();
 
         // Long-running sender task
+        #[cfg(unix)]
+        let writer_fds = fds.clone();
         let writer_task = tokio::spawn(async move {
             while let Some(mut sending_msg) = writer_delegate.recv().await {
                 trace!("write message: {:?}", sending_msg.msg);
+                #[cfg(unix)]
+                writer_fds.message_writing(&sending_msg.msg.header);
                 if let Err(e) = sending_msg.msg.write_to(&mut writer).await {
                     error!("write_message got error: {:?}", e);
                     sending_msg.send_result(Err(e.clone()));
@@ -73,6 +88,8 @@ where
             reader,
             writer_task,
             reader_delegate,
+            #[cfg(unix)]
+            fds,
         }
     }
 
@@ -81,17 +98,23 @@ where
             mut reader,
             mut writer_task,
             reader_delegate,
+            #[cfg(unix)]
+            fds,
         } = self;
         loop {
             select! {
-                res = GenMessage::read_from(&mut reader) => {
+                res = GenMessage::read_from_with_limit(&mut reader, reader_delegate.max_recv_message_size()) => {
                     match res {
                         Ok(msg) => {
                             trace!("Got Message {:?}", msg);
+                            #[cfg(unix)]
+                            fds.message_read(&msg.header);
                             reader_delegate.handle_msg(msg).await;
                         }
                         Err(GenMessageError::ReturnError(header, e)) => {
                             trace!("Read msg err (can be return): {:?}", e);
+                            #[cfg(unix)]
+                            fds.message_discarded();
                             reader_delegate.handle_err(header, e).await;
                         }
 
@@ -0,0 +1,660 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+//! A client calling methods by name, the messages described at runtime by
+//! `FileDescriptorProto`s instead of generated code.
+//!
+//! The descriptors come from a serialized `FileDescriptorSet` (`protoc --descriptor_set_out`),
+//! from the pure parser of `ttrpc_codegen` or from the reflection service of the server. The
+//! messages are built from and printed to their JSON mapping or the text format.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use protobuf::descriptor::{FileDescriptorProto, FileDescriptorSet, MethodDescriptorProto};
+use protobuf::reflect::{FileDescriptor, MessageDescriptor, MethodDescriptor, ServiceDescriptor};
+use protobuf::{Message as _, MessageDyn};
+
+use crate::context::Context;
+use crate::error::{get_rpc_status, Error, Result};
+use crate::proto::{Code, Request};
+use crate::r#async::{Client, StreamInner};
+use crate::reflection::r#async::ReflectionClient;
+
+/// A method of the files added to a [`DynamicClient`].
+#[derive(Clone)]
+pub struct Method {
+    service_name: String,
+    service: ServiceDescriptor,
+    index: usize,
+}
+
+impl Method {
+    /// The full name of the service, `package.Service`.
+    pub fn service(&self) -> &str {
+        &self.service_name
+    }
+
+    pub fn name(&self) -> &str {
+        self.proto().name()
+    }
+
+    pub fn proto(&self) -> &MethodDescriptorProto {
+        &self.service.proto().method[self.index]
+    }
+
+    pub fn descriptor(&self) -> MethodDescriptor {
+        self.service.methods().nth(self.index).unwrap()
+    }
+
+    pub fn input_type(&self) -> MessageDescriptor {
+        self.descriptor().input_type()
+    }
+
+    pub fn output_type(&self) -> MessageDescriptor {
+        self.descriptor().output_type()
+    }
+
+    pub fn client_streaming(&self) -> bool {
+        self.proto().client_streaming()
+    }
+
+    pub fn server_streaming(&self) -> bool {
+        self.proto().server_streaming()
+    }
+
+    fn kind(&self) -> &'static str {
+        match (self.client_streaming(), self.server_streaming()) {
+            (false, false) => "unary",
+            (true, false) => "client streaming",
+            (false, true) => "server streaming",
+            (true, true) => "bidirectional streaming",
+        }
+    }
+
+    fn request(&self, ctx: Context, req: Option<&dyn MessageDyn>) -> Result<Request> {
+        let mut creq = Request::new();
+        creq.set_service(self.service_name.clone());
+        creq.set_method(self.name().to_string());
+        creq.set_timeout_nano(ctx.request_timeout_nano()?);
+        creq.set_metadata(ctx.metadata.to_pb());
+        if let Some(req) = req {
+            creq.payload = self.encode(req)?;
+        }
+        Ok(creq)
+    }
+
+    fn encode(&self, req: &dyn MessageDyn) -> Result<Vec<u8>> {
+        let input = self.input_type();
+        if req.descriptor_dyn() != input {
+            return Err(get_rpc_status(
+                Code::INVALID_ARGUMENT,
+                format!(
+                    "{}/{} takes {}, not {}",
+                    self.service(),
+                    self.name(),
+                    input.full_name(),
+                    req.descriptor_dyn().full_name()
+                ),
+            ));
+        }
+        req.write_to_bytes_dyn()
+            .map_err(err_to_codec_err!(e, "Encode message failed."))
+    }
+
+    fn decode(&self, buf: &[u8]) -> Result<Box<dyn MessageDyn>> {
+        self.output_type()
+            .parse_from_bytes(buf)
+            .map_err(err_to_codec_err!(e, "Decode message failed."))
+    }
+
+    fn check_kind(&self, client_streaming: bool, server_streaming: bool) -> Result<()> {
+        if (self.client_streaming(), self.server_streaming())
+            == (client_streaming, server_streaming)
+        {
+            return Ok(());
+        }
+        Err(get_rpc_status(
+            Code::INVALID_ARGUMENT,
+            format!(
+                "{}/{} is a {} method",
+                self.service(),
+                self.name(),
+                self.kind()
+            ),
+        ))
+    }
+}
+
+/// A client calling the methods described by the added files, see the [module](self) doc.
+#[derive(Clone)]
+pub struct DynamicClient {
+    client: Client,
+    files: HashMap<String, FileDescriptor>,
+}
+
+impl DynamicClient {
+    pub fn new(client: Client) -> Self {
+        DynamicClient {
+            client,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Add `FileDescriptorProto`s in any order, e.g. the `file_descriptors` parsed by
+    /// `ttrpc_codegen::parse_and_typecheck`.
+    ///
+    /// The files they import must be among them, added before or well-known types of
+    /// `google/protobuf`. A file added twice is kept once.
+    pub fn add_file_descriptor_protos(mut self, files: Vec<FileDescriptorProto>) -> Result<Self> {
+        let mut names = HashSet::new();
+        let files: Vec<_> = files
+            .into_iter()
+            .filter(|f| !self.files.contains_key(f.name()) && names.insert(f.name().to_string()))
+            .collect();
+
+        let mut dependencies: HashMap<String, FileDescriptor> = well_known_files()
+            .map(|f| (f.proto().name().to_string(), f))
+            .collect();
+        dependencies.extend(self.files.clone());
+        dependencies.retain(|name, _| !names.contains(name));
+        let dependencies: Vec<_> = dependencies.into_values().collect();
+
+        let files = FileDescriptor::new_dynamic_fds(files, &dependencies)
+            .map_err(err_to_others_err!(e, "invalid file descriptors: "))?;
+        for file in files {
+            self.files.insert(file.proto().name().to_string(), file);
+        }
+        Ok(self)
+    }
+
+    /// Add a serialized `FileDescriptorSet`, as written by `protoc --descriptor_set_out`.
+    pub fn add_file_descriptor_set(self, set: &[u8]) -> Result<Self> {
+        let set = FileDescriptorSet::parse_from_bytes(set)
+            .map_err(err_to_others_err!(e, "invalid file descriptor set: "))?;
+        self.add_file_descriptor_protos(set.file)
+    }
+
+    /// Add the serialized `FileDescriptorSet` of the file at `path`.
+    pub fn add_file_descriptor_set_file(self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let set =
+            std::fs::read(path).map_err(err_to_io_err!(e, format!("read {}: ", path.display())))?;
+        self.add_file_descriptor_set(&set)
+    }
+
+    /// Add the file declaring `symbol` and the files it imports, got from the reflection
+    /// service of the server.
+    pub async fn add_from_reflection(self, ctx: Context, symbol: &str) -> Result<Self> {
+        let res = ReflectionClient::new(self.client.clone())
+            .file_containing_symbol(ctx, symbol)
+            .await?;
+        let files = res
+            .file_descriptor_proto
+            .iter()
+            .map(|file| {
+                FileDescriptorProto::parse_from_bytes(file)
+                    .map_err(err_to_others_err!(e, "invalid file descriptor: "))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.add_file_descriptor_protos(files)
+    }
+
+    /// The full names of the services of the added files, sorted.
+    pub fn services(&self) -> Vec<String> {
+        let mut services: Vec<_> = self.service_descriptors().map(|(name, _)| name).collect();
+        services.sort();
+        services
+    }
+
+    /// The methods of the service with the full name `service`.
+    pub fn methods(&self, service: &str) -> Result<Vec<Method>> {
+        let name = service.trim_start_matches('.');
+        let (service_name, service) = self
+            .service_descriptors()
+            .find(|(n, _)| n == name)
+            .ok_or_else(|| Error::Others(format!("unknown service {:?}", name)))?;
+        Ok((0..service.proto().method.len())
+            .map(|index| Method {
+                service_name: service_name.clone(),
+                service: service.clone(),
+                index,
+            })
+            .collect())
+    }
+
+    /// Find a method by its path `/package.Service/Method`, with or without the leading `/`,
+    /// or its full name `package.Service.Method`.
+    pub fn method(&self, name: &str) -> Result<Method> {
+        let path = name.trim_start_matches(['/', '.']);
+        let (service, method) = path
+            .rsplit_once('/')
+            .or_else(|| path.rsplit_once('.'))
+            .ok_or_else(|| Error::Others(format!("invalid method name {:?}", name)))?;
+        self.methods(service)?
+            .into_iter()
+            .find(|m| m.name() == method)
+            .ok_or_else(|| Error::Others(format!("unknown method {:?}", name)))
+    }
+
+    /// Find a message type by its full name `package.Message`.
+    pub fn message(&self, name: &str) -> Result<MessageDescriptor> {
+        let full_name = format!(".{}", name.trim_start_matches('.'));
+        self.files
+            .values()
+            .find_map(|file| file.message_by_full_name(&full_name))
+            .ok_or_else(|| Error::Others(format!("unknown message {:?}", name)))
+    }
+
+    /// Call the unary `method`, named as for [`method`](Self::method).
+    pub async fn call(
+        &self,
+        ctx: Context,
+        method: &str,
+        req: &dyn MessageDyn,
+    ) -> Result<Box<dyn MessageDyn>> {
+        let method = self.method(method)?;
+        method.check_kind(false, false)?;
+        let res = self.client.request(method.request(ctx, Some(req))?).await?;
+        method.decode(&res.payload)
+    }
+
+    /// Open the server streaming `method` with its request.
+    pub async fn server_stream(
+        &self,
+        ctx: Context,
+        method: &str,
+        req: &dyn MessageDyn,
+    ) -> Result<DynamicStream> {
+        let method = self.method(method)?;
+        method.check_kind(false, true)?;
+        let inner = self
+            .client
+            .new_stream(method.request(ctx, Some(req))?, false, true)
+            .await?;
+        Ok(DynamicStream::new(inner, method, self.client.clone()))
+    }
+
+    /// Open the client streaming or bidirectional streaming `method`, the requests are sent
+    /// on the stream.
+    pub async fn stream(&self, ctx: Context, method: &str) -> Result<DynamicStream> {
+        let method = self.method(method)?;
+        if !method.client_streaming() {
+            method.check_kind(true, method.server_streaming())?;
+        }
+        let inner = self
+            .client
+            .new_stream(method.request(ctx, None)?, true, method.server_streaming())
+            .await?;
+        Ok(DynamicStream::new(inner, method, self.client.clone()))
+    }
+
+    // The services of the added files, with their full names.
+    fn service_descriptors(&self) -> impl Iterator<Item = (String, ServiceDescriptor)> + '_ {
+        self.files.values().flat_map(|file| {
+            let package = file.proto().package();
+            file.services()
+                .map(|service| {
+                    let name = match package {
+                        "" => service.proto().name().to_string(),
+                        package => format!("{}.{}", package, service.proto().name()),
+                    };
+                    (name, service)
+                })
+                .collect::<Vec<_>>()
+        })
+    }
+}
+
+/// The stream of a call of [`DynamicClient`], whatever the streaming kind of the method.
+pub struct DynamicStream {
+    inner: StreamInner,
+    method: Method,
+    _client_guard: Client,
+}
+
+impl DynamicStream {
+    fn new(inner: StreamInner, method: Method, _client_guard: Client) -> Self {
+        DynamicStream {
+            inner,
+            method,
+            _client_guard,
+        }
+    }
+
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Send a request, the method must be client streaming.
+    pub async fn send(&self, req: &dyn MessageDyn) -> Result<()> {
+        self.check_sendable()?;
+        self.inner.send(self.method.encode(req)?).await
+    }
+
+    /// Tell the server all the requests are sent, the method must be client streaming.
+    pub async fn close_send(&self) -> Result<()> {
+        self.check_sendable()?;
+        self.inner.close_send().await
+    }
+
+    /// Receive the next response, `None` once the stream is over.
+    ///
+    /// The only response of a client streaming method comes after [`close_send`](Self::close_send).
+    pub async fn recv(&mut self) -> Result<Option<Box<dyn MessageDyn>>> {
+        match self.inner.recv().await {
+            Ok(buf) => self.method.decode(&buf).map(Some),
+            Err(Error::Eof) | Err(Error::RemoteClosed) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn check_sendable(&self) -> Result<()> {
+        if self.method.client_streaming() {
+            return Ok(());
+        }
+        self.method.check_kind(true, true)
+    }
+}
+
+/// Parse a message of type `descriptor` from its JSON mapping.
+pub fn parse_json(descriptor: &MessageDescriptor, json: &str) -> Result<Box<dyn MessageDyn>> {
+    protobuf_json_mapping::parse_dyn_from_str(descriptor, json).map_err(err_to_others_err!(
+        e,
+        format!("invalid {}: ", descriptor.full_name())
+    ))
+}
+
+/// Print `message` in its JSON mapping.
+pub fn print_json(message: &dyn MessageDyn) -> Result<String> {
+    protobuf_json_mapping::print_to_string(message).map_err(err_to_others_err!(e, ""))
+}
+
+/// Parse a message of type `descriptor` from the text format.
+pub fn parse_text(descriptor: &MessageDescriptor, text: &str) -> Result<Box<dyn MessageDyn>> {
+    let mut message = descriptor.new_instance();
+    protobuf::text_format::merge_from_str(&mut *message, text).map_err(err_to_others_err!(
+        e,
+        format!("invalid {}: ", descriptor.full_name())
+    ))?;
+    Ok(message)
+}
+
+/// Print `message` in the text format.
+pub fn print_text(message: &dyn MessageDyn) -> String {
+    protobuf::text_format::print_to_string(message)
+}
+
+// The files of `google/protobuf`, which the added files may import without adding them.
+fn well_known_files() -> impl Iterator<Item = FileDescriptor> {
+    use protobuf::well_known_types::*;
+
+    vec![
+        any::file_descriptor(),
+        api::file_descriptor(),
+        duration::file_descriptor(),
+        empty::file_descriptor(),
+        field_mask::file_descriptor(),
+        source_context::file_descriptor(),
+        struct_::file_descriptor(),
+        timestamp::file_descriptor(),
+        type_::file_descriptor(),
+        wrappers::file_descriptor(),
+        protobuf::descriptor::file_descriptor(),
+    ]
+    .into_iter()
+    .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context;
+    use crate::health::HealthCheckRequest;
+
+    fn client() -> DynamicClient {
+        let (stream, _) = tokio::io::duplex(1024);
+        let client = Client::new(crate::r#async::transport::Socket::new(stream));
+        let health = crate::proto::compiled::health::file_descriptor().proto();
+        DynamicClient::new(client)
+            .add_file_descriptor_protos(vec![health.clone(), health.clone()])
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_method() {
+        let client = client();
+        assert_eq!(client.services(), ["grpc.health.v1.Health"]);
+
+        let names = |methods: Vec<Method>| -> Vec<(String, &str)> {
+            methods
+                .iter()
+                .map(|m| (m.name().to_string(), m.kind()))
+                .collect()
+        };
+        assert_eq!(
+            names(client.methods("grpc.health.v1.Health").unwrap()),
+            [
+                ("Check".to_string(), "unary"),
+                ("Watch".to_string(), "server streaming")
+            ]
+        );
+        for name in [
+            "/grpc.health.v1.Health/Watch",
+            "grpc.health.v1.Health/Watch",
+            "grpc.health.v1.Health.Watch",
+        ] {
+            let method = client.method(name).unwrap();
+            assert_eq!(method.service(), "grpc.health.v1.Health");
+            assert_eq!(method.name(), "Watch");
+            assert_eq!(
+                method.output_type().full_name(),
+                "grpc.health.v1.HealthCheckResponse"
+            );
+        }
+        assert!(client.method("grpc.health.v1.Health/Unknown").is_err());
+        assert!(client.method("Watch").is_err());
+        assert!(client.message(".grpc.health.v1.HealthCheckRequest").is_ok());
+        assert!(client.message("grpc.health.v1.Unknown").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_messages() {
+        let client = client();
+        let descriptor = client.message("grpc.health.v1.HealthCheckRequest").unwrap();
+
+        let message = parse_json(&descriptor, r#"{"service": "agent"}"#).unwrap();
+        let req = HealthCheckRequest::parse_from_bytes(&message.write_to_bytes_dyn().unwrap());
+        assert_eq!(req.unwrap().service, "agent");
+        assert_eq!(print_json(&*message).unwrap(), r#"{"service": "agent"}"#);
+        assert!(parse_json(&descriptor, r#"{"unknown": 1}"#).is_err());
+
+        let message = parse_text(&descriptor, r#"service: "agent""#).unwrap();
+        assert_eq!(print_text(&*message), r#"service: "agent""#);
+        assert!(parse_text(&descriptor, "service: 1").is_err());
+
+        // The request must be of the input type of the method, of the kind called.
+        let method = client.method("grpc.health.v1.Health/Check").unwrap();
+        assert!(method.encode(&*message).is_ok());
+        let response = method.output_type().new_instance();
+        match method.encode(&*response) {
+            Err(Error::RpcStatus(s)) => assert_eq!(s.code(), Code::INVALID_ARGUMENT),
+            res => panic!("unexpected result {:?}", res),
+        }
+        let ctx = context::with_timeout(0);
+        match client.stream(ctx, "grpc.health.v1.Health/Check").await {
+            Err(Error::RpcStatus(s)) => assert_eq!(s.code(), Code::INVALID_ARGUMENT),
+            Err(e) => panic!("unexpected error {:?}", e),
+            Ok(_) => panic!("unexpected stream"),
+        }
+    }
+
+    // Join the services of the requests, or echo them back.
+    #[cfg(target_os = "linux")]
+    struct EchoStream {
+        join: bool,
+    }
+
+    #[cfg(target_os = "linux")]
+    #[async_trait::async_trait]
+    impl crate::r#async::StreamHandler for EchoStream {
+        async fn handler(
+            &self,
+            _ctx: crate::r#async::TtrpcContext,
+            mut inner: StreamInner,
+        ) -> Result<Option<crate::proto::Response>> {
+            let mut services = Vec::new();
+            loop {
+                let buf = match inner.recv().await {
+                    Err(Error::Eof) => break,
+                    res => res?,
+                };
+                if !self.join {
+                    inner.send(buf).await?;
+                    continue;
+                }
+                services.push(HealthCheckRequest::parse_from_bytes(&buf).unwrap().service);
+            }
+            if !self.join {
+                return Ok(None);
+            }
+
+            let mut res = crate::proto::Response::new();
+            res.set_status(crate::error::get_status(Code::OK, ""));
+            let rep = HealthCheckRequest {
+                service: services.join(","),
+                ..Default::default()
+            };
+            res.payload = rep.write_to_bytes().unwrap();
+            Ok(Some(res))
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_call() {
+        use std::sync::Arc;
+
+        use protobuf::descriptor::ServiceDescriptorProto;
+
+        use crate::health::HealthReporter;
+        use crate::r#async::{Server, Service, StreamHandler};
+        use crate::reflection::Reflection;
+
+        // ttrpc.test.Echo: Join(stream Req) returns Req and Echo(stream Req) returns stream Req.
+        let health = crate::proto::compiled::health::file_descriptor().proto();
+        let mut echo = FileDescriptorProto::new();
+        echo.set_name("echo.proto".to_string());
+        echo.set_package("ttrpc.test".to_string());
+        echo.dependency.push(health.name().to_string());
+        let mut service = ServiceDescriptorProto::new();
+        service.set_name("Echo".to_string());
+        for name in ["Join", "Echo"] {
+            let mut method = MethodDescriptorProto::new();
+            method.set_name(name.to_string());
+            method.set_input_type(".grpc.health.v1.HealthCheckRequest".to_string());
+            method.set_output_type(".grpc.health.v1.HealthCheckRequest".to_string());
+            method.set_client_streaming(true);
+            method.set_server_streaming(name == "Echo");
+            service.method.push(method);
+        }
+        echo.service.push(service);
+        let mut streams = HashMap::new();
+        for (name, join) in [("Join", true), ("Echo", false)] {
+            streams.insert(
+                name.to_string(),
+                Arc::new(EchoStream { join }) as Arc<dyn StreamHandler + Send + Sync>,
+            );
+        }
+        let mut services = HashMap::new();
+        let methods = HashMap::new();
+        services.insert("ttrpc.test.Echo".to_string(), Service { methods, streams });
+
+        let addr = r"unix://@/tmp/ttrpc-dynamic-unit-test";
+        let descriptors = [
+            health.write_to_bytes().unwrap(),
+            echo.write_to_bytes().unwrap(),
+        ];
+        let reflection = Reflection::new()
+            .add_file_descriptors(&[&descriptors[0], &descriptors[1]])
+            .unwrap();
+        let reporter = HealthReporter::new();
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(services)
+            .register_reflection_service(reflection)
+            .register_health_service(reporter.clone());
+        server.start().await.unwrap();
+
+        let client = DynamicClient::new(Client::connect(addr).await.unwrap())
+            .add_from_reflection(context::with_timeout(0), "ttrpc.test.Echo")
+            .await
+            .unwrap();
+        assert_eq!(
+            client.services(),
+            ["grpc.health.v1.Health", "ttrpc.test.Echo"]
+        );
+        let req = |service: &str| {
+            let input = client.method("ttrpc.test.Echo/Join").unwrap().input_type();
+            parse_json(&input, &format!(r#"{{"service": "{}"}}"#, service)).unwrap()
+        };
+
+        // Unary
+        let res = client
+            .call(
+                context::with_timeout(0),
+                "grpc.health.v1.Health/Check",
+                &*req(""),
+            )
+            .await
+            .unwrap();
+        assert_eq!(print_json(&*res).unwrap(), r#"{"status": "SERVING"}"#);
+
+        // Server streaming
+        let mut watch = client
+            .server_stream(
+                context::with_timeout(0),
+                "grpc.health.v1.Health/Watch",
+                &*req(""),
+            )
+            .await
+            .unwrap();
+        let res = tokio::time::timeout(std::time::Duration::from_secs(3), watch.recv()).await;
+        let res = res.unwrap().unwrap().unwrap();
+        assert_eq!(print_json(&*res).unwrap(), r#"{"status": "SERVING"}"#);
+        reporter.shutdown();
+        let res = tokio::time::timeout(std::time::Duration::from_secs(3), watch.recv()).await;
+        let res = res.unwrap().unwrap().unwrap();
+        assert_eq!(print_json(&*res).unwrap(), r#"{"status": "NOT_SERVING"}"#);
+
+        // Client streaming
+        let mut join = client
+            .stream(context::with_timeout(0), "ttrpc.test.Echo/Join")
+            .await
+            .unwrap();
+        join.send(&*req("a")).await.unwrap();
+        join.send(&*req("b")).await.unwrap();
+        join.close_send().await.unwrap();
+        let res = join.recv().await.unwrap().unwrap();
+        assert_eq!(print_json(&*res).unwrap(), r#"{"service": "a,b"}"#);
+        assert!(join.recv().await.unwrap().is_none());
+
+        // Bidirectional streaming
+        let mut echo = client
+            .stream(context::with_timeout(0), "/ttrpc.test.Echo/Echo")
+            .await
+            .unwrap();
+        for service in ["a", "b"] {
+            echo.send(&*req(service)).await.unwrap();
+            let res = echo.recv().await.unwrap().unwrap();
+            assert_eq!(
+                print_json(&*res).unwrap(),
+                format!(r#"{{"service": "{}"}}"#, service)
+            );
+        }
+        echo.close_send().await.unwrap();
+        assert!(echo.recv().await.unwrap().is_none());
+
+        server.shutdown().await.unwrap();
+    }
+}
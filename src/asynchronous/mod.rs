@@ -12,6 +12,9 @@ mod stream;
 #[doc(hidden)]
 mod utils;
 mod connection;
+#[cfg(feature = "dynamic")]
+#[cfg_attr(docsrs, doc(cfg(feature = "dynamic")))]
+pub mod dynamic;
 pub mod shutdown;
 pub mod transport;
 
@@ -21,8 +24,12 @@ pub use self::stream::{
     StreamSender,
 };
 #[doc(inline)]
-pub use crate::r#async::client::Client;
+pub use crate::r#async::client::{Backoff, Client, ConnectionState};
 #[doc(inline)]
 pub use crate::r#async::server::{Server, Service};
+#[doc(no_inline)]
+pub use tokio_util::sync::{CancellationToken, DropGuard};
 #[doc(inline)]
-pub use utils::{MethodHandler, StreamHandler, TtrpcContext};
+pub use utils::{
+    ClientInterceptor, ClientNext, Interceptor, MethodHandler, Next, StreamHandler, TtrpcContext,
+};
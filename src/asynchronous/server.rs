@@ -13,22 +13,33 @@ use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use async_trait::async_trait;
-use futures::StreamExt as _;
+use futures::future::FutureExt as _;
+use futures::stream::{select_all, StreamExt as _};
 use protobuf::Message as _;
 use tokio::{
     self, select, spawn,
     sync::mpsc::{channel, Sender},
     task,
-    time::timeout,
+    time::{sleep, timeout},
 };
 
 use crate::asynchronous::stream::SendingMessage;
 use crate::asynchronous::transport::{Listener, Socket};
+#[cfg(unix)]
+use crate::bind::SocketFile;
 use crate::context;
-use crate::error::{get_status, Error, Result};
+use crate::error::{get_rpc_status, get_status, Error, Result};
+#[cfg(unix)]
+use crate::fd::{CallFds, FdTable};
+use crate::health::{self, HealthReporter};
+use crate::metadata::Metadata;
+use crate::peer::{Authorizer, Peer};
+#[cfg(feature = "cancel")]
+use crate::proto::FLAG_CANCEL;
 use crate::proto::{
     check_oversize, Code, Codec, GenMessage, Message, MessageHeader, Request, Response, Status,
-    FLAG_NO_DATA, FLAG_REMOTE_CLOSED, MESSAGE_TYPE_DATA, MESSAGE_TYPE_REQUEST,
+    FLAG_NO_DATA, FLAG_REMOTE_CLOSED, FLAG_REMOTE_OPEN, MESSAGE_LENGTH_MAX, MESSAGE_TYPE_DATA,
+    MESSAGE_TYPE_REQUEST,
 };
 use crate::r#async::connection::*;
 use crate::r#async::shutdown;
@@ -36,7 +47,12 @@ use crate::r#async::stream::{
     Kind, MessageReceiver, MessageSender, ResultReceiver, ResultSender, StreamInner,
 };
 use crate::r#async::utils;
-use crate::r#async::{MethodHandler, StreamHandler, TtrpcContext};
+use crate::r#async::{
+    CancellationToken, DropGuard, Interceptor, MethodHandler, Next, StreamHandler, TtrpcContext,
+};
+use crate::reflection::{self, Reflection};
+#[cfg(unix)]
+use crate::UnixBindOptions;
 
 const DEFAULT_CONN_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 const DEFAULT_SERVER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
@@ -60,18 +76,39 @@ impl Service {
 pub struct Server {
     listeners: Vec<Listener>,
     services: Arc<HashMap<String, Service>>,
+    interceptors: Arc<Vec<Arc<dyn Interceptor + Send + Sync>>>,
+    authorizer: Option<Arc<dyn Authorizer + Send + Sync>>,
+    max_send_message_size: usize,
+    max_recv_message_size: usize,
 
     shutdown: shutdown::Notifier,
-    stop_listen_tx: Option<Sender<Sender<Listener>>>,
+    cancel_token: CancellationToken,
+    // Cancels the handlers of all connections once the server is dropped.
+    _cancel_guard: DropGuard,
+    stop_listen_tx: Option<Sender<Sender<Vec<Listener>>>>,
+    // Registered once all services are.
+    reflection: Option<Reflection>,
+    #[cfg(unix)]
+    socket_files: Vec<SocketFile>,
 }
 
 impl Default for Server {
     fn default() -> Self {
+        let cancel_token = CancellationToken::new();
         Server {
             listeners: Vec::with_capacity(1),
             services: Arc::new(HashMap::new()),
+            interceptors: Arc::new(Vec::new()),
+            authorizer: None,
+            max_send_message_size: MESSAGE_LENGTH_MAX,
+            max_recv_message_size: MESSAGE_LENGTH_MAX,
             shutdown: shutdown::with_timeout(DEFAULT_SERVER_SHUTDOWN_TIMEOUT).0,
+            _cancel_guard: cancel_token.clone().drop_guard(),
+            cancel_token,
             stop_listen_tx: None,
+            reflection: None,
+            #[cfg(unix)]
+            socket_files: Vec::new(),
         }
     }
 }
@@ -83,7 +120,36 @@ impl Server {
 
     pub fn bind(self, sockaddr: &str) -> Result<Self> {
         let listener =
-            Listener::bind(sockaddr).map_err(err_to_others_err!(e, "Listener::bind error "))?;
+            Listener::bind(sockaddr).map_err(err_to_io_err!(e, "Listener::bind error "))?;
+        Ok(self.add_listener(listener))
+    }
+
+    /// Listen on `sockaddr` of the form `tls://host:port`, the connections are secured with
+    /// `config`.
+    #[cfg(feature = "tls")]
+    pub fn bind_tls(
+        self,
+        sockaddr: &str,
+        config: Arc<crate::r#async::transport::tls::rustls::ServerConfig>,
+    ) -> Result<Self> {
+        let addr = sockaddr
+            .strip_prefix("tls://")
+            .ok_or_else(|| Error::Others(format!("Scheme of {sockaddr:?} is not tls")))?;
+        let listener = Listener::bind_tls(addr, config)
+            .map_err(err_to_io_err!(e, "Listener::bind_tls error "))?;
+        Ok(self.add_listener(listener))
+    }
+
+    /// Bind the unix socket `sockaddr`, its socket file is set up with `options`.
+    #[cfg(unix)]
+    pub fn bind_unix(mut self, sockaddr: &str, options: UnixBindOptions) -> Result<Self> {
+        let path = sockaddr
+            .strip_prefix("unix://")
+            .ok_or_else(|| Error::Others(format!("Scheme of {sockaddr:?} is not unix")))?;
+        let (listener, file) = options.bind(path, |path| {
+            Listener::bind_unix(path).map_err(err_to_io_err!(e, "Listener::bind error "))
+        })?;
+        self.socket_files.extend(file);
         Ok(self.add_listener(listener))
     }
 
@@ -97,7 +163,7 @@ impl Server {
     /// The file descriptor must represent a unix listener.
     pub unsafe fn add_unix_listener(self, fd: RawFd) -> Result<Server> {
         let listener = Listener::from_raw_unix_listener_fd(fd)
-            .map_err(err_to_others_err!(e, "from_raw_unix_listener_fd error"))?;
+            .map_err(err_to_io_err!(e, "from_raw_unix_listener_fd error"))?;
         Ok(self.add_listener(listener))
     }
 
@@ -106,31 +172,126 @@ impl Server {
     /// The file descriptor must represent a vsock listener.
     pub unsafe fn add_vsock_listener(self, fd: RawFd) -> Result<Self> {
         let listener = Listener::from_raw_vsock_listener_fd(fd)
-            .map_err(err_to_others_err!(e, "from_raw_unix_listener_fd error"))?;
+            .map_err(err_to_io_err!(e, "from_raw_unix_listener_fd error"))?;
         Ok(self.add_listener(listener))
     }
 
+    /// Add the listeners passed to the process with socket activation, see
+    /// [`activation`](crate::activation).
+    #[cfg(unix)]
+    pub fn add_activated_listeners(mut self) -> Result<Server> {
+        let listeners = crate::activation::listen_fds()?;
+        if listeners.is_empty() {
+            return Err(Error::Others(
+                "no listener is passed with socket activation".to_string(),
+            ));
+        }
+        for listener in listeners {
+            let listener = Listener::try_from(listener)
+                .map_err(err_to_io_err!(e, "inherited listener error "))?;
+            self = self.add_listener(listener);
+        }
+        Ok(self)
+    }
+
     pub fn register_service(mut self, new: HashMap<String, Service>) -> Server {
         let services = Arc::get_mut(&mut self.services).unwrap();
         services.extend(new);
         self
     }
 
-    fn get_listener(&mut self) -> Result<Listener> {
-        self.listeners.pop().ok_or_else(|| {
-            Error::Others("ttrpc-rust server started with no bound listener".to_string())
-        })
+    /// Register the health service, reporting the statuses set with `reporter`.
+    pub fn register_health_service(self, reporter: HealthReporter) -> Server {
+        self.register_service(health::asynchronous::create_health(reporter))
     }
 
-    pub async fn start(&mut self) -> Result<()> {
-        let incoming = self.get_listener()?;
-        self.do_start(incoming).await
+    /// Register the reflection service, telling the services of the server and the descriptors
+    /// added to `reflection`.
+    ///
+    /// It lists the services registered by the time the server starts.
+    pub fn register_reflection_service(mut self, reflection: Reflection) -> Server {
+        self.reflection = Some(reflection);
+        self
+    }
+
+    // Register the reflection service, now that the other services are.
+    fn register_reflection(&mut self) {
+        if let Some(reflection) = self.reflection.take() {
+            let services = Arc::get_mut(&mut self.services).unwrap();
+            let reflection = reflection::asynchronous::create_reflection(reflection, services);
+            services.extend(reflection);
+        }
+    }
+
+    /// Add an interceptor wrapping the handlers of all services.
+    ///
+    /// Interceptors run in the order they are added, the first one is the outermost.
+    pub fn add_interceptor(mut self, interceptor: Arc<dyn Interceptor + Send + Sync>) -> Server {
+        let interceptors = Arc::get_mut(&mut self.interceptors).unwrap();
+        interceptors.push(interceptor);
+        self
     }
 
-    async fn do_start(&mut self, mut incoming: Listener) -> Result<()> {
-        let services = self.services.clone();
+    /// Set the authorizer checking the accepted connections and their calls.
+    pub fn set_authorizer(mut self, authorizer: Arc<dyn Authorizer + Send + Sync>) -> Server {
+        self.authorizer = Some(authorizer);
+        self
+    }
 
-        let shutdown_waiter = self.shutdown.subscribe();
+    /// Set the maximum length of a message the server sends.
+    ///
+    /// Responses and stream data exceeding it are replaced by an error status.
+    pub fn set_max_send_message_size(mut self, size: usize) -> Server {
+        self.max_send_message_size = size;
+        self
+    }
+
+    /// Set the maximum length of a message the server receives.
+    ///
+    /// Requests exceeding it are discarded and answered with an error status.
+    pub fn set_max_recv_message_size(mut self, size: usize) -> Server {
+        self.max_recv_message_size = size;
+        self
+    }
+
+    fn builder(&self) -> ServerBuilder {
+        ServerBuilder {
+            services: self.services.clone(),
+            interceptors: self.interceptors.clone(),
+            authorizer: self.authorizer.clone(),
+            peer: None,
+            #[cfg(unix)]
+            fds: Arc::default(),
+            cancel_token: self.cancel_token.clone(),
+            shutdown_waiter: self.shutdown.subscribe(),
+            max_send_message_size: self.max_send_message_size,
+            max_recv_message_size: self.max_recv_message_size,
+        }
+    }
+
+    fn take_listeners(&mut self) -> Result<Vec<Listener>> {
+        if self.listeners.is_empty() {
+            return Err(Error::Others(
+                "ttrpc-rust server started with no bound listener".to_string(),
+            ));
+        }
+        Ok(std::mem::take(&mut self.listeners))
+    }
+
+    /// Start accepting connections on all the registered listeners.
+    ///
+    /// All listeners share the registered services and the server shutdown.
+    pub async fn start(&mut self) -> Result<()> {
+        let listeners = self.take_listeners()?;
+        self.register_reflection();
+        self.do_start(listeners).await
+    }
+
+    async fn do_start(&mut self, listeners: Vec<Listener>) -> Result<()> {
+        let builder = self.builder();
+        // Poll all listeners in one task, a listener is dropped from the
+        // set once its stream terminates.
+        let mut incoming = select_all(listeners);
 
         let (stop_listen_tx, mut stop_listen_rx) = channel(1);
         self.stop_listen_tx = Some(stop_listen_tx);
@@ -144,11 +305,7 @@ impl Server {
                             match conn {
                                 Ok(conn) => {
                                     // spawn a connection handler, would not block
-                                    spawn_connection_handler(
-                                        conn,
-                                        services.clone(),
-                                        shutdown_waiter.clone(),
-                                    ).await;
+                                    spawn_connection_handler(conn, builder.clone()).await;
                                 }
                                 Err(e) => {
                                     error!("incoming conn fail {:?}", e)
@@ -161,7 +318,7 @@ impl Server {
                     }
                     fd_tx = stop_listen_rx.recv() => {
                         if let Some(fd_tx) = fd_tx {
-                            fd_tx.send(incoming).await.unwrap();
+                            fd_tx.send(incoming.into_iter().collect()).await.unwrap();
                         }
                         break;
                     }
@@ -172,23 +329,22 @@ impl Server {
     }
 
     pub async fn accept(&mut self, conn: Socket) -> std::io::Result<()> {
-        let delegate = ServerBuilder {
-            services: self.services.clone(),
-            streams: Arc::default(),
-            shutdown_waiter: self.shutdown.subscribe(),
-        };
-        Connection::new(conn, delegate).run().await
+        self.register_reflection();
+        Connection::new(conn, self.builder()).run().await
     }
 
     pub async fn shutdown(&mut self) -> Result<()> {
         self.stop_listen().await;
         self.disconnect().await;
-        drop(self.listeners.pop());
+        self.listeners.clear();
+        #[cfg(unix)]
+        self.socket_files.drain(..).for_each(|file| file.remove());
         Ok(())
     }
 
     pub async fn disconnect(&mut self) {
         self.shutdown.shutdown();
+        self.cancel_token.cancel();
 
         self.shutdown
             .wait_all_exit()
@@ -200,28 +356,33 @@ impl Server {
         trace!("wait connection exit.");
     }
 
+    /// Stop accepting new connections on all listeners.
+    ///
+    /// The listeners are kept, so the server can be started again.
     pub async fn stop_listen(&mut self) {
         if let Some(tx) = self.stop_listen_tx.take() {
             let (fd_tx, mut fd_rx) = channel(1);
             tx.send(fd_tx).await.unwrap();
 
-            let fd = fd_rx.recv().await.unwrap();
+            let listeners = fd_rx.recv().await.unwrap();
             self.listeners.clear();
-            self.listeners.push(fd);
+            self.listeners.extend(listeners);
         }
     }
 }
 
-async fn spawn_connection_handler(
-    conn: Socket,
-    services: Arc<HashMap<String, Service>>,
-    shutdown_waiter: shutdown::Waiter,
-) {
-    let delegate = ServerBuilder {
-        services,
-        streams: Arc::new(Mutex::new(HashMap::new())),
-        shutdown_waiter,
-    };
+async fn spawn_connection_handler(conn: Socket, mut delegate: ServerBuilder) {
+    delegate.peer = conn.peer();
+    #[cfg(unix)]
+    {
+        delegate.fds = conn.fds();
+    }
+    if let Some(authorizer) = &delegate.authorizer {
+        if let Err(e) = authorizer.authorize_connection(delegate.peer.as_ref()) {
+            info!("connection of {:?} is refused: {:?}", delegate.peer, e);
+            return;
+        }
+    }
     let conn = Connection::new(conn, delegate);
     spawn(async move {
         conn.run()
@@ -233,10 +394,18 @@ async fn spawn_connection_handler(
     });
 }
 
+#[derive(Clone)]
 struct ServerBuilder {
     services: Arc<HashMap<String, Service>>,
-    streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
+    interceptors: Arc<Vec<Arc<dyn Interceptor + Send + Sync>>>,
+    authorizer: Option<Arc<dyn Authorizer + Send + Sync>>,
+    peer: Option<Peer>,
+    #[cfg(unix)]
+    fds: Arc<FdTable>,
+    cancel_token: CancellationToken,
     shutdown_waiter: shutdown::Waiter,
+    max_send_message_size: usize,
+    max_recv_message_size: usize,
 }
 
 impl Builder for ServerBuilder {
@@ -252,9 +421,18 @@ impl Builder for ServerBuilder {
             ServerReader {
                 tx,
                 services: self.services.clone(),
-                streams: self.streams.clone(),
+                interceptors: self.interceptors.clone(),
+                authorizer: self.authorizer.clone(),
+                peer: self.peer.clone(),
+                #[cfg(unix)]
+                fds: self.fds.clone(),
+                cancel_token: self.cancel_token.child_token(),
+                calls: Arc::new(Mutex::new(HashMap::new())),
+                streams: Arc::new(Mutex::new(HashMap::new())),
                 server_shutdown: self.shutdown_waiter.clone(),
                 handler_shutdown: disconnect_notifier,
+                max_send_message_size: self.max_send_message_size,
+                max_recv_message_size: self.max_recv_message_size,
             },
             ServerWriter {
                 rx,
@@ -281,9 +459,20 @@ impl WriterDelegate for ServerWriter {
 struct ServerReader {
     tx: MessageSender,
     services: Arc<HashMap<String, Service>>,
+    interceptors: Arc<Vec<Arc<dyn Interceptor + Send + Sync>>>,
+    authorizer: Option<Arc<dyn Authorizer + Send + Sync>>,
+    peer: Option<Peer>,
+    #[cfg(unix)]
+    fds: Arc<FdTable>,
+    // Cancelled when the connection closes or the server shuts down.
+    cancel_token: CancellationToken,
+    // The tokens cancelled when the client cancels the call of a stream id.
+    calls: Arc<Mutex<HashMap<u32, CancellationToken>>>,
     streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
     server_shutdown: shutdown::Waiter,
     handler_shutdown: shutdown::Notifier,
+    max_send_message_size: usize,
+    max_recv_message_size: usize,
 }
 
 #[async_trait]
@@ -293,6 +482,7 @@ impl ReaderDelegate for ServerReader {
     }
 
     async fn disconnect(&self, _: Error, _: &mut task::JoinHandle<()>) {
+        self.cancel_token.cancel();
         self.handler_shutdown.shutdown();
         // TODO: Don't wait for all requests to complete? when the connection is disconnected.
     }
@@ -328,6 +518,10 @@ impl ReaderDelegate for ServerReader {
     async fn handle_err(&self, header: MessageHeader, e: Error) {
         self.context().handle_err(header, e).await
     }
+
+    fn max_recv_message_size(&self) -> usize {
+        self.max_recv_message_size
+    }
 }
 
 impl ServerReader {
@@ -335,7 +529,15 @@ impl ServerReader {
         HandlerContext {
             tx: self.tx.clone(),
             services: self.services.clone(),
+            interceptors: self.interceptors.clone(),
+            authorizer: self.authorizer.clone(),
+            peer: self.peer.clone(),
+            #[cfg(unix)]
+            fds: self.fds.clone(),
+            cancel_token: self.cancel_token.clone(),
+            calls: self.calls.clone(),
             streams: self.streams.clone(),
+            max_send_message_size: self.max_send_message_size,
             _handler_shutdown_waiter: self.handler_shutdown.subscribe(),
         }
     }
@@ -344,7 +546,15 @@ impl ServerReader {
 struct HandlerContext {
     tx: MessageSender,
     services: Arc<HashMap<String, Service>>,
+    interceptors: Arc<Vec<Arc<dyn Interceptor + Send + Sync>>>,
+    authorizer: Option<Arc<dyn Authorizer + Send + Sync>>,
+    peer: Option<Peer>,
+    #[cfg(unix)]
+    fds: Arc<FdTable>,
+    cancel_token: CancellationToken,
+    calls: Arc<Mutex<HashMap<u32, CancellationToken>>>,
     streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
+    max_send_message_size: usize,
     // Used for waiting handler exit.
     _handler_shutdown_waiter: shutdown::Waiter,
 }
@@ -365,7 +575,10 @@ impl HandlerContext {
             Self::respond_with_status(
                 self.tx.clone(),
                 stream_id,
-                get_status(Code::INVALID_ARGUMENT, "stream id must be odd"),
+                get_status(
+                    Code::INVALID_ARGUMENT,
+                    "StreamID must be odd for client initiated streams",
+                ),
             )
             .await;
             return;
@@ -376,7 +589,11 @@ impl HandlerContext {
                 Ok(opt_msg) => match opt_msg {
                     Some(mut resp) => {
                         // Server: check size before sending to client
-                        if let Err(e) = check_oversize(resp.compute_size() as usize, true) {
+                        if let Err(e) = check_oversize(
+                            resp.compute_size() as usize,
+                            self.max_send_message_size,
+                            true,
+                        ) {
                             resp = e.into();
                         }
 
@@ -398,16 +615,39 @@ impl HandlerContext {
                         self.tx
                             .send(SendingMessage::new(msg))
                             .await
-                            .map_err(err_to_others_err!(e, "Send packet to sender error "))
+                            .map_err(|e| Error::Closed(format!("Send packet to sender error {e}")))
                             .ok();
                     }
                 },
                 Err(status) => Self::respond_with_status(self.tx.clone(), stream_id, status).await,
             },
+            #[cfg(feature = "cancel")]
+            MESSAGE_TYPE_DATA if (msg.header.flags & FLAG_CANCEL) == FLAG_CANCEL => {
+                // The client gave up on the call, it doesn't wait for a response.
+                if let Some(token) = self.calls.lock().unwrap().get(&stream_id) {
+                    token.cancel();
+                }
+            }
             MESSAGE_TYPE_DATA => {
-                // no need to wait data message handling
-                drop(wait_tx);
-                // TODO(wllenyj): Compatible with golang behavior.
+                // The reader waits for the data to be queued, so that the
+                // messages of a stream are kept in order.
+                let stream_tx = self.streams.lock().unwrap().get(&stream_id).cloned();
+                let stream_tx = match stream_tx {
+                    Some(stream_tx) => stream_tx,
+                    None => {
+                        Self::respond_with_status(
+                            self.tx.clone(),
+                            stream_id,
+                            get_status(Code::INVALID_ARGUMENT, "StreamID is no longer active"),
+                        )
+                        .await;
+                        return;
+                    }
+                };
+
+                // Like the Go implementation, a close message with data closes
+                // the stream but its data is refused.
+                let mut msg = msg;
                 if (msg.header.flags & FLAG_REMOTE_CLOSED) == FLAG_REMOTE_CLOSED
                     && !msg.payload.is_empty()
                 {
@@ -416,40 +656,30 @@ impl HandlerContext {
                         stream_id,
                         get_status(
                             Code::INVALID_ARGUMENT,
-                            format!(
-                                "Stream id {stream_id}: data close message connot include data"
-                            ),
+                            "data close message cannot include data",
                         ),
                     )
                     .await;
-                    return;
+                    msg.header = MessageHeader::new_data(stream_id, 0);
+                    msg.header.set_flags(FLAG_REMOTE_CLOSED | FLAG_NO_DATA);
+                    msg.payload.clear();
                 }
-                let stream_tx = self.streams.lock().unwrap().get(&stream_id).cloned();
-                if let Some(stream_tx) = stream_tx {
-                    if let Err(e) = stream_tx.send(Ok(msg)).await {
-                        Self::respond_with_status(
-                            self.tx.clone(),
-                            stream_id,
-                            get_status(
-                                Code::INVALID_ARGUMENT,
-                                format!("Stream id {stream_id}: handling data error: {e}"),
-                            ),
-                        )
-                        .await;
-                    }
-                } else {
+                if let Err(e) = stream_tx.send(Ok(msg)).await {
                     Self::respond_with_status(
                         self.tx.clone(),
                         stream_id,
-                        get_status(Code::INVALID_ARGUMENT, "Stream is no longer active"),
+                        get_status(
+                            Code::INVALID_ARGUMENT,
+                            format!("Stream id {stream_id}: handling data error: {e}"),
+                        ),
                     )
                     .await;
                 }
+                drop(wait_tx);
             }
             _ => {
-                // TODO: else we must ignore this for future compat. log this?
-                // TODO(wllenyj): Compatible with golang behavior.
-                error!("Unknown message type. {:?}", msg.header);
+                // Ignored for future compatibility, as by the Go implementation.
+                debug!("Unknown message type. {:?}", msg.header);
             }
         }
     }
@@ -465,29 +695,55 @@ impl HandlerContext {
         //}
         // self.last_stream_id = header.stream_id;
 
+        // The descriptors sent with the request are closed if it isn't handled.
+        #[cfg(unix)]
+        let fds = CallFds::new(msg.header.stream_id, self.fds.clone());
         let req_msg = Message::<Request>::try_from(msg)
             .map_err(|e| get_status(Code::INVALID_ARGUMENT, e.to_string()))?;
 
         let req = &req_msg.payload;
         trace!("Got Message request {} {}", req.service, req.method);
+        // Registered before the reader goes on, which may read the cancel of the call.
+        let (client_cancel, _call_guard) = self.register_call(req_msg.header.stream_id);
 
-        let srv = self.services.get(&req.service).ok_or_else(|| {
-            get_status(
-                Code::INVALID_ARGUMENT,
-                format!("{} service does not exist", &req.service),
-            )
-        })?;
+        if let Some(authorizer) = &self.authorizer {
+            authorizer
+                .authorize_call(self.peer.as_ref(), req)
+                .map_err(status_of_refusal)?;
+        }
+
+        let srv = self
+            .services
+            .get(&req.service)
+            .ok_or_else(|| get_status(Code::UNIMPLEMENTED, format!("service {}", &req.service)))?;
 
         if let Some(method) = srv.get_method(&req.method) {
             drop(wait_tx);
-            return self.handle_method(method, req_msg).await;
+            return self
+                .handle_method(
+                    method,
+                    req_msg,
+                    client_cancel,
+                    #[cfg(unix)]
+                    fds,
+                )
+                .await;
         }
         if let Some(stream) = srv.get_stream(&req.method) {
-            return self.handle_stream(stream, req_msg, wait_tx).await;
+            return self
+                .handle_stream(
+                    stream,
+                    req_msg,
+                    wait_tx,
+                    client_cancel,
+                    #[cfg(unix)]
+                    fds,
+                )
+                .await;
         }
         Err(get_status(
             Code::UNIMPLEMENTED,
-            format!("{} method", &req.method),
+            format!("method {}", &req.method),
         ))
     }
 
@@ -495,42 +751,54 @@ impl HandlerContext {
         &self,
         method: &(dyn MethodHandler + Send + Sync),
         req_msg: Message<Request>,
+        client_cancel: CancellationToken,
+        #[cfg(unix)] fds: CallFds,
     ) -> StdResult<Option<Response>, Status> {
         let req = req_msg.payload;
         let path = utils::get_path(&req.service, &req.method);
+        let timeout_nano = req.timeout_nano;
+        let cancel_token = self.call_cancel_token(timeout_nano);
+        let _cancel_guard = cancel_token.clone().drop_guard();
 
         let ctx = TtrpcContext {
             mh: req_msg.header,
-            metadata: context::from_pb(&req.metadata),
+            metadata: Metadata::from_pb(&req.metadata),
             timeout_nano: req.timeout_nano,
+            deadline: context::deadline_of(req.timeout_nano),
+            peer: self.peer.clone(),
+            cancel_token: cancel_token.clone(),
+            #[cfg(unix)]
+            fds,
         };
 
-        let get_unknown_status_and_log_err = |e| {
+        let next = Next::new(
+            &self.interceptors,
+            Box::new(move |ctx, req| method.handler(ctx, req).map(|r| r.map(Some)).boxed()),
+        );
+        let run = async {
+            select! {
+                res = next.run(ctx, req) => res,
+                _ = client_cancel.cancelled() => Err(Error::Cancelled),
+            }
+        };
+        let get_status_and_log_err = |e| {
             error!("method handle {} got error {:?}", path, &e);
-            get_status(Code::UNKNOWN, e)
+            status_of_error(e)
         };
-        if req.timeout_nano == 0 {
-            method
-                .handler(ctx, req)
-                .await
-                .map_err(get_unknown_status_and_log_err)
-                .map(Some)
+        if timeout_nano == 0 {
+            run.await.map_err(get_status_and_log_err)
         } else {
-            timeout(
-                Duration::from_nanos(req.timeout_nano as u64),
-                method.handler(ctx, req),
-            )
-            .await
-            .map_err(|_| {
-                // Timed out
-                error!("method handle {} got error timed out", path);
-                get_status(Code::DEADLINE_EXCEEDED, "timeout")
-            })
-            .and_then(|r| {
-                // Handler finished
-                r.map_err(get_unknown_status_and_log_err)
-            })
-            .map(Some)
+            timeout(Duration::from_nanos(timeout_nano as u64), run)
+                .await
+                .map_err(|_| {
+                    // Timed out
+                    error!("method handle {} got error timed out", path);
+                    get_status(Code::DEADLINE_EXCEEDED, "timeout")
+                })
+                .and_then(|r| {
+                    // Handler finished
+                    r.map_err(get_status_and_log_err)
+                })
         }
     }
 
@@ -539,16 +807,22 @@ impl HandlerContext {
         stream: Arc<dyn StreamHandler + Send + Sync>,
         req_msg: Message<Request>,
         wait_tx: tokio::sync::oneshot::Sender<()>,
+        client_cancel: CancellationToken,
+        #[cfg(unix)] fds: CallFds,
     ) -> StdResult<Option<Response>, Status> {
         let stream_id = req_msg.header.stream_id;
         let req = req_msg.payload;
         let path = utils::get_path(&req.service, &req.method);
+        let cancel_token = self.call_cancel_token(req.timeout_nano);
+        let _cancel_guard = cancel_token.clone().drop_guard();
 
         let (tx, rx): (ResultSender, ResultReceiver) = channel(100);
         let stream_tx = tx.clone();
         self.streams.lock().unwrap().insert(stream_id, tx);
 
-        let no_data = (req_msg.header.flags & FLAG_NO_DATA) == FLAG_NO_DATA;
+        // The payload of the request is the first message unless the client
+        // streams: Rust clients then set NO_DATA, Go clients only REMOTE_OPEN.
+        let no_data = (req_msg.header.flags & (FLAG_NO_DATA | FLAG_REMOTE_OPEN)) != 0;
 
         drop(wait_tx);
 
@@ -560,43 +834,96 @@ impl HandlerContext {
             true,
             Kind::Server,
             self.streams.clone(),
-        );
+        )
+        .with_max_send_message_size(self.max_send_message_size);
 
         let ctx = TtrpcContext {
             mh: req_msg.header,
-            metadata: context::from_pb(&req.metadata),
+            metadata: Metadata::from_pb(&req.metadata),
             timeout_nano: req.timeout_nano,
+            deadline: context::deadline_of(req.timeout_nano),
+            peer: self.peer.clone(),
+            cancel_token: cancel_token.clone(),
+            #[cfg(unix)]
+            fds,
         };
 
-        let task = spawn(async move { stream.handler(ctx, si).await });
+        let handler_path = path.clone();
+        let next = Next::new(
+            &self.interceptors,
+            Box::new(move |ctx, req: Request| {
+                async move {
+                    let path = handler_path;
+                    let mut task = spawn(async move { stream.handler(ctx, si).await });
 
-        if !no_data {
-            // Fake the first data message.
-            let msg = GenMessage {
-                header: MessageHeader::new_data(stream_id, req.payload.len() as u32),
-                payload: req.payload,
-            };
-            stream_tx.send(Ok(msg)).await.map_err(|e| {
-                error!("send stream data {} got error {:?}", path, &e);
-                get_status(Code::UNKNOWN, e)
-            })?;
+                    if !no_data {
+                        // Fake the first data message.
+                        let msg = GenMessage {
+                            header: MessageHeader::new_data(stream_id, req.payload.len() as u32),
+                            payload: req.payload,
+                        };
+                        stream_tx.send(Ok(msg)).await.map_err(|e| {
+                            error!("send stream data {} got error {:?}", path, &e);
+                            get_rpc_status(Code::UNKNOWN, e)
+                        })?;
+                    }
+                    select! {
+                        res = &mut task => res.unwrap_or_else(|e| {
+                            Err(Error::Others(format!("stream {path} task got error {e:?}")))
+                        }),
+                        _ = client_cancel.cancelled() => {
+                            task.abort();
+                            Err(Error::Cancelled)
+                        }
+                    }
+                }
+                .boxed()
+            }),
+        );
+        next.run(ctx, req).await.map_err(|e| {
+            error!("stream handle {} got error {:?}", path, &e);
+            status_of_error(e)
+        })
+    }
+
+    // Register the call of `stream_id` until the guard is dropped, the token is cancelled
+    // if the client cancels it.
+    fn register_call(&self, stream_id: u32) -> (CancellationToken, CallGuard) {
+        let token = CancellationToken::new();
+        self.calls.lock().unwrap().insert(stream_id, token.clone());
+        let guard = CallGuard {
+            stream_id,
+            calls: self.calls.clone(),
+        };
+        (token, guard)
+    }
+
+    // The token of a call is also cancelled once its deadline passes or it's done.
+    fn call_cancel_token(&self, timeout_nano: i64) -> CancellationToken {
+        let cancel_token = self.cancel_token.child_token();
+        if timeout_nano > 0 {
+            let deadline = cancel_token.clone();
+            spawn(async move {
+                select! {
+                    _ = sleep(Duration::from_nanos(timeout_nano as u64)) => deadline.cancel(),
+                    _ = deadline.cancelled() => {}
+                }
+            });
         }
-        task.await
-            .unwrap_or_else(|e| Err(Error::Others(format!("stream {path} task got error {e:?}"))))
-            .map_err(|e| get_status(Code::UNKNOWN, e))
+        cancel_token
     }
 
     async fn respond(tx: MessageSender, stream_id: u32, resp: Response) -> Result<()> {
         let payload = resp
             .encode()
-            .map_err(err_to_others_err!(e, "Encode Response failed."))?;
+            .map_err(err_to_codec_err!(e, "Encode Response failed."))?;
         let msg = GenMessage {
             header: MessageHeader::new_response(stream_id, payload.len() as u32),
             payload,
         };
         tx.send(SendingMessage::new(msg))
             .await
-            .map_err(err_to_others_err!(e, "Send packet to sender error "))
+            .map_err(|e| Error::Closed(format!("Send packet to sender error {e}")))
     }
 
     async fn respond_with_status(tx: MessageSender, stream_id: u32, status: Status) {
@@ -611,6 +938,33 @@ impl HandlerContext {
     }
 }
 
+struct CallGuard {
+    stream_id: u32,
+    calls: Arc<Mutex<HashMap<u32, CancellationToken>>>,
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        self.calls.lock().unwrap().remove(&self.stream_id);
+    }
+}
+
+// A status error of the authorizer is passed to the client as is.
+fn status_of_refusal(e: Error) -> Status {
+    match e {
+        Error::RpcStatus(status) => status,
+        e => get_status(Code::PERMISSION_DENIED, e),
+    }
+}
+
+// A status error, e.g. an interceptor rejecting the call, is passed to the client as is.
+fn status_of_error(e: Error) -> Status {
+    match e {
+        Error::RpcStatus(status) => status,
+        e => get_status(e.code(), e),
+    }
+}
+
 #[cfg(target_os = "linux")]
 #[cfg(test)]
 mod tests {
@@ -642,4 +996,269 @@ mod tests {
         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
         assert!(!is_socket_in_use(addr));
     }
+
+    #[tokio::test]
+    async fn test_server_multiple_listeners() {
+        let addrs = [
+            r"unix://@/tmp/ttrpc-server-unit-test-multi-1",
+            r"unix://@/tmp/ttrpc-server-unit-test-multi-2",
+        ];
+
+        let mut server = Server::new()
+            .bind(addrs[0])
+            .unwrap()
+            .bind(addrs[1])
+            .unwrap();
+        server.start().await.unwrap();
+
+        for addr in addrs {
+            assert!(is_socket_in_use(addr.strip_prefix("unix://@").unwrap()));
+
+            // Both listeners are served, an unknown service gets a status back.
+            let client = crate::r#async::Client::connect(addr).await.unwrap();
+            let req = Request {
+                service: "grpc.NoSuchService".to_string(),
+                method: "Check".to_string(),
+                ..Default::default()
+            };
+            match client.request(req).await {
+                Err(Error::RpcStatus(s)) => assert_eq!(s.code(), Code::UNIMPLEMENTED),
+                r => panic!("unexpected result {:?}", r),
+            }
+        }
+
+        server.stop_listen().await;
+        assert_eq!(server.listeners.len(), 2);
+
+        server.shutdown().await.unwrap();
+        assert!(server.listeners.is_empty());
+        for addr in addrs {
+            assert!(!is_socket_in_use(addr.strip_prefix("unix://@").unwrap()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_message_size() {
+        let addr = r"unix://@/tmp/ttrpc-server-unit-test-max-size";
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .set_max_recv_message_size(16);
+        server.start().await.unwrap();
+
+        let req = Request {
+            service: "grpc.NoSuchService".to_string(),
+            method: "Check".to_string(),
+            ..Default::default()
+        };
+
+        // The client refuses to send a request over its own limit.
+        let client = crate::r#async::Client::connect(addr).await.unwrap();
+        client.set_connection_max_send_message_size(16);
+        match client.request(req.clone()).await {
+            Err(Error::Others(_)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        // The server discards a request over its limit and answers with a status. The limit
+        // of the client is the connection's, so it's lifted for the clones as well.
+        let clone = client.clone();
+        client.set_connection_max_send_message_size(MESSAGE_LENGTH_MAX);
+        match clone.request(req).await {
+            Err(Error::RpcStatus(s)) => {
+                assert_eq!(s.code(), Code::RESOURCE_EXHAUSTED);
+                assert!(s.message().contains("exceed maximum message size"));
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        server.shutdown().await.unwrap();
+    }
+
+    struct Whoami;
+
+    #[async_trait]
+    impl MethodHandler for Whoami {
+        async fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<Response> {
+            let mut res = Response::new();
+            res.set_status(get_status(Code::OK, ""));
+            res.payload = ctx.metadata.get("user").unwrap().as_bytes().to_vec();
+            Ok(res)
+        }
+    }
+
+    struct Auth;
+
+    #[async_trait]
+    impl Interceptor for Auth {
+        async fn intercept(
+            &self,
+            mut ctx: TtrpcContext,
+            req: Request,
+            next: Next<'_>,
+        ) -> Result<Option<Response>> {
+            if !ctx.metadata.contains_key("token") {
+                return Err(get_rpc_status(Code::PERMISSION_DENIED, "no token"));
+            }
+            ctx.metadata.append("user", req.method.clone())?;
+            next.run(ctx, req).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_interceptor() {
+        let addr = r"unix://@/tmp/ttrpc-server-unit-test-interceptor";
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("Whoami".to_string(), Box::new(Whoami));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test.Auth".to_string(), service)]))
+            .add_interceptor(Arc::new(Auth));
+        server.start().await.unwrap();
+
+        let client = crate::r#async::Client::connect(addr).await.unwrap();
+        let mut req = Request {
+            service: "test.Auth".to_string(),
+            method: "Whoami".to_string(),
+            ..Default::default()
+        };
+        match client.request(req.clone()).await {
+            Err(Error::RpcStatus(s)) => assert_eq!(s.code(), Code::PERMISSION_DENIED),
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        req.metadata = context::to_pb(HashMap::from([(
+            "token".to_string(),
+            vec!["secret".to_string()],
+        )]));
+        let res = client.request(req).await.unwrap();
+        assert_eq!(res.payload, b"Whoami");
+
+        server.shutdown().await.unwrap();
+    }
+
+    struct PeerPid;
+
+    #[async_trait]
+    impl MethodHandler for PeerPid {
+        async fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<Response> {
+            let mut res = Response::new();
+            res.set_status(get_status(Code::OK, ""));
+            if let Some(Peer::Unix { pid: Some(pid), .. }) = ctx.peer {
+                res.payload = pid.to_string().into_bytes();
+            }
+            Ok(res)
+        }
+    }
+
+    struct OwnUid;
+
+    impl Authorizer for OwnUid {
+        fn authorize_call(&self, peer: Option<&Peer>, req: &Request) -> Result<()> {
+            match peer {
+                Some(Peer::Unix { uid, .. }) if *uid == nix::unistd::getuid().as_raw() => {}
+                _ => return Err(Error::Others("unknown peer".to_string())),
+            }
+            if req.method == "Forbidden" {
+                return Err(get_rpc_status(Code::PERMISSION_DENIED, "forbidden"));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_peer_authorizer() {
+        let addr = r"unix://@/tmp/ttrpc-server-unit-test-peer";
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("PeerPid".to_string(), Box::new(PeerPid));
+        methods.insert("Forbidden".to_string(), Box::new(PeerPid));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test.Peer".to_string(), service)]))
+            .set_authorizer(Arc::new(OwnUid));
+        server.start().await.unwrap();
+
+        let client = crate::r#async::Client::connect(addr).await.unwrap();
+        let mut req = Request {
+            service: "test.Peer".to_string(),
+            method: "PeerPid".to_string(),
+            ..Default::default()
+        };
+        let res = client.request(req.clone()).await.unwrap();
+        assert_eq!(res.payload, std::process::id().to_string().into_bytes());
+
+        req.method = "Forbidden".to_string();
+        match client.request(req).await {
+            Err(Error::RpcStatus(s)) => assert_eq!(s.code(), Code::PERMISSION_DENIED),
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        server.shutdown().await.unwrap();
+    }
+
+    struct Hang(tokio::sync::mpsc::Sender<()>);
+
+    #[async_trait]
+    impl MethodHandler for Hang {
+        async fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<Response> {
+            let tx = self.0.clone();
+            spawn(async move {
+                ctx.cancel_token.cancelled().await;
+                tx.send(()).await.unwrap();
+            });
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_cancel_token() {
+        let addr = r"unix://@/tmp/ttrpc-server-unit-test-cancel";
+        let (tx, mut rx) = channel(1);
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("Hang".to_string(), Box::new(Hang(tx)));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test.Cancel".to_string(), service)]));
+        server.start().await.unwrap();
+
+        let mut req = Request {
+            service: "test.Cancel".to_string(),
+            method: "Hang".to_string(),
+            ..Default::default()
+        };
+
+        // The deadline of the call passes.
+        let client = crate::r#async::Client::connect(addr).await.unwrap();
+        req.timeout_nano = Duration::from_millis(100).as_nanos() as i64;
+        assert!(client.request(req.clone()).await.is_err());
+        timeout(Duration::from_secs(3), rx.recv()).await.unwrap();
+
+        // The connection closes.
+        req.timeout_nano = 0;
+        let call = spawn(async move { client.request(req).await });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        call.abort();
+        let _ = call.await;
+        timeout(Duration::from_secs(3), rx.recv()).await.unwrap();
+
+        server.shutdown().await.unwrap();
+    }
 }
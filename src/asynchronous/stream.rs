@@ -13,9 +13,11 @@ use tokio::sync::mpsc;
 
 use super::Client;
 use crate::error::{Error, Result};
+#[cfg(unix)]
+use crate::fd::FdTable;
 use crate::proto::{
-    Code, Codec, GenMessage, MessageHeader, Response, FLAG_NO_DATA, FLAG_REMOTE_CLOSED,
-    MESSAGE_TYPE_DATA, MESSAGE_TYPE_RESPONSE,
+    Code, Codec, DefaultEncoding, Encoding, GenMessage, MessageHeader, Response, FLAG_CANCEL,
+    FLAG_NO_DATA, FLAG_REMOTE_CLOSED, MESSAGE_LENGTH_MAX, MESSAGE_TYPE_DATA, MESSAGE_TYPE_RESPONSE,
 };
 
 pub type MessageSender = mpsc::Sender<SendingMessage>;
@@ -55,17 +57,14 @@ impl SendingMessage {
 }
 
 #[derive(Debug)]
-pub struct ClientStream<Q, P> {
-    tx: CSSender<Q>,
-    rx: CSReceiver<P>,
+pub struct ClientStream<Q, P, C = DefaultEncoding> {
+    tx: CSSender<Q, C>,
+    rx: CSReceiver<P, C>,
 }
 
-impl<Q, P> ClientStream<Q, P>
+impl<Q, P, C> ClientStream<Q, P, C>
 where
-    Q: Codec,
-    P: Codec,
-    <Q as Codec>::E: std::fmt::Display,
-    <P as Codec>::E: std::fmt::Display,
+    C: Encoding<Q> + Encoding<P>,
 {
     pub fn new(inner: StreamInner) -> Self {
         let (tx, rx) = inner.split();
@@ -81,7 +80,7 @@ where
         }
     }
 
-    pub fn split(self) -> (CSSender<Q>, CSReceiver<P>) {
+    pub fn split(self) -> (CSSender<Q, C>, CSReceiver<P, C>) {
         (self.tx, self.rx)
     }
 
@@ -99,20 +98,17 @@ where
 }
 
 #[derive(Clone, Debug)]
-pub struct CSSender<Q> {
+pub struct CSSender<Q, C = DefaultEncoding> {
     tx: StreamSender,
-    _send: PhantomData<Q>,
+    _send: PhantomData<(Q, C)>,
 }
 
-impl<Q> CSSender<Q>
+impl<Q, C> CSSender<Q, C>
 where
-    Q: Codec,
-    <Q as Codec>::E: std::fmt::Display,
+    C: Encoding<Q>,
 {
     pub async fn send(&self, req: &Q) -> Result<()> {
-        let msg_buf = req
-            .encode()
-            .map_err(err_to_others_err!(e, "Encode message failed."))?;
+        let msg_buf = <C as Encoding<Q>>::encode(req)?;
         self.tx.send(msg_buf).await
     }
 
@@ -122,34 +118,30 @@ where
 }
 
 #[derive(Debug)]
-pub struct CSReceiver<P> {
+pub struct CSReceiver<P, C = DefaultEncoding> {
     rx: StreamReceiver,
-    _recv: PhantomData<P>,
+    _recv: PhantomData<(P, C)>,
 }
 
-impl<P> CSReceiver<P>
+impl<P, C> CSReceiver<P, C>
 where
-    P: Codec,
-    <P as Codec>::E: std::fmt::Display,
+    C: Encoding<P>,
 {
     pub async fn recv(&mut self) -> Result<P> {
         let msg_buf = self.rx.recv().await?;
-        P::decode(msg_buf).map_err(err_to_others_err!(e, "Decode message failed."))
+        <C as Encoding<P>>::decode(msg_buf)
     }
 }
 
 #[derive(Debug)]
-pub struct ServerStream<P, Q> {
-    tx: SSSender<P>,
-    rx: SSReceiver<Q>,
+pub struct ServerStream<P, Q, C = DefaultEncoding> {
+    tx: SSSender<P, C>,
+    rx: SSReceiver<Q, C>,
 }
 
-impl<P, Q> ServerStream<P, Q>
+impl<P, Q, C> ServerStream<P, Q, C>
 where
-    P: Codec,
-    Q: Codec,
-    <P as Codec>::E: std::fmt::Display,
-    <Q as Codec>::E: std::fmt::Display,
+    C: Encoding<P> + Encoding<Q>,
 {
     pub fn new(inner: StreamInner) -> Self {
         let (tx, rx) = inner.split();
@@ -165,7 +157,7 @@ where
         }
     }
 
-    pub fn split(self) -> (SSSender<P>, SSReceiver<Q>) {
+    pub fn split(self) -> (SSSender<P, C>, SSReceiver<Q, C>) {
         (self.tx, self.rx)
     }
 
@@ -179,34 +171,30 @@ where
 }
 
 #[derive(Clone, Debug)]
-pub struct SSSender<P> {
+pub struct SSSender<P, C = DefaultEncoding> {
     tx: StreamSender,
-    _send: PhantomData<P>,
+    _send: PhantomData<(P, C)>,
 }
 
-impl<P> SSSender<P>
+impl<P, C> SSSender<P, C>
 where
-    P: Codec,
-    <P as Codec>::E: std::fmt::Display,
+    C: Encoding<P>,
 {
     pub async fn send(&self, resp: &P) -> Result<()> {
-        let msg_buf = resp
-            .encode()
-            .map_err(err_to_others_err!(e, "Encode message failed."))?;
+        let msg_buf = <C as Encoding<P>>::encode(resp)?;
         self.tx.send(msg_buf).await
     }
 }
 
 #[derive(Debug)]
-pub struct SSReceiver<Q> {
+pub struct SSReceiver<Q, C = DefaultEncoding> {
     rx: StreamReceiver,
-    _recv: PhantomData<Q>,
+    _recv: PhantomData<(Q, C)>,
 }
 
-impl<Q> SSReceiver<Q>
+impl<Q, C> SSReceiver<Q, C>
 where
-    Q: Codec,
-    <Q as Codec>::E: std::fmt::Display,
+    C: Encoding<Q>,
 {
     pub async fn recv(&mut self) -> Result<Option<Q>> {
         let res = self.rx.recv().await;
@@ -215,24 +203,19 @@ where
             return Ok(None);
         }
         let msg_buf = res?;
-        Q::decode(msg_buf)
-            .map_err(err_to_others_err!(e, "Decode message failed."))
-            .map(Some)
+        <C as Encoding<Q>>::decode(msg_buf).map(Some)
     }
 }
 
-pub struct ClientStreamSender<Q, P> {
+pub struct ClientStreamSender<Q, P, C = DefaultEncoding> {
     inner: StreamInner,
-    _send: PhantomData<Q>,
-    _recv: PhantomData<P>,
+    _send: PhantomData<(Q, C)>,
+    _recv: PhantomData<(P, C)>,
 }
 
-impl<Q, P> ClientStreamSender<Q, P>
+impl<Q, P, C> ClientStreamSender<Q, P, C>
 where
-    Q: Codec,
-    P: Codec,
-    <Q as Codec>::E: std::fmt::Display,
-    <P as Codec>::E: std::fmt::Display,
+    C: Encoding<Q> + Encoding<P>,
 {
     pub fn new(inner: StreamInner) -> Self {
         Self {
@@ -243,28 +226,25 @@ where
     }
 
     pub async fn send(&self, req: &Q) -> Result<()> {
-        let msg_buf = req
-            .encode()
-            .map_err(err_to_others_err!(e, "Encode message failed."))?;
+        let msg_buf = <C as Encoding<Q>>::encode(req)?;
         self.inner.send(msg_buf).await
     }
 
     pub async fn close_and_recv(&mut self) -> Result<P> {
         self.inner.close_send().await?;
         let msg_buf = self.inner.recv().await?;
-        P::decode(msg_buf).map_err(err_to_others_err!(e, "Decode message failed."))
+        <C as Encoding<P>>::decode(msg_buf)
     }
 }
 
-pub struct ServerStreamSender<P> {
+pub struct ServerStreamSender<P, C = DefaultEncoding> {
     inner: StreamSender,
-    _send: PhantomData<P>,
+    _send: PhantomData<(P, C)>,
 }
 
-impl<P> ServerStreamSender<P>
+impl<P, C> ServerStreamSender<P, C>
 where
-    P: Codec,
-    <P as Codec>::E: std::fmt::Display,
+    C: Encoding<P>,
 {
     pub fn new(inner: StreamInner) -> Self {
         Self {
@@ -274,24 +254,21 @@ where
     }
 
     pub async fn send(&self, resp: &P) -> Result<()> {
-        let msg_buf = resp
-            .encode()
-            .map_err(err_to_others_err!(e, "Encode message failed."))?;
+        let msg_buf = <C as Encoding<P>>::encode(resp)?;
         self.inner.send(msg_buf).await
     }
 }
 
-pub struct ClientStreamReceiver<P> {
+pub struct ClientStreamReceiver<P, C = DefaultEncoding> {
     inner: StreamReceiver,
-    _recv: PhantomData<P>,
+    _recv: PhantomData<(P, C)>,
     // Hold the req_tx in Client to keep receiver task running
     _client_guard: Client,
 }
 
-impl<P> ClientStreamReceiver<P>
+impl<P, C> ClientStreamReceiver<P, C>
 where
-    P: Codec,
-    <P as Codec>::E: std::fmt::Display,
+    C: Encoding<P>,
 {
     pub fn new(inner: StreamInner, _client_guard: Client) -> Self {
         Self {
@@ -307,21 +284,18 @@ where
             return Ok(None);
         }
         let msg_buf = res?;
-        P::decode(msg_buf)
-            .map_err(err_to_others_err!(e, "Decode message failed."))
-            .map(Some)
+        <C as Encoding<P>>::decode(msg_buf).map(Some)
     }
 }
 
-pub struct ServerStreamReceiver<Q> {
+pub struct ServerStreamReceiver<Q, C = DefaultEncoding> {
     inner: StreamReceiver,
-    _recv: PhantomData<Q>,
+    _recv: PhantomData<(Q, C)>,
 }
 
-impl<Q> ServerStreamReceiver<Q>
+impl<Q, C> ServerStreamReceiver<Q, C>
 where
-    Q: Codec,
-    <Q as Codec>::E: std::fmt::Display,
+    C: Encoding<Q>,
 {
     pub fn new(inner: StreamInner) -> Self {
         Self {
@@ -336,15 +310,13 @@ where
             return Ok(None);
         }
         let msg_buf = res?;
-        Q::decode(msg_buf)
-            .map_err(err_to_others_err!(e, "Decode message failed."))
-            .map(Some)
+        <C as Encoding<Q>>::decode(msg_buf).map(Some)
     }
 }
 
 async fn _recv(rx: &mut ResultReceiver) -> Result<GenMessage> {
     rx.recv().await.unwrap_or_else(|| {
-        Err(Error::Others(
+        Err(Error::Closed(
             "Receive packet from Receiver error".to_string(),
         ))
     })
@@ -354,10 +326,10 @@ async fn _send(tx: &MessageSender, msg: GenMessage) -> Result<()> {
     let (res_tx, res_rx) = tokio::sync::oneshot::channel();
     tx.send(SendingMessage::new_with_result(msg, res_tx))
         .await
-        .map_err(|e| Error::Others(format!("Send data packet to sender error {:?}", e)))?;
+        .map_err(|e| Error::Closed(format!("Send data packet to sender error {:?}", e)))?;
     res_rx
         .await
-        .map_err(|e| Error::Others(format!("Failed to wait send result {:?}", e)))?
+        .map_err(|e| Error::Closed(format!("Failed to wait send result {:?}", e)))?
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -385,23 +357,43 @@ impl StreamInner {
     ) -> Self {
         Self {
             sender: StreamSender {
-                tx,
+                tx: tx.clone(),
                 stream_id,
                 sendable,
                 local_closed: Arc::new(AtomicBool::new(false)),
                 kind,
+                max_send_message_size: MESSAGE_LENGTH_MAX,
             },
             receiver: StreamReceiver {
                 rx,
-                stream_id,
                 recveivable,
                 remote_closed: false,
                 kind,
-                streams,
+                _guard: StreamGuard::new(stream_id, kind, tx, streams),
             },
         }
     }
 
+    /// Set the maximum length of a data message sent through the stream.
+    pub(crate) fn with_max_send_message_size(mut self, size: usize) -> Self {
+        self.sender.max_send_message_size = size;
+        self
+    }
+
+    /// Cancel the call on the server if the stream is dropped before it's closed.
+    #[cfg(feature = "cancel")]
+    pub(crate) fn with_cancel(mut self, cancel: bool) -> Self {
+        self.receiver._guard.cancel = cancel;
+        self
+    }
+
+    /// Close the file descriptors left for the stream in `fds` once it's dropped.
+    #[cfg(unix)]
+    pub(crate) fn with_fds(mut self, fds: Arc<FdTable>) -> Self {
+        self.receiver._guard = self.receiver._guard.with_fds(fds);
+        self
+    }
+
     fn split(self) -> (StreamSender, StreamReceiver) {
         (self.sender, self.receiver)
     }
@@ -426,21 +418,94 @@ pub struct StreamSender {
     sendable: bool,
     local_closed: Arc<AtomicBool>,
     kind: Kind,
+    max_send_message_size: usize,
 }
 
 #[derive(Debug)]
 pub struct StreamReceiver {
     rx: ResultReceiver,
-    stream_id: u32,
     recveivable: bool,
     remote_closed: bool,
     kind: Kind,
+    _guard: StreamGuard,
+}
+
+/// Removes a stream from `streams` once dropped, whatever the way the call ends, so that
+/// any late response is dropped as one of an unknown stream.
+///
+/// If the stream of a client is still there, the server hasn't finished the call: when
+/// `cancel` is set, which takes the `cancel` feature, it's cancelled with a message flagged
+/// [`FLAG_CANCEL`], so that the server stops handling it.
+#[derive(Debug)]
+pub(crate) struct StreamGuard {
+    stream_id: u32,
+    kind: Kind,
+    cancel: bool,
+    tx: MessageSender,
     streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
+    #[cfg(unix)]
+    fds: Option<Arc<FdTable>>,
+}
+
+impl StreamGuard {
+    pub(crate) fn new(
+        stream_id: u32,
+        kind: Kind,
+        tx: MessageSender,
+        streams: Arc<Mutex<HashMap<u32, ResultSender>>>,
+    ) -> Self {
+        Self {
+            stream_id,
+            kind,
+            cancel: false,
+            tx,
+            streams,
+            #[cfg(unix)]
+            fds: None,
+        }
+    }
+
+    /// Close the file descriptors left for the stream in `fds` once dropped.
+    #[cfg(unix)]
+    pub(crate) fn with_fds(mut self, fds: Arc<FdTable>) -> Self {
+        self.fds = Some(fds);
+        self
+    }
+
+    /// Cancel the call on the server if the guard is dropped before it's finished.
+    #[cfg(feature = "cancel")]
+    pub(crate) fn with_cancel(mut self, cancel: bool) -> Self {
+        self.cancel = cancel;
+        self
+    }
 }
 
-impl Drop for StreamReceiver {
+impl Drop for StreamGuard {
     fn drop(&mut self) {
-        self.streams.lock().unwrap().remove(&self.stream_id);
+        #[cfg(unix)]
+        if let Some(fds) = &self.fds {
+            fds.forget(self.stream_id);
+        }
+
+        let active = self
+            .streams
+            .lock()
+            .map(|mut streams| streams.remove(&self.stream_id).is_some())
+            .unwrap_or(false);
+        if !active || !self.cancel || self.kind != Kind::Client {
+            return;
+        }
+
+        let mut header = MessageHeader::new_data(self.stream_id, 0);
+        header.set_flags(FLAG_REMOTE_CLOSED | FLAG_NO_DATA | FLAG_CANCEL);
+        let msg = GenMessage {
+            header,
+            payload: Vec::new(),
+        };
+        // Best effort, the connection may be closed or too busy to wait for.
+        self.tx
+            .try_send(SendingMessage::new(msg))
+            .unwrap_or_else(|e| debug!("cancel stream {} got error {}", self.stream_id, e));
     }
 }
 
@@ -457,7 +522,7 @@ impl StreamSender {
             payload: buf,
         };
 
-        msg.check()?;
+        msg.check_with_limit(self.max_send_message_size)?;
 
         _send(&self.tx, msg).await?;
 
@@ -494,7 +559,7 @@ impl StreamReceiver {
                 debug_assert_eq!(self.kind, Kind::Client);
                 self.remote_closed = true;
                 let resp = Response::decode(&msg.payload)
-                    .map_err(err_to_others_err!(e, "Decode message failed."))?;
+                    .map_err(err_to_codec_msg!(e, "Decode message failed."))?;
                 if let Some(status) = resp.status.as_ref() {
                     if status.code() != Code::OK {
                         return Err(Error::RpcStatus((*status).clone()));
@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::io::{Error as IoError, Result as IoResult};
+use std::os::unix::net::UnixStream as StdUnixStream;
+
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::net::UnixStream;
+use tokio::time::{sleep, timeout};
+
+use super::unix::parse_unix_addr;
+use super::Socket;
+use crate::common::{
+    check_hvsock_reply, parse_hvsock, HVSOCK_HANDSHAKE_TIMEOUT, HVSOCK_RETRIES,
+    HVSOCK_RETRY_INTERVAL,
+};
+
+impl Socket {
+    /// Connect to a port of the guest behind a hybrid vsock, `addr` being `path:port` with
+    /// `path` the unix socket of the VMM. The attempts are retried while the guest doesn't
+    /// accept, e.g. as it's still booting.
+    pub async fn connect_hvsock(addr: impl AsRef<str>) -> IoResult<Self> {
+        let addr = addr.as_ref();
+        let (path, port) = parse_hvsock(addr).map_err(|e| io_other!("{e}"))?;
+        let mut retries = HVSOCK_RETRIES;
+        loop {
+            let res = timeout(HVSOCK_HANDSHAKE_TIMEOUT, handshake(path, port))
+                .await
+                .unwrap_or_else(|_| Err(io_other!("hvsock handshake timed out")));
+            match res {
+                // The bytes go to the guest, file descriptors would stop at the VMM.
+                Ok(socket) => return Ok(Self::new(socket)),
+                Err(e) if retries > 0 => {
+                    debug!("connect to hvsock {} failed, retry: {:?}", addr, e);
+                    retries -= 1;
+                    sleep(HVSOCK_RETRY_INTERVAL).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+async fn handshake(path: &str, port: u32) -> IoResult<UnixStream> {
+    let socket = StdUnixStream::connect_addr(&parse_unix_addr(path)?)?;
+    socket.set_nonblocking(true)?;
+    let mut socket = UnixStream::from_std(socket)?;
+    socket
+        .write_all(format!("CONNECT {port}\n").as_bytes())
+        .await?;
+
+    // Read byte by byte, what comes after the reply belongs to the connection.
+    let mut reply = Vec::new();
+    while reply.last() != Some(&b'\n') {
+        reply.push(socket.read_u8().await?);
+    }
+    check_hvsock_reply(&reply).map_err(|e| io_other!("{e}"))?;
+    Ok(socket)
+}
+
+#[cfg(target_os = "linux")]
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::error::Result;
+    use crate::proto::{Request, Response};
+    use crate::r#async::{Client, MethodHandler, Server, Service, TtrpcContext};
+
+    struct Pong;
+
+    #[async_trait]
+    impl MethodHandler for Pong {
+        async fn handler(&self, _ctx: TtrpcContext, _req: Request) -> Result<Response> {
+            let mut res = Response::new();
+            res.payload = b"pong".to_vec();
+            Ok(res)
+        }
+    }
+
+    // Stands in for the hybrid vsock of a VMM whose guest listens on `port` from its second
+    // connection on, the guest is the server at `server_addr`.
+    async fn fake_hvsock(listener: tokio::net::UnixListener, port: u32, server_addr: &str) {
+        use tokio::io::{AsyncBufReadExt as _, BufReader};
+
+        for attempt in 0.. {
+            let (host, _) = listener.accept().await.unwrap();
+            let mut host = BufReader::new(host);
+            let mut line = String::new();
+            host.read_line(&mut line).await.unwrap();
+            assert_eq!(line, format!("CONNECT {port}\n"));
+            if attempt == 0 {
+                // Not booted yet.
+                continue;
+            }
+
+            host.write_all(b"OK 1073741824\n").await.unwrap();
+            let mut guest = Socket::connect(server_addr).await.unwrap();
+            tokio::io::copy_bidirectional(&mut host, &mut guest)
+                .await
+                .ok();
+            break;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hvsock() {
+        let addr = r"unix://@/tmp/ttrpc-hvsock-unit-test-guest";
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("Pong".to_string(), Box::new(Pong));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_service(HashMap::from([("test.Pong".to_string(), service)]));
+        server.start().await.unwrap();
+
+        let vmm = std::env::temp_dir().join(format!("ttrpc-hvsock-{}", std::process::id()));
+        let listener = tokio::net::UnixListener::bind(&vmm).unwrap();
+        let vmm_task = tokio::spawn(fake_hvsock(listener, 1024, addr));
+
+        let client = Client::connect(&format!("hvsock://{}:1024", vmm.display()))
+            .await
+            .unwrap();
+        let req = Request {
+            service: "test.Pong".to_string(),
+            method: "Pong".to_string(),
+            ..Default::default()
+        };
+        let res = client.request(req).await.unwrap();
+        assert_eq!(res.payload, b"pong");
+
+        drop(client);
+        vmm_task.await.unwrap();
+        std::fs::remove_file(vmm).unwrap();
+        server.shutdown().await.unwrap();
+    }
+}
@@ -0,0 +1,33 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::convert::TryFrom;
+use std::io::{Error as IoError, Result as IoResult};
+use std::os::unix::net::UnixListener as StdUnixListener;
+
+use nix::sys::socket::AddressFamily;
+
+use super::Listener;
+use crate::activation::InheritedListener;
+
+impl TryFrom<InheritedListener> for Listener {
+    type Error = IoError;
+    fn try_from(listener: InheritedListener) -> IoResult<Self> {
+        let (fd, family) = listener.into_listener().map_err(|e| io_other!("{e}"))?;
+        match family {
+            AddressFamily::Unix => Self::try_from(StdUnixListener::from(fd)),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            AddressFamily::Vsock => {
+                use std::os::fd::{FromRawFd as _, IntoRawFd as _};
+                // Safety: the descriptor is a vsock listener owned by nothing else.
+                let listener = unsafe { tokio_vsock::VsockListener::from_raw_fd(fd.into_raw_fd()) };
+                Ok(Self::from(listener))
+            }
+            #[cfg(feature = "tcp")]
+            AddressFamily::Inet | AddressFamily::Inet6 => {
+                Self::try_from(std::net::TcpListener::from(fd))
+            }
+            family => Err(io_other!("{family:?} listener is not supported")),
+        }
+    }
+}
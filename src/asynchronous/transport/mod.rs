@@ -1,14 +1,24 @@
 use std::io::{Error as IoError, Result as IoResult};
 use std::pin::Pin;
+#[cfg(unix)]
+use std::sync::Arc;
 
 use futures::stream::{BoxStream, Stream, StreamExt as _};
 use tokio::io::{AsyncRead, AsyncWrite};
 
+#[cfg(unix)]
+use crate::fd::FdTable;
+use crate::peer::Peer;
+
 trait AsyncReadWrite: AsyncRead + AsyncWrite {}
 impl<T: AsyncRead + AsyncWrite> AsyncReadWrite for T {}
 
 pub struct Listener(BoxStream<'static, IoResult<Socket>>);
-pub struct Socket(Pin<Box<dyn AsyncReadWrite + Send + Sync + 'static>>);
+pub struct Socket(
+    Pin<Box<dyn AsyncReadWrite + Send + Sync + 'static>>,
+    Option<Peer>,
+    #[cfg(unix)] Arc<FdTable>,
+);
 
 macro_rules! io_other {
     ($fmt_str:literal, $($args:expr),*) => {
@@ -22,12 +32,24 @@ macro_rules! io_other {
 #[cfg(unix)]
 mod unix;
 
+#[cfg(unix)]
+mod hvsock;
+
+#[cfg(unix)]
+mod inherited;
+
 #[cfg(any(target_os = "linux", target_os = "android"))]
 mod vsock;
 
 #[cfg(windows)]
 mod windows;
 
+#[cfg(feature = "tcp")]
+mod tcp;
+
+#[cfg(feature = "tls")]
+pub mod tls;
+
 impl Listener {
     pub fn new<S: AsyncRead + AsyncWrite + Send + Sync + 'static>(
         listener: impl Stream<Item = IoResult<S>> + Send + 'static,
@@ -35,6 +57,11 @@ impl Listener {
         Self(listener.map(|s| s.map(Socket::new)).boxed())
     }
 
+    #[cfg(any(unix, feature = "tcp"))]
+    fn from_sockets(listener: impl Stream<Item = IoResult<Socket>> + Send + 'static) -> Self {
+        Self(listener.boxed())
+    }
+
     pub fn bind(addr: impl AsRef<str>) -> std::io::Result<Self> {
         let addr = addr.as_ref();
 
@@ -53,13 +80,53 @@ impl Listener {
             return Self::bind_named_pipe(addr);
         }
 
+        #[cfg(feature = "tcp")]
+        if let Some(addr) = addr.strip_prefix("tcp://") {
+            return Self::bind_tcp(addr);
+        }
+
+        #[cfg(feature = "tls")]
+        if addr.starts_with("tls://") {
+            return Err(io_other!(
+                "{addr:?} needs a TLS configuration, use bind_tls"
+            ));
+        }
+
         Err(io_other!("Scheme of {addr:?} is not supported"))
     }
 }
 
 impl Socket {
     pub fn new(socket: impl AsyncRead + AsyncWrite + Send + Sync + 'static) -> Self {
-        Self(Box::pin(socket))
+        Self(
+            Box::pin(socket),
+            None,
+            #[cfg(unix)]
+            Arc::default(),
+        )
+    }
+
+    #[cfg(any(unix, feature = "tcp"))]
+    fn with_peer(mut self, peer: Option<Peer>) -> Self {
+        self.1 = peer;
+        self
+    }
+
+    #[cfg(unix)]
+    fn with_fds(mut self, fds: Arc<FdTable>) -> Self {
+        self.2 = fds;
+        self
+    }
+
+    /// The file descriptors passed over the socket.
+    #[cfg(unix)]
+    pub(crate) fn fds(&self) -> Arc<FdTable> {
+        self.2.clone()
+    }
+
+    /// The process at the other end of the socket, if the transport tells it.
+    pub fn peer(&self) -> Option<Peer> {
+        self.1.clone()
     }
 
     pub async fn connect(addr: impl AsRef<str>) -> IoResult<Self> {
@@ -75,11 +142,28 @@ impl Socket {
             return Self::connect_vsock(addr).await;
         }
 
+        #[cfg(unix)]
+        if let Some(addr) = addr.strip_prefix("hvsock://") {
+            return Self::connect_hvsock(addr).await;
+        }
+
         #[cfg(windows)]
         if addr.starts_with(r"\\.\pipe\") {
             return Self::connect_named_pipe(addr).await;
         }
 
+        #[cfg(feature = "tcp")]
+        if let Some(addr) = addr.strip_prefix("tcp://") {
+            return Self::connect_tcp(addr).await;
+        }
+
+        #[cfg(feature = "tls")]
+        if addr.starts_with("tls://") {
+            return Err(io_other!(
+                "{addr:?} needs a TLS configuration, use connect_tls"
+            ));
+        }
+
         Err(io_other!("Scheme of {addr:?} is not supported"))
     }
 }
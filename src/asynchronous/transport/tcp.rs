@@ -0,0 +1,117 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::convert::TryFrom;
+use std::io::{Error as IoError, Result as IoResult};
+use std::net::TcpListener as StdTcpListener;
+
+use async_stream::stream;
+use tokio::net::{TcpListener, TcpStream};
+
+use super::{Listener, Socket};
+use crate::peer::Peer;
+
+impl Listener {
+    /// Listen on `addr` of the form `host:port`.
+    pub fn bind_tcp(addr: impl AsRef<str>) -> IoResult<Self> {
+        let listener = StdTcpListener::bind(addr.as_ref())?;
+        Self::try_from(listener)
+    }
+}
+
+impl Socket {
+    /// Connect to `addr` of the form `host:port`.
+    pub async fn connect_tcp(addr: impl AsRef<str>) -> IoResult<Self> {
+        Self::try_from(TcpStream::connect(addr.as_ref()).await?)
+    }
+}
+
+impl From<TcpListener> for Listener {
+    fn from(listener: TcpListener) -> Self {
+        Self::from_sockets(stream! {
+            loop {
+                yield listener.accept().await.and_then(|(socket, _)| Socket::try_from(socket));
+            }
+        })
+    }
+}
+
+impl TryFrom<StdTcpListener> for Listener {
+    type Error = IoError;
+    fn try_from(listener: StdTcpListener) -> IoResult<Self> {
+        listener.set_nonblocking(true)?;
+        Ok(Self::from(TcpListener::from_std(listener)?))
+    }
+}
+
+impl TryFrom<TcpStream> for Socket {
+    type Error = IoError;
+    fn try_from(socket: TcpStream) -> IoResult<Self> {
+        // Messages are written at once, don't wait to coalesce them.
+        socket.set_nodelay(true)?;
+        let peer = Peer::Tcp {
+            addr: socket.peer_addr()?,
+        };
+        Ok(Self::new(socket).with_peer(Some(peer)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use async_trait::async_trait;
+
+    use crate::error::Result;
+    use crate::peer::Peer;
+    use crate::proto::{Request, Response};
+    use crate::r#async::{Client, MethodHandler, Server, Service, TtrpcContext};
+
+    // Answers with the address of the client.
+    struct PeerAddr;
+
+    #[async_trait]
+    impl MethodHandler for PeerAddr {
+        async fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<Response> {
+            let mut res = Response::new();
+            if let Some(Peer::Tcp { addr }) = ctx.peer {
+                res.payload = addr.to_string().into_bytes();
+            }
+            Ok(res)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tcp() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr = format!("tcp://127.0.0.1:{}", port);
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("PeerAddr".to_string(), Box::new(PeerAddr));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+
+        let mut server = Server::new()
+            .bind(&addr)
+            .unwrap()
+            .register_service(HashMap::from([("test.Tcp".to_string(), service)]));
+        server.start().await.unwrap();
+
+        let client = Client::connect(&addr).await.unwrap();
+        let req = Request {
+            service: "test.Tcp".to_string(),
+            method: "PeerAddr".to_string(),
+            ..Default::default()
+        };
+        let res = client.request(req).await.unwrap();
+        let peer: std::net::SocketAddr = String::from_utf8(res.payload).unwrap().parse().unwrap();
+        assert!(peer.ip().is_loopback());
+
+        server.shutdown().await.unwrap();
+    }
+}
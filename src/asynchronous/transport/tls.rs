@@ -0,0 +1,261 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! TLS over TCP, with [rustls](https://docs.rs/rustls).
+//!
+//! The configurations are plain rustls ones, [`server_config`] and [`client_config`] build
+//! them from PEM files. The certificate chain a client was authenticated with is told by
+//! [`Peer::Tls`] of its calls.
+
+use std::convert::TryFrom;
+use std::io::{BufReader, Error as IoError, ErrorKind, Result as IoResult};
+use std::net::TcpListener as StdTcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_stream::stream;
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerConfig, ServerName};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+use super::{Listener, Socket};
+use crate::peer::Peer;
+
+pub use tokio_rustls::rustls;
+
+// A client not done with the handshake by then is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl Listener {
+    /// Listen on `addr` of the form `host:port`, the accepted connections are secured with
+    /// `config`.
+    pub fn bind_tls(addr: impl AsRef<str>, config: Arc<ServerConfig>) -> IoResult<Self> {
+        let listener = StdTcpListener::bind(addr.as_ref())?;
+        listener.set_nonblocking(true)?;
+        Ok(Self::tls(TcpListener::from_std(listener)?, config))
+    }
+
+    fn tls(listener: TcpListener, config: Arc<ServerConfig>) -> Self {
+        let acceptor = TlsAcceptor::from(config);
+        // The handshakes run in their own tasks, so a slow client doesn't hold the others.
+        let (tx, mut rx) = mpsc::channel(16);
+        Self::from_sockets(stream! {
+            loop {
+                tokio::select! {
+                    res = listener.accept() => match res {
+                        Ok((socket, addr)) => {
+                            let acceptor = acceptor.clone();
+                            let tx = tx.clone();
+                            tokio::spawn(async move {
+                                match tokio::time::timeout(HANDSHAKE_TIMEOUT, accept(acceptor, socket)).await {
+                                    Ok(Ok(socket)) => {
+                                        tx.send(socket).await.ok();
+                                    }
+                                    Ok(Err(e)) => warn!("TLS handshake with {} failed: {}", addr, e),
+                                    Err(_) => warn!("TLS handshake with {} timed out", addr),
+                                }
+                            });
+                        }
+                        Err(e) => yield Err(e),
+                    },
+                    Some(socket) = rx.recv() => yield Ok(socket),
+                }
+            }
+        })
+    }
+}
+
+impl Socket {
+    /// Connect to `addr` of the form `host:port` and secure the connection with `config`,
+    /// the certificate of the server must be valid for `server_name`.
+    pub async fn connect_tls(
+        addr: impl AsRef<str>,
+        config: Arc<ClientConfig>,
+        server_name: &str,
+    ) -> IoResult<Self> {
+        let server_name = ServerName::try_from(server_name)
+            .map_err(|e| IoError::new(ErrorKind::InvalidInput, e))?;
+        let socket = TcpStream::connect(addr.as_ref()).await?;
+        socket.set_nodelay(true)?;
+        let addr = socket.peer_addr()?;
+
+        let socket = TlsConnector::from(config)
+            .connect(server_name, socket)
+            .await?;
+        let certificates = der_chain(socket.get_ref().1.peer_certificates());
+        Ok(Self::new(socket).with_peer(Some(Peer::Tls { addr, certificates })))
+    }
+}
+
+async fn accept(acceptor: TlsAcceptor, socket: TcpStream) -> IoResult<Socket> {
+    socket.set_nodelay(true)?;
+    let addr = socket.peer_addr()?;
+
+    let socket = acceptor.accept(socket).await?;
+    let certificates = der_chain(socket.get_ref().1.peer_certificates());
+    Ok(Socket::new(socket).with_peer(Some(Peer::Tls { addr, certificates })))
+}
+
+fn der_chain(certificates: Option<&[Certificate]>) -> Vec<Vec<u8>> {
+    certificates
+        .unwrap_or_default()
+        .iter()
+        .map(|cert| cert.0.clone())
+        .collect()
+}
+
+/// Build a server configuration from the PEM encoded certificate chain and private key of
+/// the server.
+///
+/// With `client_ca_pem`, clients must authenticate with a certificate issued by one of its
+/// certificates (mutual TLS).
+pub fn server_config(
+    cert_pem: &[u8],
+    key_pem: &[u8],
+    client_ca_pem: Option<&[u8]>,
+) -> IoResult<ServerConfig> {
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match client_ca_pem {
+        Some(ca_pem) => builder.with_client_cert_verifier(
+            AllowAnyAuthenticatedClient::new(root_store(ca_pem)?).boxed(),
+        ),
+        None => builder.with_no_client_auth(),
+    };
+    builder
+        .with_single_cert(certificates(cert_pem)?, private_key(key_pem)?)
+        .map_err(|e| IoError::new(ErrorKind::InvalidInput, e))
+}
+
+/// Build a client configuration trusting the PEM encoded certificates of `ca_pem`.
+///
+/// `identity` is the PEM encoded certificate chain and private key the client authenticates
+/// with, if the server requires it.
+pub fn client_config(ca_pem: &[u8], identity: Option<(&[u8], &[u8])>) -> IoResult<ClientConfig> {
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store(ca_pem)?);
+    match identity {
+        Some((cert_pem, key_pem)) => builder
+            .with_client_auth_cert(certificates(cert_pem)?, private_key(key_pem)?)
+            .map_err(|e| IoError::new(ErrorKind::InvalidInput, e)),
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+fn certificates(pem: &[u8]) -> IoResult<Vec<Certificate>> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(pem))?;
+    if certs.is_empty() {
+        return Err(IoError::new(
+            ErrorKind::InvalidInput,
+            "no certificate found",
+        ));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn private_key(pem: &[u8]) -> IoResult<PrivateKey> {
+    use rustls_pemfile::Item;
+
+    for item in rustls_pemfile::read_all(&mut BufReader::new(pem))? {
+        if let Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) = item {
+            return Ok(PrivateKey(key));
+        }
+    }
+    Err(IoError::new(
+        ErrorKind::InvalidInput,
+        "no private key found",
+    ))
+}
+
+fn root_store(pem: &[u8]) -> IoResult<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in certificates(pem)? {
+        roots
+            .add(&cert)
+            .map_err(|e| IoError::new(ErrorKind::InvalidInput, e))?;
+    }
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::error::Result;
+    use crate::proto::{Request, Response};
+    use crate::r#async::{Client, MethodHandler, Server, Service, TtrpcContext};
+
+    const CA: &[u8] = include_bytes!("../../../tests/certs/ca.pem");
+    const SERVER_CERT: &[u8] = include_bytes!("../../../tests/certs/server.pem");
+    const SERVER_KEY: &[u8] = include_bytes!("../../../tests/certs/server.key");
+    const CLIENT_CERT: &[u8] = include_bytes!("../../../tests/certs/client.pem");
+    const CLIENT_KEY: &[u8] = include_bytes!("../../../tests/certs/client.key");
+
+    // Answers with the end entity certificate of the client.
+    struct PeerCert;
+
+    #[async_trait]
+    impl MethodHandler for PeerCert {
+        async fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<Response> {
+            let mut res = Response::new();
+            if let Some(Peer::Tls { certificates, .. }) = ctx.peer {
+                res.payload = certificates.into_iter().next().unwrap_or_default();
+            }
+            Ok(res)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls() {
+        let port = StdTcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>> = HashMap::new();
+        methods.insert("PeerCert".to_string(), Box::new(PeerCert));
+        let service = Service {
+            methods,
+            streams: HashMap::new(),
+        };
+
+        let config = server_config(SERVER_CERT, SERVER_KEY, Some(CA)).unwrap();
+        let mut server = Server::new()
+            .bind_tls(&format!("tls://127.0.0.1:{}", port), Arc::new(config))
+            .unwrap()
+            .register_service(HashMap::from([("test.Tls".to_string(), service)]));
+        server.start().await.unwrap();
+
+        let addr = format!("tls://localhost:{}", port);
+        let req = Request {
+            service: "test.Tls".to_string(),
+            method: "PeerCert".to_string(),
+            ..Default::default()
+        };
+        let config = client_config(CA, Some((CLIENT_CERT, CLIENT_KEY))).unwrap();
+        let client = Client::connect_tls(&addr, Arc::new(config)).await.unwrap();
+        let res = client.request(req.clone()).await.unwrap();
+        let pem = rustls_pemfile::certs(&mut &CLIENT_CERT[..]).unwrap();
+        assert_eq!(res.payload, pem[0]);
+
+        // The server requires a client certificate.
+        let config = client_config(CA, None).unwrap();
+        let res = async {
+            Client::connect_tls(&addr, Arc::new(config))
+                .await?
+                .request(req)
+                .await
+        };
+        assert!(tokio::time::timeout(Duration::from_secs(3), res)
+            .await
+            .unwrap()
+            .is_err());
+
+        server.shutdown().await.unwrap();
+    }
+}
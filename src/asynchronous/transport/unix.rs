@@ -1,14 +1,20 @@
 use std::convert::TryFrom;
-use std::io::{Error as IoError, Result as IoResult};
-use std::os::fd::{FromRawFd as _, RawFd};
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::os::fd::{AsRawFd as _, FromRawFd as _, RawFd};
 use std::os::unix::net::{
     SocketAddr, UnixListener as StdUnixListener, UnixStream as StdUnixStream,
 };
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
 
 use async_stream::stream;
+use tokio::io::{AsyncRead, AsyncWrite, Interest, ReadBuf};
 use tokio::net::{UnixListener, UnixStream};
 
 use super::{Listener, Socket};
+use crate::fd::FdTable;
+use crate::peer::Peer;
 
 impl Listener {
     pub fn bind_unix(addr: impl AsRef<str>) -> IoResult<Self> {
@@ -42,9 +48,9 @@ impl Socket {
 
 impl From<UnixListener> for Listener {
     fn from(listener: UnixListener) -> Self {
-        Self::new(stream! {
+        Self::from_sockets(stream! {
             loop {
-                yield listener.accept().await.map(|(socket, _)| socket);
+                yield listener.accept().await.map(|(socket, _)| Socket::from(socket));
             }
         })
     }
@@ -60,7 +66,14 @@ impl TryFrom<StdUnixListener> for Listener {
 
 impl From<UnixStream> for Socket {
     fn from(socket: UnixStream) -> Self {
-        Self::new(socket)
+        let peer = Peer::from_fd(socket.as_raw_fd());
+        let fds = Arc::new(FdTable::new(socket.as_raw_fd()));
+        Self::new(FdStream {
+            inner: socket,
+            fds: fds.clone(),
+        })
+        .with_peer(peer)
+        .with_fds(fds)
     }
 }
 
@@ -72,7 +85,67 @@ impl TryFrom<StdUnixStream> for Socket {
     }
 }
 
-fn parse_unix_addr(addr: impl AsRef<str>) -> IoResult<SocketAddr> {
+// A unix stream passing file descriptors along with its bytes.
+struct FdStream {
+    inner: UnixStream,
+    fds: Arc<FdTable>,
+}
+
+impl AsyncRead for FdStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<IoResult<()>> {
+        let this = self.get_mut();
+        loop {
+            ready!(this.inner.poll_read_ready(cx))?;
+            let unfilled = buf.initialize_unfilled();
+            let res = this.inner.try_io(Interest::READABLE, || {
+                this.fds
+                    .recv(this.inner.as_raw_fd(), unfilled)
+                    .map_err(IoError::from)
+            });
+            match res {
+                Ok(size) => {
+                    buf.advance(size);
+                    return Poll::Ready(Ok(()));
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {}
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for FdStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<IoResult<usize>> {
+        let this = self.get_mut();
+        loop {
+            ready!(this.inner.poll_write_ready(cx))?;
+            let res = this.inner.try_io(Interest::WRITABLE, || {
+                this.fds
+                    .send(this.inner.as_raw_fd(), buf)
+                    .map_err(IoError::from)
+            });
+            match res {
+                Ok(size) => return Poll::Ready(Ok(size)),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {}
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+pub(super) fn parse_unix_addr(addr: impl AsRef<str>) -> IoResult<SocketAddr> {
     let addr = addr.as_ref();
 
     #[cfg(any(target_os = "linux", target_os = "android"))]
@@ -1,10 +1,11 @@
 use std::io::{Error as IoError, Result as IoResult};
-use std::os::fd::{FromRawFd as _, RawFd};
+use std::os::fd::{AsRawFd as _, FromRawFd as _, RawFd};
 
 use async_stream::stream;
 use tokio_vsock::{VsockAddr, VsockListener, VsockStream, VMADDR_CID_ANY};
 
 use super::{Listener, Socket};
+use crate::peer::Peer;
 
 impl Listener {
     pub fn bind_vsock(addr: impl AsRef<str>) -> IoResult<Self> {
@@ -29,9 +30,9 @@ impl Socket {
 
 impl From<VsockListener> for Listener {
     fn from(listener: VsockListener) -> Self {
-        Self::new(stream! {
+        Self::from_sockets(stream! {
             loop {
-                yield listener.accept().await.map(|(socket, _)| socket);
+                yield listener.accept().await.map(|(socket, _)| Socket::from(socket));
             }
         })
     }
@@ -39,7 +40,8 @@ impl From<VsockListener> for Listener {
 
 impl From<VsockStream> for Socket {
     fn from(socket: VsockStream) -> Self {
-        Self::new(socket)
+        let peer = Peer::from_fd(socket.as_raw_fd());
+        Self::new(socket).with_peer(peer)
     }
 }
 
@@ -4,11 +4,13 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
-use std::collections::HashMap;
+use std::sync::Arc;
 
 use async_trait::async_trait;
+use futures::future::BoxFuture;
 
 use crate::error::Result;
+use crate::metadata::Metadata;
 use crate::proto::{MessageHeader, Request, Response};
 
 /// Handle request in async mode.
@@ -19,7 +21,7 @@ macro_rules! async_request_handler {
         {
             let mut s = CodedInputStream::from_bytes(&$req.payload);
             req.merge_from(&mut s)
-                .map_err(::ttrpc::err_to_others!(e, ""))?;
+                .map_err(::ttrpc::err_to_codec!(e, ""))?;
         }
 
         let mut res = ::ttrpc::Response::new();
@@ -29,18 +31,15 @@ macro_rules! async_request_handler {
                 res.payload.reserve(rep.compute_size() as usize);
                 let mut s = protobuf::CodedOutputStream::vec(&mut res.payload);
                 rep.write_to(&mut s)
-                    .map_err(::ttrpc::err_to_others!(e, ""))?;
-                s.flush().map_err(::ttrpc::err_to_others!(e, ""))?;
+                    .map_err(::ttrpc::err_to_codec!(e, ""))?;
+                s.flush().map_err(::ttrpc::err_to_codec!(e, ""))?;
             }
             Err(x) => match x {
                 ::ttrpc::Error::RpcStatus(s) => {
                     res.set_status(s);
                 }
                 _ => {
-                    res.set_status(::ttrpc::get_status(
-                        ::ttrpc::Code::UNKNOWN,
-                        format!("{:?}", x),
-                    ));
+                    res.set_status(::ttrpc::get_status(x.code(), format!("{:?}", x)));
                 }
             },
         }
@@ -61,18 +60,15 @@ macro_rules! async_client_streamimg_handler {
                 res.payload.reserve(rep.compute_size() as usize);
                 let mut s = protobuf::CodedOutputStream::vec(&mut res.payload);
                 rep.write_to(&mut s)
-                    .map_err(::ttrpc::err_to_others!(e, ""))?;
-                s.flush().map_err(::ttrpc::err_to_others!(e, ""))?;
+                    .map_err(::ttrpc::err_to_codec!(e, ""))?;
+                s.flush().map_err(::ttrpc::err_to_codec!(e, ""))?;
             }
             Err(x) => match x {
                 ::ttrpc::Error::RpcStatus(s) => {
                     res.set_status(s);
                 }
                 _ => {
-                    res.set_status(::ttrpc::get_status(
-                        ::ttrpc::Code::UNKNOWN,
-                        format!("{:?}", x),
-                    ));
+                    res.set_status(::ttrpc::get_status(x.code(), format!("{:?}", x)));
                 }
             },
         }
@@ -86,7 +82,7 @@ macro_rules! async_server_streamimg_handler {
     ($class: ident, $ctx: ident, $inner: ident, $server: ident, $req_type: ident, $req_fn: ident) => {
         let req_buf = $inner.recv().await?;
         let req = <super::$server::$req_type as ::ttrpc::proto::Codec>::decode(&req_buf)
-            .map_err(|e| ::ttrpc::Error::Others(e.to_string()))?;
+            .map_err(::ttrpc::err_to_codec!(e, ""))?;
         let stream = ::ttrpc::r#async::ServerStreamSender::new($inner);
         match $class.service.$req_fn(&$ctx, req, stream).await {
             Ok(_) => {
@@ -99,10 +95,7 @@ macro_rules! async_server_streamimg_handler {
                         res.set_status(s);
                     }
                     _ => {
-                        res.set_status(::ttrpc::get_status(
-                            ::ttrpc::Code::UNKNOWN,
-                            format!("{:?}", x),
-                        ));
+                        res.set_status(::ttrpc::get_status(x.code(), format!("{:?}", x)));
                     }
                 }
                 return Ok(Some(res));
@@ -127,10 +120,7 @@ macro_rules! async_duplex_streamimg_handler {
                         res.set_status(s);
                     }
                     _ => {
-                        res.set_status(::ttrpc::get_status(
-                            ::ttrpc::Code::UNKNOWN,
-                            format!("{:?}", x),
-                        ));
+                        res.set_status(::ttrpc::get_status(x.code(), format!("{:?}", x)));
                     }
                 }
                 return Ok(Some(res));
@@ -146,8 +136,8 @@ macro_rules! async_client_request {
         let mut creq = ttrpc::Request {
             service: $server.to_string(),
             method: $method.to_string(),
-            timeout_nano: $ctx.timeout_nano,
-            metadata: ttrpc::context::to_pb($ctx.metadata),
+            timeout_nano: $ctx.request_timeout_nano()?,
+            metadata: $ctx.metadata.to_pb(),
             payload: Vec::with_capacity($req.compute_size() as usize),
             ..Default::default()
         };
@@ -155,15 +145,15 @@ macro_rules! async_client_request {
         {
             let mut s = CodedOutputStream::vec(&mut creq.payload);
             $req.write_to(&mut s)
-                .map_err(::ttrpc::err_to_others!(e, ""))?;
-            s.flush().map_err(::ttrpc::err_to_others!(e, ""))?;
+                .map_err(::ttrpc::err_to_codec!(e, ""))?;
+            s.flush().map_err(::ttrpc::err_to_codec!(e, ""))?;
         }
 
         let res = $self.client.request(creq).await?;
         let mut s = CodedInputStream::from_bytes(&res.payload);
         $cres
             .merge_from(&mut s)
-            .map_err(::ttrpc::err_to_others!(e, "Unpack get error "))?;
+            .map_err(::ttrpc::err_to_codec!(e, "Unpack get error "))?;
 
         return Ok($cres);
     };
@@ -176,8 +166,8 @@ macro_rules! async_client_stream {
         let mut creq = ::ttrpc::Request::new();
         creq.set_service($server.to_string());
         creq.set_method($method.to_string());
-        creq.set_timeout_nano($ctx.timeout_nano);
-        let md = ::ttrpc::context::to_pb($ctx.metadata);
+        creq.set_timeout_nano($ctx.request_timeout_nano()?);
+        let md = $ctx.metadata.to_pb();
         creq.set_metadata(md);
 
         let inner = $self.client.new_stream(creq, true, true).await?;
@@ -194,8 +184,8 @@ macro_rules! async_client_stream_send {
         let mut creq = ::ttrpc::Request::new();
         creq.set_service($server.to_string());
         creq.set_method($method.to_string());
-        creq.set_timeout_nano($ctx.timeout_nano);
-        let md = ::ttrpc::context::to_pb($ctx.metadata);
+        creq.set_timeout_nano($ctx.request_timeout_nano()?);
+        let md = $ctx.metadata.to_pb();
         creq.set_metadata(md);
 
         let inner = $self.client.new_stream(creq, true, false).await?;
@@ -212,15 +202,15 @@ macro_rules! async_client_stream_receive {
         let mut creq = ::ttrpc::Request::new();
         creq.set_service($server.to_string());
         creq.set_method($method.to_string());
-        creq.set_timeout_nano($ctx.timeout_nano);
-        let md = ::ttrpc::context::to_pb($ctx.metadata);
+        creq.set_timeout_nano($ctx.request_timeout_nano()?);
+        let md = $ctx.metadata.to_pb();
         creq.set_metadata(md);
         creq.payload.reserve($req.compute_size() as usize);
         {
             let mut s = CodedOutputStream::vec(&mut creq.payload);
             $req.write_to(&mut s)
-                .map_err(::ttrpc::err_to_others!(e, ""))?;
-            s.flush().map_err(::ttrpc::err_to_others!(e, ""))?;
+                .map_err(::ttrpc::err_to_codec!(e, ""))?;
+            s.flush().map_err(::ttrpc::err_to_codec!(e, ""))?;
         }
 
         let inner = $self.client.new_stream(creq, false, true).await?;
@@ -230,6 +220,131 @@ macro_rules! async_client_stream_receive {
     };
 }
 
+/// Handle request carrying a prost message in async mode.
+#[cfg(feature = "prost")]
+#[macro_export]
+macro_rules! async_prost_request_handler {
+    ($class: ident, $ctx: ident, $req: ident, $req_type: ty, $req_fn: ident) => {
+        let req = <$req_type as ::prost::Message>::decode(&$req.payload[..])
+            .map_err(::ttrpc::err_to_codec!(e, ""))?;
+
+        let mut res = ::ttrpc::Response::new();
+        match $class.service.$req_fn(&$ctx, req).await {
+            Ok(rep) => {
+                res.set_status(::ttrpc::get_status(::ttrpc::Code::OK, "".to_string()));
+                res.payload = ::prost::Message::encode_to_vec(&rep);
+            }
+            Err(x) => match x {
+                ::ttrpc::Error::RpcStatus(s) => {
+                    res.set_status(s);
+                }
+                _ => {
+                    res.set_status(::ttrpc::get_status(x.code(), format!("{:?}", x)));
+                }
+            },
+        }
+
+        return Ok(res);
+    };
+}
+
+/// Handle client streaming of prost messages in async mode.
+#[cfg(feature = "prost")]
+#[macro_export]
+macro_rules! async_prost_client_streaming_handler {
+    ($class: ident, $ctx: ident, $inner: ident, $req_fn: ident) => {
+        let stream = ::ttrpc::r#async::ServerStreamReceiver::new($inner);
+        let mut res = ::ttrpc::Response::new();
+        match $class.service.$req_fn(&$ctx, stream).await {
+            Ok(rep) => {
+                res.set_status(::ttrpc::get_status(::ttrpc::Code::OK, "".to_string()));
+                res.payload = ::prost::Message::encode_to_vec(&rep);
+            }
+            Err(x) => match x {
+                ::ttrpc::Error::RpcStatus(s) => {
+                    res.set_status(s);
+                }
+                _ => {
+                    res.set_status(::ttrpc::get_status(x.code(), format!("{:?}", x)));
+                }
+            },
+        }
+        return Ok(Some(res));
+    };
+}
+
+/// Handle server streaming of prost messages in async mode.
+#[cfg(feature = "prost")]
+#[macro_export]
+macro_rules! async_prost_server_streaming_handler {
+    ($class: ident, $ctx: ident, $inner: ident, $req_type: ty, $req_fn: ident) => {
+        let req_buf = $inner.recv().await?;
+        let req = <$req_type as ::prost::Message>::decode(&req_buf[..])
+            .map_err(::ttrpc::err_to_codec!(e, ""))?;
+        let stream = ::ttrpc::r#async::ServerStreamSender::new($inner);
+        match $class.service.$req_fn(&$ctx, req, stream).await {
+            Ok(_) => {
+                return Ok(None);
+            }
+            Err(x) => {
+                let mut res = ::ttrpc::Response::new();
+                match x {
+                    ::ttrpc::Error::RpcStatus(s) => {
+                        res.set_status(s);
+                    }
+                    _ => {
+                        res.set_status(::ttrpc::get_status(x.code(), format!("{:?}", x)));
+                    }
+                }
+                return Ok(Some(res));
+            }
+        }
+    };
+}
+
+/// Send request carrying a prost message through async client.
+#[cfg(feature = "prost")]
+#[macro_export]
+macro_rules! async_prost_client_request {
+    ($self: ident, $ctx: ident, $req: ident, $server: expr, $method: expr, $cres_type: ty) => {
+        let creq = ::ttrpc::Request {
+            service: $server.to_string(),
+            method: $method.to_string(),
+            timeout_nano: $ctx.request_timeout_nano()?,
+            metadata: $ctx.metadata.to_pb(),
+            payload: ::prost::Message::encode_to_vec($req),
+            ..Default::default()
+        };
+
+        let res = $self.client.request(creq).await?;
+        let cres = <$cres_type as ::prost::Message>::decode(&res.payload[..])
+            .map_err(::ttrpc::err_to_codec!(e, "Unpack get error "))?;
+
+        return Ok(cres);
+    };
+}
+
+/// Only receive streaming of prost messages through async client.
+#[cfg(feature = "prost")]
+#[macro_export]
+macro_rules! async_prost_client_stream_receive {
+    ($self: ident, $ctx: ident, $req: ident, $server: expr, $method: expr) => {
+        let creq = ::ttrpc::Request {
+            service: $server.to_string(),
+            method: $method.to_string(),
+            timeout_nano: $ctx.request_timeout_nano()?,
+            metadata: $ctx.metadata.to_pb(),
+            payload: ::prost::Message::encode_to_vec($req),
+            ..Default::default()
+        };
+
+        let inner = $self.client.new_stream(creq, false, true).await?;
+        let stream = ::ttrpc::r#async::ClientStreamReceiver::new(inner, $self.client.clone());
+
+        return Ok(stream);
+    };
+}
+
 /// Trait that implements handler which is a proxy to the desired method (async).
 #[async_trait]
 pub trait MethodHandler {
@@ -246,12 +361,154 @@ pub trait StreamHandler {
     ) -> Result<Option<Response>>;
 }
 
+/// Trait that implements an interceptor wrapping the method and stream handlers (async).
+///
+/// `req` is the raw request of the call, naming its service and method. Calling
+/// [`Next::run`] runs the rest of the chain and the handler; an interceptor may change
+/// `ctx` and `req` before, and inspect the result after. Returning `Error::RpcStatus`
+/// without running `next` rejects the call with that status.
+///
+/// The result of a stream handler is `None` when the stream ends without a response.
+#[async_trait]
+pub trait Interceptor {
+    async fn intercept(
+        &self,
+        ctx: TtrpcContext,
+        req: Request,
+        next: Next<'_>,
+    ) -> Result<Option<Response>>;
+}
+
+type Handler<'a> =
+    Box<dyn FnOnce(TtrpcContext, Request) -> BoxFuture<'a, Result<Option<Response>>> + Send + 'a>;
+
+/// The rest of an interceptor chain, ending with the handler of the call (async).
+pub struct Next<'a> {
+    interceptors: &'a [Arc<dyn Interceptor + Send + Sync>],
+    handler: Handler<'a>,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(
+        interceptors: &'a [Arc<dyn Interceptor + Send + Sync>],
+        handler: Handler<'a>,
+    ) -> Self {
+        Next {
+            interceptors,
+            handler,
+        }
+    }
+
+    /// Run the next interceptor, or the handler if there is none left.
+    pub async fn run(self, ctx: TtrpcContext, req: Request) -> Result<Option<Response>> {
+        match self.interceptors.split_first() {
+            Some((interceptor, rest)) => {
+                interceptor
+                    .intercept(ctx, req, Next::new(rest, self.handler))
+                    .await
+            }
+            None => (self.handler)(ctx, req).await,
+        }
+    }
+}
+
+/// Trait that implements an interceptor wrapping the calls of a client (async).
+///
+/// `intercept` runs around [`Client::request`](crate::asynchronous::Client::request) and
+/// `intercept_stream` around [`Client::new_stream`](crate::asynchronous::Client::new_stream);
+/// both pass the call on unchanged by default. An interceptor may change `req`, e.g. its
+/// metadata or timeout, before calling [`ClientNext::run`], and inspect the result after.
+#[async_trait]
+pub trait ClientInterceptor {
+    async fn intercept(&self, req: Request, next: ClientNext<'_, Response>) -> Result<Response> {
+        next.run(req).await
+    }
+
+    async fn intercept_stream(
+        &self,
+        req: Request,
+        next: ClientNext<'_, crate::r#async::StreamInner>,
+    ) -> Result<crate::r#async::StreamInner> {
+        next.run(req).await
+    }
+}
+
+type ClientHandler<'a, T> = Box<dyn FnOnce(Request) -> BoxFuture<'a, Result<T>> + Send + 'a>;
+
+/// The rest of a client interceptor chain, ending with sending the call (async).
+pub struct ClientNext<'a, T> {
+    interceptors: &'a [Arc<dyn ClientInterceptor + Send + Sync>],
+    handler: ClientHandler<'a, T>,
+}
+
+impl<'a, T> ClientNext<'a, T> {
+    pub(crate) fn new(
+        interceptors: &'a [Arc<dyn ClientInterceptor + Send + Sync>],
+        handler: ClientHandler<'a, T>,
+    ) -> Self {
+        ClientNext {
+            interceptors,
+            handler,
+        }
+    }
+}
+
+impl<'a> ClientNext<'a, Response> {
+    /// Run the next interceptor, or send the request if there is none left.
+    pub async fn run(self, req: Request) -> Result<Response> {
+        match self.interceptors.split_first() {
+            Some((interceptor, rest)) => {
+                interceptor
+                    .intercept(req, ClientNext::new(rest, self.handler))
+                    .await
+            }
+            None => (self.handler)(req).await,
+        }
+    }
+}
+
+impl<'a> ClientNext<'a, crate::r#async::StreamInner> {
+    /// Run the next interceptor, or open the stream if there is none left.
+    pub async fn run(self, req: Request) -> Result<crate::r#async::StreamInner> {
+        match self.interceptors.split_first() {
+            Some((interceptor, rest)) => {
+                interceptor
+                    .intercept_stream(req, ClientNext::new(rest, self.handler))
+                    .await
+            }
+            None => (self.handler)(req).await,
+        }
+    }
+}
+
 /// The context of ttrpc (async).
 #[derive(Debug)]
 pub struct TtrpcContext {
     pub mh: MessageHeader,
-    pub metadata: HashMap<String, Vec<String>>,
+    pub metadata: Metadata,
     pub timeout_nano: i64,
+    /// When the call times out, from the timeout sent by the client.
+    pub deadline: Option<std::time::Instant>,
+    /// The process at the other end of the connection, if the transport tells it.
+    pub peer: Option<crate::peer::Peer>,
+    /// Cancelled when the connection closes, the deadline of the call passes or the server
+    /// shuts down, and once the call is done.
+    pub cancel_token: crate::r#async::CancellationToken,
+    /// The file descriptors passed with the call.
+    #[cfg(unix)]
+    pub fds: crate::fd::CallFds,
+}
+
+impl TtrpcContext {
+    /// The context of the calls made while handling this one, with its metadata and the
+    /// time left before its deadline.
+    pub fn child_context(&self) -> crate::context::Context {
+        crate::context::Context {
+            metadata: self.metadata.clone(),
+            deadline: self.deadline,
+            ..Default::default()
+        }
+    }
 }
 
 pub(crate) fn get_path(service: &str, method: &str) -> String {
@@ -0,0 +1,309 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Options of the socket files of unix listeners.
+
+use std::fs::{self, DirBuilder, Permissions};
+use std::io::ErrorKind;
+use std::os::unix::fs::{
+    DirBuilderExt as _, FileTypeExt as _, MetadataExt as _, PermissionsExt as _,
+};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use nix::unistd::{chown, Gid, Uid};
+
+use crate::error::{Error, Result};
+
+/// How the socket file of a unix listener is set up and cleaned up.
+///
+/// Abstract sockets have no file, the options don't apply to them.
+#[derive(Debug, Clone, Default)]
+pub struct UnixBindOptions {
+    mode: Option<u32>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    create_dirs: bool,
+    remove_stale: bool,
+    remove_on_shutdown: bool,
+}
+
+impl UnixBindOptions {
+    pub fn new() -> UnixBindOptions {
+        UnixBindOptions::default()
+    }
+
+    /// Set the permissions of the socket file, e.g. `0o660`.
+    ///
+    /// The socket is bound in a private directory next to it and only appears at its path
+    /// with its permissions and owner set, the path must leave room for that directory
+    /// within the length limit of unix socket paths.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Set the owner and the group of the socket file, `None` keeps the one of the process.
+    pub fn owner(mut self, uid: Option<u32>, gid: Option<u32>) -> Self {
+        self.uid = uid;
+        self.gid = gid;
+        self
+    }
+
+    /// Create the missing parent directories of the socket file.
+    pub fn create_dirs(mut self, create: bool) -> Self {
+        self.create_dirs = create;
+        self
+    }
+
+    /// Remove a socket file left over by a server which is gone, e.g. after a crash.
+    ///
+    /// The file is only removed if nobody accepts connections on it.
+    pub fn remove_stale(mut self, remove: bool) -> Self {
+        self.remove_stale = remove;
+        self
+    }
+
+    /// Remove the socket file when the server shuts down.
+    pub fn remove_on_shutdown(mut self, remove: bool) -> Self {
+        self.remove_on_shutdown = remove;
+        self
+    }
+
+    // Set up the socket file `path` around `bind`, which binds the path it is given.
+    pub(crate) fn bind<T>(
+        &self,
+        path: &str,
+        bind: impl FnOnce(&str) -> Result<T>,
+    ) -> Result<(T, Option<SocketFile>)> {
+        if path.starts_with('@') {
+            return Ok((bind(path)?, None));
+        }
+
+        let path = Path::new(path);
+        if self.create_dirs {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(err_to_others_err!(
+                    e,
+                    format!("failed to create {}: ", parent.display())
+                ))?;
+            }
+        }
+        if self.remove_stale {
+            remove_stale(path)?;
+        }
+
+        let bound = if self.mode.is_some() || self.uid.is_some() || self.gid.is_some() {
+            self.bind_private(path, bind)?
+        } else {
+            bind(&path.to_string_lossy())?
+        };
+        if !self.remove_on_shutdown {
+            return Ok((bound, None));
+        }
+        let meta = fs::metadata(path).map_err(|e| {
+            fs::remove_file(path).ok();
+            Error::Others(format!("{}: {}", path.display(), e))
+        })?;
+        let file = SocketFile {
+            path: path.to_path_buf(),
+            dev: meta.dev(),
+            ino: meta.ino(),
+        };
+        Ok((bound, Some(file)))
+    }
+
+    // Binds in a directory only we can enter and links the socket file to `path` once its
+    // permissions and owner are set, so that nobody can connect before. Like bind, it fails
+    // if `path` exists.
+    fn bind_private<T>(&self, path: &Path, bind: impl FnOnce(&str) -> Result<T>) -> Result<T> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        let parent = match path.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        };
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let dir = parent.join(format!(".ttrpc-{}-{}", std::process::id(), id));
+        DirBuilder::new()
+            .mode(0o700)
+            .create(&dir)
+            .map_err(err_to_others_err!(
+                e,
+                format!("failed to create {}: ", dir.display())
+            ))?;
+
+        let private = dir.join("s");
+        let res = bind(&private.to_string_lossy()).and_then(|bound| {
+            self.set_up(&private)?;
+            fs::hard_link(&private, path).map_err(err_to_others_err!(
+                e,
+                format!("failed to link {}: ", path.display())
+            ))?;
+            Ok(bound)
+        });
+        fs::remove_file(&private).ok();
+        if let Err(e) = fs::remove_dir(&dir) {
+            warn!("failed to remove {}: {}", dir.display(), e);
+        }
+        res
+    }
+
+    fn set_up(&self, path: &Path) -> Result<()> {
+        if let Some(mode) = self.mode {
+            fs::set_permissions(path, Permissions::from_mode(mode)).map_err(err_to_others_err!(
+                e,
+                format!("failed to chmod {}: ", path.display())
+            ))?;
+        }
+        if self.uid.is_some() || self.gid.is_some() {
+            chown(
+                path,
+                self.uid.map(Uid::from_raw),
+                self.gid.map(Gid::from_raw),
+            )
+            .map_err(err_to_others_err!(
+                e,
+                format!("failed to chown {}: ", path.display())
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+fn remove_stale(path: &Path) -> Result<()> {
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(Error::Others(format!("{}: {}", path.display(), e))),
+    };
+    if !meta.file_type().is_socket() {
+        return Err(Error::Others(format!(
+            "{} exists and is not a socket",
+            path.display()
+        )));
+    }
+
+    match UnixStream::connect(path) {
+        Ok(_) => Err(Error::Others(format!(
+            "{} is in use by another server",
+            path.display()
+        ))),
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+            debug!("remove stale socket {}", path.display());
+            fs::remove_file(path).map_err(err_to_others_err!(
+                e,
+                format!("failed to remove {}: ", path.display())
+            ))
+        }
+        // Let bind tell what's wrong.
+        Err(_) => Ok(()),
+    }
+}
+
+// A socket file removed when its server shuts down.
+#[derive(Debug)]
+pub(crate) struct SocketFile {
+    path: PathBuf,
+    dev: u64,
+    ino: u64,
+}
+
+impl SocketFile {
+    pub(crate) fn remove(&self) {
+        // Keep it if another server has bound the path since.
+        match fs::symlink_metadata(&self.path) {
+            Ok(meta) if meta.dev() == self.dev && meta.ino() == self.ino => {
+                if let Err(e) = fs::remove_file(&self.path) {
+                    warn!("failed to remove {}: {}", self.path.display(), e);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixListener;
+
+    use super::*;
+
+    fn bind_std(path: &str) -> Result<UnixListener> {
+        UnixListener::bind(path).map_err(err_to_others_err!(e, ""))
+    }
+
+    #[test]
+    fn test_bind_private() {
+        let dir = std::env::temp_dir().join(format!("ttrpc-bind-private-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ttrpc.sock");
+        let path = path.to_str().unwrap();
+        let options = UnixBindOptions::new().mode(0o600).remove_on_shutdown(true);
+
+        let (listener, file) = options.bind(path, bind_std).unwrap();
+        let meta = fs::metadata(path).unwrap();
+        assert!(meta.file_type().is_socket());
+        assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+        UnixStream::connect(path).unwrap();
+
+        // The path is in use, and the private directory is gone either way.
+        assert!(options.bind(path, bind_std).is_err());
+        let entries: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        assert_eq!(entries, [Path::new(path)]);
+
+        drop(listener);
+        file.unwrap().remove();
+        fs::remove_dir(dir).unwrap();
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_bind_unix_options_async() {
+        use crate::proto::{Code, Request};
+        use crate::r#async::{Client, Server};
+
+        let dir = std::env::temp_dir().join(format!("ttrpc-bind-{}", std::process::id()));
+        let path = dir.join("run").join("ttrpc.sock");
+        let addr = format!("unix://{}", path.display());
+        let options = || {
+            UnixBindOptions::new()
+                .create_dirs(true)
+                .remove_stale(true)
+                .remove_on_shutdown(true)
+                .mode(0o600)
+                .owner(None, Some(nix::unistd::getgid().as_raw()))
+        };
+
+        // Left over by a crashed server.
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(Server::new().bind(&addr).is_err());
+
+        let mut server = Server::new().bind_unix(&addr, options()).unwrap();
+        server.start().await.unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // It's not stale while a server listens on it.
+        assert!(Server::new().bind_unix(&addr, options()).is_err());
+        let client = Client::connect(&addr).await.unwrap();
+        let req = Request {
+            service: "test.Bind".to_string(),
+            method: "Unknown".to_string(),
+            ..Default::default()
+        };
+        match client.request(req).await {
+            Err(Error::RpcStatus(s)) => assert_eq!(s.code(), Code::UNIMPLEMENTED),
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        server.shutdown().await.unwrap();
+        assert!(!path.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -8,7 +8,12 @@
 
 use nix::fcntl::{fcntl, FcntlArg, OFlag};
 use nix::sys::socket::*;
-use std::os::unix::io::RawFd;
+use std::io::{Read as _, Write as _};
+#[cfg(feature = "tcp")]
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::os::unix::io::{FromRawFd as _, IntoRawFd as _, RawFd};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
 
 use crate::error::{Error, Result};
 
@@ -17,8 +22,19 @@ pub(crate) enum Domain {
     Unix,
     #[cfg(any(target_os = "linux", target_os = "android"))]
     Vsock,
+    #[cfg(feature = "tcp")]
+    Tcp,
+    // The unix socket of a hybrid vsock, as exposed by Firecracker and Cloud Hypervisor.
+    Hvsock,
 }
 
+/// The time the host side of a hybrid vsock is given to answer a `CONNECT`.
+pub(crate) const HVSOCK_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The delay between the connection attempts to a hybrid vsock, e.g. while its guest boots.
+pub(crate) const HVSOCK_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+/// The attempts made to connect to a hybrid vsock after the first one failed.
+pub(crate) const HVSOCK_RETRIES: u32 = 50;
+
 pub(crate) fn do_listen(listener: RawFd) -> Result<()> {
     if let Err(e) = fcntl(listener, FcntlArg::F_SETFL(OFlag::O_NONBLOCK)) {
         return Err(Error::Others(format!(
@@ -39,6 +55,15 @@ fn parse_sockaddr(addr: &str) -> Result<(Domain, &str)> {
         return Ok((Domain::Vsock, addr));
     }
 
+    #[cfg(feature = "tcp")]
+    if let Some(addr) = addr.strip_prefix("tcp://") {
+        return Ok((Domain::Tcp, addr));
+    }
+
+    if let Some(addr) = addr.strip_prefix("hvsock://") {
+        return Ok((Domain::Hvsock, addr));
+    }
+
     Err(Error::Others(format!("Scheme {addr:?} is not supported")))
 }
 
@@ -53,6 +78,15 @@ fn parse_sockaddr(addr: &str) -> Result<(Domain, &str)> {
         return Ok((Domain::Unix, addr));
     }
 
+    #[cfg(feature = "tcp")]
+    if let Some(addr) = addr.strip_prefix("tcp://") {
+        return Ok((Domain::Tcp, addr));
+    }
+
+    if let Some(addr) = addr.strip_prefix("hvsock://") {
+        return Ok((Domain::Hvsock, addr));
+    }
+
     Err(Error::Others(format!("Scheme {addr:?} is not supported")))
 }
 
@@ -86,6 +120,13 @@ fn make_addr(domain: Domain, sockaddr: &str) -> Result<UnixAddr> {
         Domain::Vsock => Err(Error::Others(
             "function make_addr does not support create vsock socket".to_string(),
         )),
+        #[cfg(feature = "tcp")]
+        Domain::Tcp => Err(Error::Others(
+            "function make_addr does not support create tcp socket".to_string(),
+        )),
+        Domain::Hvsock => Err(Error::Others(
+            "function make_addr does not support create hvsock socket".to_string(),
+        )),
     }
 }
 
@@ -127,6 +168,41 @@ fn parse_vscok(addr: &str) -> Result<(u32, u32)> {
     Ok((cid, port))
 }
 
+// addr: host:port
+#[cfg(feature = "tcp")]
+pub(crate) fn parse_tcp(addr: &str) -> Result<SocketAddr> {
+    addr.to_socket_addrs()
+        .map_err(err_to_others_err!(
+            e,
+            format!("failed to resolve {addr:?}: ")
+        ))?
+        .next()
+        .ok_or_else(|| Error::Others(format!("{addr:?} is not resolved to any address")))
+}
+
+// addr: path:port
+// return (path, port)
+pub(crate) fn parse_hvsock(addr: &str) -> Result<(&str, u32)> {
+    let (path, port) = addr
+        .rsplit_once(':')
+        .ok_or_else(|| Error::Others(format!("sockaddr {addr} is not right for hvsock")))?;
+    let port = port
+        .parse()
+        .map_err(|e| Error::Others(format!("failed to parse port from {port:?} error: {e:?}")))?;
+    Ok((path, port))
+}
+
+// The host side answers `CONNECT <port>` with `OK <host port>` once the guest accepted.
+pub(crate) fn check_hvsock_reply(reply: &[u8]) -> Result<()> {
+    if reply.starts_with(b"OK ") {
+        return Ok(());
+    }
+    Err(Error::Others(format!(
+        "hvsock handshake is refused: {:?}",
+        String::from_utf8_lossy(reply).trim_end()
+    )))
+}
+
 fn make_socket(sockaddr: &str) -> Result<(RawFd, Domain, Box<dyn SockaddrLike>)> {
     let (domain, sockaddrv) = parse_sockaddr(sockaddr)?;
 
@@ -157,6 +233,30 @@ fn make_socket(sockaddr: &str) -> Result<(RawFd, Domain, Box<dyn SockaddrLike>)>
             let sockaddr = VsockAddr::new(cid, port);
             (fd, Box::new(sockaddr))
         }
+        #[cfg(feature = "tcp")]
+        Domain::Tcp => {
+            let addr = parse_tcp(sockaddrv)?;
+            let family = match addr {
+                SocketAddr::V4(_) => AddressFamily::Inet,
+                SocketAddr::V6(_) => AddressFamily::Inet6,
+            };
+            let fd = socket(family, SockType::Stream, SOCK_CLOEXEC, None)
+                .map_err(|e| Error::Socket(e.to_string()))?;
+            #[cfg(target_os = "macos")]
+            set_fd_close_exec(fd)?;
+            // Accepted sockets inherit it from the listener.
+            setsockopt(fd, sockopt::TcpNoDelay, &true).map_err(err_to_others_err!(e, ""))?;
+            let sockaddr: Box<dyn SockaddrLike> = match addr {
+                SocketAddr::V4(addr) => Box::new(SockaddrIn::from(addr)),
+                SocketAddr::V6(addr) => Box::new(SockaddrIn6::from(addr)),
+            };
+            (fd, sockaddr)
+        }
+        Domain::Hvsock => {
+            return Err(Error::Others(format!(
+                "{sockaddr:?} can only be connected to"
+            )))
+        }
     };
 
     Ok((fd, domain, sockaddr))
@@ -165,13 +265,21 @@ fn make_socket(sockaddr: &str) -> Result<(RawFd, Domain, Box<dyn SockaddrLike>)>
 pub(crate) fn do_bind(sockaddr: &str) -> Result<(RawFd, Domain)> {
     let (fd, domain, sockaddr) = make_socket(sockaddr)?;
 
+    #[cfg(feature = "tcp")]
+    if domain == Domain::Tcp {
+        setsockopt(fd, sockopt::ReuseAddr, &true).map_err(err_to_others_err!(e, ""))?;
+    }
     bind(fd, sockaddr.as_ref()).map_err(err_to_others_err!(e, ""))?;
 
     Ok((fd, domain))
 }
 
-/// Creates a unix socket for client.
+/// Creates a socket for client.
 pub(crate) unsafe fn client_connect(sockaddr: &str) -> Result<RawFd> {
+    if let (Domain::Hvsock, addr) = parse_sockaddr(sockaddr)? {
+        return hvsock_connect(addr);
+    }
+
     let (fd, _, sockaddr) = make_socket(sockaddr)?;
 
     connect(fd, sockaddr.as_ref())?;
@@ -179,6 +287,51 @@ pub(crate) unsafe fn client_connect(sockaddr: &str) -> Result<RawFd> {
     Ok(fd)
 }
 
+fn hvsock_connect(addr: &str) -> Result<RawFd> {
+    let (path, port) = parse_hvsock(addr)?;
+    let mut retries = HVSOCK_RETRIES;
+    loop {
+        match hvsock_handshake(path, port) {
+            Ok(fd) => return Ok(fd),
+            Err(e) if retries > 0 => {
+                debug!("connect to hvsock {} failed, retry: {:?}", addr, e);
+                retries -= 1;
+                std::thread::sleep(HVSOCK_RETRY_INTERVAL);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn hvsock_handshake(path: &str, port: u32) -> Result<RawFd> {
+    let fd = unsafe { client_connect(&format!("unix://{path}"))? };
+    // Safety: the socket was just connected and is owned by nothing else.
+    let mut stream = unsafe { UnixStream::from_raw_fd(fd) };
+    let io_err = |e: std::io::Error| Error::Socket(e.to_string());
+    stream
+        .set_read_timeout(Some(HVSOCK_HANDSHAKE_TIMEOUT))
+        .map_err(io_err)?;
+    stream
+        .write_all(format!("CONNECT {port}\n").as_bytes())
+        .map_err(io_err)?;
+
+    // Read byte by byte, what comes after the reply belongs to the connection.
+    let mut reply = Vec::new();
+    let mut byte = [0u8];
+    while reply.last() != Some(&b'\n') {
+        if stream.read(&mut byte).map_err(io_err)? == 0 {
+            return Err(Error::Others(
+                "hvsock is closed during the handshake".to_string(),
+            ));
+        }
+        reply.push(byte[0]);
+    }
+    check_hvsock_reply(&reply)?;
+
+    stream.set_read_timeout(None).map_err(io_err)?;
+    Ok(stream.into_raw_fd())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -201,6 +354,12 @@ mod tests {
                 "@/run/b.sock",
                 true,
             ),
+            (
+                "hvsock:///run/vm.sock:1024",
+                Some(Domain::Hvsock),
+                "/run/vm.sock:1024",
+                true,
+            ),
             ("abc:///run/c.sock", None, "", false),
         ] {
             let (input, domain, addr, success) = (i.0, i.1, i.2, i.3);
@@ -257,4 +416,32 @@ mod tests {
             assert_eq!(r.unwrap(), (cid, port), "parse {:?} failed", i);
         }
     }
+
+    #[test]
+    fn test_parse_hvsock() {
+        assert_eq!(
+            parse_hvsock("/run/vm.sock:1024").unwrap(),
+            ("/run/vm.sock", 1024)
+        );
+        assert_eq!(parse_hvsock("@vm:c:1").unwrap(), ("@vm:c", 1));
+        assert!(parse_hvsock("/run/vm.sock").is_err());
+        assert!(parse_hvsock("/run/vm.sock:-1").is_err());
+
+        assert!(check_hvsock_reply(b"OK 1073741824\n").is_ok());
+        assert!(check_hvsock_reply(b"ERR no listener\n").is_err());
+    }
+
+    #[cfg(feature = "tcp")]
+    #[test]
+    fn test_parse_tcp() {
+        assert_eq!(
+            parse_sockaddr("tcp://127.0.0.1:1024").unwrap(),
+            (Domain::Tcp, "127.0.0.1:1024")
+        );
+        assert_eq!(
+            parse_tcp("[::1]:1025").unwrap(),
+            "[::1]:1025".parse().unwrap()
+        );
+        assert!(parse_tcp("127.0.0.1").is_err());
+    }
 }
@@ -3,13 +3,18 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
-use crate::proto::KeyValue;
+use crate::error::{get_rpc_status, Result};
+use crate::metadata::Metadata;
+use crate::proto::{Code, KeyValue};
 use core::time::Duration;
 use std::collections::HashMap;
+use std::time::Instant;
 #[derive(Clone, Default, Debug)]
 pub struct Context {
-    pub metadata: HashMap<String, Vec<String>>,
+    pub metadata: Metadata,
     pub timeout_nano: i64,
+    /// When the call times out, whichever of this and `timeout_nano` comes first.
+    pub deadline: Option<Instant>,
 }
 
 pub fn with_timeout(i: i64) -> Context {
@@ -24,45 +29,103 @@ pub fn with_duration(du: Duration) -> Context {
     with_timeout(du.as_nanos() as i64)
 }
 
+pub fn with_deadline(deadline: Instant) -> Context {
+    Context {
+        deadline: Some(deadline),
+        ..Default::default()
+    }
+}
+
 pub fn with_metadata(md: HashMap<String, Vec<String>>) -> Context {
     Context {
-        metadata: md,
+        metadata: md.into(),
         ..Default::default()
     }
 }
 
 impl Context {
-    // appends additional values to the given key.
+    /// Appends `value` to `key`, dropping it with a warning if `Metadata::append_encoded`
+    /// refuses it.
+    #[deprecated(since = "0.8.5", note = "use metadata.append or metadata.append_bin")]
     pub fn add(&mut self, key: String, value: String) {
-        if let Some(ref mut vl) = self.metadata.get_mut(&key) {
-            vl.push(value);
-        } else {
-            self.metadata.insert(key.to_lowercase(), vec![value]);
+        if let Err(e) = self.metadata.append_encoded(&key, value) {
+            warn!("drop the metadata {}: {}", key, e);
         }
     }
 
-    // Set sets the provided values for a given key.
-    // The values will overwrite any existing values.
-    // If no values provided, a key will be deleted.
+    /// Replaces the values of `key` with `value`, removing it if `value` is empty. Values
+    /// refused by `Metadata::append_encoded` are dropped with a warning.
+    #[deprecated(
+        since = "0.8.5",
+        note = "use metadata.remove then metadata.append or metadata.append_bin"
+    )]
     pub fn set(&mut self, key: String, value: Vec<String>) {
-        if value.is_empty() {
-            self.metadata.remove(&key);
-        } else {
-            self.metadata.insert(key.to_lowercase(), value);
+        self.metadata.remove(&key);
+        for v in value {
+            if let Err(e) = self.metadata.append_encoded(&key, v) {
+                warn!("drop the metadata {}: {}", key, e);
+            }
         }
     }
-}
 
-pub fn from_pb(kvs: &Vec<KeyValue>) -> HashMap<String, Vec<String>> {
-    let mut meta: HashMap<String, Vec<String>> = HashMap::new();
-    for kv in kvs {
-        if let Some(ref mut vl) = meta.get_mut(&kv.key) {
-            vl.push(kv.value.clone());
+    /// Moves the deadline to `deadline` if that is earlier, never later.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(match self.deadline {
+            Some(d) => d.min(deadline),
+            None => deadline,
+        });
+        self
+    }
+
+    /// Moves the deadline to `du` from now if that is earlier, never later.
+    pub fn with_timeout(self, du: Duration) -> Self {
+        match Instant::now().checked_add(du) {
+            Some(deadline) => self.with_deadline(deadline),
+            None => self,
+        }
+    }
+
+    /// The time left before the call times out, `None` if it does not.
+    pub fn remaining(&self) -> Option<Duration> {
+        let left = self
+            .deadline
+            .map(|d| d.saturating_duration_since(Instant::now()));
+        let timeout = if self.timeout_nano > 0 {
+            Some(Duration::from_nanos(self.timeout_nano as u64))
         } else {
-            meta.insert(kv.key.clone(), vec![kv.value.clone()]);
+            None
+        };
+        match (left, timeout) {
+            (Some(left), Some(timeout)) => Some(left.min(timeout)),
+            (left, timeout) => left.or(timeout),
+        }
+    }
+
+    /// The timeout to send with a request, 0 for none. Fails with `DEADLINE_EXCEEDED`
+    /// once the deadline has passed.
+    pub fn request_timeout_nano(&self) -> Result<i64> {
+        match self.remaining() {
+            None => Ok(0),
+            Some(left) if left.is_zero() => {
+                Err(get_rpc_status(Code::DEADLINE_EXCEEDED, "deadline exceeded"))
+            }
+            Some(left) => Ok(left.as_nanos().min(i64::MAX as u128) as i64),
         }
     }
-    meta
+}
+
+// The deadline of a call received with `timeout_nano`.
+pub(crate) fn deadline_of(timeout_nano: i64) -> Option<Instant> {
+    if timeout_nano > 0 {
+        Instant::now().checked_add(Duration::from_nanos(timeout_nano as u64))
+    } else {
+        None
+    }
+}
+
+/// Kept for compatibility, see [`Metadata::from_pb`]. The keys are in lowercase.
+pub fn from_pb(kvs: &[KeyValue]) -> HashMap<String, Vec<String>> {
+    Metadata::from_pb(kvs).into()
 }
 
 pub fn to_pb(kvs: HashMap<String, Vec<String>>) -> Vec<KeyValue> {
@@ -84,8 +147,11 @@ pub fn to_pb(kvs: HashMap<String, Vec<String>>) -> Vec<KeyValue> {
 
 #[cfg(test)]
 mod tests {
+    use std::time::{Duration, Instant};
+
     use crate::context;
-    use crate::proto::KeyValue;
+    use crate::error::Error;
+    use crate::proto::{Code, KeyValue};
 
     #[test]
     fn test_metadata() {
@@ -140,26 +206,68 @@ mod tests {
         assert_eq!(99, ctx.timeout_nano);
         assert_eq!(ctx.metadata.len(), 0);
 
-        ctx.add("key1".to_string(), "value1-1".to_string());
+        ctx.metadata.append("key1", "value1-1").unwrap();
         assert_eq!(ctx.metadata.len(), 1);
-        assert_eq!(
-            ctx.metadata.get("key1"),
-            Some(&vec!["value1-1".to_string()])
-        );
+        assert_eq!(ctx.metadata.get_all("key1"), ["value1-1"]);
 
-        ctx.add("key1".to_string(), "value1-2".to_string());
+        ctx.metadata.append("key1", "value1-2").unwrap();
         assert_eq!(ctx.metadata.len(), 1);
-        assert_eq!(
-            ctx.metadata.get("key1"),
-            Some(&vec!["value1-1".to_string(), "value1-2".to_string()])
-        );
+        assert_eq!(ctx.metadata.get_all("KEY1"), ["value1-1", "value1-2"]);
 
-        ctx.set("key2".to_string(), vec!["value2".to_string()]);
+        ctx.metadata.append("key2", "value2").unwrap();
         assert_eq!(ctx.metadata.len(), 2);
-        assert_eq!(ctx.metadata.get("key2"), Some(&vec!["value2".to_string()]));
+        assert_eq!(ctx.metadata.get("key2"), Some("value2"));
 
-        ctx.set("key1".to_string(), vec![]);
+        ctx.metadata.remove("key1");
         assert_eq!(ctx.metadata.len(), 1);
         assert_eq!(ctx.metadata.get("key1"), None);
     }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_add_and_set() {
+        let mut ctx = context::Context::default();
+        ctx.add("Key1".to_string(), "value1".to_string());
+        ctx.add("key1-bin".to_string(), "AQI".to_string());
+        assert_eq!(ctx.metadata.get("key1"), Some("value1"));
+        assert_eq!(ctx.metadata.get_bin("key1-bin"), Some(vec![1, 2]));
+
+        // Invalid, reserved and non-base64 binary metadata are dropped.
+        ctx.add("key 2".to_string(), "value2".to_string());
+        ctx.add("ttrpc-key2".to_string(), "value2".to_string());
+        ctx.add("key2-bin".to_string(), "not base64!".to_string());
+        assert_eq!(ctx.metadata.len(), 2);
+
+        ctx.set("KEY1".to_string(), vec!["v1".to_string(), "v2".to_string()]);
+        assert_eq!(ctx.metadata.get_all("key1"), ["v1", "v2"]);
+        ctx.set("ttrpc-key1".to_string(), vec!["v".to_string()]);
+        ctx.set("key1".to_string(), vec![]);
+        assert_eq!(ctx.metadata.len(), 1);
+    }
+
+    #[test]
+    fn test_deadline() {
+        let ctx: context::Context = Default::default();
+        assert_eq!(ctx.remaining(), None);
+        assert_eq!(ctx.request_timeout_nano().unwrap(), 0);
+
+        // The timeout caps the time left before the deadline.
+        let ctx = context::with_timeout(1000).with_timeout(Duration::from_secs(10));
+        assert_eq!(ctx.remaining(), Some(Duration::from_nanos(1000)));
+        assert_eq!(ctx.request_timeout_nano().unwrap(), 1000);
+
+        // A later deadline does not extend an earlier one.
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let ctx = context::with_deadline(deadline).with_timeout(Duration::from_secs(60));
+        assert_eq!(ctx.deadline, Some(deadline));
+        let left = ctx.request_timeout_nano().unwrap();
+        assert!(left > 0 && left <= 10_000_000_000);
+
+        let ctx = ctx.with_deadline(Instant::now());
+        assert_eq!(ctx.remaining(), Some(Duration::ZERO));
+        match ctx.request_timeout_nano() {
+            Err(Error::RpcStatus(s)) => assert_eq!(s.code(), Code::DEADLINE_EXCEEDED),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
 }
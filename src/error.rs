@@ -15,7 +15,9 @@
 //! Error and Result of ttrpc and relevant functions, macros.
 
 use crate::proto::{Code, Response, Status};
+use std::fmt;
 use std::result;
+use std::sync::Arc;
 use thiserror::Error;
 
 /// The error type for ttrpc.
@@ -44,16 +46,114 @@ pub enum Error {
     #[error("eof")]
     Eof,
 
+    /// The call or the wait for the connection timed out.
+    #[error("ttrpc err: {0}")]
+    Timeout(String),
+
+    /// A message could not be encoded or decoded.
+    #[error("ttrpc err: {msg}")]
+    Codec {
+        msg: String,
+        #[source]
+        source: Option<Source>,
+    },
+
+    /// An I/O operation failed, e.g. connecting or binding.
+    #[error("io err: {msg}")]
+    Io {
+        kind: std::io::ErrorKind,
+        msg: String,
+        #[source]
+        source: Source,
+    },
+
+    /// The connection or the stream the call was on is closed.
+    #[error("ttrpc err: {0}")]
+    Closed(String),
+
+    /// The call was cancelled.
+    #[error("ttrpc err: cancelled")]
+    Cancelled,
+
+    /// A lock shared by the calls was poisoned by a panic.
+    #[error("ttrpc err: {0}")]
+    Poisoned(String),
+
     #[error("ttrpc err: {0}")]
     Others(String),
 }
 
+impl Error {
+    /// The code of the status the error is responded with.
+    pub fn code(&self) -> Code {
+        match self {
+            Error::RpcStatus(s) => s.code(),
+            Error::Timeout(_) => Code::DEADLINE_EXCEEDED,
+            Error::Codec { .. } => Code::INTERNAL,
+            Error::Io { .. }
+            | Error::Closed(_)
+            | Error::Socket(_)
+            | Error::LocalClosed
+            | Error::RemoteClosed
+            | Error::Eof => Code::UNAVAILABLE,
+            Error::Cancelled => Code::CANCELLED,
+            Error::Poisoned(_) => Code::INTERNAL,
+            _ => Code::UNKNOWN,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io {
+            kind: e.kind(),
+            msg: e.to_string(),
+            source: Source::new(e),
+        }
+    }
+}
+
+/// The error underlying an [`Error`](enum@Error), shared so that the error can be cloned.
+///
+/// Two sources are equal if they have the same message.
+#[derive(Clone, Debug)]
+pub struct Source(Arc<dyn std::error::Error + Send + Sync>);
+
+impl Source {
+    pub fn new(e: impl std::error::Error + Send + Sync + 'static) -> Self {
+        Source(Arc::new(e))
+    }
+
+    /// The underlying error, to downcast.
+    pub fn get_ref(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+        &*self.0
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for Source {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+impl PartialEq for Source {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || self.to_string() == other.to_string()
+    }
+}
+
 impl From<Error> for Response {
     fn from(e: Error) -> Self {
         let status = if let Error::RpcStatus(stat) = e {
             stat
         } else {
-            get_status(Code::UNKNOWN, e)
+            get_status(e.code(), e)
         };
 
         let mut res = Response::new();
@@ -93,6 +193,47 @@ macro_rules! err_to_others_err {
     };
 }
 
+macro_rules! err_to_codec_err {
+    ($e: ident, $s: expr) => {
+        |$e| Error::Codec {
+            msg: $s.to_string() + &$e.to_string(),
+            source: Some(crate::error::Source::new($e)),
+        }
+    };
+}
+
+// For the errors of a generic codec, only known to be displayable.
+macro_rules! err_to_codec_msg {
+    ($e: ident, $s: expr) => {
+        |$e| Error::Codec {
+            msg: $s.to_string() + &$e.to_string(),
+            source: None,
+        }
+    };
+}
+
+#[cfg(feature = "async")]
+macro_rules! err_to_io_err {
+    ($e: ident, $s: expr) => {
+        |$e: std::io::Error| Error::Io {
+            kind: $e.kind(),
+            msg: $s.to_string() + &$e.to_string(),
+            source: crate::error::Source::new($e),
+        }
+    };
+}
+
+/// Convert to ttrpc::Error::Codec.
+#[macro_export]
+macro_rules! err_to_codec {
+    ($e: ident, $s: expr) => {
+        |$e| ::ttrpc::Error::Codec {
+            msg: $s.to_string() + &$e.to_string(),
+            source: Some(::ttrpc::error::Source::new($e)),
+        }
+    };
+}
+
 /// Convert to ttrpc::Error::Others.
 #[macro_export]
 macro_rules! err_to_others {
@@ -100,3 +241,58 @@ macro_rules! err_to_others {
         |$e| ::ttrpc::Error::Others($s.to_string() + &$e.to_string())
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+    use std::io;
+
+    use super::*;
+
+    #[test]
+    fn test_code() {
+        let cases = [
+            (get_rpc_status(Code::NOT_FOUND, ""), Code::NOT_FOUND),
+            (Error::Timeout("".to_string()), Code::DEADLINE_EXCEEDED),
+            (Error::Closed("".to_string()), Code::UNAVAILABLE),
+            (Error::Cancelled, Code::CANCELLED),
+            (Error::Socket("".to_string()), Code::UNAVAILABLE),
+            (Error::LocalClosed, Code::UNAVAILABLE),
+            (Error::RemoteClosed, Code::UNAVAILABLE),
+            (Error::Eof, Code::UNAVAILABLE),
+            (Error::Poisoned("".to_string()), Code::INTERNAL),
+            (Error::Others("".to_string()), Code::UNKNOWN),
+        ];
+        for (e, code) in cases {
+            assert_eq!(e.code(), code);
+            let res: Response = e.into();
+            assert_eq!(res.status().code(), code);
+        }
+    }
+
+    #[test]
+    fn test_source() {
+        let e: Error = io::Error::new(io::ErrorKind::ConnectionRefused, "refused").into();
+        assert!(matches!(
+            e,
+            Error::Io {
+                kind: io::ErrorKind::ConnectionRefused,
+                ..
+            }
+        ));
+        assert_eq!(e.code(), Code::UNAVAILABLE);
+        let source = e.source().unwrap();
+        assert_eq!(source.to_string(), "refused");
+        let source = source.downcast_ref::<Source>().unwrap();
+        assert!(source.get_ref().downcast_ref::<io::Error>().is_some());
+
+        let e = <crate::proto::Response as crate::proto::Codec>::decode([0xff])
+            .map_err(err_to_codec_err!(e, "Decode failed: "))
+            .unwrap_err();
+        assert_eq!(e.code(), Code::INTERNAL);
+        assert!(e.to_string().starts_with("ttrpc err: Decode failed: "));
+        let source = e.source().unwrap().downcast_ref::<Source>().unwrap();
+        assert!(source.get_ref().downcast_ref::<protobuf::Error>().is_some());
+        assert_eq!(e.clone(), e);
+    }
+}
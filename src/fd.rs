@@ -0,0 +1,264 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Passing file descriptors with the messages of unix socket connections.
+//!
+//! The descriptors attached to a message are sent with `SCM_RIGHTS` along with its first
+//! bytes. The receiving side hands the ones which come with a request or a response over
+//! to its call, the ones which come with stream data are closed.
+
+use std::collections::HashMap;
+use std::io::{IoSlice, IoSliceMut};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::{Arc, Mutex};
+
+use nix::sys::socket::{
+    getsockname, recv, recvmsg, send, sendmsg, AddressFamily, ControlMessage, ControlMessageOwned,
+    MsgFlags, SockaddrLike, SockaddrStorage,
+};
+
+use crate::error::{Error, Result};
+use crate::proto::{MessageHeader, MESSAGE_TYPE_REQUEST, MESSAGE_TYPE_RESPONSE};
+
+/// The maximum number of file descriptors passed with a message, `SCM_MAX_FD` of Linux.
+pub const MAX_FDS: usize = 253;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const RECV_FLAGS: MsgFlags = MsgFlags::MSG_CMSG_CLOEXEC;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const RECV_FLAGS: MsgFlags = MsgFlags::empty();
+
+/// The file descriptors passed over a connection.
+#[derive(Debug, Default)]
+pub(crate) struct FdTable {
+    supported: bool,
+    // Received by the socket and not yet given to a message.
+    incoming: Mutex<Vec<OwnedFd>>,
+    // Sent with the next bytes written to the socket.
+    outgoing: Mutex<Vec<OwnedFd>>,
+    // Received with a request or a response, by stream id.
+    received: Mutex<HashMap<u32, Vec<OwnedFd>>>,
+    // Sent with the next message of a stream, by stream id.
+    sending: Mutex<HashMap<u32, Vec<OwnedFd>>>,
+}
+
+impl FdTable {
+    /// Create the table of the socket `fd`, descriptors are only passed over unix sockets.
+    pub(crate) fn new(fd: RawFd) -> FdTable {
+        let family = getsockname::<SockaddrStorage>(fd)
+            .ok()
+            .and_then(|addr| addr.family());
+        FdTable {
+            supported: family == Some(AddressFamily::Unix),
+            ..Default::default()
+        }
+    }
+
+    /// Receive bytes from the socket `fd`, keeping the descriptors coming with them.
+    pub(crate) fn recv(&self, fd: RawFd, buf: &mut [u8]) -> nix::Result<usize> {
+        if !self.supported {
+            return recv(fd, buf, MsgFlags::empty());
+        }
+
+        let mut iov = [IoSliceMut::new(buf)];
+        let mut cmsg = nix::cmsg_space!([RawFd; MAX_FDS]);
+        let msg = recvmsg::<()>(fd, &mut iov, Some(&mut cmsg), RECV_FLAGS)?;
+        if msg.flags.contains(MsgFlags::MSG_CTRUNC) {
+            warn!("file descriptors received from fd {} are truncated", fd);
+        }
+
+        let mut incoming = self.incoming.lock().unwrap();
+        for cmsg in msg.cmsgs() {
+            if let ControlMessageOwned::ScmRights(fds) = cmsg {
+                // Safety: the descriptors were just created by recvmsg.
+                incoming.extend(
+                    fds.into_iter()
+                        .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }),
+                );
+            }
+        }
+        Ok(msg.bytes)
+    }
+
+    /// Send bytes to the socket `fd`, the outgoing descriptors are sent with them.
+    pub(crate) fn send(&self, fd: RawFd, buf: &[u8]) -> nix::Result<usize> {
+        let mut outgoing = self.outgoing.lock().unwrap();
+        if outgoing.is_empty() {
+            return send(fd, buf, MsgFlags::empty());
+        }
+
+        let fds: Vec<RawFd> = outgoing.iter().map(|fd| fd.as_raw_fd()).collect();
+        let size = sendmsg::<()>(
+            fd,
+            &[IoSlice::new(buf)],
+            &[ControlMessage::ScmRights(&fds)],
+            MsgFlags::empty(),
+            None,
+        )?;
+        // The peer has its own copies now.
+        outgoing.clear();
+        Ok(size)
+    }
+
+    /// Give the descriptors received so far to the message `mh` which has been read.
+    pub(crate) fn message_read(&self, mh: &MessageHeader) {
+        let fds = std::mem::take(&mut *self.incoming.lock().unwrap());
+        if fds.is_empty() {
+            return;
+        }
+        if mh.type_ != MESSAGE_TYPE_REQUEST && mh.type_ != MESSAGE_TYPE_RESPONSE {
+            debug!("close {} file descriptors sent with {:?}", fds.len(), mh);
+            return;
+        }
+        self.received
+            .lock()
+            .unwrap()
+            .entry(mh.stream_id)
+            .or_default()
+            .extend(fds);
+    }
+
+    /// Close the descriptors received with a message which has been discarded.
+    pub(crate) fn message_discarded(&self) {
+        self.incoming.lock().unwrap().clear();
+    }
+
+    /// Queue the descriptors attached to the stream of the message `mh` which is written next.
+    pub(crate) fn message_writing(&self, mh: &MessageHeader) {
+        if let Some(fds) = self.sending.lock().unwrap().remove(&mh.stream_id) {
+            self.outgoing.lock().unwrap().extend(fds);
+        }
+    }
+
+    /// Take the descriptors received with a request or a response of the stream.
+    pub(crate) fn take_received(&self, stream_id: u32) -> Vec<OwnedFd> {
+        self.received
+            .lock()
+            .unwrap()
+            .remove(&stream_id)
+            .unwrap_or_default()
+    }
+
+    /// Close the descriptors left for a stream whose call is over, e.g. it timed out before
+    /// they were taken or written.
+    pub(crate) fn forget(&self, stream_id: u32) {
+        self.received.lock().unwrap().remove(&stream_id);
+        self.sending.lock().unwrap().remove(&stream_id);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.received.lock().unwrap().is_empty() && self.sending.lock().unwrap().is_empty()
+    }
+
+    /// Attach descriptors to the next message written for the stream.
+    pub(crate) fn attach(&self, stream_id: u32, fds: Vec<OwnedFd>) -> Result<()> {
+        if fds.is_empty() {
+            return Ok(());
+        }
+        if !self.supported {
+            return Err(Error::Others(
+                "file descriptors can only be passed over unix sockets".to_string(),
+            ));
+        }
+
+        let mut sending = self.sending.lock().unwrap();
+        let attached = sending.entry(stream_id).or_default();
+        if attached.len() + fds.len() > MAX_FDS {
+            return Err(Error::Others(format!(
+                "more than {MAX_FDS} file descriptors are attached to a message"
+            )));
+        }
+        attached.extend(fds);
+        Ok(())
+    }
+}
+
+/// The file descriptors passed with a call, on unix sockets only.
+#[derive(Debug)]
+pub struct CallFds {
+    stream_id: u32,
+    received: Mutex<Vec<OwnedFd>>,
+    table: Arc<FdTable>,
+}
+
+impl CallFds {
+    pub(crate) fn new(stream_id: u32, table: Arc<FdTable>) -> CallFds {
+        CallFds {
+            stream_id,
+            received: Mutex::new(table.take_received(stream_id)),
+            table,
+        }
+    }
+
+    /// Take the file descriptors sent with the request, the ones not taken are closed
+    /// with the context.
+    pub fn take(&self) -> Vec<OwnedFd> {
+        std::mem::take(&mut *self.received.lock().unwrap())
+    }
+
+    /// Send file descriptors with the next message of the call, which should be its response
+    /// as the ones sent with stream data are closed by the client.
+    pub fn send(&self, fds: Vec<OwnedFd>) -> Result<()> {
+        self.table.attach(self.stream_id, fds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read as _, Write as _};
+    use std::os::unix::net::UnixStream;
+
+    use super::*;
+
+    fn pipe_with(content: &[u8]) -> OwnedFd {
+        let (rx, tx) = nix::unistd::pipe().unwrap();
+        // Safety: the descriptors were just created by pipe.
+        let (rx, tx) = unsafe { (OwnedFd::from_raw_fd(rx), OwnedFd::from_raw_fd(tx)) };
+        std::fs::File::from(tx).write_all(content).unwrap();
+        rx
+    }
+
+    fn read_all(fd: OwnedFd) -> String {
+        let mut content = String::new();
+        std::fs::File::from(fd)
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn test_pass_fds() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let sender = FdTable::new(a.as_raw_fd());
+        let receiver = Arc::new(FdTable::new(b.as_raw_fd()));
+
+        let request = MessageHeader::new_request(1, 4);
+        sender.attach(1, vec![pipe_with(b"fd1")]).unwrap();
+        sender.attach(3, vec![pipe_with(b"fd3")]).unwrap();
+        sender.message_writing(&request);
+        assert_eq!(sender.send(a.as_raw_fd(), b"1234").unwrap(), 4);
+        let data = MessageHeader::new_data(3, 4);
+        sender.message_writing(&data);
+        assert_eq!(sender.send(a.as_raw_fd(), b"5678").unwrap(), 4);
+
+        let mut buf = [0; 4];
+        assert_eq!(receiver.recv(b.as_raw_fd(), &mut buf).unwrap(), 4);
+        receiver.message_read(&request);
+        assert_eq!(receiver.recv(b.as_raw_fd(), &mut buf).unwrap(), 4);
+        receiver.message_read(&data);
+
+        let fds = CallFds::new(1, receiver.clone()).take();
+        assert_eq!(fds.len(), 1);
+        assert_eq!(read_all(fds.into_iter().next().unwrap()), "fd1");
+        // The ones sent with stream data are closed.
+        assert!(receiver.take_received(3).is_empty());
+
+        // The ones left for a call which is over are closed.
+        sender.attach(5, vec![pipe_with(b"fd5")]).unwrap();
+        sender.forget(5);
+        assert!(sender.is_empty());
+
+        assert!(FdTable::default().attach(1, vec![pipe_with(b"")]).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! The health service and client (async).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+use super::{
+    request, response, HealthCheckRequest, HealthCheckResponse, HealthReporter, SERVICE_NAME,
+};
+use crate::context::Context;
+use crate::error::{get_status, Error, Result};
+use crate::proto::{Code, Codec, Request, Response};
+use crate::r#async::{
+    Client, ClientStreamReceiver, MethodHandler, ServerStreamSender, Service, StreamHandler,
+    StreamInner, TtrpcContext,
+};
+
+/// Create the health service, for `Server::register_service`.
+pub fn create_health(reporter: HealthReporter) -> HashMap<String, Service> {
+    let mut methods = HashMap::new();
+    let mut streams = HashMap::new();
+
+    methods.insert(
+        "Check".to_string(),
+        Box::new(CheckMethod {
+            reporter: reporter.clone(),
+        }) as Box<dyn MethodHandler + Send + Sync>,
+    );
+    streams.insert(
+        "Watch".to_string(),
+        Arc::new(WatchMethod { reporter }) as Arc<dyn StreamHandler + Send + Sync>,
+    );
+
+    let mut ret = HashMap::new();
+    ret.insert(SERVICE_NAME.to_string(), Service { methods, streams });
+    ret
+}
+
+struct CheckMethod {
+    reporter: HealthReporter,
+}
+
+#[async_trait]
+impl MethodHandler for CheckMethod {
+    async fn handler(&self, _ctx: TtrpcContext, req: Request) -> Result<Response> {
+        let req = HealthCheckRequest::decode(&req.payload).map_err(err_to_others_err!(e, ""))?;
+        match self.reporter.check(&req) {
+            Ok(rep) => {
+                let mut res = Response::new();
+                res.set_status(get_status(Code::OK, ""));
+                res.payload = rep.encode().map_err(err_to_others_err!(e, ""))?;
+                Ok(res)
+            }
+            Err(e) => Ok(e.into()),
+        }
+    }
+}
+
+struct WatchMethod {
+    reporter: HealthReporter,
+}
+
+#[async_trait]
+impl StreamHandler for WatchMethod {
+    async fn handler(&self, ctx: TtrpcContext, mut inner: StreamInner) -> Result<Option<Response>> {
+        let req =
+            HealthCheckRequest::decode(inner.recv().await?).map_err(err_to_others_err!(e, ""))?;
+        let stream = ServerStreamSender::<HealthCheckResponse>::new(inner);
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let current = self
+            .reporter
+            .watch(&req.service, move |status| tx.send(status).is_ok());
+        stream.send(&response(current)).await?;
+
+        // The stream lasts until the client or the connection is gone.
+        loop {
+            tokio::select! {
+                status = rx.recv() => match status {
+                    Some(status) => stream.send(&response(status)).await?,
+                    None => return Ok(None),
+                },
+                _ = ctx.cancel_token.cancelled() => return Ok(None),
+            }
+        }
+    }
+}
+
+/// The client of the health service (async).
+#[derive(Clone)]
+pub struct HealthClient {
+    client: Client,
+}
+
+impl HealthClient {
+    pub fn new(client: Client) -> Self {
+        HealthClient { client }
+    }
+
+    /// Get the status of `service`, the empty name checks the whole server.
+    ///
+    /// A service the server doesn't know fails with `NOT_FOUND`.
+    pub async fn check(&self, ctx: Context, service: &str) -> Result<HealthCheckResponse> {
+        let res = self.client.request(request(ctx, "Check", service)?).await?;
+        HealthCheckResponse::decode(&res.payload)
+            .map_err(err_to_others_err!(e, "Unpack get error "))
+    }
+
+    /// Watch the status of `service`, the current one comes first, then every change.
+    pub async fn watch(
+        &self,
+        ctx: Context,
+        service: &str,
+    ) -> Result<ClientStreamReceiver<HealthCheckResponse>> {
+        let inner = self
+            .client
+            .new_stream(request(ctx, "Watch", service)?, false, true)
+            .await?;
+        Ok(ClientStreamReceiver::new(inner, self.client.clone()))
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::context;
+    use crate::health::ServingStatus;
+    use crate::r#async::Server;
+
+    #[tokio::test]
+    async fn test_health() {
+        let addr = r"unix://@/tmp/ttrpc-health-unit-test";
+        let reporter = HealthReporter::new();
+        reporter.set_not_serving("agent");
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_health_service(reporter.clone());
+        server.start().await.unwrap();
+
+        let client = HealthClient::new(Client::connect(addr).await.unwrap());
+        let res = client.check(context::with_timeout(0), "").await.unwrap();
+        assert_eq!(res.status.enum_value(), Ok(ServingStatus::SERVING));
+        match client.check(context::with_timeout(0), "unknown").await {
+            Err(Error::RpcStatus(s)) => assert_eq!(s.code(), Code::NOT_FOUND),
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let mut watch = client
+            .watch(context::with_timeout(0), "agent")
+            .await
+            .unwrap();
+        async fn next(watch: &mut ClientStreamReceiver<HealthCheckResponse>) -> ServingStatus {
+            let res = timeout(Duration::from_secs(3), watch.recv()).await;
+            res.unwrap().unwrap().unwrap().status.enum_value().unwrap()
+        }
+        assert_eq!(next(&mut watch).await, ServingStatus::NOT_SERVING);
+        reporter.set_serving("agent");
+        assert_eq!(next(&mut watch).await, ServingStatus::SERVING);
+        reporter.shutdown();
+        assert_eq!(next(&mut watch).await, ServingStatus::NOT_SERVING);
+
+        server.shutdown().await.unwrap();
+    }
+}
@@ -0,0 +1,224 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! The health checking service of gRPC, `grpc.health.v1.Health`.
+//!
+//! The statuses it reports are set with a [`HealthReporter`], by service name. The empty name
+//! stands for the whole server, it's `SERVING` from the start.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::context::Context;
+use crate::error::{get_rpc_status, Error, Result};
+use crate::proto::{Code, Codec, Request};
+
+#[doc(inline)]
+pub use crate::proto::compiled::health::{
+    health_check_response::ServingStatus, HealthCheckRequest, HealthCheckResponse,
+};
+
+cfg_sync! {
+    pub mod sync;
+}
+
+cfg_async! {
+    pub mod asynchronous;
+    #[doc(hidden)]
+    pub use asynchronous as r#async;
+}
+
+/// The name of the health service.
+pub const SERVICE_NAME: &str = "grpc.health.v1.Health";
+
+// Told about the changes of the statuses, dropped once it returns `false`.
+type Watcher = Box<dyn Fn(&str, ServingStatus) -> bool + Send>;
+
+#[derive(Default)]
+struct Inner {
+    statuses: HashMap<String, ServingStatus>,
+    watchers: Vec<Watcher>,
+    shut_down: bool,
+}
+
+/// The handle setting the statuses reported by the health service, clones share them.
+#[derive(Clone)]
+pub struct HealthReporter {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Default for HealthReporter {
+    fn default() -> Self {
+        let reporter = HealthReporter {
+            inner: Arc::default(),
+        };
+        reporter.set_serving("");
+        reporter
+    }
+}
+
+impl std::fmt::Debug for HealthReporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inner = self.inner.lock().unwrap();
+        f.debug_struct("HealthReporter")
+            .field("statuses", &inner.statuses)
+            .field("shut_down", &inner.shut_down)
+            .finish()
+    }
+}
+
+impl HealthReporter {
+    pub fn new() -> HealthReporter {
+        HealthReporter::default()
+    }
+
+    /// Report `service` as serving.
+    pub fn set_serving(&self, service: &str) {
+        self.set_status(service, ServingStatus::SERVING);
+    }
+
+    /// Report `service` as not serving.
+    pub fn set_not_serving(&self, service: &str) {
+        self.set_status(service, ServingStatus::NOT_SERVING);
+    }
+
+    /// Set the status of `service`, ignored once the reporter is shut down.
+    pub fn set_status(&self, service: &str, status: ServingStatus) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.shut_down {
+            return;
+        }
+        if inner.statuses.insert(service.to_string(), status) != Some(status) {
+            inner.watchers.retain(|watcher| watcher(service, status));
+        }
+    }
+
+    /// Forget `service`, it's checked like a service which was never reported.
+    pub fn clear_status(&self, service: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.statuses.remove(service).is_some() {
+            inner
+                .watchers
+                .retain(|watcher| watcher(service, ServingStatus::SERVICE_UNKNOWN));
+        }
+    }
+
+    /// Get the status of `service`, `None` if it's unknown.
+    pub fn status(&self, service: &str) -> Option<ServingStatus> {
+        self.inner.lock().unwrap().statuses.get(service).copied()
+    }
+
+    /// Report all services as not serving and ignore the later statuses, e.g. as the server is
+    /// about to shut down.
+    pub fn shutdown(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.shut_down = true;
+        let services: Vec<String> = inner.statuses.keys().cloned().collect();
+        for service in services {
+            let status = ServingStatus::NOT_SERVING;
+            if inner.statuses.insert(service.clone(), status) != Some(status) {
+                inner.watchers.retain(|watcher| watcher(&service, status));
+            }
+        }
+    }
+
+    // The response of `Check`.
+    fn check(&self, req: &HealthCheckRequest) -> Result<HealthCheckResponse> {
+        match self.status(&req.service) {
+            Some(status) => Ok(response(status)),
+            None => Err(get_rpc_status(
+                Code::NOT_FOUND,
+                format!("unknown service {:?}", req.service),
+            )),
+        }
+    }
+
+    // Start watching the status of `service`, `notify` is called with the next ones until it
+    // returns `false`. Return the current one.
+    fn watch(
+        &self,
+        service: &str,
+        notify: impl Fn(ServingStatus) -> bool + Send + 'static,
+    ) -> ServingStatus {
+        let mut inner = self.inner.lock().unwrap();
+        let watched = service.to_string();
+        inner.watchers.push(Box::new(move |service, status| {
+            service != watched || notify(status)
+        }));
+        inner
+            .statuses
+            .get(service)
+            .copied()
+            .unwrap_or(ServingStatus::SERVICE_UNKNOWN)
+    }
+}
+
+// The request of the `method` of the health service about `service`.
+fn request(ctx: Context, method: &str, service: &str) -> Result<Request> {
+    let req = HealthCheckRequest {
+        service: service.to_string(),
+        ..Default::default()
+    };
+    let mut creq = Request::new();
+    creq.set_service(SERVICE_NAME.to_string());
+    creq.set_method(method.to_string());
+    creq.set_timeout_nano(ctx.request_timeout_nano()?);
+    creq.set_metadata(ctx.metadata.to_pb());
+    creq.payload = req.encode().map_err(err_to_others_err!(e, ""))?;
+    Ok(creq)
+}
+
+fn response(status: ServingStatus) -> HealthCheckResponse {
+    HealthCheckResponse {
+        status: status.into(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use super::*;
+
+    #[test]
+    fn test_reporter() {
+        let reporter = HealthReporter::new();
+        let check = |service: &str| {
+            reporter.check(&HealthCheckRequest {
+                service: service.to_string(),
+                ..Default::default()
+            })
+        };
+        assert_eq!(check("").unwrap(), response(ServingStatus::SERVING));
+        assert!(check("agent").is_err());
+
+        let (tx, rx) = channel();
+        let current = reporter.watch("agent", move |status| tx.send(status).is_ok());
+        assert_eq!(current, ServingStatus::SERVICE_UNKNOWN);
+
+        reporter.set_not_serving("agent");
+        // Only the changes are told.
+        reporter.set_not_serving("agent");
+        reporter.set_serving("other");
+        reporter.clear_status("agent");
+        reporter.set_serving("agent");
+        reporter.shutdown();
+        reporter.set_serving("agent");
+        assert_eq!(
+            check("agent").unwrap(),
+            response(ServingStatus::NOT_SERVING)
+        );
+        drop(reporter);
+
+        assert_eq!(
+            rx.iter().collect::<Vec<_>>(),
+            [
+                ServingStatus::NOT_SERVING,
+                ServingStatus::SERVICE_UNKNOWN,
+                ServingStatus::SERVING,
+                ServingStatus::NOT_SERVING,
+            ]
+        );
+    }
+}
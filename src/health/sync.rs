@@ -0,0 +1,126 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! The health service and client (sync).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::{
+    request, response, HealthCheckRequest, HealthCheckResponse, HealthReporter, SERVICE_NAME,
+};
+use crate::context::Context;
+use crate::error::{get_status, Error, Result};
+use crate::proto::{Code, Codec, Request, Response};
+use crate::sync::{
+    response_to_channel, Client, ClientStreamReceiver, MethodHandler, ServerStreamSender,
+    StreamHandler, StreamInner, TtrpcContext,
+};
+
+/// Create the method handlers of the health service, for `Server::register_service`.
+pub fn create_health(
+    reporter: HealthReporter,
+) -> HashMap<String, Box<dyn MethodHandler + Send + Sync>> {
+    let mut methods = HashMap::new();
+    methods.insert(
+        format!("/{}/Check", SERVICE_NAME),
+        Box::new(CheckMethod { reporter }) as Box<dyn MethodHandler + Send + Sync>,
+    );
+    methods
+}
+
+/// Create the stream handlers of the health service, for `Server::register_stream_service`.
+///
+/// A `Watch` holds a thread of the server until its connection is closed: the sync server
+/// does not tell its handlers when a client drops the stream.
+pub fn create_health_streams(
+    reporter: HealthReporter,
+) -> HashMap<String, Arc<dyn StreamHandler + Send + Sync>> {
+    let mut streams = HashMap::new();
+    streams.insert(
+        format!("/{}/Watch", SERVICE_NAME),
+        Arc::new(WatchMethod { reporter }) as Arc<dyn StreamHandler + Send + Sync>,
+    );
+    streams
+}
+
+struct CheckMethod {
+    reporter: HealthReporter,
+}
+
+impl MethodHandler for CheckMethod {
+    fn handler(&self, ctx: TtrpcContext, req: Request) -> Result<()> {
+        let req = HealthCheckRequest::decode(&req.payload).map_err(err_to_others_err!(e, ""))?;
+        let res = match self.reporter.check(&req) {
+            Ok(rep) => {
+                let mut res = Response::new();
+                res.set_status(get_status(Code::OK, ""));
+                res.payload = rep.encode().map_err(err_to_others_err!(e, ""))?;
+                res
+            }
+            Err(e) => e.into(),
+        };
+        response_to_channel(ctx.mh.stream_id, res, ctx.res_tx)
+    }
+}
+
+struct WatchMethod {
+    reporter: HealthReporter,
+}
+
+impl StreamHandler for WatchMethod {
+    fn handler(&self, ctx: TtrpcContext, mut inner: StreamInner) -> Result<Option<Response>> {
+        let req = HealthCheckRequest::decode(inner.recv()?).map_err(err_to_others_err!(e, ""))?;
+        let stream = ServerStreamSender::<HealthCheckResponse>::new(inner);
+
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let current = self
+            .reporter
+            .watch(&req.service, move |status| tx.send(status).is_ok());
+        stream.send(&response(current))?;
+
+        // The stream, and the thread, last until the connection is gone.
+        loop {
+            crossbeam::select! {
+                recv(rx) -> status => match status {
+                    Ok(status) => stream.send(&response(status))?,
+                    Err(_) => return Ok(None),
+                },
+                recv(ctx.cancel_rx) -> _ => return Ok(None),
+            }
+        }
+    }
+}
+
+/// The client of the health service (sync).
+#[derive(Clone)]
+pub struct HealthClient {
+    client: Client,
+}
+
+impl HealthClient {
+    pub fn new(client: Client) -> Self {
+        HealthClient { client }
+    }
+
+    /// Get the status of `service`, the empty name checks the whole server.
+    ///
+    /// A service the server doesn't know fails with `NOT_FOUND`.
+    pub fn check(&self, ctx: Context, service: &str) -> Result<HealthCheckResponse> {
+        let res = self.client.request(request(ctx, "Check", service)?)?;
+        HealthCheckResponse::decode(&res.payload)
+            .map_err(err_to_others_err!(e, "Unpack get error "))
+    }
+
+    /// Watch the status of `service`, the current one comes first, then every change.
+    pub fn watch(
+        &self,
+        ctx: Context,
+        service: &str,
+    ) -> Result<ClientStreamReceiver<HealthCheckResponse>> {
+        let inner = self
+            .client
+            .new_stream(request(ctx, "Watch", service)?, false, true)?;
+        Ok(ClientStreamReceiver::new(inner))
+    }
+}
@@ -24,6 +24,7 @@
 //!
 //! - `async`: Enables async server and client.
 //! - `sync`: Enables traditional sync server and client (default enabled).
+//! - `prost`: Enables the [`Codec`](proto::Codec) adapter for messages generated by prost.
 //!
 //! # Socket address
 //!
@@ -51,12 +52,25 @@ mod common;
 #[macro_use]
 mod macros;
 
+#[cfg(unix)]
+pub mod activation;
+#[cfg(unix)]
+mod bind;
 pub mod context;
+#[cfg(unix)]
+pub mod fd;
+pub mod health;
+pub mod metadata;
+pub mod peer;
+pub mod reflection;
+pub mod status;
 
 pub mod proto;
 #[doc(inline)]
 pub use self::proto::{Code, MessageHeader, Request, Response, Status};
 
+#[cfg(unix)]
+pub use crate::bind::UnixBindOptions;
 #[doc(inline)]
 pub use crate::error::{get_status, Error, Result};
 
@@ -65,7 +79,7 @@ cfg_sync! {
     #[doc(hidden)]
     pub use sync::response_to_channel;
     #[doc(inline)]
-    pub use sync::{MethodHandler, TtrpcContext};
+    pub use sync::{MethodHandler, StreamHandler, TtrpcContext};
     pub use sync::Client;
     #[doc(inline)]
     pub use sync::Server;
@@ -24,3 +24,13 @@ macro_rules! cfg_async {
         )*
     }
 }
+
+macro_rules! cfg_prost {
+    ($($item:item)*) => {
+        $(
+            #[cfg(feature = "prost")]
+            #[cfg_attr(docsrs, doc(cfg(feature = "prost")))]
+            $item
+        )*
+    }
+}
@@ -0,0 +1,253 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Metadata of the calls.
+//!
+//! Keys are case-insensitive: they are kept in lowercase and looked up as such. The values
+//! of keys ending with `-bin` are binary; they travel base64-encoded in the `KeyValue`s of
+//! the request, like with gRPC.
+
+use std::collections::HashMap;
+
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+
+use crate::error::{Error, Result};
+use crate::proto::KeyValue;
+
+/// The suffix of the keys with binary values.
+pub const BINARY_SUFFIX: &str = "-bin";
+
+/// The prefix of the keys reserved for ttrpc itself.
+pub const RESERVED_PREFIX: &str = "ttrpc-";
+
+// Encodes without padding and decodes with or without, like gRPC.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_encode_padding(false)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// The metadata of a call: the values of each key, in the order they were added.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    map: HashMap<String, Vec<String>>,
+}
+
+impl Metadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The first value of `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_all(key).first().map(|v| v.as_str())
+    }
+
+    /// The values of `key`, empty if there is none.
+    pub fn get_all(&self, key: &str) -> &[String] {
+        self.map
+            .get(&key.to_lowercase())
+            .map_or(&[], |vl| vl.as_slice())
+    }
+
+    /// The first value of the binary `key`, `None` if there is none or it is not valid base64.
+    pub fn get_bin(&self, key: &str) -> Option<Vec<u8>> {
+        self.get(key).and_then(|v| BASE64.decode(v).ok())
+    }
+
+    /// The values of the binary `key`, leaving out those which are not valid base64.
+    pub fn get_all_bin(&self, key: &str) -> Vec<Vec<u8>> {
+        self.get_all(key)
+            .iter()
+            .filter_map(|v| BASE64.decode(v).ok())
+            .collect()
+    }
+
+    /// Appends a text value to `key`, which must be valid, not reserved and not binary.
+    pub fn append(&mut self, key: &str, value: impl Into<String>) -> Result<()> {
+        let key = check_key(key)?;
+        if key.ends_with(BINARY_SUFFIX) {
+            return Err(Error::Others(format!(
+                "metadata key {} is binary, use append_bin",
+                key
+            )));
+        }
+        self.push(key, value.into());
+        Ok(())
+    }
+
+    /// Appends a binary value to `key`, which must be valid, not reserved and end with `-bin`.
+    pub fn append_bin(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        let key = check_key(key)?;
+        if !key.ends_with(BINARY_SUFFIX) {
+            return Err(Error::Others(format!(
+                "metadata key {} is not binary, it must end with {}",
+                key, BINARY_SUFFIX
+            )));
+        }
+        self.push(key, BASE64.encode(value));
+        Ok(())
+    }
+
+    /// Appends `value` to `key` as it travels: in base64 if `key` ends with `-bin`, as text
+    /// otherwise. `key` must be valid and not reserved.
+    pub fn append_encoded(&mut self, key: &str, value: impl Into<String>) -> Result<()> {
+        let key = check_key(key)?;
+        let value = value.into();
+        if key.ends_with(BINARY_SUFFIX) && BASE64.decode(&value).is_err() {
+            return Err(Error::Others(format!(
+                "value of the binary metadata key {} is not base64",
+                key
+            )));
+        }
+        self.push(key, value);
+        Ok(())
+    }
+
+    /// Removes `key`, returning its values.
+    pub fn remove(&mut self, key: &str) -> Option<Vec<String>> {
+        self.map.remove(&key.to_lowercase())
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.map.contains_key(&key.to_lowercase())
+    }
+
+    /// The number of keys.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// The keys and their values, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.map.iter().map(|(k, vl)| (k.as_str(), vl.as_slice()))
+    }
+
+    pub fn from_pb(kvs: &[KeyValue]) -> Self {
+        let mut md = Metadata::new();
+        for kv in kvs {
+            md.push(kv.key.to_lowercase(), kv.value.clone());
+        }
+        md
+    }
+
+    pub fn to_pb(&self) -> Vec<KeyValue> {
+        self.iter()
+            .flat_map(|(k, vl)| {
+                vl.iter().map(move |v| KeyValue {
+                    key: k.to_string(),
+                    value: v.clone(),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    // Appends without checking the key, which must be in lowercase.
+    pub(crate) fn push(&mut self, key: String, value: String) {
+        self.map.entry(key).or_default().push(value);
+    }
+}
+
+impl From<HashMap<String, Vec<String>>> for Metadata {
+    fn from(map: HashMap<String, Vec<String>>) -> Self {
+        let mut md = Metadata::new();
+        for (k, vl) in map {
+            let key = k.to_lowercase();
+            for v in vl {
+                md.push(key.clone(), v);
+            }
+        }
+        md
+    }
+}
+
+impl From<Metadata> for HashMap<String, Vec<String>> {
+    fn from(md: Metadata) -> Self {
+        md.map
+    }
+}
+
+// The key in lowercase, if it is made of `0-9 a-z - _ .` and not reserved.
+fn check_key(key: &str) -> Result<String> {
+    let key = key.to_lowercase();
+    let valid = key
+        .bytes()
+        .all(|b| b.is_ascii_digit() || b.is_ascii_lowercase() || b"-_.".contains(&b));
+    if key.is_empty() || !valid {
+        return Err(Error::Others(format!("invalid metadata key {:?}", key)));
+    }
+    if key.starts_with(RESERVED_PREFIX) {
+        return Err(Error::Others(format!("metadata key {} is reserved", key)));
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kv(key: &str, value: &str) -> KeyValue {
+        KeyValue {
+            key: key.to_string(),
+            value: value.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let mut md = Metadata::from_pb(&[kv("User", "a"), kv("USER", "b")]);
+        assert_eq!(md.len(), 1);
+        assert_eq!(md.get("user"), Some("a"));
+        assert_eq!(md.get_all("uSeR"), ["a", "b"]);
+
+        md.append("User", "c").unwrap();
+        assert_eq!(md.get_all("user"), ["a", "b", "c"]);
+        assert_eq!(md.to_pb().len(), 3);
+        assert!(md.to_pb().iter().all(|kv| kv.key == "user"));
+
+        assert_eq!(
+            md.remove("USER"),
+            Some(vec!["a".into(), "b".into(), "c".into()])
+        );
+        assert!(md.is_empty());
+        assert_eq!(md.get("user"), None);
+        assert!(md.get_all("user").is_empty());
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut md = Metadata::new();
+        for key in ["", "a b", "ключ", "a:b"] {
+            assert!(md.append(key, "v").is_err(), "{:?}", key);
+        }
+        assert!(md.append("ttrpc-timeout", "v").is_err());
+        assert!(md.append("data-bin", "v").is_err());
+        assert!(md.append_bin("data", b"v").is_err());
+        assert!(md.is_empty());
+
+        md.append("trace_id.v-1", "v").unwrap();
+        assert_eq!(md.get("TRACE_ID.V-1"), Some("v"));
+    }
+
+    #[test]
+    fn test_binary() {
+        let mut md = Metadata::new();
+        md.append_bin("Data-Bin", &[0, 1, 0xfe, 0xff]).unwrap();
+        md.append_bin("data-bin", b"").unwrap();
+        assert_eq!(md.get("data-bin"), Some("AAH+/w"));
+        assert_eq!(md.get_bin("data-bin"), Some(vec![0, 1, 0xfe, 0xff]));
+
+        // Padded values are accepted, invalid ones left out.
+        let md = Metadata::from_pb(&[kv("data-bin", "AAH+/w=="), kv("data-bin", "*")]);
+        assert_eq!(md.get_all_bin("data-bin"), [vec![0, 1, 0xfe, 0xff]]);
+    }
+}
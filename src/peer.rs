@@ -0,0 +1,113 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! The peer of a connection and the authorization of its calls.
+
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+
+use crate::error::Result;
+use crate::proto::Request;
+
+/// The process at the other end of a server connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Peer {
+    /// A unix domain socket peer, `pid` is only known on Linux and Android.
+    Unix {
+        pid: Option<i32>,
+        uid: u32,
+        gid: u32,
+    },
+    /// A vsock peer.
+    Vsock { cid: u32, port: u32 },
+    /// A TCP peer.
+    Tcp { addr: SocketAddr },
+    /// A TCP peer over TLS, `certificates` is the DER encoded chain it was authenticated
+    /// with, its end entity first. It's empty if client certificates are not required.
+    Tls {
+        addr: SocketAddr,
+        certificates: Vec<Vec<u8>>,
+    },
+}
+
+#[cfg(unix)]
+impl Peer {
+    /// Get the peer of a connected socket, `None` if its family is not supported.
+    pub(crate) fn from_fd(fd: RawFd) -> Option<Peer> {
+        use nix::sys::socket::{getsockname, AddressFamily, SockaddrLike, SockaddrStorage};
+
+        let family = getsockname::<SockaddrStorage>(fd)
+            .map_err(|e| debug!("getsockname of fd {} got error {:?}", fd, e))
+            .ok()?
+            .family()?;
+        let peer = match family {
+            AddressFamily::Unix => unix_peer(fd),
+            AddressFamily::Inet | AddressFamily::Inet6 => tcp_peer(fd),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            AddressFamily::Vsock => {
+                nix::sys::socket::getpeername::<nix::sys::socket::VsockAddr>(fd).map(|addr| {
+                    Peer::Vsock {
+                        cid: addr.cid(),
+                        port: addr.port(),
+                    }
+                })
+            }
+            _ => return None,
+        };
+        peer.map_err(|e| debug!("peer of fd {} got error {:?}", fd, e))
+            .ok()
+    }
+}
+
+#[cfg(unix)]
+fn tcp_peer(fd: RawFd) -> nix::Result<Peer> {
+    use nix::sys::socket::{getpeername, SockaddrStorage};
+    use std::net::{SocketAddrV4, SocketAddrV6};
+
+    let addr = getpeername::<SockaddrStorage>(fd)?;
+    let addr = match (addr.as_sockaddr_in(), addr.as_sockaddr_in6()) {
+        (Some(addr), _) => SocketAddr::V4(SocketAddrV4::from(*addr)),
+        (_, Some(addr)) => SocketAddr::V6(SocketAddrV6::from(*addr)),
+        _ => return Err(nix::Error::EAFNOSUPPORT),
+    };
+    Ok(Peer::Tcp { addr })
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn unix_peer(fd: RawFd) -> nix::Result<Peer> {
+    use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
+
+    let cred = getsockopt(fd, PeerCredentials)?;
+    Ok(Peer::Unix {
+        pid: Some(cred.pid()),
+        uid: cred.uid(),
+        gid: cred.gid(),
+    })
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn unix_peer(fd: RawFd) -> nix::Result<Peer> {
+    let (uid, gid) = nix::unistd::getpeereid(fd)?;
+    Ok(Peer::Unix {
+        pid: None,
+        uid: uid.as_raw(),
+        gid: gid.as_raw(),
+    })
+}
+
+/// Trait that implements the authorization of connections and calls of a server.
+///
+/// `peer` is `None` when the transport doesn't tell the peer, e.g. windows named pipes.
+pub trait Authorizer {
+    /// Check a newly accepted connection, it's closed if an error is returned.
+    fn authorize_connection(&self, _peer: Option<&Peer>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Check a call before it's dispatched, an error is returned to the client as its status.
+    fn authorize_call(&self, _peer: Option<&Peer>, _req: &Request) -> Result<()> {
+        Ok(())
+    }
+}
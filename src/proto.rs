@@ -5,7 +5,7 @@
 //
 
 #[allow(soft_unstable, clippy::type_complexity, clippy::too_many_arguments)]
-mod compiled {
+pub(crate) mod compiled {
     include!(concat!(env!("OUT_DIR"), "/mod.rs"));
 }
 pub use compiled::ttrpc::*;
@@ -26,15 +26,26 @@ pub const MESSAGE_TYPE_DATA: u8 = 0x3;
 pub const FLAG_REMOTE_CLOSED: u8 = 0x1;
 pub const FLAG_REMOTE_OPEN: u8 = 0x2;
 pub const FLAG_NO_DATA: u8 = 0x4;
-
-pub(crate) fn check_oversize(len: usize, return_rpc_error: bool) -> TtResult<()> {
-    if len > MESSAGE_LENGTH_MAX {
+/// Set on a data message closing a stream along with [`FLAG_REMOTE_CLOSED`] and [`FLAG_NO_DATA`]
+/// when the client gives up on the call, so that the server stops handling it.
+///
+/// This is an extension of the protocol, behind the `cancel` feature, only sent when the async
+/// client is asked to with `set_cancel_calls`. Peers which don't know it take the message for
+/// data on the stream: for a unary call, the sync and Go servers answer it with
+/// `INVALID_ARGUMENT`.
+#[cfg(feature = "cancel")]
+pub const FLAG_CANCEL: u8 = 0x8;
+#[cfg(all(feature = "async", not(feature = "cancel")))]
+pub(crate) const FLAG_CANCEL: u8 = 0x8;
+
+pub(crate) fn check_oversize(len: usize, max_len: usize, return_rpc_error: bool) -> TtResult<()> {
+    if len > max_len {
         let msg = format!(
             "message length {} exceed maximum message size of {}",
-            len, MESSAGE_LENGTH_MAX
+            len, max_len
         );
         let e = if return_rpc_error {
-            get_rpc_status(Code::INVALID_ARGUMENT, msg)
+            get_rpc_status(Code::RESOURCE_EXHAUSTED, msg)
         } else {
             Error::Others(msg)
         };
@@ -222,18 +233,32 @@ impl GenMessage {
             .write_all(&self.payload)
             .await
             .map_err(|e| Error::Socket(e.to_string()))?;
+        // Buffering writers, e.g. TLS, hold the payload back otherwise.
+        writer
+            .flush()
+            .await
+            .map_err(|e| Error::Socket(e.to_string()))?;
         Ok(())
     }
 
     /// Decodes a MessageHeader from reader.
     pub async fn read_from(
+        reader: impl tokio::io::AsyncReadExt + Unpin,
+    ) -> std::result::Result<Self, GenMessageError> {
+        Self::read_from_with_limit(reader, MESSAGE_LENGTH_MAX).await
+    }
+
+    /// Decodes a MessageHeader from reader, the body of a message longer
+    /// than `max_len` is discarded and an error is returned.
+    pub async fn read_from_with_limit(
         mut reader: impl tokio::io::AsyncReadExt + Unpin,
+        max_len: usize,
     ) -> std::result::Result<Self, GenMessageError> {
         let header = MessageHeader::read_from(&mut reader)
             .await
             .map_err(|e| Error::Socket(e.to_string()))?;
 
-        if let Err(e) = check_oversize(header.length as usize, true) {
+        if let Err(e) = check_oversize(header.length as usize, max_len, true) {
             discard_message_body(reader, &header).await?;
             return Err(GenMessageError::ReturnError(header, e));
         }
@@ -251,7 +276,12 @@ impl GenMessage {
     }
 
     pub fn check(&self) -> TtResult<()> {
-        check_oversize(self.header.length as usize, true)
+        self.check_with_limit(MESSAGE_LENGTH_MAX)
+    }
+
+    /// Checks the message length against the given maximum.
+    pub fn check_with_limit(&self, max_len: usize) -> TtResult<()> {
+        check_oversize(self.header.length as usize, max_len, true)
     }
 }
 
@@ -288,6 +318,50 @@ impl<M: protobuf::Message> Codec for M {
     }
 }
 
+/// How the messages of a stream are encoded.
+///
+/// The stream types take the encoding as their last type parameter, it
+/// defaults to [`DefaultEncoding`] for the rust-protobuf messages.
+pub trait Encoding<M> {
+    fn encode(msg: &M) -> TtResult<Vec<u8>>;
+    fn decode(buf: Vec<u8>) -> TtResult<M>;
+}
+
+/// Encodes the messages through their [`Codec`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DefaultEncoding;
+
+impl<M> Encoding<M> for DefaultEncoding
+where
+    M: Codec,
+    <M as Codec>::E: std::fmt::Display,
+{
+    fn encode(msg: &M) -> TtResult<Vec<u8>> {
+        msg.encode()
+            .map_err(err_to_codec_msg!(e, "Encode message failed."))
+    }
+
+    fn decode(buf: Vec<u8>) -> TtResult<M> {
+        M::decode(buf).map_err(err_to_codec_msg!(e, "Decode message failed."))
+    }
+}
+
+cfg_prost! {
+    /// Encodes [`prost::Message`]s, the streams of the prost generated code use it.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct ProstEncoding;
+
+    impl<M: prost::Message + Default> Encoding<M> for ProstEncoding {
+        fn encode(msg: &M) -> TtResult<Vec<u8>> {
+            Ok(msg.encode_to_vec())
+        }
+
+        fn decode(buf: Vec<u8>) -> TtResult<M> {
+            M::decode(&*buf).map_err(err_to_codec_err!(e, "Decode message failed."))
+        }
+    }
+}
+
 /// Message of ttrpc.
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct Message<C> {
@@ -323,7 +397,12 @@ where
 
 impl<C: Codec> Message<C> {
     pub fn new_request(stream_id: u32, message: C) -> TtResult<Self> {
-        check_oversize(message.size() as usize, false)?;
+        Self::new_request_with_limit(stream_id, message, MESSAGE_LENGTH_MAX)
+    }
+
+    /// Creates a request message whose size must not exceed `max_len`.
+    pub fn new_request_with_limit(stream_id: u32, message: C, max_len: usize) -> TtResult<Self> {
+        check_oversize(message.size() as usize, max_len, false)?;
 
         Ok(Self {
             header: MessageHeader::new_request(stream_id, message.size()),
@@ -350,25 +429,38 @@ where
         let content = self
             .payload
             .encode()
-            .map_err(err_to_others_err!(e, "Encode payload failed."))?;
+            .map_err(err_to_codec_msg!(e, "Encode payload failed."))?;
         writer
             .write_all(&content)
             .await
             .map_err(|e| Error::Socket(e.to_string()))?;
+        writer
+            .flush()
+            .await
+            .map_err(|e| Error::Socket(e.to_string()))?;
         Ok(())
     }
 
     /// Decodes a MessageHeader from reader.
-    pub async fn read_from(mut reader: impl tokio::io::AsyncReadExt + Unpin) -> TtResult<Self> {
+    pub async fn read_from(reader: impl tokio::io::AsyncReadExt + Unpin) -> TtResult<Self> {
+        Self::read_from_with_limit(reader, MESSAGE_LENGTH_MAX).await
+    }
+
+    /// Decodes a MessageHeader from reader, the body of a message longer
+    /// than `max_len` is discarded and the payload is left empty.
+    pub async fn read_from_with_limit(
+        mut reader: impl tokio::io::AsyncReadExt + Unpin,
+        max_len: usize,
+    ) -> TtResult<Self> {
         let header = MessageHeader::read_from(&mut reader)
             .await
             .map_err(|e| Error::Socket(e.to_string()))?;
 
-        if check_oversize(header.length as usize, true).is_err() {
+        if check_oversize(header.length as usize, max_len, true).is_err() {
             discard_message_body(reader, &header).await?;
             return Ok(Self {
                 header,
-                payload: C::decode("").map_err(err_to_others_err!(e, "Decode payload failed."))?,
+                payload: C::decode("").map_err(err_to_codec_msg!(e, "Decode payload failed."))?,
             });
         }
 
@@ -377,8 +469,7 @@ where
             .read_exact(&mut content)
             .await
             .map_err(|e| Error::Socket(e.to_string()))?;
-        let payload =
-            C::decode(content).map_err(err_to_others_err!(e, "Decode payload failed."))?;
+        let payload = C::decode(content).map_err(err_to_codec_msg!(e, "Decode payload failed."))?;
         Ok(Self { header, payload })
     }
 }
@@ -464,6 +555,35 @@ mod tests {
         assert_eq!(msg_clone, dmsg);
     }
 
+    #[cfg(feature = "prost")]
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct ProstEcho {
+        #[prost(string, tag = "1")]
+        msg: String,
+        #[prost(uint32, tag = "2")]
+        seq: u32,
+    }
+
+    #[cfg(feature = "prost")]
+    #[test]
+    fn prost_encoding() {
+        let echo = ProstEcho {
+            msg: "ttrpc".to_string(),
+            seq: 7,
+        };
+
+        let buf = ProstEncoding::encode(&echo).unwrap();
+        let decoded: ProstEcho = ProstEncoding::decode(buf).unwrap();
+        assert_eq!(decoded, echo);
+        let err = <ProstEncoding as Encoding<ProstEcho>>::decode(vec![0xff]).unwrap_err();
+        assert!(matches!(err, Error::Codec { .. }));
+
+        assert!(<ProstEncoding as Encoding<()>>::encode(&())
+            .unwrap()
+            .is_empty());
+        <ProstEncoding as Encoding<()>>::decode(vec![]).unwrap();
+    }
+
     #[cfg(feature = "async")]
     #[tokio::test]
     async fn async_message_header() {
@@ -488,7 +608,7 @@ mod tests {
 
         match GenMessage::read_from(&*buf).await {
             Err(GenMessageError::ReturnError(h, Error::RpcStatus(s))) => {
-                if h != header || s.code() != crate::proto::Code::INVALID_ARGUMENT {
+                if h != header || s.code() != crate::proto::Code::RESOURCE_EXHAUSTED {
                     panic!("got invalid error when the size exceeds limit");
                 }
             }
@@ -518,6 +638,52 @@ mod tests {
         assert_eq!(&*dbuf, &buf[..MESSAGE_HEADER_LENGTH + TEST_PAYLOAD_LEN]);
     }
 
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_gen_message_with_limit() {
+        let mut buf = Vec::from(PROTOBUF_MESSAGE_HEADER);
+        buf.extend_from_slice(&PROTOBUF_REQUEST);
+        buf.extend_from_slice(&[0x0, 0x0]);
+
+        // The body is discarded, the data behind it is left untouched.
+        let mut reader = &buf[..];
+        match GenMessage::read_from_with_limit(&mut reader, TEST_PAYLOAD_LEN - 1).await {
+            Err(GenMessageError::ReturnError(h, Error::RpcStatus(s))) => {
+                assert_eq!(h.length as usize, TEST_PAYLOAD_LEN);
+                assert_eq!(s.code(), crate::proto::Code::RESOURCE_EXHAUSTED);
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert_eq!(reader, &[0x0, 0x0]);
+
+        let gen = GenMessage::read_from_with_limit(&buf[..], TEST_PAYLOAD_LEN)
+            .await
+            .unwrap();
+        assert_eq!(&gen.payload, &PROTOBUF_REQUEST);
+        assert!(gen.check_with_limit(TEST_PAYLOAD_LEN).is_ok());
+        assert!(gen.check_with_limit(TEST_PAYLOAD_LEN - 1).is_err());
+
+        let req = Request::new();
+        assert!(Message::new_request_with_limit(1, req.clone(), 0).is_ok());
+        let req = Request {
+            service: "grpc.TestServices".to_string(),
+            ..Default::default()
+        };
+        assert!(Message::new_request_with_limit(1, req, 1).is_err());
+
+        let mut reader = &buf[..];
+        let msg = Message::<Request>::read_from_with_limit(&mut reader, TEST_PAYLOAD_LEN - 1)
+            .await
+            .unwrap();
+        assert_eq!(msg.header.length as usize, TEST_PAYLOAD_LEN);
+        assert_eq!(msg.payload, Request::new());
+        assert_eq!(reader, &[0x0, 0x0]);
+        let msg = Message::<Request>::read_from_with_limit(&buf[..], TEST_PAYLOAD_LEN)
+            .await
+            .unwrap();
+        assert_eq!(&msg.payload.service, "grpc.TestServices");
+    }
+
     #[cfg(feature = "async")]
     #[tokio::test]
     async fn async_message() {
@@ -0,0 +1,192 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! The reflection service and client (async).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use super::{
+    request, response, FileContainingSymbolRequest, FileDescriptorResponse, ListServicesRequest,
+    ListServicesResponse, Reflection, SERVICE_NAME,
+};
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::proto::{Codec, Request, Response};
+use crate::r#async::{Client, MethodHandler, Service, TtrpcContext};
+
+/// Create the reflection service, listing `services` besides itself.
+pub(crate) fn create_reflection(
+    mut reflection: Reflection,
+    services: &HashMap<String, Service>,
+) -> HashMap<String, Service> {
+    reflection.set_services(services.iter().map(|(name, service)| {
+        (
+            name.as_str(),
+            service.methods.keys().map(String::as_str).collect(),
+            service.streams.keys().map(String::as_str).collect(),
+        )
+    }));
+
+    let reflection = Arc::new(reflection);
+    let mut methods = HashMap::new();
+    methods.insert(
+        "ListServices".to_string(),
+        Box::new(ListServicesMethod {
+            reflection: reflection.clone(),
+        }) as Box<dyn MethodHandler + Send + Sync>,
+    );
+    methods.insert(
+        "FileContainingSymbol".to_string(),
+        Box::new(FileContainingSymbolMethod { reflection }) as Box<dyn MethodHandler + Send + Sync>,
+    );
+
+    let mut ret = HashMap::new();
+    ret.insert(
+        SERVICE_NAME.to_string(),
+        Service {
+            methods,
+            streams: HashMap::new(),
+        },
+    );
+    ret
+}
+
+struct ListServicesMethod {
+    reflection: Arc<Reflection>,
+}
+
+#[async_trait]
+impl MethodHandler for ListServicesMethod {
+    async fn handler(&self, _ctx: TtrpcContext, _req: Request) -> Result<Response> {
+        response(Ok(self.reflection.list_services()))
+    }
+}
+
+struct FileContainingSymbolMethod {
+    reflection: Arc<Reflection>,
+}
+
+#[async_trait]
+impl MethodHandler for FileContainingSymbolMethod {
+    async fn handler(&self, _ctx: TtrpcContext, req: Request) -> Result<Response> {
+        let req =
+            FileContainingSymbolRequest::decode(&req.payload).map_err(err_to_others_err!(e, ""))?;
+        response(self.reflection.file_containing_symbol(&req))
+    }
+}
+
+/// The client of the reflection service (async).
+#[derive(Clone)]
+pub struct ReflectionClient {
+    client: Client,
+}
+
+impl ReflectionClient {
+    pub fn new(client: Client) -> Self {
+        ReflectionClient { client }
+    }
+
+    /// List the services of the server and their methods.
+    pub async fn list_services(&self, ctx: Context) -> Result<ListServicesResponse> {
+        let req = request(ctx, "ListServices", &ListServicesRequest::new())?;
+        let res = self.client.request(req).await?;
+        ListServicesResponse::decode(&res.payload)
+            .map_err(err_to_others_err!(e, "Unpack get error "))
+    }
+
+    /// Get the descriptor of the file declaring `symbol`, after the ones it imports.
+    ///
+    /// A symbol the server has no descriptor for fails with `NOT_FOUND`.
+    pub async fn file_containing_symbol(
+        &self,
+        ctx: Context,
+        symbol: &str,
+    ) -> Result<FileDescriptorResponse> {
+        let req = FileContainingSymbolRequest {
+            symbol: symbol.to_string(),
+            ..Default::default()
+        };
+        let res = self
+            .client
+            .request(request(ctx, "FileContainingSymbol", &req)?)
+            .await?;
+        FileDescriptorResponse::decode(&res.payload)
+            .map_err(err_to_others_err!(e, "Unpack get error "))
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[cfg(test)]
+mod tests {
+    use protobuf::Message as _;
+
+    use super::*;
+    use crate::context;
+    use crate::health;
+    use crate::proto::Code;
+    use crate::r#async::Server;
+
+    #[tokio::test]
+    async fn test_reflection() {
+        let addr = r"unix://@/tmp/ttrpc-reflection-unit-test";
+        let health = crate::proto::compiled::health::file_descriptor()
+            .proto()
+            .write_to_bytes()
+            .unwrap();
+        let reflection = Reflection::new().add_file_descriptors(&[&health]).unwrap();
+        let mut server = Server::new()
+            .bind(addr)
+            .unwrap()
+            .register_reflection_service(reflection)
+            .register_health_service(health::HealthReporter::new());
+        server.start().await.unwrap();
+
+        let client = ReflectionClient::new(Client::connect(addr).await.unwrap());
+        let services = client
+            .list_services(context::with_timeout(0))
+            .await
+            .unwrap()
+            .services;
+        let services: Vec<(&str, Vec<(&str, bool)>)> = services
+            .iter()
+            .map(|s| {
+                let methods = s.methods.iter();
+                (
+                    s.name.as_str(),
+                    methods.map(|m| (m.name.as_str(), m.streaming)).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            services,
+            [
+                (
+                    health::SERVICE_NAME,
+                    vec![("Check", false), ("Watch", true)]
+                ),
+                (
+                    SERVICE_NAME,
+                    vec![("FileContainingSymbol", false), ("ListServices", false)]
+                ),
+            ]
+        );
+
+        let res = client
+            .file_containing_symbol(context::with_timeout(0), "grpc.health.v1.Health.Watch")
+            .await
+            .unwrap();
+        assert_eq!(res.file_descriptor_proto, [health]);
+        match client
+            .file_containing_symbol(context::with_timeout(0), "ttrpc.Request")
+            .await
+        {
+            Err(Error::RpcStatus(s)) => assert_eq!(s.code(), Code::NOT_FOUND),
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        server.shutdown().await.unwrap();
+    }
+}
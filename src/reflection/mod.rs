@@ -0,0 +1,293 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! The reflection service of ttrpc, `ttrpc.reflection.v1.ServerReflection`.
+//!
+//! It lists the services registered on a server with their methods. With the descriptors
+//! embedded by the compiler (`Customize::gen_file_descriptors`) and added to [`Reflection`],
+//! it also gives the `FileDescriptorProto`s declaring them, so generic tools can call them.
+
+use std::collections::{BTreeMap, HashMap};
+
+use protobuf::descriptor::{DescriptorProto, FileDescriptorProto};
+use protobuf::Message as _;
+
+use crate::context::Context;
+use crate::error::{get_rpc_status, get_status, Error, Result};
+use crate::proto::{Code, Codec, Request, Response};
+
+#[doc(inline)]
+pub use crate::proto::compiled::reflection::{
+    FileContainingSymbolRequest, FileDescriptorResponse, ListServicesRequest, ListServicesResponse,
+    MethodInfo, ServiceInfo,
+};
+
+cfg_sync! {
+    pub mod sync;
+}
+
+cfg_async! {
+    pub mod asynchronous;
+    #[doc(hidden)]
+    pub use asynchronous as r#async;
+}
+
+/// The name of the reflection service.
+pub const SERVICE_NAME: &str = "ttrpc.reflection.v1.ServerReflection";
+
+/// What the reflection service tells: the services of the server and the descriptors of the
+/// files declaring them.
+#[derive(Debug, Clone, Default)]
+pub struct Reflection {
+    files: HashMap<String, File>,
+    // The files declaring the symbols, by full name.
+    symbols: HashMap<String, String>,
+    services: Vec<ServiceInfo>,
+}
+
+#[derive(Debug, Clone)]
+struct File {
+    descriptor: Vec<u8>,
+    dependencies: Vec<String>,
+}
+
+impl Reflection {
+    pub fn new() -> Reflection {
+        Reflection::default()
+    }
+
+    /// Add serialized `FileDescriptorProto`s, e.g. the `<SERVICE>_FILE_DESCRIPTORS` generated
+    /// by the compiler. A file added twice is kept once.
+    pub fn add_file_descriptors(mut self, descriptors: &[&[u8]]) -> Result<Self> {
+        for descriptor in descriptors {
+            let file = FileDescriptorProto::parse_from_bytes(descriptor)
+                .map_err(err_to_others_err!(e, "invalid file descriptor: "))?;
+            if self.files.contains_key(file.name()) {
+                continue;
+            }
+            for symbol in symbols(&file) {
+                self.symbols.insert(symbol, file.name().to_string());
+            }
+            self.files.insert(
+                file.name().to_string(),
+                File {
+                    descriptor: descriptor.to_vec(),
+                    dependencies: file.dependency.clone(),
+                },
+            );
+        }
+        Ok(self)
+    }
+
+    // Set the services of the server, the reflection service included.
+    fn set_services<'a>(
+        &mut self,
+        services: impl IntoIterator<Item = (&'a str, Vec<&'a str>, Vec<&'a str>)>,
+    ) {
+        let mut all: BTreeMap<&str, Vec<MethodInfo>> = BTreeMap::new();
+        all.insert(
+            SERVICE_NAME,
+            ["FileContainingSymbol", "ListServices"]
+                .iter()
+                .map(|name| method_info(name, false))
+                .collect(),
+        );
+        for (service, methods, streams) in services {
+            let service = all.entry(service).or_default();
+            service.extend(methods.into_iter().map(|name| method_info(name, false)));
+            service.extend(streams.into_iter().map(|name| method_info(name, true)));
+            service.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+
+        self.services = all
+            .into_iter()
+            .map(|(name, methods)| ServiceInfo {
+                name: name.to_string(),
+                methods,
+                ..Default::default()
+            })
+            .collect();
+    }
+
+    fn list_services(&self) -> ListServicesResponse {
+        ListServicesResponse {
+            services: self.services.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn file_containing_symbol(
+        &self,
+        req: &FileContainingSymbolRequest,
+    ) -> Result<FileDescriptorResponse> {
+        let symbol = req.symbol.trim_start_matches('.');
+        let name = self.symbols.get(symbol).ok_or_else(|| {
+            get_rpc_status(Code::NOT_FOUND, format!("unknown symbol {:?}", symbol))
+        })?;
+
+        let mut added = Vec::new();
+        self.add_file(name, &mut added);
+        Ok(FileDescriptorResponse {
+            file_descriptor_proto: added
+                .into_iter()
+                .map(|name| self.files[name].descriptor.clone())
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    // Add `name` to `added` after the files it imports, the unknown ones are left out.
+    fn add_file<'a>(&'a self, name: &'a str, added: &mut Vec<&'a str>) {
+        if added.contains(&name) {
+            return;
+        }
+        if let Some(file) = self.files.get(name) {
+            for dependency in &file.dependencies {
+                self.add_file(dependency, added);
+            }
+            added.push(name);
+        }
+    }
+}
+
+fn method_info(name: &str, streaming: bool) -> MethodInfo {
+    MethodInfo {
+        name: name.to_string(),
+        streaming,
+        ..Default::default()
+    }
+}
+
+// The full names of the messages, enums, services and methods declared in `file`.
+fn symbols(file: &FileDescriptorProto) -> Vec<String> {
+    fn add_message(prefix: &str, message: &DescriptorProto, symbols: &mut Vec<String>) {
+        let name = format!("{}{}", prefix, message.name());
+        for nested in &message.nested_type {
+            add_message(&format!("{}.", name), nested, symbols);
+        }
+        for e in &message.enum_type {
+            symbols.push(format!("{}.{}", name, e.name()));
+        }
+        symbols.push(name);
+    }
+
+    let prefix = if file.package().is_empty() {
+        String::new()
+    } else {
+        format!("{}.", file.package())
+    };
+    let mut symbols = Vec::new();
+    for message in &file.message_type {
+        add_message(&prefix, message, &mut symbols);
+    }
+    for e in &file.enum_type {
+        symbols.push(format!("{}{}", prefix, e.name()));
+    }
+    for service in &file.service {
+        let name = format!("{}{}", prefix, service.name());
+        for method in &service.method {
+            symbols.push(format!("{}.{}", name, method.name()));
+        }
+        symbols.push(name);
+    }
+    symbols
+}
+
+// The request of the `method` of the reflection service.
+fn request(ctx: Context, method: &str, req: &impl Codec<E = protobuf::Error>) -> Result<Request> {
+    let mut creq = Request::new();
+    creq.set_service(SERVICE_NAME.to_string());
+    creq.set_method(method.to_string());
+    creq.set_timeout_nano(ctx.request_timeout_nano()?);
+    creq.set_metadata(ctx.metadata.to_pb());
+    creq.payload = req.encode().map_err(err_to_others_err!(e, ""))?;
+    Ok(creq)
+}
+
+// The response of a call of the reflection service.
+fn response(res: Result<impl Codec<E = protobuf::Error>>) -> Result<Response> {
+    match res {
+        Ok(rep) => {
+            let mut res = Response::new();
+            res.set_status(get_status(Code::OK, ""));
+            res.payload = rep.encode().map_err(err_to_others_err!(e, ""))?;
+            Ok(res)
+        }
+        Err(e) => Ok(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, package: &str, dependencies: &[&str]) -> Vec<u8> {
+        let mut file = FileDescriptorProto::new();
+        file.set_name(name.to_string());
+        file.set_package(package.to_string());
+        file.dependency = dependencies.iter().map(|d| d.to_string()).collect();
+
+        let mut message = DescriptorProto::new();
+        message.set_name(format!("{}Request", package));
+        let mut nested = DescriptorProto::new();
+        nested.set_name("Nested".to_string());
+        message.nested_type.push(nested);
+        file.message_type.push(message);
+        file.write_to_bytes().unwrap()
+    }
+
+    #[test]
+    fn test_file_containing_symbol() {
+        let empty = file("empty.proto", "empty", &[]);
+        let types = file("types.proto", "types", &["empty.proto", "missing.proto"]);
+        let agent = file("agent.proto", "agent", &["types.proto", "empty.proto"]);
+        let reflection = Reflection::new()
+            .add_file_descriptors(&[&agent, &types, &empty, &agent])
+            .unwrap();
+
+        let get = |symbol: &str| {
+            reflection.file_containing_symbol(&FileContainingSymbolRequest {
+                symbol: symbol.to_string(),
+                ..Default::default()
+            })
+        };
+        assert_eq!(
+            get(".agent.agentRequest.Nested")
+                .unwrap()
+                .file_descriptor_proto,
+            [empty.clone(), types.clone(), agent]
+        );
+        assert_eq!(
+            get("types.typesRequest").unwrap().file_descriptor_proto,
+            [empty, types]
+        );
+        match get("agent.Nested") {
+            Err(Error::RpcStatus(s)) => assert_eq!(s.code(), Code::NOT_FOUND),
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_list_services() {
+        let mut reflection = Reflection::new();
+        reflection.set_services([
+            ("agent.Agent", vec!["Start", "Exec"], vec!["Logs"]),
+            ("grpc.health.v1.Health", vec!["Check"], vec!["Watch"]),
+        ]);
+
+        let services = reflection.list_services().services;
+        let names: Vec<_> = services.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["agent.Agent", "grpc.health.v1.Health", SERVICE_NAME]
+        );
+        assert_eq!(
+            services[0].methods,
+            [
+                method_info("Exec", false),
+                method_info("Logs", true),
+                method_info("Start", false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! The reflection service and client (sync).
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use super::{
+    request, response, FileContainingSymbolRequest, FileDescriptorResponse, ListServicesRequest,
+    ListServicesResponse, Reflection, SERVICE_NAME,
+};
+use crate::context::Context;
+use crate::error::{Error, Result};
+use crate::proto::{Codec, Request};
+use crate::sync::{response_to_channel, Client, MethodHandler, StreamHandler, TtrpcContext};
+
+type Methods = HashMap<String, Box<dyn MethodHandler + Send + Sync>>;
+
+/// Create the method handlers of the reflection service, listing the services of `methods`
+/// and `streams` keyed by `/service/method` path.
+pub(crate) fn create_reflection(
+    mut reflection: Reflection,
+    methods: &Methods,
+    streams: &HashMap<String, Arc<dyn StreamHandler + Send + Sync>>,
+) -> Methods {
+    let mut services: BTreeMap<&str, (Vec<&str>, Vec<&str>)> = BTreeMap::new();
+    for path in methods.keys() {
+        if let Some((service, method)) = split_path(path) {
+            services.entry(service).or_default().0.push(method);
+        }
+    }
+    for path in streams.keys() {
+        if let Some((service, method)) = split_path(path) {
+            services.entry(service).or_default().1.push(method);
+        }
+    }
+    reflection.set_services(
+        services
+            .into_iter()
+            .map(|(service, (methods, streams))| (service, methods, streams)),
+    );
+
+    let reflection = Arc::new(reflection);
+    let mut methods = HashMap::new();
+    methods.insert(
+        format!("/{}/ListServices", SERVICE_NAME),
+        Box::new(ListServicesMethod {
+            reflection: reflection.clone(),
+        }) as Box<dyn MethodHandler + Send + Sync>,
+    );
+    methods.insert(
+        format!("/{}/FileContainingSymbol", SERVICE_NAME),
+        Box::new(FileContainingSymbolMethod { reflection }) as Box<dyn MethodHandler + Send + Sync>,
+    );
+    methods
+}
+
+fn split_path(path: &str) -> Option<(&str, &str)> {
+    path.strip_prefix('/')?.rsplit_once('/')
+}
+
+struct ListServicesMethod {
+    reflection: Arc<Reflection>,
+}
+
+impl MethodHandler for ListServicesMethod {
+    fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<()> {
+        let res = response(Ok(self.reflection.list_services()))?;
+        response_to_channel(ctx.mh.stream_id, res, ctx.res_tx)
+    }
+}
+
+struct FileContainingSymbolMethod {
+    reflection: Arc<Reflection>,
+}
+
+impl MethodHandler for FileContainingSymbolMethod {
+    fn handler(&self, ctx: TtrpcContext, req: Request) -> Result<()> {
+        let req =
+            FileContainingSymbolRequest::decode(&req.payload).map_err(err_to_others_err!(e, ""))?;
+        let res = response(self.reflection.file_containing_symbol(&req))?;
+        response_to_channel(ctx.mh.stream_id, res, ctx.res_tx)
+    }
+}
+
+/// The client of the reflection service (sync).
+#[derive(Clone)]
+pub struct ReflectionClient {
+    client: Client,
+}
+
+impl ReflectionClient {
+    pub fn new(client: Client) -> Self {
+        ReflectionClient { client }
+    }
+
+    /// List the services of the server and their methods.
+    pub fn list_services(&self, ctx: Context) -> Result<ListServicesResponse> {
+        let req = request(ctx, "ListServices", &ListServicesRequest::new())?;
+        let res = self.client.request(req)?;
+        ListServicesResponse::decode(&res.payload)
+            .map_err(err_to_others_err!(e, "Unpack get error "))
+    }
+
+    /// Get the descriptor of the file declaring `symbol`, after the ones it imports.
+    ///
+    /// A symbol the server has no descriptor for fails with `NOT_FOUND`.
+    pub fn file_containing_symbol(
+        &self,
+        ctx: Context,
+        symbol: &str,
+    ) -> Result<FileDescriptorResponse> {
+        let req = FileContainingSymbolRequest {
+            symbol: symbol.to_string(),
+            ..Default::default()
+        };
+        let res = self
+            .client
+            .request(request(ctx, "FileContainingSymbol", &req)?)?;
+        FileDescriptorResponse::decode(&res.payload)
+            .map_err(err_to_others_err!(e, "Unpack get error "))
+    }
+}
@@ -0,0 +1,260 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Rich error details on a [`Status`].
+//!
+//! A status can carry messages in its `details`, packed as `Any` with the type URL
+//! `type.googleapis.com/<full name>`, so that clients can act on an error without parsing
+//! its message. The standard messages of `google.rpc`, such as [`ErrorInfo`], [`RetryInfo`]
+//! and [`BadRequest`], are provided; [`StatusBuilder`] adds them to a status and
+//! [`StatusDetails`] gets them back from a status or an [`Error::RpcStatus`].
+
+use protobuf::MessageFull;
+
+use crate::error::Error;
+use crate::proto::{Any, Code, Status};
+
+#[doc(inline)]
+pub use crate::proto::compiled::error_details::{
+    bad_request, help, precondition_failure, quota_failure, BadRequest, DebugInfo, ErrorInfo, Help,
+    LocalizedMessage, PreconditionFailure, QuotaFailure, RequestInfo, ResourceInfo, RetryInfo,
+};
+
+/// The prefix of the type URLs of the details.
+pub const TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+
+/// Builds a [`Status`] with details.
+///
+/// ```
+/// use std::time::Duration;
+/// use ttrpc::status::StatusBuilder;
+/// use ttrpc::Code;
+///
+/// let err = StatusBuilder::new(Code::UNAVAILABLE, "too many calls")
+///     .error_info("OVERLOADED", "example.com", [("queue", "42")])
+///     .retry_info(Duration::from_secs(2))
+///     .into_error();
+/// ```
+#[derive(Clone, Debug)]
+pub struct StatusBuilder {
+    status: Status,
+}
+
+impl StatusBuilder {
+    pub fn new(code: Code, msg: impl ToString) -> Self {
+        StatusBuilder {
+            status: crate::error::get_status(code, msg),
+        }
+    }
+
+    /// Adds `message` to the details.
+    ///
+    /// If `message` cannot be serialized, e.g. a proto2 message missing required fields, the
+    /// status is turned into an `INTERNAL` one telling why, without the detail.
+    pub fn detail<M: MessageFull>(mut self, message: &M) -> Self {
+        let full_name = M::descriptor().full_name().to_string();
+        match message.write_to_bytes() {
+            Ok(value) => self.status.details.push(Any {
+                type_url: format!("{}{}", TYPE_URL_PREFIX, full_name),
+                value,
+                ..Default::default()
+            }),
+            Err(e) => {
+                self.status = crate::error::get_status(
+                    Code::INTERNAL,
+                    format!("serialize the detail {} of a status: {}", full_name, e),
+                )
+            }
+        }
+        self
+    }
+
+    /// Adds an [`ErrorInfo`], the reason of the error in `domain` with its `metadata`.
+    pub fn error_info<K, V>(
+        self,
+        reason: impl ToString,
+        domain: impl ToString,
+        metadata: impl IntoIterator<Item = (K, V)>,
+    ) -> Self
+    where
+        K: ToString,
+        V: ToString,
+    {
+        let info = ErrorInfo {
+            reason: reason.to_string(),
+            domain: domain.to_string(),
+            metadata: metadata
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+        self.detail(&info)
+    }
+
+    /// Adds a [`RetryInfo`], telling the client to retry after `delay`.
+    pub fn retry_info(self, delay: std::time::Duration) -> Self {
+        let info = RetryInfo {
+            retry_delay: protobuf::MessageField::some(delay.into()),
+            ..Default::default()
+        };
+        self.detail(&info)
+    }
+
+    /// Adds a [`BadRequest`] with the `(field, description)` of each violation.
+    pub fn bad_request<F, D>(self, violations: impl IntoIterator<Item = (F, D)>) -> Self
+    where
+        F: ToString,
+        D: ToString,
+    {
+        let req = BadRequest {
+            field_violations: violations
+                .into_iter()
+                .map(|(field, description)| bad_request::FieldViolation {
+                    field: field.to_string(),
+                    description: description.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        self.detail(&req)
+    }
+
+    pub fn build(self) -> Status {
+        self.status
+    }
+
+    /// The [`Error::RpcStatus`] of the status, to return from a handler.
+    pub fn into_error(self) -> Error {
+        Error::RpcStatus(self.status)
+    }
+}
+
+/// Gets the details of a status, or of an error if it is [`Error::RpcStatus`].
+pub trait StatusDetails {
+    fn status(&self) -> Option<&Status>;
+
+    /// The details of type `M`, leaving out those which cannot be decoded.
+    fn details<M: MessageFull>(&self) -> Vec<M> {
+        let type_url = format!("{}{}", TYPE_URL_PREFIX, M::descriptor().full_name());
+        self.status()
+            .map(|s| s.details.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .filter(|any| any.type_url == type_url)
+            .filter_map(|any| M::parse_from_bytes(&any.value).ok())
+            .collect()
+    }
+
+    /// The first [`ErrorInfo`] in the details.
+    fn error_info(&self) -> Option<ErrorInfo> {
+        self.details().into_iter().next()
+    }
+
+    /// The delay of the first [`RetryInfo`] in the details.
+    fn retry_delay(&self) -> Option<std::time::Duration> {
+        let info: RetryInfo = self.details().into_iter().next()?;
+        let delay = info.retry_delay.into_option()?;
+        if delay.seconds < 0 || delay.nanos < 0 {
+            return Some(std::time::Duration::ZERO);
+        }
+        Some(delay.into())
+    }
+
+    /// The first [`BadRequest`] in the details.
+    fn bad_request(&self) -> Option<BadRequest> {
+        self.details().into_iter().next()
+    }
+}
+
+impl StatusDetails for Status {
+    fn status(&self) -> Option<&Status> {
+        Some(self)
+    }
+}
+
+impl StatusDetails for Error {
+    fn status(&self) -> Option<&Status> {
+        match self {
+            Error::RpcStatus(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::proto::{Codec, Response};
+
+    #[test]
+    fn test_details() {
+        let status = StatusBuilder::new(Code::INVALID_ARGUMENT, "bad request")
+            .error_info("EMPTY_NAME", "example.com", [("field", "name")])
+            .retry_info(Duration::from_millis(1500))
+            .bad_request([("name", "must not be empty")])
+            .detail(&DebugInfo {
+                detail: "in create".to_string(),
+                ..Default::default()
+            })
+            .build();
+        assert_eq!(status.code(), Code::INVALID_ARGUMENT);
+        assert_eq!(status.message(), "bad request");
+        assert_eq!(
+            status.details[0].type_url,
+            "type.googleapis.com/google.rpc.ErrorInfo"
+        );
+
+        // The details go through a response unchanged.
+        let mut res = Response::new();
+        res.set_status(status);
+        let res = Response::decode(res.encode().unwrap()).unwrap();
+        let err = Error::RpcStatus(res.status.unwrap());
+
+        let info = err.error_info().unwrap();
+        assert_eq!(info.reason, "EMPTY_NAME");
+        assert_eq!(info.domain, "example.com");
+        assert_eq!(info.metadata["field"], "name");
+        assert_eq!(err.retry_delay(), Some(Duration::from_millis(1500)));
+        let violations = err.bad_request().unwrap().field_violations;
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].field, "name");
+        assert_eq!(violations[0].description, "must not be empty");
+        let debug: Vec<DebugInfo> = err.details();
+        assert_eq!(debug[0].detail, "in create");
+        assert!(err.details::<Help>().is_empty());
+    }
+
+    #[test]
+    fn test_no_details() {
+        let err = crate::error::get_rpc_status(Code::NOT_FOUND, "missing");
+        assert_eq!(err.error_info(), None);
+        assert_eq!(err.retry_delay(), None);
+        assert_eq!(Error::Eof.bad_request(), None);
+
+        // A detail which does not decode is left out.
+        let mut status = StatusBuilder::new(Code::INTERNAL, "").build();
+        status.details.push(Any {
+            type_url: "type.googleapis.com/google.rpc.ErrorInfo".to_string(),
+            value: vec![0xff],
+            ..Default::default()
+        });
+        assert_eq!(status.error_info(), None);
+    }
+
+    #[test]
+    fn test_bad_detail() {
+        // A proto2 message missing its required field can't be serialized.
+        let mut message = protobuf::descriptor::UninterpretedOption::new();
+        message.name.push(Default::default());
+        let status = StatusBuilder::new(Code::NOT_FOUND, "missing")
+            .detail(&message)
+            .build();
+        assert_eq!(status.code(), Code::INTERNAL);
+        assert!(status.message().contains("UninterpretedOption"));
+        assert!(status.details.is_empty());
+    }
+}
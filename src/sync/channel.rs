@@ -93,13 +93,20 @@ fn read_message_header(conn: &PipeConnection) -> Result<MessageHeader> {
     Ok(mh)
 }
 
-pub fn read_message(conn: &PipeConnection) -> Result<(MessageHeader, Result<Vec<u8>>)> {
+/// Reads a message, the body of a message longer than `max_len` is
+/// discarded and an error is returned along with the header.
+pub fn read_message(
+    conn: &PipeConnection,
+    max_len: usize,
+) -> Result<(MessageHeader, Result<Vec<u8>>)> {
     let mh = read_message_header(conn)?;
     trace!("Got Message header {:?}", mh);
 
     let mh_len = mh.length as usize;
-    if let Err(e) = check_oversize(mh_len, true) {
+    if let Err(e) = check_oversize(mh_len, max_len, true) {
         discard_count(conn, mh_len)?;
+        #[cfg(unix)]
+        conn.fds().message_discarded();
         return Ok((mh, Err(e)));
     }
 
@@ -112,6 +119,8 @@ pub fn read_message(conn: &PipeConnection) -> Result<(MessageHeader, Result<Vec<
         ));
     }
     trace!("Got Message body {:?}", buf);
+    #[cfg(unix)]
+    conn.fds().message_read(&mh);
 
     Ok((mh, Ok(buf)))
 }
@@ -131,6 +140,8 @@ fn write_message_header(conn: &PipeConnection, mh: MessageHeader) -> Result<()>
 }
 
 pub fn write_message(conn: &PipeConnection, mh: MessageHeader, buf: Vec<u8>) -> Result<()> {
+    #[cfg(unix)]
+    conn.fds().message_writing(&mh);
     write_message_header(conn, mh)?;
 
     let size = write_count(conn, &buf, buf.len())?;
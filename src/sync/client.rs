@@ -15,34 +15,49 @@
 //! Sync client of ttrpc.
 
 #[cfg(unix)]
-use std::os::unix::io::RawFd;
+use std::os::unix::io::{OwnedFd, RawFd};
 
 use protobuf::Message;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
-use crate::error::{Error, Result};
+use crate::error::{get_rpc_status, Error, Result};
+#[cfg(unix)]
+use crate::fd::FdTable;
 use crate::proto::{
-    check_oversize, Code, Codec, MessageHeader, Request, Response, MESSAGE_TYPE_RESPONSE,
+    check_oversize, Code, Codec, MessageHeader, Request, Response, FLAG_NO_DATA,
+    FLAG_REMOTE_CLOSED, FLAG_REMOTE_OPEN, MESSAGE_LENGTH_MAX, MESSAGE_TYPE_DATA,
+    MESSAGE_TYPE_RESPONSE,
 };
 use crate::sync::channel::{read_message, write_message};
+use crate::sync::stream::{Kind, MessageSender, ResultSender, StreamInner, StreamMap};
 use crate::sync::sys::ClientConnection;
+use crate::sync::{ClientInterceptor, ClientNext};
 
 #[cfg(windows)]
 use super::sys::PipeConnection;
 
-type Sender = mpsc::Sender<(Vec<u8>, mpsc::SyncSender<Result<Vec<u8>>>)>;
-type Receiver = mpsc::Receiver<(Vec<u8>, mpsc::SyncSender<Result<Vec<u8>>>)>;
-type ReciverMap = Arc<Mutex<HashMap<u32, mpsc::SyncSender<Result<Vec<u8>>>>>>;
+type Receiver = mpsc::Receiver<(MessageHeader, Vec<u8>)>;
 
 /// A ttrpc Client (sync).
+///
+/// Clones share the connection, with its message size limits. The interceptors are options of
+/// each clone instead, which it passes on to the clones made from it.
 #[derive(Clone)]
 pub struct Client {
     _connection: Arc<ClientConnection>,
-    sender_tx: Sender,
+    sender_tx: MessageSender,
+    streams: StreamMap,
+    next_stream_id: Arc<Mutex<u32>>,
+    max_send_message_size: Arc<AtomicUsize>,
+    max_recv_message_size: Arc<AtomicUsize>,
+    interceptors: Arc<Vec<Arc<dyn ClientInterceptor + Send + Sync>>>,
+    #[cfg(unix)]
+    fds: Arc<FdTable>,
 }
 
 impl Client {
@@ -64,36 +79,30 @@ impl Client {
     fn new_client(pipe_client: ClientConnection) -> Result<Client> {
         let client = Arc::new(pipe_client);
         let weak_client = Arc::downgrade(&client);
-        let (sender_tx, rx): (Sender, Receiver) = mpsc::channel();
-        let recver_map_orig = Arc::new(Mutex::new(HashMap::new()));
+        let (sender_tx, rx): (MessageSender, Receiver) = mpsc::channel();
+        let recver_map_orig: StreamMap = Arc::new(Mutex::new(HashMap::new()));
+        let streams = recver_map_orig.clone();
 
         let receiver_map = recver_map_orig.clone();
+        let max_recv_message_size = Arc::new(AtomicUsize::new(MESSAGE_LENGTH_MAX));
+        let receiver_max_recv_message_size = max_recv_message_size.clone();
         let connection = Arc::new(client.get_pipe_connection()?);
+        #[cfg(unix)]
+        let fds = connection.fds().clone();
         let sender_client = connection.clone();
 
         //Sender
         thread::spawn(move || {
-            let mut stream_id: u32 = 1;
-            for (buf, recver_tx) in rx.iter() {
-                let current_stream_id = stream_id;
-                stream_id += 2;
-                //Put current_stream_id and recver_tx to recver_map
-                {
-                    let mut map = receiver_map.lock().unwrap();
-                    map.insert(current_stream_id, recver_tx.clone());
-                }
-                let mut mh = MessageHeader::new_request(0, buf.len() as u32);
-                mh.set_stream_id(current_stream_id);
-
+            for (mh, buf) in rx.iter() {
+                let stream_id = mh.stream_id;
                 if let Err(e) = write_message(&sender_client, mh, buf) {
-                    //Remove current_stream_id and recver_tx to recver_map
-                    {
-                        let mut map = receiver_map.lock().unwrap();
-                        map.remove(&current_stream_id);
+                    //Remove stream_id and its recver_tx from recver_map
+                    let recver_tx = receiver_map.lock().unwrap().remove(&stream_id);
+                    if let Some(recver_tx) = recver_tx {
+                        recver_tx
+                            .send(Err(e))
+                            .unwrap_or_else(|_e| error!("The request has returned"));
                     }
-                    recver_tx
-                        .send(Err(e))
-                        .unwrap_or_else(|_e| error!("The request has returned"));
                 }
             }
             trace!("Sender quit");
@@ -105,6 +114,8 @@ impl Client {
         //ClientConnection's drop will be not call until the thread finished. It means if all the external references are finished,
         //this thread should be release.
         let receiver_client = weak_client.clone();
+        #[cfg(unix)]
+        let receiver_fds = fds.clone();
         thread::spawn(move || {
             loop {
                 //The count of ClientConnection's Arc will be add one , and back to original value when this code ends. 
@@ -123,9 +134,16 @@ impl Client {
                     break;
                 }
 
-                match read_message(&receiver_connection) {
+                let max_len = receiver_max_recv_message_size.load(Ordering::Relaxed);
+                match read_message(&receiver_connection, max_len) {
                     Ok((mh, buf)) => {
-                        trans_resp(recver_map_orig.clone(), mh, buf);
+                        let _stream_id = mh.stream_id;
+                        if !trans_resp(recver_map_orig.clone(), mh, buf) {
+                            // The call is over, e.g. it timed out, nobody takes the
+                            // descriptors sent with it.
+                            #[cfg(unix)]
+                            receiver_fds.forget(_stream_id);
+                        }
                     }
                     Err(x) => match x {
                         Error::Socket(y) => {
@@ -155,35 +173,131 @@ impl Client {
         Ok(Client {
             _connection: client,
             sender_tx,
+            streams,
+            next_stream_id: Arc::new(Mutex::new(1)),
+            max_send_message_size: Arc::new(AtomicUsize::new(MESSAGE_LENGTH_MAX)),
+            max_recv_message_size,
+            interceptors: Arc::new(Vec::new()),
+            #[cfg(unix)]
+            fds,
         })
     }
+
+    /// Set the maximum length of a message sent on the connection.
+    ///
+    /// The limit is the connection's: it applies to every clone of this [`Client`], those
+    /// made before included.
+    pub fn set_connection_max_send_message_size(&self, size: usize) {
+        self.max_send_message_size.store(size, Ordering::Relaxed);
+    }
+
+    /// Set the maximum length of a message received on the connection.
+    ///
+    /// The limit is the connection's: it applies to every clone of this [`Client`], those
+    /// made before included.
+    pub fn set_connection_max_recv_message_size(&self, size: usize) {
+        self.max_recv_message_size.store(size, Ordering::Relaxed);
+    }
+
+    /// Add an interceptor wrapping the requests and streams of the client.
+    ///
+    /// Interceptors run in the order they are added, the first one is the outermost. The
+    /// interceptor applies to this [`Client`] and the clones made from it afterwards, e.g. by
+    /// generated clients, not to the clones made before.
+    pub fn add_interceptor(
+        mut self,
+        interceptor: Arc<dyn ClientInterceptor + Send + Sync>,
+    ) -> Client {
+        Arc::make_mut(&mut self.interceptors).push(interceptor);
+        self
+    }
+
     pub fn request(&self, req: Request) -> Result<Response> {
-        check_oversize(req.compute_size() as usize, false)?;
+        ClientNext::new(
+            &self.interceptors,
+            Box::new(|req| {
+                self.do_request(
+                    req,
+                    #[cfg(unix)]
+                    &mut Vec::new(),
+                )
+            }),
+        )
+        .run(req)
+    }
+
+    /// Send `req` along with file descriptors, and return the response along with the file
+    /// descriptors sent back by the server.
+    ///
+    /// File descriptors can only be passed over unix sockets.
+    #[cfg(unix)]
+    pub fn request_with_fds(
+        &self,
+        req: Request,
+        mut fds: Vec<OwnedFd>,
+    ) -> Result<(Response, Vec<OwnedFd>)> {
+        let res = ClientNext::new(
+            &self.interceptors,
+            Box::new(|req| self.do_request(req, &mut fds)),
+        )
+        .run(req)?;
+        Ok((res, fds))
+    }
+
+    // On unix, `fds` are sent with the request and replaced by the ones received with the
+    // response.
+    fn do_request(&self, req: Request, #[cfg(unix)] fds: &mut Vec<OwnedFd>) -> Result<Response> {
+        check_oversize(
+            req.compute_size() as usize,
+            self.max_send_message_size.load(Ordering::Relaxed),
+            false,
+        )?;
 
         let buf = req.encode().map_err(err_to_others_err!(e, ""))?;
         // Notice: pure client problem can't be rpc error
 
-        let (tx, rx) = mpsc::sync_channel(0);
-
-        self.sender_tx
-            .send((buf, tx))
-            .map_err(err_to_others_err!(e, "Send packet to sender error "))?;
+        let (tx, rx) = mpsc::channel();
+        let _stream_id = self.send_request(
+            buf,
+            0,
+            tx,
+            #[cfg(unix)]
+            std::mem::take(fds),
+        )?;
+        // The call is forgotten however it ends, e.g. when it times out.
+        let _guard = CallGuard {
+            stream_id: _stream_id,
+            streams: &self.streams,
+        };
 
         let result = if req.timeout_nano == 0 {
-            rx.recv().map_err(err_to_others_err!(
-                e,
-                "Receive packet from Receiver error: "
-            ))?
+            rx.recv()
+                .map_err(|e| Error::Closed(format!("Receive packet from Receiver error: {e}")))
         } else {
             rx.recv_timeout(Duration::from_nanos(req.timeout_nano as u64))
-                .map_err(err_to_others_err!(
-                    e,
-                    "Receive packet from Receiver timeout: "
-                ))?
+                .map_err(|e| match e {
+                    mpsc::RecvTimeoutError::Timeout => {
+                        Error::Timeout(format!("Receive packet from Receiver timeout: {e}"))
+                    }
+                    mpsc::RecvTimeoutError::Disconnected => {
+                        Error::Closed(format!("Receive packet from Receiver error: {e}"))
+                    }
+                })
         };
 
-        let buf = result?;
-        let res = Response::decode(buf).map_err(err_to_others_err!(e, "Unpack response error "))?;
+        #[cfg(unix)]
+        match result {
+            Ok(Ok(_)) => *fds = self.fds.take_received(_stream_id),
+            // The descriptors are not written or taken anymore.
+            _ => self.fds.forget(_stream_id),
+        }
+        let (mh, buf) = result??;
+        if mh.type_ != MESSAGE_TYPE_RESPONSE {
+            return Err(Error::Others(format!(
+                "Recver got malformed packet {mh:?} {buf:?}"
+            )));
+        }
+        let res = Response::decode(buf).map_err(err_to_codec_err!(e, "Unpack response error "))?;
 
         let status = res.status();
         if status.code() != Code::OK {
@@ -192,6 +306,110 @@ impl Client {
 
         Ok(res)
     }
+
+    /// Open a stream of `req`.
+    ///
+    /// The payload of `req` is sent as the only request message unless
+    /// `streaming_client` is set, in which case it must be empty.
+    pub fn new_stream(
+        &self,
+        req: Request,
+        streaming_client: bool,
+        streaming_server: bool,
+    ) -> Result<StreamInner> {
+        ClientNext::new(
+            &self.interceptors,
+            Box::new(|req| self.do_new_stream(req, streaming_client, streaming_server)),
+        )
+        .run(req)
+    }
+
+    fn do_new_stream(
+        &self,
+        req: Request,
+        streaming_client: bool,
+        streaming_server: bool,
+    ) -> Result<StreamInner> {
+        let max_send_message_size = self.max_send_message_size.load(Ordering::Relaxed);
+        check_oversize(req.compute_size() as usize, max_send_message_size, false)?;
+
+        let flags = if streaming_client {
+            if !req.payload.is_empty() {
+                return Err(get_rpc_status(
+                    Code::INVALID_ARGUMENT,
+                    "Creating a ClientStream and sending payload at the same time is not allowed",
+                ));
+            }
+            FLAG_REMOTE_OPEN | FLAG_NO_DATA
+        } else {
+            FLAG_REMOTE_CLOSED
+        };
+
+        let buf = req.encode().map_err(err_to_others_err!(e, ""))?;
+        let (tx, rx) = mpsc::channel();
+        let stream_id = self.send_request(
+            buf,
+            flags,
+            tx,
+            #[cfg(unix)]
+            Vec::new(),
+        )?;
+
+        Ok(StreamInner::new(
+            stream_id,
+            self.sender_tx.clone(),
+            rx,
+            streaming_client,
+            streaming_server,
+            Kind::Client,
+            self.streams.clone(),
+        )
+        .with_max_send_message_size(max_send_message_size)
+        .with_client_guard(self.clone()))
+    }
+
+    /// Send a request message on a new stream whose messages go to `tx`.
+    fn send_request(
+        &self,
+        buf: Vec<u8>,
+        flags: u8,
+        tx: ResultSender,
+        #[cfg(unix)] fds: Vec<OwnedFd>,
+    ) -> Result<u32> {
+        // Hold the lock until the request is queued so that stream ids reach
+        // the server in increasing order.
+        let mut next_stream_id = self.next_stream_id.lock().unwrap();
+        let stream_id = *next_stream_id;
+
+        let mut mh = MessageHeader::new_request(stream_id, buf.len() as u32);
+        mh.set_flags(flags);
+
+        #[cfg(unix)]
+        self.fds.attach(stream_id, fds)?;
+        self.streams.lock().unwrap().insert(stream_id, tx);
+        if let Err(e) = self.sender_tx.send((mh, buf)) {
+            self.streams.lock().unwrap().remove(&stream_id);
+            return Err(Error::Others(format!("Send packet to sender error {e:?}")));
+        }
+
+        *next_stream_id += 2;
+        Ok(stream_id)
+    }
+}
+
+/// Removes a call from `streams` once dropped, so that a late response is dropped as one of
+/// an unknown stream.
+struct CallGuard<'a> {
+    stream_id: u32,
+    streams: &'a StreamMap,
+}
+
+impl Drop for CallGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut streams) = self.streams.lock() {
+            streams.remove(&self.stream_id);
+        }
+    }
 }
 
 impl Drop for ClientConnection {
@@ -213,29 +431,79 @@ impl Drop for PipeConnection {
     }
 }
 
-/// Transfer the response
-fn trans_resp(recver_map_orig: ReciverMap, mh: MessageHeader, buf: Result<Vec<u8>>) {
+/// Transfer the response or stream data
+// Returns whether the message is passed on to its call.
+fn trans_resp(recver_map_orig: StreamMap, mh: MessageHeader, buf: Result<Vec<u8>>) -> bool {
     let mut map = recver_map_orig.lock().unwrap();
     let recver_tx = match map.get(&mh.stream_id) {
         Some(tx) => tx,
         None => {
             debug!("Recver got unknown packet {:?} {:?}", mh, buf);
-            return;
+            return false;
         }
     };
-    if mh.type_ != MESSAGE_TYPE_RESPONSE {
+    if mh.type_ != MESSAGE_TYPE_RESPONSE && mh.type_ != MESSAGE_TYPE_DATA {
         recver_tx
             .send(Err(Error::Others(format!(
                 "Recver got malformed packet {:?} {:?}",
                 mh, buf
             ))))
             .unwrap_or_else(|_e| error!("The request has returned"));
-        return;
+        return false;
+    }
+
+    let closed =
+        mh.type_ == MESSAGE_TYPE_RESPONSE || (mh.flags & FLAG_REMOTE_CLOSED) == FLAG_REMOTE_CLOSED;
+    let stream_id = mh.stream_id;
+    let sent = recver_tx.send(buf.map(|buf| (mh, buf))).is_ok();
+    if !sent {
+        error!("The request has returned");
+    }
+
+    if closed {
+        map.remove(&stream_id);
     }
+    sent
+}
 
-    recver_tx
-        .send(buf)
-        .unwrap_or_else(|_e| error!("The request has returned"));
+#[cfg(target_os = "linux")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::{MethodHandler, Server, TtrpcContext};
 
-    map.remove(&mh.stream_id);
+    // Never answers.
+    struct Silent;
+
+    impl MethodHandler for Silent {
+        fn handler(&self, _ctx: TtrpcContext, _req: Request) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_timeout() {
+        let addr = "unix://@/tmp/ttrpc-sync-client-unit-test-timeout";
+        let methods = HashMap::from([(
+            "/test.Silent/Silent".to_string(),
+            Box::new(Silent) as Box<dyn MethodHandler + Send + Sync>,
+        )]);
+        let mut server = Server::new().bind(addr).unwrap().register_service(methods);
+        server.start().unwrap();
+
+        let client = Client::connect(addr).unwrap();
+        let req = Request {
+            service: "test.Silent".to_string(),
+            method: "Silent".to_string(),
+            timeout_nano: Duration::from_millis(100).as_nanos() as i64,
+            ..Default::default()
+        };
+        match client.request(req) {
+            Err(Error::Timeout(_)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert!(client.streams.lock().unwrap().is_empty());
+
+        server.shutdown();
+    }
 }
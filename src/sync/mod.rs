@@ -8,6 +8,7 @@
 mod channel;
 mod client;
 mod server;
+mod stream;
 mod sys;
 
 #[macro_use]
@@ -15,7 +16,14 @@ mod utils;
 
 pub use client::Client;
 pub use server::Server;
+pub use stream::{
+    CSReceiver, CSSender, ClientStream, ClientStreamReceiver, ClientStreamSender, Kind, SSReceiver,
+    SSSender, ServerStream, ServerStreamReceiver, ServerStreamSender, StreamInner, StreamReceiver,
+    StreamSender,
+};
 
 #[doc(hidden)]
 pub use utils::response_to_channel;
-pub use utils::{MethodHandler, TtrpcContext};
+pub use utils::{
+    ClientInterceptor, ClientNext, Interceptor, MethodHandler, Next, StreamHandler, TtrpcContext,
+};
@@ -16,7 +16,7 @@
 //!
 
 #[cfg(unix)]
-use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
 use std::time::Duration;
 
 use protobuf::{CodedInputStream, Message};
@@ -27,13 +27,30 @@ use std::sync::{Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
 
-use super::utils::{response_error_to_channel, response_to_channel};
+use super::stream::{Kind, ResultReceiver, StreamInner, StreamMap};
+use super::utils::{limit_response_size, response_error_to_channel, response_to_channel};
+#[cfg(unix)]
+use crate::activation::InheritedListener;
+#[cfg(unix)]
+use crate::bind::SocketFile;
 use crate::context;
 use crate::error::{get_status, Error, Result};
-use crate::proto::{Code, MessageHeader, Request, Response, MESSAGE_TYPE_REQUEST};
+#[cfg(unix)]
+use crate::fd::CallFds;
+use crate::health::{self, HealthReporter};
+use crate::metadata::Metadata;
+use crate::peer::Authorizer;
+use crate::proto::{
+    Code, Codec, MessageHeader, Request, Response, FLAG_NO_DATA, FLAG_REMOTE_CLOSED,
+    FLAG_REMOTE_OPEN, MESSAGE_LENGTH_MAX, MESSAGE_TYPE_DATA, MESSAGE_TYPE_REQUEST,
+};
+use crate::reflection::{self, Reflection};
 use crate::sync::channel::{read_message, write_message};
 use crate::sync::sys::{PipeConnection, PipeListener};
-use crate::{MethodHandler, TtrpcContext};
+use crate::sync::{Interceptor, Next};
+#[cfg(unix)]
+use crate::UnixBindOptions;
+use crate::{MethodHandler, StreamHandler, TtrpcContext};
 
 // poll_queue will create WAIT_THREAD_COUNT_DEFAULT threads in begin.
 // If wait thread count < WAIT_THREAD_COUNT_MIN, create number to WAIT_THREAD_COUNT_DEFAULT.
@@ -45,8 +62,12 @@ const DEFAULT_ACCEPT_RETRY_INTERVAL: Duration = Duration::from_secs(10);
 
 type MessageSender = Sender<(MessageHeader, Vec<u8>)>;
 type MessageReceiver = Receiver<(MessageHeader, Vec<u8>)>;
-type WorkloadSender = crossbeam::channel::Sender<(MessageHeader, Result<Vec<u8>>)>;
-type WorkloadReceiver = crossbeam::channel::Receiver<(MessageHeader, Result<Vec<u8>>)>;
+type Workload = (MessageHeader, Result<Vec<u8>>, Option<ResultReceiver>);
+type WorkloadSender = crossbeam::channel::Sender<Workload>;
+type WorkloadReceiver = crossbeam::channel::Receiver<Workload>;
+type StreamHandlers = Arc<HashMap<String, Arc<dyn StreamHandler + Send + Sync>>>;
+type Interceptors = Arc<Vec<Arc<dyn Interceptor + Send + Sync>>>;
+type SharedAuthorizer = Option<Arc<dyn Authorizer + Send + Sync>>;
 
 /// A ttrpc Server (sync).
 pub struct Server {
@@ -54,12 +75,21 @@ pub struct Server {
     listener_quit_flag: Arc<AtomicBool>,
     connections: Arc<Mutex<HashMap<i32, Connection>>>,
     methods: Arc<HashMap<String, Box<dyn MethodHandler + Send + Sync>>>,
+    streams: StreamHandlers,
+    interceptors: Interceptors,
+    authorizer: SharedAuthorizer,
     handler: Option<JoinHandle<()>>,
     reaper: Option<(Sender<i32>, JoinHandle<()>)>,
     thread_count_default: usize,
     thread_count_min: usize,
     thread_count_max: usize,
     accept_retry_interval: Duration,
+    max_send_message_size: usize,
+    max_recv_message_size: usize,
+    // Registered once all services are.
+    reflection: Option<Reflection>,
+    #[cfg(unix)]
+    socket_files: Vec<SocketFile>,
 }
 
 struct Connection {
@@ -87,12 +117,17 @@ struct ThreadS<'a> {
     wtc: &'a Arc<AtomicUsize>,
     quit: &'a Arc<AtomicBool>,
     methods: &'a Arc<HashMap<String, Box<dyn MethodHandler + Send + Sync>>>,
+    streams: &'a StreamHandlers,
+    interceptors: &'a Interceptors,
+    authorizer: &'a SharedAuthorizer,
+    stream_map: &'a StreamMap,
     res_tx: &'a MessageSender,
     control_tx: &'a SyncSender<()>,
     cancel_rx: &'a crossbeam::channel::Receiver<()>,
     default: usize,
     min: usize,
     max: usize,
+    max_send_message_size: usize,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -102,13 +137,19 @@ fn start_method_handler_thread(
     wtc: Arc<AtomicUsize>,
     quit: Arc<AtomicBool>,
     methods: Arc<HashMap<String, Box<dyn MethodHandler + Send + Sync>>>,
+    streams: StreamHandlers,
+    interceptors: Interceptors,
+    authorizer: SharedAuthorizer,
+    stream_map: StreamMap,
     res_tx: MessageSender,
     control_tx: SyncSender<()>,
     cancel_rx: crossbeam::channel::Receiver<()>,
     min: usize,
     max: usize,
+    max_send_message_size: usize,
 ) {
     thread::spawn(move || {
+        let peer = connection.peer();
         while !quit.load(Ordering::SeqCst) {
             let c = wtc.fetch_add(1, Ordering::SeqCst) + 1;
             if c > max {
@@ -134,21 +175,8 @@ fn start_method_handler_thread(
                     .unwrap_or_else(|err| trace!("Failed to send {:?}", err));
             }
 
-            let mh;
-            let buf;
-            match result {
-                Ok((x, Ok(y))) => {
-                    mh = x;
-                    buf = y;
-                }
-                Ok((mh, Err(e))) => {
-                    if let Err(x) = response_error_to_channel(mh.stream_id, e, res_tx.clone()) {
-                        debug!("response_error_to_channel get error {:?}", x);
-                        quit_connection(quit, control_tx);
-                        break;
-                    }
-                    continue;
-                }
+            let (mh, buf, stream_rx) = match result {
+                Ok(x) => x,
                 Err(x) => match x {
                     crossbeam::channel::RecvError => {
                         trace!("workload_rx recv error");
@@ -157,7 +185,35 @@ fn start_method_handler_thread(
                         break;
                     }
                 },
-            }
+            };
+            // The descriptors sent with the request are closed if it isn't handled.
+            #[cfg(unix)]
+            let fds = CallFds::new(mh.stream_id, connection.fds().clone());
+            // The stream registered by the reader thread is unregistered
+            // once the inner stream is dropped.
+            let stream_inner = stream_rx.map(|rx| {
+                StreamInner::new(
+                    mh.stream_id,
+                    res_tx.clone(),
+                    rx,
+                    true,
+                    true,
+                    Kind::Server,
+                    stream_map.clone(),
+                )
+                .with_max_send_message_size(max_send_message_size)
+            });
+            let buf = match buf {
+                Ok(buf) => buf,
+                Err(e) => {
+                    if let Err(x) = response_error_to_channel(mh.stream_id, e, res_tx.clone()) {
+                        debug!("response_error_to_channel get error {:?}", x);
+                        quit_connection(quit, control_tx);
+                        break;
+                    }
+                    continue;
+                }
+            };
 
             if mh.type_ != MESSAGE_TYPE_REQUEST {
                 continue;
@@ -177,30 +233,98 @@ fn start_method_handler_thread(
             }
             trace!("Got Message request {:?}", req);
 
-            let path = format!("/{}/{}", req.service, req.method);
-            let method = if let Some(x) = methods.get(&path) {
-                x
-            } else {
-                let status = get_status(Code::INVALID_ARGUMENT, format!("{path} does not exist"));
-                let mut res = Response::new();
-                res.set_status(status);
-                if let Err(x) = response_to_channel(mh.stream_id, res, res_tx.clone()) {
-                    info!("response_to_channel get error {:?}", x);
-                    quit_connection(quit, control_tx);
-                    break;
+            if let Some(authorizer) = &authorizer {
+                if let Err(e) = authorizer.authorize_call(peer.as_ref(), &req) {
+                    let status = match e {
+                        Error::RpcStatus(status) => status,
+                        e => get_status(Code::PERMISSION_DENIED, e),
+                    };
+                    let mut res = Response::new();
+                    res.set_status(status);
+                    if let Err(x) = response_to_channel(mh.stream_id, res, res_tx.clone()) {
+                        info!("response_to_channel get error {:?}", x);
+                        quit_connection(quit, control_tx);
+                        break;
+                    }
+                    continue;
                 }
-                continue;
-            };
+            }
+
+            let path = format!("/{}/{}", req.service, req.method);
+            let is_stream = streams.contains_key(&path);
+            let handler: Box<dyn FnOnce(TtrpcContext, Request) -> Result<Option<Response>>> =
+                if let Some(stream) = streams.get(&path) {
+                    let res_tx = res_tx.clone();
+                    let stream_map = stream_map.clone();
+                    Box::new(move |ctx: TtrpcContext, req: Request| {
+                        let inner = stream_inner.unwrap_or_else(|| {
+                            // Fake the first data message.
+                            let (tx, rx) = channel();
+                            let data =
+                                MessageHeader::new_data(ctx.mh.stream_id, req.payload.len() as u32);
+                            tx.send(Ok((data, req.payload)))
+                                .unwrap_or_else(|err| trace!("Failed to send {:?}", err));
+                            StreamInner::new(
+                                ctx.mh.stream_id,
+                                res_tx.clone(),
+                                rx,
+                                true,
+                                true,
+                                Kind::Server,
+                                stream_map,
+                            )
+                            .with_max_send_message_size(max_send_message_size)
+                        });
+                        stream.handler(ctx, inner)
+                    })
+                } else if let Some(method) = methods.get(&path) {
+                    Box::new(move |mut ctx: TtrpcContext, req| {
+                        // The handler responds through `ctx.res_tx`, take the response back
+                        // for the interceptors.
+                        let (tx, rx) = channel();
+                        ctx.res_tx = tx;
+                        method.handler(ctx, req)?;
+                        rx.try_iter()
+                            .next()
+                            .map(|(_, buf)| Response::decode(buf))
+                            .transpose()
+                            .map_err(err_to_codec_err!(e, ""))
+                    })
+                } else {
+                    let service = format!("/{}/", req.service);
+                    let known = methods
+                        .keys()
+                        .chain(streams.keys())
+                        .any(|p| p.starts_with(&service));
+                    let status = if known {
+                        get_status(Code::UNIMPLEMENTED, format!("method {}", req.method))
+                    } else {
+                        get_status(Code::UNIMPLEMENTED, format!("service {}", req.service))
+                    };
+                    let mut res = Response::new();
+                    res.set_status(status);
+                    if let Err(x) = response_to_channel(mh.stream_id, res, res_tx.clone()) {
+                        info!("response_to_channel get error {:?}", x);
+                        quit_connection(quit, control_tx);
+                        break;
+                    }
+                    continue;
+                };
             let ctx = TtrpcContext {
                 fd: connection.id(),
                 cancel_rx: cancel_rx.clone(),
                 mh,
                 res_tx: res_tx.clone(),
-                metadata: context::from_pb(&req.metadata),
+                metadata: Metadata::from_pb(&req.metadata),
                 timeout_nano: req.timeout_nano,
+                deadline: context::deadline_of(req.timeout_nano),
+                peer: peer.clone(),
+                #[cfg(unix)]
+                fds,
             };
-            if let Err(x) = method.handler(ctx, req) {
-                debug!("method handle {} get error {:?}", path, x);
+            let res = Next::new(&interceptors, handler).run(ctx, req);
+            if let Err(x) = send_result(mh.stream_id, res, is_stream, res_tx.clone()) {
+                debug!("handle {} get error {:?}", path, x);
                 quit_connection(quit, control_tx);
                 break;
             }
@@ -219,11 +343,16 @@ fn start_method_handler_threads(num: usize, ts: &ThreadS) {
             ts.wtc.clone(),
             ts.quit.clone(),
             ts.methods.clone(),
+            ts.streams.clone(),
+            ts.interceptors.clone(),
+            ts.authorizer.clone(),
+            ts.stream_map.clone(),
             ts.res_tx.clone(),
             ts.control_tx.clone(),
             ts.cancel_rx.clone(),
             ts.min,
             ts.max,
+            ts.max_send_message_size,
         );
     }
 }
@@ -242,12 +371,20 @@ impl Default for Server {
             listener_quit_flag: Arc::new(AtomicBool::new(false)),
             connections: Arc::new(Mutex::new(HashMap::new())),
             methods: Arc::new(HashMap::new()),
+            streams: Arc::new(HashMap::new()),
+            interceptors: Arc::new(Vec::new()),
+            authorizer: None,
             handler: None,
             reaper: None,
             thread_count_default: DEFAULT_WAIT_THREAD_COUNT_DEFAULT,
             thread_count_min: DEFAULT_WAIT_THREAD_COUNT_MIN,
             thread_count_max: DEFAULT_WAIT_THREAD_COUNT_MAX,
             accept_retry_interval: DEFAULT_ACCEPT_RETRY_INTERVAL,
+            max_send_message_size: MESSAGE_LENGTH_MAX,
+            max_recv_message_size: MESSAGE_LENGTH_MAX,
+            reflection: None,
+            #[cfg(unix)]
+            socket_files: Vec::new(),
         }
     }
 }
@@ -270,6 +407,26 @@ impl Server {
         Ok(self)
     }
 
+    /// Bind the unix socket `sockaddr`, its socket file is set up with `options`.
+    #[cfg(unix)]
+    pub fn bind_unix(mut self, sockaddr: &str, options: UnixBindOptions) -> Result<Server> {
+        if !self.listeners.is_empty() {
+            return Err(Error::Others(
+                "ttrpc-rust just support 1 sockaddr now".to_string(),
+            ));
+        }
+
+        let path = sockaddr
+            .strip_prefix("unix://")
+            .ok_or_else(|| Error::Others(format!("Scheme of {sockaddr:?} is not unix")))?;
+        let (listener, file) =
+            options.bind(path, |path| PipeListener::new(&format!("unix://{}", path)))?;
+
+        self.listeners.push(Arc::new(listener));
+        self.socket_files.extend(file);
+        Ok(self)
+    }
+
     #[cfg(unix)]
     pub fn add_listener(mut self, fd: RawFd) -> Result<Server> {
         if !self.listeners.is_empty() {
@@ -285,6 +442,53 @@ impl Server {
         Ok(self)
     }
 
+    /// Add a listener inherited by the process.
+    #[cfg(unix)]
+    pub fn add_inherited_listener(mut self, listener: InheritedListener) -> Result<Server> {
+        if !self.listeners.is_empty() {
+            return Err(Error::Others(
+                "ttrpc-rust just support 1 sockaddr now".to_string(),
+            ));
+        }
+
+        let (fd, _) = listener.into_listener()?;
+        let listener = PipeListener::new_from_fd(fd.as_raw_fd())?;
+        // The listener owns it from now on.
+        let _ = fd.into_raw_fd();
+        self.listeners.push(Arc::new(listener));
+        Ok(self)
+    }
+
+    /// Add the listener passed to the process with socket activation, see
+    /// [`activation`](crate::activation).
+    ///
+    /// Unlike the async server, it takes a single listener: if more are passed, or the server
+    /// already has one, it fails and leaves them to the process.
+    #[cfg(unix)]
+    pub fn add_activated_listeners(self) -> Result<Server> {
+        match crate::activation::listen_fds_env()?.len() {
+            0 => {
+                return Err(Error::Others(
+                    "no listener is passed with socket activation".to_string(),
+                ))
+            }
+            1 if self.listeners.is_empty() => {}
+            count => {
+                return Err(Error::Others(format!(
+                    "the sync server takes a single listener, {} are passed with socket activation and it has {}",
+                    count,
+                    self.listeners.len()
+                )))
+            }
+        }
+
+        let listener = crate::activation::listen_fds()?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::Others("the activated listener is gone".to_string()))?;
+        self.add_inherited_listener(listener)
+    }
+
     pub fn register_service(
         mut self,
         methods: HashMap<String, Box<dyn MethodHandler + Send + Sync>>,
@@ -294,6 +498,60 @@ impl Server {
         self
     }
 
+    /// Register the handlers of streaming methods, keyed by `/service/method` path.
+    ///
+    /// Each open stream holds a thread of its connection until its handler returns, see
+    /// [`StreamHandler`].
+    pub fn register_stream_service(
+        mut self,
+        streams: HashMap<String, Arc<dyn StreamHandler + Send + Sync>>,
+    ) -> Server {
+        let mut_streams = Arc::get_mut(&mut self.streams).unwrap();
+        mut_streams.extend(streams);
+        self
+    }
+
+    /// Register the health service, reporting the statuses set with `reporter`.
+    ///
+    /// A `Watch` holds a thread until its connection is closed.
+    pub fn register_health_service(self, reporter: HealthReporter) -> Server {
+        self.register_service(health::sync::create_health(reporter.clone()))
+            .register_stream_service(health::sync::create_health_streams(reporter))
+    }
+
+    /// Register the reflection service, telling the services of the server and the descriptors
+    /// added to `reflection`.
+    ///
+    /// It lists the services registered by the time the server starts.
+    pub fn register_reflection_service(mut self, reflection: Reflection) -> Server {
+        self.reflection = Some(reflection);
+        self
+    }
+
+    // Register the reflection service, now that the other services are.
+    fn register_reflection(&mut self) {
+        if let Some(reflection) = self.reflection.take() {
+            let reflection =
+                reflection::sync::create_reflection(reflection, &self.methods, &self.streams);
+            Arc::get_mut(&mut self.methods).unwrap().extend(reflection);
+        }
+    }
+
+    /// Add an interceptor wrapping the method and stream handlers.
+    ///
+    /// Interceptors run in the order they are added, the first one is the outermost.
+    pub fn add_interceptor(mut self, interceptor: Arc<dyn Interceptor + Send + Sync>) -> Server {
+        let interceptors = Arc::get_mut(&mut self.interceptors).unwrap();
+        interceptors.push(interceptor);
+        self
+    }
+
+    /// Set the authorizer checking the accepted connections and their calls.
+    pub fn set_authorizer(mut self, authorizer: Arc<dyn Authorizer + Send + Sync>) -> Server {
+        self.authorizer = Some(authorizer);
+        self
+    }
+
     pub fn set_thread_count_default(mut self, count: usize) -> Server {
         self.thread_count_default = count;
         self
@@ -314,6 +572,22 @@ impl Server {
         self
     }
 
+    /// Set the maximum length of a message the server sends.
+    ///
+    /// Responses exceeding it are replaced by an error status.
+    pub fn set_max_send_message_size(mut self, size: usize) -> Server {
+        self.max_send_message_size = size;
+        self
+    }
+
+    /// Set the maximum length of a message the server receives.
+    ///
+    /// Requests exceeding it are discarded and answered with an error status.
+    pub fn set_max_recv_message_size(mut self, size: usize) -> Server {
+        self.max_recv_message_size = size;
+        self
+    }
+
     pub fn start_listen(&mut self) -> Result<()> {
         let connections = self.connections.clone();
 
@@ -322,14 +596,20 @@ impl Server {
         }
 
         self.listener_quit_flag.store(false, Ordering::SeqCst);
+        self.register_reflection();
 
         let listener = self.listeners[0].clone();
         let methods = self.methods.clone();
+        let streams = self.streams.clone();
+        let interceptors = self.interceptors.clone();
+        let authorizer = self.authorizer.clone();
         let default = self.thread_count_default;
         let min = self.thread_count_min;
         let max = self.thread_count_max;
         let listener_quit_flag = self.listener_quit_flag.clone();
         let accept_retry_interval = self.accept_retry_interval;
+        let max_send_message_size = self.max_send_message_size;
+        let max_recv_message_size = self.max_recv_message_size;
 
         let reaper_tx = match self.reaper.take() {
             None => {
@@ -395,7 +675,19 @@ impl Server {
                         }
                     };
 
+                    if let Some(authorizer) = &authorizer {
+                        let peer = pipe_connection.peer();
+                        if let Err(e) = authorizer.authorize_connection(peer.as_ref()) {
+                            info!("connection of {:?} is refused: {:?}", peer, e);
+                            pipe_connection.close().unwrap_or(());
+                            continue;
+                        }
+                    }
+
                     let methods = methods.clone();
+                    let streams = streams.clone();
+                    let interceptors = interceptors.clone();
+                    let authorizer = authorizer.clone();
                     let quit = Arc::new(AtomicBool::new(false));
                     let child_quit = quit.clone();
                     let reaper_tx_child = reaper_tx.clone();
@@ -412,7 +704,9 @@ impl Server {
                             let handler = thread::spawn(move || {
                                 for r in res_rx.iter() {
                                     trace!("response thread get {:?}", r);
-                                    if let Err(e) = write_message(&pipe, r.0, r.1) {
+                                    let r = limit_response_size(r.0, r.1, max_send_message_size)
+                                        .and_then(|(mh, buf)| write_message(&pipe, mh, buf));
+                                    if let Err(e) = r {
                                         error!("write_message got {:?}", e);
                                         quit_res.store(true, Ordering::SeqCst);
                                         break;
@@ -432,12 +726,40 @@ impl Server {
                                 crossbeam::channel::unbounded();
                             let (cancel_tx, cancel_rx) = crossbeam::channel::unbounded::<()>();
                             let control_tx_reader = control_tx.clone();
+                            let stream_map: StreamMap = Arc::new(Mutex::new(HashMap::new()));
+                            let reader_stream_map = stream_map.clone();
+                            let res_tx_reader = res_tx.clone();
                             let reader = thread::spawn(move || {
                                 while !quit_reader.load(Ordering::SeqCst) {
-                                    let msg = read_message(&pipe_reader);
+                                    let msg = read_message(&pipe_reader, max_recv_message_size);
                                     match msg {
                                         Ok((x, y)) => {
-                                            let res = workload_tx.send((x, y));
+                                            if x.type_ == MESSAGE_TYPE_DATA {
+                                                handle_data(
+                                                    &reader_stream_map,
+                                                    x,
+                                                    y,
+                                                    &res_tx_reader,
+                                                )
+                                                .unwrap_or_else(|err| {
+                                                    debug!("handle_data get error {:?}", err)
+                                                });
+                                                continue;
+                                            }
+                                            // Register the stream before handing the request over,
+                                            // so that the following data is not lost.
+                                            let mut z = None;
+                                            if x.type_ == MESSAGE_TYPE_REQUEST
+                                                && (x.flags & FLAG_REMOTE_OPEN) == FLAG_REMOTE_OPEN
+                                            {
+                                                let (tx, rx) = channel();
+                                                reader_stream_map
+                                                    .lock()
+                                                    .unwrap()
+                                                    .insert(x.stream_id, tx);
+                                                z = Some(rx);
+                                            }
+                                            let res = workload_tx.send((x, y, z));
                                             match res {
                                                 Ok(_) => {}
                                                 Err(crossbeam::channel::SendError(e)) => {
@@ -481,6 +803,10 @@ impl Server {
                                 workload_rx: &workload_rx,
                                 wtc: &Arc::new(AtomicUsize::new(0)),
                                 methods: &methods,
+                                streams: &streams,
+                                interceptors: &interceptors,
+                                authorizer: &authorizer,
+                                stream_map: &stream_map,
                                 res_tx: &res_tx,
                                 control_tx: &control_tx,
                                 cancel_rx: &cancel_rx,
@@ -488,6 +814,7 @@ impl Server {
                                 default,
                                 min,
                                 max,
+                                max_send_message_size,
                             };
                             start_method_handler_threads(ts.default, &ts);
 
@@ -587,8 +914,13 @@ impl Server {
         info!("reaper thread stopped");
     }
 
-    pub fn shutdown(self) {
+    #[cfg_attr(not(unix), allow(unused_mut))]
+    pub fn shutdown(mut self) {
+        #[cfg(unix)]
+        let socket_files = std::mem::take(&mut self.socket_files);
         self.stop_listen().disconnect();
+        #[cfg(unix)]
+        socket_files.iter().for_each(SocketFile::remove);
     }
 }
 
@@ -606,6 +938,88 @@ impl AsRawFd for Server {
     }
 }
 
+/// Pass a data message to the stream it belongs to.
+///
+/// Like the Go implementation, a close message with data closes the stream but
+/// its data is refused.
+fn handle_data(
+    stream_map: &StreamMap,
+    mut mh: MessageHeader,
+    buf: Result<Vec<u8>>,
+    res_tx: &MessageSender,
+) -> Result<()> {
+    let stream_id = mh.stream_id;
+    let respond = |status| {
+        let mut res = Response::new();
+        res.set_status(status);
+        response_to_channel(stream_id, res, res_tx.clone())
+    };
+    let tx = match stream_map.lock().unwrap().get(&stream_id) {
+        Some(tx) => tx.clone(),
+        None => {
+            return respond(get_status(
+                Code::INVALID_ARGUMENT,
+                "StreamID is no longer active",
+            ))
+        }
+    };
+
+    let closed_with_data = (mh.flags & FLAG_REMOTE_CLOSED) == FLAG_REMOTE_CLOSED
+        && buf.as_ref().map_or(false, |buf| !buf.is_empty());
+    let buf = if closed_with_data {
+        respond(get_status(
+            Code::INVALID_ARGUMENT,
+            "data close message cannot include data",
+        ))?;
+        mh = MessageHeader::new_data(stream_id, 0);
+        mh.set_flags(FLAG_REMOTE_CLOSED | FLAG_NO_DATA);
+        Ok(Vec::new())
+    } else {
+        buf
+    };
+
+    match tx.send(buf.map(|buf| (mh, buf))) {
+        Ok(_) => Ok(()),
+        Err(e) => respond(get_status(
+            Code::INVALID_ARGUMENT,
+            format!("Stream id {stream_id}: handling data error: {e}"),
+        )),
+    }
+}
+
+/// Run a stream handler and finish the stream with its result.
+// Send the result of the interceptors and handler of a call: a failure that isn't a status
+// fails the call alone, with an INTERNAL status.
+fn send_result(
+    stream_id: u32,
+    res: Result<Option<Response>>,
+    is_stream: bool,
+    res_tx: MessageSender,
+) -> Result<()> {
+    match res {
+        Ok(Some(res)) => response_to_channel(stream_id, res, res_tx),
+        Ok(None) if is_stream => {
+            let mut mh = MessageHeader::new_data(stream_id, 0);
+            mh.set_flags(FLAG_REMOTE_CLOSED | FLAG_NO_DATA);
+            res_tx
+                .send((mh, Vec::new()))
+                .map_err(|e| Error::Closed(format!("Send packet to sender error {e}")))
+        }
+        // The method handler sent nothing.
+        Ok(None) => Ok(()),
+        Err(Error::RpcStatus(status)) => {
+            let mut res = Response::new();
+            res.set_status(status);
+            response_to_channel(stream_id, res, res_tx)
+        }
+        Err(e) => {
+            let mut res = Response::new();
+            res.set_status(get_status(Code::INTERNAL, e));
+            response_to_channel(stream_id, res, res_tx)
+        }
+    }
+}
+
 fn quit_connection(quit: Arc<AtomicBool>, control_tx: SyncSender<()>) {
     quit.store(true, Ordering::SeqCst);
     // the client connection would be closed and
@@ -623,3 +1037,505 @@ fn is_resource_limit_error(e: std::io::Error) -> bool {
 
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::get_rpc_status;
+    use crate::peer::Peer;
+    use crate::sync::Client;
+
+    fn request(service: &str, method: &str) -> Request {
+        Request {
+            service: service.to_string(),
+            method: method.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn respond(ctx: TtrpcContext, payload: Vec<u8>) -> Result<()> {
+        let mut res = Response::new();
+        res.set_status(get_status(Code::OK, ""));
+        res.payload = payload;
+        response_to_channel(ctx.mh.stream_id, res, ctx.res_tx)
+    }
+
+    fn start(mut server: Server) -> Server {
+        server.start().unwrap();
+        server
+    }
+
+    // Echo the data of the stream back, or sum their lengths once the client is done.
+    struct Echo {
+        sum: bool,
+    }
+
+    impl StreamHandler for Echo {
+        fn handler(&self, _ctx: TtrpcContext, mut inner: StreamInner) -> Result<Option<Response>> {
+            let mut len = 0;
+            loop {
+                match inner.recv() {
+                    Ok(buf) if self.sum => len += buf.len(),
+                    Ok(buf) => inner.send(buf)?,
+                    Err(Error::Eof) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            if !self.sum {
+                return Ok(None);
+            }
+            let mut res = Response::new();
+            res.set_status(get_status(Code::OK, ""));
+            res.payload = len.to_string().into_bytes();
+            Ok(Some(res))
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_streaming() {
+        let addr = "unix://@/tmp/ttrpc-sync-server-unit-test-streaming";
+        let streams = HashMap::from([
+            (
+                "/test.Stream/Echo".to_string(),
+                Arc::new(Echo { sum: false }) as Arc<dyn StreamHandler + Send + Sync>,
+            ),
+            ("/test.Stream/Sum".to_string(), Arc::new(Echo { sum: true })),
+        ]);
+        let server = start(
+            Server::new()
+                .bind(addr)
+                .unwrap()
+                .register_stream_service(streams),
+        );
+        let client = Client::connect(addr).unwrap();
+
+        let mut echo = client
+            .new_stream(request("test.Stream", "Echo"), true, true)
+            .unwrap();
+        echo.send(b"ping".to_vec()).unwrap();
+        echo.send(b"pong".to_vec()).unwrap();
+        echo.close_send().unwrap();
+        assert_eq!(echo.recv().unwrap(), b"ping");
+        assert_eq!(echo.recv().unwrap(), b"pong");
+        assert!(matches!(echo.recv(), Err(Error::Eof)));
+
+        let mut sum = client
+            .new_stream(request("test.Stream", "Sum"), true, false)
+            .unwrap();
+        sum.send(b"ping".to_vec()).unwrap();
+        sum.send(b"ping pong".to_vec()).unwrap();
+        sum.close_send().unwrap();
+        assert_eq!(sum.recv().unwrap(), b"13");
+
+        match client
+            .new_stream(request("test.Stream", "Unknown"), true, true)
+            .and_then(|mut s| s.recv())
+        {
+            Err(Error::RpcStatus(s)) => assert_eq!(s.code(), Code::UNIMPLEMENTED),
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        server.shutdown();
+    }
+
+    struct Whoami;
+
+    impl MethodHandler for Whoami {
+        fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<()> {
+            let user = ctx.metadata.get("user").unwrap_or_default().to_string();
+            respond(ctx, user.into_bytes())
+        }
+    }
+
+    struct Auth;
+
+    impl Interceptor for Auth {
+        fn intercept(
+            &self,
+            mut ctx: TtrpcContext,
+            req: Request,
+            next: Next<'_>,
+        ) -> Result<Option<Response>> {
+            if !ctx.metadata.contains_key("token") {
+                return Err(get_rpc_status(Code::PERMISSION_DENIED, "no token"));
+            }
+            ctx.metadata.append("user", req.method.clone())?;
+            next.run(ctx, req)
+        }
+    }
+
+    // Fails the calls of `Fail` with an error that isn't a status, and marks the responses.
+    struct Mark;
+
+    impl Interceptor for Mark {
+        fn intercept(
+            &self,
+            ctx: TtrpcContext,
+            req: Request,
+            next: Next<'_>,
+        ) -> Result<Option<Response>> {
+            if req.method == "Fail" {
+                return Err(Error::Others("failed".to_string()));
+            }
+            let mut res = next.run(ctx, req)?;
+            if let Some(res) = &mut res {
+                res.payload.extend_from_slice(b"!");
+            }
+            Ok(res)
+        }
+    }
+
+    struct Token;
+
+    impl crate::sync::ClientInterceptor for Token {
+        fn intercept(
+            &self,
+            mut req: Request,
+            next: crate::sync::ClientNext<'_, Response>,
+        ) -> Result<Response> {
+            req.metadata.push(crate::proto::KeyValue {
+                key: "token".to_string(),
+                value: "secret".to_string(),
+                ..Default::default()
+            });
+            next.run(req)
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_interceptors() {
+        let addr = "unix://@/tmp/ttrpc-sync-server-unit-test-interceptors";
+        let methods = HashMap::from([
+            (
+                "/test.Auth/Whoami".to_string(),
+                Box::new(Whoami) as Box<dyn MethodHandler + Send + Sync>,
+            ),
+            ("/test.Auth/Fail".to_string(), Box::new(Whoami)),
+        ]);
+        let server = start(
+            Server::new()
+                .bind(addr)
+                .unwrap()
+                .register_service(methods)
+                .add_interceptor(Arc::new(Auth))
+                .add_interceptor(Arc::new(Mark)),
+        );
+
+        // The clones made afterwards have the client interceptor, those made before don't.
+        let client = Client::connect(addr).unwrap();
+        let earlier = client.clone();
+        let client = client.add_interceptor(Arc::new(Token));
+        match earlier.request(request("test.Auth", "Whoami")) {
+            Err(Error::RpcStatus(s)) => assert_eq!(s.code(), Code::PERMISSION_DENIED),
+            r => panic!("unexpected result {:?}", r),
+        }
+        let res = client
+            .clone()
+            .request(request("test.Auth", "Whoami"))
+            .unwrap();
+        assert_eq!(res.payload, b"Whoami!");
+
+        // The error fails the call alone, the connection is still up.
+        match client.request(request("test.Auth", "Fail")) {
+            Err(Error::RpcStatus(s)) => assert_eq!(s.code(), Code::INTERNAL),
+            r => panic!("unexpected result {:?}", r),
+        }
+        let res = client.request(request("test.Auth", "Whoami")).unwrap();
+        assert_eq!(res.payload, b"Whoami!");
+
+        server.shutdown();
+    }
+
+    struct PeerPid;
+
+    impl MethodHandler for PeerPid {
+        fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<()> {
+            let pid = match ctx.peer {
+                Some(Peer::Unix { pid: Some(pid), .. }) => pid.to_string(),
+                _ => String::new(),
+            };
+            respond(ctx, pid.into_bytes())
+        }
+    }
+
+    struct Forbidden;
+
+    impl Authorizer for Forbidden {
+        fn authorize_call(&self, peer: Option<&Peer>, req: &Request) -> Result<()> {
+            match peer {
+                Some(Peer::Unix { uid, .. }) if *uid == nix::unistd::getuid().as_raw() => {}
+                _ => return Err(Error::Others("unknown peer".to_string())),
+            }
+            if req.method == "Forbidden" {
+                return Err(get_rpc_status(Code::PERMISSION_DENIED, "forbidden"));
+            }
+            Ok(())
+        }
+    }
+
+    fn peer_methods() -> HashMap<String, Box<dyn MethodHandler + Send + Sync>> {
+        HashMap::from([
+            (
+                "/test.Peer/PeerPid".to_string(),
+                Box::new(PeerPid) as Box<dyn MethodHandler + Send + Sync>,
+            ),
+            ("/test.Peer/Forbidden".to_string(), Box::new(PeerPid)),
+        ])
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_peer_authorizer() {
+        let addr = "unix://@/tmp/ttrpc-sync-server-unit-test-peer";
+        let server = start(
+            Server::new()
+                .bind(addr)
+                .unwrap()
+                .register_service(peer_methods())
+                .set_authorizer(Arc::new(Forbidden)),
+        );
+
+        let client = Client::connect(addr).unwrap();
+        let res = client.request(request("test.Peer", "PeerPid")).unwrap();
+        assert_eq!(res.payload, std::process::id().to_string().into_bytes());
+        match client.request(request("test.Peer", "Forbidden")) {
+            Err(Error::RpcStatus(s)) => assert_eq!(s.code(), Code::PERMISSION_DENIED),
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        server.shutdown();
+    }
+
+    // Echo the content of the received pipes with a pipe.
+    #[cfg(unix)]
+    struct PipeEcho;
+
+    #[cfg(unix)]
+    impl MethodHandler for PipeEcho {
+        fn handler(&self, ctx: TtrpcContext, _req: Request) -> Result<()> {
+            let content = ctx
+                .fds
+                .take()
+                .into_iter()
+                .map(read_pipe)
+                .collect::<String>();
+            ctx.fds.send(vec![pipe_with(&content)])?;
+            respond(ctx, Vec::new())
+        }
+    }
+
+    #[cfg(unix)]
+    fn pipe_with(content: &str) -> std::os::fd::OwnedFd {
+        use std::io::Write as _;
+        use std::os::fd::OwnedFd;
+
+        let (rx, tx) = nix::unistd::pipe().unwrap();
+        // Safety: the descriptors were just created by pipe.
+        let (rx, tx) = unsafe { (OwnedFd::from_raw_fd(rx), OwnedFd::from_raw_fd(tx)) };
+        std::fs::File::from(tx)
+            .write_all(content.as_bytes())
+            .unwrap();
+        rx
+    }
+
+    #[cfg(unix)]
+    fn read_pipe(fd: std::os::fd::OwnedFd) -> String {
+        use std::io::Read as _;
+
+        let mut content = String::new();
+        std::fs::File::from(fd)
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_pass_fds() {
+        let addr = "unix://@/tmp/ttrpc-sync-server-unit-test-fds";
+        let methods = HashMap::from([(
+            "/test.Fds/PipeEcho".to_string(),
+            Box::new(PipeEcho) as Box<dyn MethodHandler + Send + Sync>,
+        )]);
+        let server = start(Server::new().bind(addr).unwrap().register_service(methods));
+
+        let client = Client::connect(addr).unwrap();
+        let req = request("test.Fds", "PipeEcho");
+        let (_, fds) = client
+            .request_with_fds(req.clone(), vec![pipe_with("ping"), pipe_with("pong")])
+            .unwrap();
+        let content: String = fds.into_iter().map(read_pipe).collect();
+        assert_eq!(content, "pingpong");
+
+        // The descriptors only go with the call they are attached to.
+        let (_, fds) = client.request_with_fds(req, Vec::new()).unwrap();
+        assert_eq!(fds.into_iter().map(read_pipe).collect::<String>(), "");
+
+        server.shutdown();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_bind_unix_options() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let dir = std::env::temp_dir().join(format!("ttrpc-sync-bind-{}", std::process::id()));
+        let path = dir.join("run").join("ttrpc.sock");
+        let addr = format!("unix://{}", path.display());
+        let options = || {
+            UnixBindOptions::new()
+                .create_dirs(true)
+                .remove_stale(true)
+                .remove_on_shutdown(true)
+                .mode(0o600)
+        };
+
+        // Left over by a crashed server.
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(Server::new().bind(&addr).is_err());
+
+        let server = start(
+            Server::new()
+                .bind_unix(&addr, options())
+                .unwrap()
+                .register_service(peer_methods()),
+        );
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // It's not stale while a server listens on it.
+        assert!(Server::new().bind_unix(&addr, options()).is_err());
+        let client = Client::connect(&addr).unwrap();
+        client.request(request("test.Peer", "PeerPid")).unwrap();
+
+        server.shutdown();
+        assert!(!path.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_health_service() {
+        use crate::health::{sync::HealthClient, HealthReporter, ServingStatus};
+
+        let addr = "unix://@/tmp/ttrpc-sync-server-unit-test-health";
+        let reporter = HealthReporter::new();
+        reporter.set_not_serving("agent");
+        let server = start(
+            Server::new()
+                .bind(addr)
+                .unwrap()
+                .register_health_service(reporter.clone()),
+        );
+
+        let client = HealthClient::new(Client::connect(addr).unwrap());
+        let res = client.check(context::with_timeout(0), "").unwrap();
+        assert_eq!(res.status.enum_value(), Ok(ServingStatus::SERVING));
+        match client.check(context::with_timeout(0), "unknown") {
+            Err(Error::RpcStatus(s)) => assert_eq!(s.code(), Code::NOT_FOUND),
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let mut watch = client.watch(context::with_timeout(0), "agent").unwrap();
+        let mut next = || {
+            let res = watch.recv().unwrap().unwrap();
+            res.status.enum_value().unwrap()
+        };
+        assert_eq!(next(), ServingStatus::NOT_SERVING);
+        reporter.set_serving("agent");
+        assert_eq!(next(), ServingStatus::SERVING);
+        reporter.shutdown();
+        assert_eq!(next(), ServingStatus::NOT_SERVING);
+
+        server.shutdown();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_reflection_service() {
+        use crate::reflection::sync::ReflectionClient;
+
+        let addr = "unix://@/tmp/ttrpc-sync-server-unit-test-reflection";
+        let health = crate::proto::compiled::health::file_descriptor()
+            .proto()
+            .write_to_bytes()
+            .unwrap();
+        let reflection = Reflection::new().add_file_descriptors(&[&health]).unwrap();
+        let server = start(
+            Server::new()
+                .bind(addr)
+                .unwrap()
+                .register_reflection_service(reflection)
+                .register_health_service(HealthReporter::new()),
+        );
+
+        let client = ReflectionClient::new(Client::connect(addr).unwrap());
+        let services = client
+            .list_services(context::with_timeout(0))
+            .unwrap()
+            .services;
+        let services: Vec<(&str, Vec<(&str, bool)>)> = services
+            .iter()
+            .map(|s| {
+                let methods = s.methods.iter();
+                (
+                    s.name.as_str(),
+                    methods.map(|m| (m.name.as_str(), m.streaming)).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            services,
+            [
+                (
+                    health::SERVICE_NAME,
+                    vec![("Check", false), ("Watch", true)]
+                ),
+                (
+                    reflection::SERVICE_NAME,
+                    vec![("FileContainingSymbol", false), ("ListServices", false)]
+                ),
+            ]
+        );
+
+        let res = client
+            .file_containing_symbol(context::with_timeout(0), "grpc.health.v1.Health.Watch")
+            .unwrap();
+        assert_eq!(res.file_descriptor_proto, [health]);
+
+        server.shutdown();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_activated_listeners() {
+        // Two listeners are passed: they are left in the environment, not half taken.
+        std::env::set_var("LISTEN_PID", std::process::id().to_string());
+        std::env::set_var("LISTEN_FDS", "2");
+        assert!(Server::new().add_activated_listeners().is_err());
+        assert_eq!(std::env::var("LISTEN_FDS").as_deref(), Ok("2"));
+
+        std::env::remove_var("LISTEN_PID");
+        std::env::remove_var("LISTEN_FDS");
+        assert!(Server::new().add_activated_listeners().is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_add_inherited_listener() {
+        let dir = std::env::temp_dir().join(format!("ttrpc-sync-inherited-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let inherited = || {
+            let listener = std::os::unix::net::UnixListener::bind(dir.join("s")).unwrap();
+            std::fs::remove_file(dir.join("s")).unwrap();
+            InheritedListener::new(listener.into(), None).unwrap()
+        };
+
+        let server = Server::new().add_inherited_listener(inherited()).unwrap();
+        assert!(server.add_inherited_listener(inherited()).is_err());
+        std::fs::remove_dir(dir).unwrap();
+    }
+}
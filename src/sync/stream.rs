@@ -0,0 +1,466 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Blocking streams of ttrpc (sync).
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+use super::Client;
+use crate::error::{Error, Result};
+use crate::proto::{
+    check_oversize, Code, Codec, DefaultEncoding, Encoding, MessageHeader, Response, FLAG_NO_DATA,
+    FLAG_REMOTE_CLOSED, MESSAGE_LENGTH_MAX, MESSAGE_TYPE_DATA, MESSAGE_TYPE_RESPONSE,
+};
+
+pub(crate) type MessageSender = mpsc::Sender<(MessageHeader, Vec<u8>)>;
+
+pub(crate) type ResultSender = mpsc::Sender<Result<(MessageHeader, Vec<u8>)>>;
+pub(crate) type ResultReceiver = mpsc::Receiver<Result<(MessageHeader, Vec<u8>)>>;
+
+pub(crate) type StreamMap = Arc<Mutex<HashMap<u32, ResultSender>>>;
+
+#[derive(Debug)]
+pub struct ClientStream<Q, P, C = DefaultEncoding> {
+    tx: CSSender<Q, C>,
+    rx: CSReceiver<P, C>,
+}
+
+impl<Q, P, C> ClientStream<Q, P, C>
+where
+    C: Encoding<Q> + Encoding<P>,
+{
+    pub fn new(inner: StreamInner) -> Self {
+        let (tx, rx) = inner.split();
+        Self {
+            tx: CSSender {
+                tx,
+                _send: PhantomData,
+            },
+            rx: CSReceiver {
+                rx,
+                _recv: PhantomData,
+            },
+        }
+    }
+
+    pub fn split(self) -> (CSSender<Q, C>, CSReceiver<P, C>) {
+        (self.tx, self.rx)
+    }
+
+    pub fn send(&self, req: &Q) -> Result<()> {
+        self.tx.send(req)
+    }
+
+    pub fn close_send(&self) -> Result<()> {
+        self.tx.close_send()
+    }
+
+    pub fn recv(&mut self) -> Result<P> {
+        self.rx.recv()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CSSender<Q, C = DefaultEncoding> {
+    tx: StreamSender,
+    _send: PhantomData<(Q, C)>,
+}
+
+impl<Q, C> CSSender<Q, C>
+where
+    C: Encoding<Q>,
+{
+    pub fn send(&self, req: &Q) -> Result<()> {
+        let msg_buf = <C as Encoding<Q>>::encode(req)?;
+        self.tx.send(msg_buf)
+    }
+
+    pub fn close_send(&self) -> Result<()> {
+        self.tx.close_send()
+    }
+}
+
+#[derive(Debug)]
+pub struct CSReceiver<P, C = DefaultEncoding> {
+    rx: StreamReceiver,
+    _recv: PhantomData<(P, C)>,
+}
+
+impl<P, C> CSReceiver<P, C>
+where
+    C: Encoding<P>,
+{
+    pub fn recv(&mut self) -> Result<P> {
+        let msg_buf = self.rx.recv()?;
+        <C as Encoding<P>>::decode(msg_buf)
+    }
+}
+
+#[derive(Debug)]
+pub struct ServerStream<P, Q, C = DefaultEncoding> {
+    tx: SSSender<P, C>,
+    rx: SSReceiver<Q, C>,
+}
+
+impl<P, Q, C> ServerStream<P, Q, C>
+where
+    C: Encoding<P> + Encoding<Q>,
+{
+    pub fn new(inner: StreamInner) -> Self {
+        let (tx, rx) = inner.split();
+        Self {
+            tx: SSSender {
+                tx,
+                _send: PhantomData,
+            },
+            rx: SSReceiver {
+                rx,
+                _recv: PhantomData,
+            },
+        }
+    }
+
+    pub fn split(self) -> (SSSender<P, C>, SSReceiver<Q, C>) {
+        (self.tx, self.rx)
+    }
+
+    pub fn send(&self, resp: &P) -> Result<()> {
+        self.tx.send(resp)
+    }
+
+    pub fn recv(&mut self) -> Result<Option<Q>> {
+        self.rx.recv()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SSSender<P, C = DefaultEncoding> {
+    tx: StreamSender,
+    _send: PhantomData<(P, C)>,
+}
+
+impl<P, C> SSSender<P, C>
+where
+    C: Encoding<P>,
+{
+    pub fn send(&self, resp: &P) -> Result<()> {
+        let msg_buf = <C as Encoding<P>>::encode(resp)?;
+        self.tx.send(msg_buf)
+    }
+}
+
+#[derive(Debug)]
+pub struct SSReceiver<Q, C = DefaultEncoding> {
+    rx: StreamReceiver,
+    _recv: PhantomData<(Q, C)>,
+}
+
+impl<Q, C> SSReceiver<Q, C>
+where
+    C: Encoding<Q>,
+{
+    pub fn recv(&mut self) -> Result<Option<Q>> {
+        let res = self.rx.recv();
+
+        if matches!(res, Err(Error::Eof)) {
+            return Ok(None);
+        }
+        let msg_buf = res?;
+        <C as Encoding<Q>>::decode(msg_buf).map(Some)
+    }
+}
+
+pub struct ClientStreamSender<Q, P, C = DefaultEncoding> {
+    inner: StreamInner,
+    _send: PhantomData<(Q, C)>,
+    _recv: PhantomData<(P, C)>,
+}
+
+impl<Q, P, C> ClientStreamSender<Q, P, C>
+where
+    C: Encoding<Q> + Encoding<P>,
+{
+    pub fn new(inner: StreamInner) -> Self {
+        Self {
+            inner,
+            _send: PhantomData,
+            _recv: PhantomData,
+        }
+    }
+
+    pub fn send(&self, req: &Q) -> Result<()> {
+        let msg_buf = <C as Encoding<Q>>::encode(req)?;
+        self.inner.send(msg_buf)
+    }
+
+    pub fn close_and_recv(&mut self) -> Result<P> {
+        self.inner.close_send()?;
+        let msg_buf = self.inner.recv()?;
+        <C as Encoding<P>>::decode(msg_buf)
+    }
+}
+
+pub struct ServerStreamSender<P, C = DefaultEncoding> {
+    inner: StreamSender,
+    _send: PhantomData<(P, C)>,
+}
+
+impl<P, C> ServerStreamSender<P, C>
+where
+    C: Encoding<P>,
+{
+    pub fn new(inner: StreamInner) -> Self {
+        Self {
+            inner: inner.split().0,
+            _send: PhantomData,
+        }
+    }
+
+    pub fn send(&self, resp: &P) -> Result<()> {
+        let msg_buf = <C as Encoding<P>>::encode(resp)?;
+        self.inner.send(msg_buf)
+    }
+}
+
+pub struct ClientStreamReceiver<P, C = DefaultEncoding> {
+    inner: StreamReceiver,
+    _recv: PhantomData<(P, C)>,
+}
+
+impl<P, C> ClientStreamReceiver<P, C>
+where
+    C: Encoding<P>,
+{
+    pub fn new(inner: StreamInner) -> Self {
+        Self {
+            inner: inner.split().1,
+            _recv: PhantomData,
+        }
+    }
+
+    pub fn recv(&mut self) -> Result<Option<P>> {
+        let res = self.inner.recv();
+        if matches!(res, Err(Error::Eof)) {
+            return Ok(None);
+        }
+        let msg_buf = res?;
+        <C as Encoding<P>>::decode(msg_buf).map(Some)
+    }
+}
+
+pub struct ServerStreamReceiver<Q, C = DefaultEncoding> {
+    inner: StreamReceiver,
+    _recv: PhantomData<(Q, C)>,
+}
+
+impl<Q, C> ServerStreamReceiver<Q, C>
+where
+    C: Encoding<Q>,
+{
+    pub fn new(inner: StreamInner) -> Self {
+        Self {
+            inner: inner.split().1,
+            _recv: PhantomData,
+        }
+    }
+
+    pub fn recv(&mut self) -> Result<Option<Q>> {
+        let res = self.inner.recv();
+        if matches!(res, Err(Error::Eof)) {
+            return Ok(None);
+        }
+        let msg_buf = res?;
+        <C as Encoding<Q>>::decode(msg_buf).map(Some)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Client,
+    Server,
+}
+
+#[derive(Debug)]
+pub struct StreamInner {
+    sender: StreamSender,
+    receiver: StreamReceiver,
+}
+
+impl StreamInner {
+    pub(crate) fn new(
+        stream_id: u32,
+        tx: MessageSender,
+        rx: ResultReceiver,
+        sendable: bool,
+        recveivable: bool,
+        kind: Kind,
+        streams: StreamMap,
+    ) -> Self {
+        Self {
+            sender: StreamSender {
+                tx,
+                stream_id,
+                sendable,
+                local_closed: Arc::new(AtomicBool::new(false)),
+                kind,
+                max_send_message_size: MESSAGE_LENGTH_MAX,
+            },
+            receiver: StreamReceiver {
+                rx,
+                stream_id,
+                recveivable,
+                remote_closed: false,
+                kind,
+                streams,
+                _client_guard: None,
+            },
+        }
+    }
+
+    /// Set the maximum length of a data message sent through the stream.
+    pub(crate) fn with_max_send_message_size(mut self, size: usize) -> Self {
+        self.sender.max_send_message_size = size;
+        self
+    }
+
+    /// Keep the connection of `client` open as long as the stream receives.
+    pub(crate) fn with_client_guard(mut self, client: Client) -> Self {
+        self.receiver._client_guard = Some(client);
+        self
+    }
+
+    fn split(self) -> (StreamSender, StreamReceiver) {
+        (self.sender, self.receiver)
+    }
+
+    pub fn send(&self, buf: Vec<u8>) -> Result<()> {
+        self.sender.send(buf)
+    }
+
+    pub fn close_send(&self) -> Result<()> {
+        self.sender.close_send()
+    }
+
+    pub fn recv(&mut self) -> Result<Vec<u8>> {
+        self.receiver.recv()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StreamSender {
+    tx: MessageSender,
+    stream_id: u32,
+    sendable: bool,
+    local_closed: Arc<AtomicBool>,
+    kind: Kind,
+    max_send_message_size: usize,
+}
+
+pub struct StreamReceiver {
+    rx: ResultReceiver,
+    stream_id: u32,
+    recveivable: bool,
+    remote_closed: bool,
+    kind: Kind,
+    streams: StreamMap,
+    // Hold the Client to keep the connection and its receiver thread alive
+    _client_guard: Option<Client>,
+}
+
+impl std::fmt::Debug for StreamReceiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamReceiver")
+            .field("stream_id", &self.stream_id)
+            .field("recveivable", &self.recveivable)
+            .field("remote_closed", &self.remote_closed)
+            .field("kind", &self.kind)
+            .finish()
+    }
+}
+
+impl Drop for StreamReceiver {
+    fn drop(&mut self) {
+        self.streams.lock().unwrap().remove(&self.stream_id);
+    }
+}
+
+impl StreamSender {
+    pub fn send(&self, buf: Vec<u8>) -> Result<()> {
+        debug_assert!(self.sendable);
+        if self.local_closed.load(Ordering::Relaxed) {
+            debug_assert_eq!(self.kind, Kind::Client);
+            return Err(Error::LocalClosed);
+        }
+        check_oversize(buf.len(), self.max_send_message_size, false)?;
+
+        let mh = MessageHeader::new_data(self.stream_id, buf.len() as u32);
+        self.tx
+            .send((mh, buf))
+            .map_err(|e| Error::Closed(format!("Send data packet to sender error {e}")))
+    }
+
+    pub fn close_send(&self) -> Result<()> {
+        debug_assert_eq!(self.kind, Kind::Client);
+        debug_assert!(self.sendable);
+        if self.local_closed.load(Ordering::Relaxed) {
+            return Err(Error::LocalClosed);
+        }
+        let mut mh = MessageHeader::new_data(self.stream_id, 0);
+        mh.set_flags(FLAG_REMOTE_CLOSED | FLAG_NO_DATA);
+        self.tx
+            .send((mh, Vec::new()))
+            .map_err(|e| Error::Closed(format!("Send data packet to sender error {e}")))?;
+        self.local_closed.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl StreamReceiver {
+    pub fn recv(&mut self) -> Result<Vec<u8>> {
+        if self.remote_closed {
+            return Err(Error::RemoteClosed);
+        }
+        let (mh, buf) = self
+            .rx
+            .recv()
+            .map_err(|e| Error::Closed(format!("Receive packet from Receiver error: {e}")))??;
+
+        let payload = match mh.type_ {
+            MESSAGE_TYPE_RESPONSE => {
+                debug_assert_eq!(self.kind, Kind::Client);
+                self.remote_closed = true;
+                let resp = Response::decode(&buf)
+                    .map_err(err_to_codec_msg!(e, "Decode message failed."))?;
+                if let Some(status) = resp.status.as_ref() {
+                    if status.code() != Code::OK {
+                        return Err(Error::RpcStatus((*status).clone()));
+                    }
+                }
+                resp.payload
+            }
+            MESSAGE_TYPE_DATA => {
+                if !self.recveivable {
+                    self.remote_closed = true;
+                    return Err(Error::Others(
+                        "received data from non-streaming server.".to_string(),
+                    ));
+                }
+                if (mh.flags & FLAG_REMOTE_CLOSED) == FLAG_REMOTE_CLOSED {
+                    self.remote_closed = true;
+                    if (mh.flags & FLAG_NO_DATA) == FLAG_NO_DATA {
+                        return Err(Error::Eof);
+                    }
+                }
+                buf
+            }
+            _ => {
+                return Err(Error::Others("not support".to_string()));
+            }
+        };
+        Ok(payload)
+    }
+}
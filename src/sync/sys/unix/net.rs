@@ -14,10 +14,13 @@
 	limitations under the License.
 */
 use crate::error::Result;
+use crate::fd::FdTable;
+use crate::peer::Peer;
 use nix::sys::socket::*;
 use std::io::{self};
 use std::os::unix::io::RawFd;
 use std::os::unix::prelude::AsRawFd;
+use std::sync::Arc;
 use nix::Error;
 
 use nix::unistd::*;
@@ -149,7 +152,11 @@ impl PipeListener {
         };
 
 
-        Ok(Some(PipeConnection { fd }))
+        Ok(Some(PipeConnection {
+            fd,
+            peer: Peer::from_fd(fd),
+            fds: Arc::new(FdTable::new(fd)),
+        }))
     }
 
     pub fn close(&self) -> Result<()> {
@@ -166,20 +173,35 @@ impl PipeListener {
 
 pub struct PipeConnection {
     fd: RawFd,
+    peer: Option<Peer>,
+    fds: Arc<FdTable>,
 }
 
 impl PipeConnection {
     pub(crate) fn new(fd: RawFd) -> PipeConnection {
-        PipeConnection { fd }
+        PipeConnection {
+            fd,
+            peer: None,
+            fds: Arc::new(FdTable::new(fd)),
+        }
     }
 
     pub(crate) fn id(&self) -> i32 {
         self.fd
     }
 
+    pub(crate) fn peer(&self) -> Option<Peer> {
+        self.peer.clone()
+    }
+
+    /// The file descriptors passed over the connection.
+    pub(crate) fn fds(&self) -> &Arc<FdTable> {
+        &self.fds
+    }
+
     pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
         loop {
-            match  recv(self.fd, buf, MsgFlags::empty()) {
+            match self.fds.recv(self.fd, buf) {
                 Ok(l) => return Ok(l),
                 Err(e) if retryable(e) => {
                     // Should retry
@@ -194,7 +216,7 @@ impl PipeConnection {
 
     pub fn write(&self, buf: &[u8]) -> Result<usize> {
         loop {
-            match send(self.fd, buf, MsgFlags::empty()) {
+            match self.fds.send(self.fd, buf) {
                 Ok(l) => return Ok(l),
                 Err(e) if retryable(e) => {
                     // Should retry
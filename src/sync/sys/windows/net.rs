@@ -209,6 +209,10 @@ impl PipeConnection {
         self.named_pipe as i32
     }
 
+    pub(crate) fn peer(&self) -> Option<crate::peer::Peer> {
+        None
+    }
+
     pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
         trace!("starting read for thread {:?} on pipe instance {}", std::thread::current().id(), self.named_pipe as i32);
         let ol = Overlapped::new_with_event(self.read_event);
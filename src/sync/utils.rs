@@ -4,10 +4,11 @@
 //
 
 use crate::error::{Error, Result};
+use crate::metadata::Metadata;
 use crate::proto::{
     check_oversize, Codec, MessageHeader, Request, Response, MESSAGE_TYPE_RESPONSE,
 };
-use std::collections::HashMap;
+use std::sync::Arc;
 
 /// Response message through a channel.
 /// Eventually  the message will sent to Client.
@@ -16,12 +17,7 @@ pub fn response_to_channel(
     res: Response,
     tx: std::sync::mpsc::Sender<(MessageHeader, Vec<u8>)>,
 ) -> Result<()> {
-    let mut buf = res.encode().map_err(err_to_others_err!(e, ""))?;
-
-    if let Err(e) = check_oversize(buf.len(), true) {
-        let resp: Response = e.into();
-        buf = resp.encode().map_err(err_to_others_err!(e, ""))?;
-    };
+    let buf = res.encode().map_err(err_to_codec_err!(e, ""))?;
 
     let mh = MessageHeader {
         length: buf.len() as u32,
@@ -30,11 +26,30 @@ pub fn response_to_channel(
         flags: 0,
     };
 
-    tx.send((mh, buf)).map_err(err_to_others_err!(e, ""))?;
+    tx.send((mh, buf))
+        .map_err(|e| Error::Closed(e.to_string()))?;
 
     Ok(())
 }
 
+/// Replace a response exceeding `max_len` with an error status.
+pub(crate) fn limit_response_size(
+    mh: MessageHeader,
+    buf: Vec<u8>,
+    max_len: usize,
+) -> Result<(MessageHeader, Vec<u8>)> {
+    if let Err(e) = check_oversize(buf.len(), max_len, true) {
+        let resp: Response = e.into();
+        let buf = resp.encode().map_err(err_to_codec_err!(e, ""))?;
+        return Ok((
+            MessageHeader::new_response(mh.stream_id, buf.len() as u32),
+            buf,
+        ));
+    }
+
+    Ok((mh, buf))
+}
+
 pub fn response_error_to_channel(
     stream_id: u32,
     e: Error,
@@ -50,7 +65,7 @@ macro_rules! request_handler {
         let mut s = CodedInputStream::from_bytes(&$req.payload);
         let mut req = super::$server::$req_type::new();
         req.merge_from(&mut s)
-            .map_err(::ttrpc::err_to_others!(e, ""))?;
+            .map_err(::ttrpc::err_to_codec!(e, ""))?;
 
         let mut res = ::ttrpc::Response::new();
         match $class.service.$req_fn(&$ctx, req) {
@@ -59,18 +74,15 @@ macro_rules! request_handler {
                 res.payload.reserve(rep.compute_size() as usize);
                 let mut s = protobuf::CodedOutputStream::vec(&mut res.payload);
                 rep.write_to(&mut s)
-                    .map_err(::ttrpc::err_to_others!(e, ""))?;
-                s.flush().map_err(::ttrpc::err_to_others!(e, ""))?;
+                    .map_err(::ttrpc::err_to_codec!(e, ""))?;
+                s.flush().map_err(::ttrpc::err_to_codec!(e, ""))?;
             }
             Err(x) => match x {
                 ::ttrpc::Error::RpcStatus(s) => {
                     res.set_status(s);
                 }
                 _ => {
-                    res.set_status(::ttrpc::get_status(
-                        ::ttrpc::Code::UNKNOWN,
-                        format!("{:?}", x),
-                    ));
+                    res.set_status(::ttrpc::get_status(x.code(), format!("{:?}", x)));
                 }
             },
         }
@@ -85,14 +97,14 @@ macro_rules! client_request {
         let mut creq = ::ttrpc::Request::new();
         creq.set_service($server.to_string());
         creq.set_method($method.to_string());
-        creq.set_timeout_nano($ctx.timeout_nano);
-        let md = ::ttrpc::context::to_pb($ctx.metadata);
+        creq.set_timeout_nano($ctx.request_timeout_nano()?);
+        let md = $ctx.metadata.to_pb();
         creq.set_metadata(md);
         creq.payload.reserve($req.compute_size() as usize);
         let mut s = CodedOutputStream::vec(&mut creq.payload);
         $req.write_to(&mut s)
-            .map_err(::ttrpc::err_to_others!(e, ""))?;
-        s.flush().map_err(::ttrpc::err_to_others!(e, ""))?;
+            .map_err(::ttrpc::err_to_codec!(e, ""))?;
+        s.flush().map_err(::ttrpc::err_to_codec!(e, ""))?;
 
         drop(s);
 
@@ -100,10 +112,340 @@ macro_rules! client_request {
         let mut s = CodedInputStream::from_bytes(&res.payload);
         $cres
             .merge_from(&mut s)
-            .map_err(::ttrpc::err_to_others!(e, "Unpack get error "))?;
+            .map_err(::ttrpc::err_to_codec!(e, "Unpack get error "))?;
+    };
+}
+
+/// Handle client streaming in sync mode.
+#[macro_export]
+macro_rules! client_streaming_handler {
+    ($class: ident, $ctx: ident, $inner: ident, $req_fn: ident) => {
+        let stream = ::ttrpc::sync::ServerStreamReceiver::new($inner);
+        let mut res = ::ttrpc::Response::new();
+        match $class.service.$req_fn(&$ctx, stream) {
+            Ok(rep) => {
+                res.set_status(::ttrpc::get_status(::ttrpc::Code::OK, "".to_string()));
+                res.payload.reserve(rep.compute_size() as usize);
+                let mut s = protobuf::CodedOutputStream::vec(&mut res.payload);
+                rep.write_to(&mut s)
+                    .map_err(::ttrpc::err_to_codec!(e, ""))?;
+                s.flush().map_err(::ttrpc::err_to_codec!(e, ""))?;
+            }
+            Err(x) => match x {
+                ::ttrpc::Error::RpcStatus(s) => {
+                    res.set_status(s);
+                }
+                _ => {
+                    res.set_status(::ttrpc::get_status(x.code(), format!("{:?}", x)));
+                }
+            },
+        }
+        return Ok(Some(res));
+    };
+}
+
+/// Handle server streaming in sync mode.
+#[macro_export]
+macro_rules! server_streaming_handler {
+    ($class: ident, $ctx: ident, $inner: ident, $server: ident, $req_type: ident, $req_fn: ident) => {
+        let req_buf = $inner.recv()?;
+        let req = <super::$server::$req_type as ::ttrpc::proto::Codec>::decode(&req_buf)
+            .map_err(::ttrpc::err_to_codec!(e, ""))?;
+        let stream = ::ttrpc::sync::ServerStreamSender::new($inner);
+        match $class.service.$req_fn(&$ctx, req, stream) {
+            Ok(_) => {
+                return Ok(None);
+            }
+            Err(x) => {
+                let mut res = ::ttrpc::Response::new();
+                match x {
+                    ::ttrpc::Error::RpcStatus(s) => {
+                        res.set_status(s);
+                    }
+                    _ => {
+                        res.set_status(::ttrpc::get_status(x.code(), format!("{:?}", x)));
+                    }
+                }
+                return Ok(Some(res));
+            }
+        }
+    };
+}
+
+/// Handle duplex streaming in sync mode.
+#[macro_export]
+macro_rules! duplex_streaming_handler {
+    ($class: ident, $ctx: ident, $inner: ident, $req_fn: ident) => {
+        let stream = ::ttrpc::sync::ServerStream::new($inner);
+        match $class.service.$req_fn(&$ctx, stream) {
+            Ok(_) => {
+                return Ok(None);
+            }
+            Err(x) => {
+                let mut res = ::ttrpc::Response::new();
+                match x {
+                    ::ttrpc::Error::RpcStatus(s) => {
+                        res.set_status(s);
+                    }
+                    _ => {
+                        res.set_status(::ttrpc::get_status(x.code(), format!("{:?}", x)));
+                    }
+                }
+                return Ok(Some(res));
+            }
+        }
+    };
+}
+
+/// Duplex streaming through sync client.
+#[macro_export]
+macro_rules! client_stream {
+    ($self: ident, $ctx: ident, $server: expr, $method: expr) => {
+        let mut creq = ::ttrpc::Request::new();
+        creq.set_service($server.to_string());
+        creq.set_method($method.to_string());
+        creq.set_timeout_nano($ctx.request_timeout_nano()?);
+        let md = $ctx.metadata.to_pb();
+        creq.set_metadata(md);
+
+        let inner = $self.client.new_stream(creq, true, true)?;
+        let stream = ::ttrpc::sync::ClientStream::new(inner);
+
+        return Ok(stream);
+    };
+}
+
+/// Only send streaming through sync client.
+#[macro_export]
+macro_rules! client_stream_send {
+    ($self: ident, $ctx: ident, $server: expr, $method: expr) => {
+        let mut creq = ::ttrpc::Request::new();
+        creq.set_service($server.to_string());
+        creq.set_method($method.to_string());
+        creq.set_timeout_nano($ctx.request_timeout_nano()?);
+        let md = $ctx.metadata.to_pb();
+        creq.set_metadata(md);
+
+        let inner = $self.client.new_stream(creq, true, false)?;
+        let stream = ::ttrpc::sync::ClientStreamSender::new(inner);
+
+        return Ok(stream);
+    };
+}
+
+/// Only receive streaming through sync client.
+#[macro_export]
+macro_rules! client_stream_receive {
+    ($self: ident, $ctx: ident, $req: ident, $server: expr, $method: expr) => {
+        let mut creq = ::ttrpc::Request::new();
+        creq.set_service($server.to_string());
+        creq.set_method($method.to_string());
+        creq.set_timeout_nano($ctx.request_timeout_nano()?);
+        let md = $ctx.metadata.to_pb();
+        creq.set_metadata(md);
+        creq.payload.reserve($req.compute_size() as usize);
+        {
+            let mut s = CodedOutputStream::vec(&mut creq.payload);
+            $req.write_to(&mut s)
+                .map_err(::ttrpc::err_to_codec!(e, ""))?;
+            s.flush().map_err(::ttrpc::err_to_codec!(e, ""))?;
+        }
+
+        let inner = $self.client.new_stream(creq, false, true)?;
+        let stream = ::ttrpc::sync::ClientStreamReceiver::new(inner);
+
+        return Ok(stream);
+    };
+}
+
+/// Handle request carrying a prost message in sync mode.
+#[cfg(feature = "prost")]
+#[macro_export]
+macro_rules! prost_request_handler {
+    ($class: ident, $ctx: ident, $req: ident, $req_type: ty, $req_fn: ident) => {
+        let req = <$req_type as ::prost::Message>::decode(&$req.payload[..])
+            .map_err(::ttrpc::err_to_codec!(e, ""))?;
+
+        let mut res = ::ttrpc::Response::new();
+        match $class.service.$req_fn(&$ctx, req) {
+            Ok(rep) => {
+                res.set_status(::ttrpc::get_status(::ttrpc::Code::OK, "".to_string()));
+                res.payload = ::prost::Message::encode_to_vec(&rep);
+            }
+            Err(x) => match x {
+                ::ttrpc::Error::RpcStatus(s) => {
+                    res.set_status(s);
+                }
+                _ => {
+                    res.set_status(::ttrpc::get_status(x.code(), format!("{:?}", x)));
+                }
+            },
+        }
+        ::ttrpc::response_to_channel($ctx.mh.stream_id, res, $ctx.res_tx)?
+    };
+}
+
+/// Send request carrying a prost message through sync client.
+#[cfg(feature = "prost")]
+#[macro_export]
+macro_rules! prost_client_request {
+    ($self: ident, $ctx: ident, $req: ident, $server: expr, $method: expr, $cres_type: ty) => {
+        let creq = ::ttrpc::Request {
+            service: $server.to_string(),
+            method: $method.to_string(),
+            timeout_nano: $ctx.request_timeout_nano()?,
+            metadata: $ctx.metadata.to_pb(),
+            payload: ::prost::Message::encode_to_vec($req),
+            ..Default::default()
+        };
+
+        let res = $self.client.request(creq)?;
+        let cres = <$cres_type as ::prost::Message>::decode(&res.payload[..])
+            .map_err(::ttrpc::err_to_codec!(e, "Unpack get error "))?;
+
+        return Ok(cres);
+    };
+}
+
+/// Handle client streaming of prost messages in sync mode.
+#[cfg(feature = "prost")]
+#[macro_export]
+macro_rules! prost_client_streaming_handler {
+    ($class: ident, $ctx: ident, $inner: ident, $req_fn: ident) => {
+        let stream = ::ttrpc::sync::ServerStreamReceiver::new($inner);
+        let mut res = ::ttrpc::Response::new();
+        match $class.service.$req_fn(&$ctx, stream) {
+            Ok(rep) => {
+                res.set_status(::ttrpc::get_status(::ttrpc::Code::OK, "".to_string()));
+                res.payload = ::prost::Message::encode_to_vec(&rep);
+            }
+            Err(x) => match x {
+                ::ttrpc::Error::RpcStatus(s) => {
+                    res.set_status(s);
+                }
+                _ => {
+                    res.set_status(::ttrpc::get_status(x.code(), format!("{:?}", x)));
+                }
+            },
+        }
+        return Ok(Some(res));
+    };
+}
+
+/// Handle server streaming of prost messages in sync mode.
+#[cfg(feature = "prost")]
+#[macro_export]
+macro_rules! prost_server_streaming_handler {
+    ($class: ident, $ctx: ident, $inner: ident, $req_type: ty, $req_fn: ident) => {
+        let req_buf = $inner.recv()?;
+        let req = <$req_type as ::prost::Message>::decode(&req_buf[..])
+            .map_err(::ttrpc::err_to_codec!(e, ""))?;
+        let stream = ::ttrpc::sync::ServerStreamSender::new($inner);
+        match $class.service.$req_fn(&$ctx, req, stream) {
+            Ok(_) => {
+                return Ok(None);
+            }
+            Err(x) => {
+                let mut res = ::ttrpc::Response::new();
+                match x {
+                    ::ttrpc::Error::RpcStatus(s) => {
+                        res.set_status(s);
+                    }
+                    _ => {
+                        res.set_status(::ttrpc::get_status(x.code(), format!("{:?}", x)));
+                    }
+                }
+                return Ok(Some(res));
+            }
+        }
     };
 }
 
+/// Only receive streaming of prost messages through sync client.
+#[cfg(feature = "prost")]
+#[macro_export]
+macro_rules! prost_client_stream_receive {
+    ($self: ident, $ctx: ident, $req: ident, $server: expr, $method: expr) => {
+        let creq = ::ttrpc::Request {
+            service: $server.to_string(),
+            method: $method.to_string(),
+            timeout_nano: $ctx.request_timeout_nano()?,
+            metadata: $ctx.metadata.to_pb(),
+            payload: ::prost::Message::encode_to_vec($req),
+            ..Default::default()
+        };
+
+        let inner = $self.client.new_stream(creq, false, true)?;
+        let stream = ::ttrpc::sync::ClientStreamReceiver::new(inner);
+
+        return Ok(stream);
+    };
+}
+
+/// Trait that implements an interceptor wrapping the calls of a client (sync).
+///
+/// `intercept` runs around [`Client::request`](crate::sync::Client::request) and
+/// `intercept_stream` around [`Client::new_stream`](crate::sync::Client::new_stream);
+/// both pass the call on unchanged by default. An interceptor may change `req`, e.g. its
+/// metadata or timeout, before calling [`ClientNext::run`], and inspect the result after.
+pub trait ClientInterceptor {
+    fn intercept(&self, req: Request, next: ClientNext<'_, Response>) -> Result<Response> {
+        next.run(req)
+    }
+
+    fn intercept_stream(
+        &self,
+        req: Request,
+        next: ClientNext<'_, super::StreamInner>,
+    ) -> Result<super::StreamInner> {
+        next.run(req)
+    }
+}
+
+type ClientHandler<'a, T> = Box<dyn FnOnce(Request) -> Result<T> + 'a>;
+
+/// The rest of a client interceptor chain, ending with sending the call (sync).
+pub struct ClientNext<'a, T> {
+    interceptors: &'a [Arc<dyn ClientInterceptor + Send + Sync>],
+    handler: ClientHandler<'a, T>,
+}
+
+impl<'a, T> ClientNext<'a, T> {
+    pub(crate) fn new(
+        interceptors: &'a [Arc<dyn ClientInterceptor + Send + Sync>],
+        handler: ClientHandler<'a, T>,
+    ) -> Self {
+        ClientNext {
+            interceptors,
+            handler,
+        }
+    }
+}
+
+impl<'a> ClientNext<'a, Response> {
+    /// Run the next interceptor, or send the request if there is none left.
+    pub fn run(self, req: Request) -> Result<Response> {
+        match self.interceptors.split_first() {
+            Some((interceptor, rest)) => {
+                interceptor.intercept(req, ClientNext::new(rest, self.handler))
+            }
+            None => (self.handler)(req),
+        }
+    }
+}
+
+impl<'a> ClientNext<'a, super::StreamInner> {
+    /// Run the next interceptor, or open the stream if there is none left.
+    pub fn run(self, req: Request) -> Result<super::StreamInner> {
+        match self.interceptors.split_first() {
+            Some((interceptor, rest)) => {
+                interceptor.intercept_stream(req, ClientNext::new(rest, self.handler))
+            }
+            None => (self.handler)(req),
+        }
+    }
+}
+
 /// The context of ttrpc (sync).
 #[derive(Debug)]
 pub struct TtrpcContext {
@@ -114,11 +456,87 @@ pub struct TtrpcContext {
     pub cancel_rx: crossbeam::channel::Receiver<()>,
     pub mh: MessageHeader,
     pub res_tx: std::sync::mpsc::Sender<(MessageHeader, Vec<u8>)>,
-    pub metadata: HashMap<String, Vec<String>>,
+    pub metadata: Metadata,
     pub timeout_nano: i64,
+    /// When the call times out, from the timeout sent by the client.
+    pub deadline: Option<std::time::Instant>,
+    /// The process at the other end of the connection, if the transport tells it.
+    pub peer: Option<crate::peer::Peer>,
+    /// The file descriptors passed with the call.
+    #[cfg(unix)]
+    pub fds: crate::fd::CallFds,
+}
+
+impl TtrpcContext {
+    /// The context of the calls made while handling this one, with its metadata and the
+    /// time left before its deadline.
+    pub fn child_context(&self) -> crate::context::Context {
+        crate::context::Context {
+            metadata: self.metadata.clone(),
+            deadline: self.deadline,
+            ..Default::default()
+        }
+    }
 }
 
 /// Trait that implements handler which is a proxy to the desired method (sync).
 pub trait MethodHandler {
     fn handler(&self, ctx: TtrpcContext, req: Request) -> Result<()>;
 }
+
+/// Trait that implements handler which is a proxy to the stream (sync).
+///
+/// The handler occupies one of the method handler threads of the connection until it
+/// returns. The connection starts other threads to keep `thread_count_min` of them waiting
+/// for calls, so there is a thread per open stream on top of those.
+pub trait StreamHandler {
+    fn handler(&self, ctx: TtrpcContext, stream: super::StreamInner) -> Result<Option<Response>>;
+}
+
+/// Trait that implements an interceptor wrapping the method and stream handlers (sync).
+///
+/// `req` is the raw request of the call, naming its service and method. Calling
+/// [`Next::run`] runs the rest of the chain and the handler; an interceptor may change
+/// `ctx` and `req` before, and inspect or replace the result after. Returning
+/// `Error::RpcStatus` without running `next` rejects the call with that status, any other
+/// error fails the call with an `INTERNAL` status.
+///
+/// The result of a stream handler is `None` when the stream ends without a response.
+pub trait Interceptor {
+    fn intercept(
+        &self,
+        ctx: TtrpcContext,
+        req: Request,
+        next: Next<'_>,
+    ) -> Result<Option<Response>>;
+}
+
+type Handler<'a> = Box<dyn FnOnce(TtrpcContext, Request) -> Result<Option<Response>> + 'a>;
+
+/// The rest of an interceptor chain, ending with the handler of the call (sync).
+pub struct Next<'a> {
+    interceptors: &'a [Arc<dyn Interceptor + Send + Sync>],
+    handler: Handler<'a>,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(
+        interceptors: &'a [Arc<dyn Interceptor + Send + Sync>],
+        handler: Handler<'a>,
+    ) -> Self {
+        Next {
+            interceptors,
+            handler,
+        }
+    }
+
+    /// Run the next interceptor, or the handler if there is none left.
+    pub fn run(self, ctx: TtrpcContext, req: Request) -> Result<Option<Response>> {
+        match self.interceptors.split_first() {
+            Some((interceptor, rest)) => {
+                interceptor.intercept(ctx, req, Next::new(rest, self.handler))
+            }
+            None => (self.handler)(ctx, req),
+        }
+    }
+}
@@ -0,0 +1,793 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Replay the conversations of the Go implementation in `go-captures` against
+//! the sync and async servers and clients.
+
+#![cfg(unix)]
+
+use std::io::{Read, Write};
+use std::ops::Deref;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use ttrpc::error::get_rpc_status;
+use ttrpc::proto::{FLAG_NO_DATA, FLAG_REMOTE_OPEN, MESSAGE_HEADER_LENGTH, MESSAGE_TYPE_REQUEST};
+use ttrpc::{Code, Error, MessageHeader, Request, Response, Result};
+
+const SERVICE: &str = "ttrpc.interop.Test";
+
+struct Frame {
+    from_client: bool,
+    comment: String,
+    header: MessageHeader,
+    // `None` for a payload of zeros.
+    payload: Option<Vec<u8>>,
+}
+
+fn load(name: &str) -> Vec<Frame> {
+    let path = format!(
+        "{}/tests/go-captures/{}.txt",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    let text = std::fs::read_to_string(&path).unwrap();
+    let mut frames = Vec::new();
+    let mut comment = String::new();
+    for line in text.lines() {
+        if let Some(c) = line.strip_prefix('#') {
+            comment = c.trim().to_string();
+            continue;
+        }
+        let fields: Vec<_> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+        let hex = |s: &str| u32::from_str_radix(s, 16).unwrap();
+        let header = MessageHeader {
+            length: hex(fields[1]),
+            stream_id: hex(fields[2]),
+            type_: hex(fields[3]) as u8,
+            flags: hex(fields[4]) as u8,
+        };
+        let payload = match fields.get(5) {
+            Some(&"...") => None,
+            Some(payload) => Some(
+                (0..payload.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&payload[i..i + 2], 16).unwrap())
+                    .collect(),
+            ),
+            None => Some(Vec::new()),
+        };
+        if let Some(payload) = &payload {
+            assert_eq!(payload.len(), header.length as usize, "{}: {}", name, line);
+        }
+        frames.push(Frame {
+            from_client: fields[0] == ">",
+            comment: comment.clone(),
+            header,
+            payload,
+        });
+    }
+    frames
+}
+
+// Play the frames of the client, or of the server, and check those of the peer.
+fn replay(name: &str, conn: &mut UnixStream, as_client: bool) {
+    conn.set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    for frame in load(name) {
+        if frame.from_client == as_client {
+            let mut buf: Vec<u8> = frame.header.into();
+            match frame.payload {
+                Some(payload) => buf.extend(payload),
+                None => buf.resize(buf.len() + frame.header.length as usize, 0),
+            }
+            conn.write_all(&buf).unwrap();
+            continue;
+        }
+
+        let mut buf = vec![0; MESSAGE_HEADER_LENGTH];
+        conn.read_exact(&mut buf)
+            .unwrap_or_else(|e| panic!("{}: {}: {}", name, frame.comment, e));
+        let mut header = MessageHeader::from(&buf);
+        if header.type_ == MESSAGE_TYPE_REQUEST && header.flags & FLAG_REMOTE_OPEN != 0 {
+            header.flags &= !FLAG_NO_DATA;
+        }
+        let mut payload = vec![0; header.length as usize];
+        conn.read_exact(&mut payload)
+            .unwrap_or_else(|e| panic!("{}: {}: {}", name, frame.comment, e));
+        assert_eq!(header, frame.header, "{}: {}", name, frame.comment);
+        assert_eq!(Some(payload), frame.payload, "{}: {}", name, frame.comment);
+    }
+}
+
+// The path of a socket file in the temporary directory, removed on drop.
+struct SocketPath(PathBuf);
+
+impl Deref for SocketPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for SocketPath {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn socket_path(name: &str) -> SocketPath {
+    let path = std::env::temp_dir().join(format!(
+        "ttrpc-go-interop-{}-{}.sock",
+        std::process::id(),
+        name
+    ));
+    let _ = std::fs::remove_file(&path);
+    SocketPath(path)
+}
+
+// Play the Go server to a client, checking the frames it sends.
+fn replay_server(name: &str, path: &Path) -> thread::JoinHandle<()> {
+    let listener = UnixListener::bind(path).unwrap();
+    let name = name.to_string();
+    thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        replay(&name, &mut conn, false);
+    })
+}
+
+// Play the Go client to the server listening on `path`.
+fn replay_client(name: &str, path: &Path) {
+    let mut conn = UnixStream::connect(path).unwrap();
+    replay(name, &mut conn, true);
+}
+
+// The messages of the test service, encoded by hand.
+
+#[derive(Debug, Default, PartialEq)]
+struct EchoPayload {
+    seq: u32,
+    msg: String,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Part {
+    add: i32,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Sum {
+    sum: i32,
+    num: i32,
+}
+
+enum Field {
+    Varint(u64),
+    Bytes(Vec<u8>),
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        buf.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+fn encode(fields: &[(u64, Field)]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for (number, field) in fields {
+        match field {
+            Field::Varint(0) => {}
+            Field::Varint(v) => {
+                put_varint(&mut buf, number << 3);
+                put_varint(&mut buf, *v);
+            }
+            Field::Bytes(b) if b.is_empty() => {}
+            Field::Bytes(b) => {
+                put_varint(&mut buf, number << 3 | 2);
+                put_varint(&mut buf, b.len() as u64);
+                buf.extend(b);
+            }
+        }
+    }
+    buf
+}
+
+fn decode(mut buf: &[u8]) -> Vec<(u64, Field)> {
+    fn varint(buf: &mut &[u8]) -> u64 {
+        let mut v = 0;
+        for shift in (0..64).step_by(7) {
+            let b = buf[0];
+            *buf = &buf[1..];
+            v |= ((b & 0x7f) as u64) << shift;
+            if b < 0x80 {
+                break;
+            }
+        }
+        v
+    }
+
+    let mut fields = Vec::new();
+    while !buf.is_empty() {
+        let key = varint(&mut buf);
+        let field = match key & 7 {
+            0 => Field::Varint(varint(&mut buf)),
+            2 => {
+                let len = varint(&mut buf) as usize;
+                let (b, rest) = buf.split_at(len);
+                buf = rest;
+                Field::Bytes(b.to_vec())
+            }
+            t => panic!("unexpected wire type {}", t),
+        };
+        fields.push((key >> 3, field));
+    }
+    fields
+}
+
+impl EchoPayload {
+    fn new(seq: u32, msg: &str) -> Self {
+        EchoPayload {
+            seq,
+            msg: msg.to_string(),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        encode(&[
+            (1, Field::Varint(self.seq as u64)),
+            (2, Field::Bytes(self.msg.clone().into_bytes())),
+        ])
+    }
+
+    fn decode(buf: &[u8]) -> Self {
+        let mut m = EchoPayload::default();
+        for field in decode(buf) {
+            match field {
+                (1, Field::Varint(v)) => m.seq = v as u32,
+                (2, Field::Bytes(b)) => m.msg = String::from_utf8(b).unwrap(),
+                _ => panic!("unexpected field"),
+            }
+        }
+        m
+    }
+}
+
+impl Part {
+    fn encode(&self) -> Vec<u8> {
+        encode(&[(1, Field::Varint(self.add as u64))])
+    }
+
+    fn decode(buf: &[u8]) -> Self {
+        let mut m = Part::default();
+        for field in decode(buf) {
+            match field {
+                (1, Field::Varint(v)) => m.add = v as i32,
+                _ => panic!("unexpected field"),
+            }
+        }
+        m
+    }
+}
+
+impl Sum {
+    fn encode(&self) -> Vec<u8> {
+        encode(&[
+            (1, Field::Varint(self.sum as u64)),
+            (2, Field::Varint(self.num as u64)),
+        ])
+    }
+
+    fn decode(buf: &[u8]) -> Self {
+        let mut m = Sum::default();
+        for field in decode(buf) {
+            match field {
+                (1, Field::Varint(v)) => m.sum = v as i32,
+                (2, Field::Varint(v)) => m.num = v as i32,
+                _ => panic!("unexpected field"),
+            }
+        }
+        m
+    }
+}
+
+// The methods of the test service, shared by the sync and async servers.
+
+fn echo(req: &[u8]) -> Result<Vec<u8>> {
+    let req = EchoPayload::decode(req);
+    if req.msg.is_empty() {
+        return Err(get_rpc_status(Code::INVALID_ARGUMENT, "empty message"));
+    }
+    Ok(EchoPayload::new(req.seq + 1, &req.msg).encode())
+}
+
+fn fill(req: &[u8]) -> Vec<u8> {
+    let req = Part::decode(req);
+    EchoPayload::new(0, &"x".repeat(req.add as usize)).encode()
+}
+
+fn divide(req: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let req = Sum::decode(req);
+    if req.num <= 0 {
+        return Err(get_rpc_status(
+            Code::INVALID_ARGUMENT,
+            "num must be positive",
+        ));
+    }
+    let mut parts = vec![req.sum / req.num; req.num as usize];
+    *parts.last_mut().unwrap() += req.sum % req.num;
+    Ok(parts.into_iter().map(|add| Part { add }.encode()).collect())
+}
+
+fn response(payload: Vec<u8>) -> Response {
+    let mut res = Response::new();
+    res.set_status(ttrpc::get_status(Code::OK, ""));
+    res.payload = payload;
+    res
+}
+
+fn request(service: &str, method: &str, payload: Vec<u8>) -> Request {
+    Request {
+        service: service.to_string(),
+        method: method.to_string(),
+        payload,
+        ..Default::default()
+    }
+}
+
+fn assert_code<T: std::fmt::Debug>(res: Result<T>, code: Code) {
+    match res {
+        Err(Error::RpcStatus(s)) => assert_eq!(s.code(), code),
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[cfg(feature = "sync")]
+mod sync {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use ttrpc::sync::{
+        response_to_channel, Client, MethodHandler, Server, StreamHandler, StreamInner,
+        TtrpcContext,
+    };
+
+    use super::*;
+
+    struct Method(fn(&[u8]) -> Result<Vec<u8>>);
+
+    impl MethodHandler for Method {
+        fn handler(&self, ctx: TtrpcContext, req: Request) -> Result<()> {
+            let res = response((self.0)(&req.payload)?);
+            response_to_channel(ctx.mh.stream_id, res, ctx.res_tx)
+        }
+    }
+
+    struct EchoStream;
+
+    impl StreamHandler for EchoStream {
+        fn handler(&self, _ctx: TtrpcContext, mut s: StreamInner) -> Result<Option<Response>> {
+            loop {
+                match s.recv() {
+                    Ok(req) => s.send(echo(&req)?)?,
+                    Err(Error::Eof) => return Ok(None),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    struct SumStream;
+
+    impl StreamHandler for SumStream {
+        fn handler(&self, _ctx: TtrpcContext, mut s: StreamInner) -> Result<Option<Response>> {
+            let mut sum = Sum::default();
+            loop {
+                match s.recv() {
+                    Ok(req) => {
+                        sum.sum += Part::decode(&req).add;
+                        sum.num += 1;
+                    }
+                    Err(Error::Eof) => return Ok(Some(response(sum.encode()))),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    struct DivideStream;
+
+    impl StreamHandler for DivideStream {
+        fn handler(&self, _ctx: TtrpcContext, mut s: StreamInner) -> Result<Option<Response>> {
+            for part in divide(&s.recv()?)? {
+                s.send(part)?;
+            }
+            Ok(None)
+        }
+    }
+
+    fn start_server(name: &str) -> (Server, SocketPath) {
+        let path = socket_path(&format!("sync-server-{}", name));
+        let mut methods = HashMap::new();
+        for (name, method) in [
+            ("Echo", Method(echo)),
+            ("Fill", Method(|req| Ok(fill(req)))),
+        ] {
+            methods.insert(
+                format!("/{}/{}", SERVICE, name),
+                Box::new(method) as Box<dyn MethodHandler + Send + Sync>,
+            );
+        }
+        let mut streams = HashMap::new();
+        let handlers: [(&str, Arc<dyn StreamHandler + Send + Sync>); 3] = [
+            ("EchoStream", Arc::new(EchoStream)),
+            ("SumStream", Arc::new(SumStream)),
+            ("DivideStream", Arc::new(DivideStream)),
+        ];
+        for (name, handler) in handlers {
+            streams.insert(format!("/{}/{}", SERVICE, name), handler);
+        }
+
+        let mut server = Server::new()
+            .bind(&format!("unix://{}", path.display()))
+            .unwrap()
+            .register_service(methods)
+            .register_stream_service(streams);
+        server.start().unwrap();
+        (server, path)
+    }
+
+    fn replay_to_server(name: &str) {
+        let (server, path) = start_server(name);
+        replay_client(name, &path);
+        server.shutdown();
+    }
+
+    #[test]
+    fn test_server() {
+        for name in [
+            "unary",
+            "server-streaming",
+            "client-streaming",
+            "duplex",
+            "errors",
+            "oversize",
+            "unknown-type",
+            "stream-errors",
+            "cancel",
+        ] {
+            replay_to_server(name);
+        }
+    }
+
+    fn call(client: &Client, method: &str, payload: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(client.request(request(SERVICE, method, payload))?.payload)
+    }
+
+    fn echo_call(client: &Client, seq: u32, msg: &str) -> Result<EchoPayload> {
+        let res = call(client, "Echo", EchoPayload::new(seq, msg).encode())?;
+        Ok(EchoPayload::decode(&res))
+    }
+
+    // Run `f` against the Go server of the conversation `name`.
+    fn replay_to_client(name: &str, f: impl FnOnce(&Client)) {
+        let path = socket_path(&format!("sync-client-{}", name));
+        let server = replay_server(name, &path);
+        let client = Client::connect(&format!("unix://{}", path.display())).unwrap();
+        f(&client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_client() {
+        replay_to_client("unary", |client| {
+            let res = echo_call(client, 1, "hello").unwrap();
+            assert_eq!(res, EchoPayload::new(2, "hello"));
+            let res = echo_call(client, 41, "ttrpc").unwrap();
+            assert_eq!(res, EchoPayload::new(42, "ttrpc"));
+        });
+
+        replay_to_client("server-streaming", |client| {
+            let req = request(SERVICE, "DivideStream", Sum { sum: 7, num: 3 }.encode());
+            let mut s = client.new_stream(req, false, true).unwrap();
+            for add in [2, 2, 3] {
+                assert_eq!(Part::decode(&s.recv().unwrap()), Part { add });
+            }
+            assert!(matches!(s.recv(), Err(Error::Eof)));
+        });
+
+        replay_to_client("client-streaming", |client| {
+            let req = request(SERVICE, "SumStream", Vec::new());
+            let mut s = client.new_stream(req, true, false).unwrap();
+            for add in [1, 2, 4] {
+                s.send(Part { add }.encode()).unwrap();
+            }
+            s.close_send().unwrap();
+            assert_eq!(Sum::decode(&s.recv().unwrap()), Sum { sum: 7, num: 3 });
+        });
+
+        replay_to_client("duplex", |client| {
+            let req = request(SERVICE, "EchoStream", Vec::new());
+            let mut s = client.new_stream(req, true, true).unwrap();
+            for (seq, msg) in [(1, "a"), (3, "b")] {
+                s.send(EchoPayload::new(seq, msg).encode()).unwrap();
+                let res = EchoPayload::decode(&s.recv().unwrap());
+                assert_eq!(res, EchoPayload::new(seq + 1, msg));
+            }
+            s.close_send().unwrap();
+            assert!(matches!(s.recv(), Err(Error::Eof)));
+        });
+
+        replay_to_client("errors", |client| {
+            let payload = EchoPayload::new(1, "hello").encode();
+            let req = request("ttrpc.interop.Missing", "Echo", payload.clone());
+            assert_code(client.request(req), Code::UNIMPLEMENTED);
+            assert_code(call(client, "Missing", payload), Code::UNIMPLEMENTED);
+            assert_code(echo_call(client, 1, ""), Code::INVALID_ARGUMENT);
+            let req = request(SERVICE, "DivideStream", Sum { sum: 7, num: 0 }.encode());
+            let mut s = client.new_stream(req, false, true).unwrap();
+            assert_code(s.recv(), Code::INVALID_ARGUMENT);
+        });
+
+        replay_to_client("oversize-response", |client| {
+            assert_code(echo_call(client, 1, "hello"), Code::RESOURCE_EXHAUSTED);
+            let res = echo_call(client, 41, "ttrpc").unwrap();
+            assert_eq!(res, EchoPayload::new(42, "ttrpc"));
+        });
+
+        replay_to_client("unknown-type-response", |client| {
+            let res = echo_call(client, 1, "hello").unwrap();
+            assert_eq!(res, EchoPayload::new(2, "hello"));
+            assert!(echo_call(client, 41, "ttrpc").is_err());
+        });
+    }
+}
+
+#[cfg(feature = "async")]
+mod asynchronous {
+    use std::collections::HashMap;
+    use std::future::Future;
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use ttrpc::r#async::{
+        Client, MethodHandler, Server, Service, StreamHandler, StreamInner, TtrpcContext,
+    };
+
+    use super::*;
+
+    struct Method(fn(&[u8]) -> Result<Vec<u8>>);
+
+    #[async_trait]
+    impl MethodHandler for Method {
+        async fn handler(&self, _ctx: TtrpcContext, req: Request) -> Result<Response> {
+            Ok(response((self.0)(&req.payload)?))
+        }
+    }
+
+    struct EchoStream;
+
+    #[async_trait]
+    impl StreamHandler for EchoStream {
+        async fn handler(
+            &self,
+            _ctx: TtrpcContext,
+            mut s: StreamInner,
+        ) -> Result<Option<Response>> {
+            loop {
+                match s.recv().await {
+                    Ok(req) => s.send(echo(&req)?).await?,
+                    Err(Error::Eof) => return Ok(None),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    struct SumStream;
+
+    #[async_trait]
+    impl StreamHandler for SumStream {
+        async fn handler(
+            &self,
+            _ctx: TtrpcContext,
+            mut s: StreamInner,
+        ) -> Result<Option<Response>> {
+            let mut sum = Sum::default();
+            loop {
+                match s.recv().await {
+                    Ok(req) => {
+                        sum.sum += Part::decode(&req).add;
+                        sum.num += 1;
+                    }
+                    Err(Error::Eof) => return Ok(Some(response(sum.encode()))),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    struct DivideStream;
+
+    #[async_trait]
+    impl StreamHandler for DivideStream {
+        async fn handler(
+            &self,
+            _ctx: TtrpcContext,
+            mut s: StreamInner,
+        ) -> Result<Option<Response>> {
+            for part in divide(&s.recv().await?)? {
+                s.send(part).await?;
+            }
+            Ok(None)
+        }
+    }
+
+    async fn start_server(name: &str) -> (Server, SocketPath) {
+        let path = socket_path(&format!("async-server-{}", name));
+        let mut methods = HashMap::new();
+        for (name, method) in [
+            ("Echo", Method(echo)),
+            ("Fill", Method(|req| Ok(fill(req)))),
+        ] {
+            methods.insert(
+                name.to_string(),
+                Box::new(method) as Box<dyn MethodHandler + Send + Sync>,
+            );
+        }
+        let mut streams = HashMap::new();
+        let handlers: [(&str, Arc<dyn StreamHandler + Send + Sync>); 3] = [
+            ("EchoStream", Arc::new(EchoStream)),
+            ("SumStream", Arc::new(SumStream)),
+            ("DivideStream", Arc::new(DivideStream)),
+        ];
+        for (name, handler) in handlers {
+            streams.insert(name.to_string(), handler);
+        }
+        let mut services = HashMap::new();
+        services.insert(SERVICE.to_string(), Service { methods, streams });
+
+        let mut server = Server::new()
+            .bind(&format!("unix://{}", path.display()))
+            .unwrap()
+            .register_service(services);
+        server.start().await.unwrap();
+        (server, path)
+    }
+
+    async fn replay_to_server(name: &'static str) {
+        let (mut server, path) = start_server(name).await;
+        tokio::task::spawn_blocking(move || replay_client(name, &path))
+            .await
+            .unwrap();
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_server() {
+        for name in [
+            "unary",
+            "server-streaming",
+            "client-streaming",
+            "duplex",
+            "errors",
+            "oversize",
+            "unknown-type",
+            "stream-errors",
+            // With the feature, the server takes the message for a cancellation instead.
+            #[cfg(not(feature = "cancel"))]
+            "cancel",
+        ] {
+            replay_to_server(name).await;
+        }
+    }
+
+    async fn call(client: &Client, method: &str, payload: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(client
+            .request(request(SERVICE, method, payload))
+            .await?
+            .payload)
+    }
+
+    async fn echo_call(client: &Client, seq: u32, msg: &str) -> Result<EchoPayload> {
+        let res = call(client, "Echo", EchoPayload::new(seq, msg).encode()).await?;
+        Ok(EchoPayload::decode(&res))
+    }
+
+    // Run `f` against the Go server of the conversation `name`.
+    async fn replay_to_client<F, Fut>(name: &str, f: F)
+    where
+        F: FnOnce(Client) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let path = socket_path(&format!("async-client-{}", name));
+        let server = replay_server(name, &path);
+        let client = Client::connect(&format!("unix://{}", path.display()))
+            .await
+            .unwrap();
+        f(client).await;
+        tokio::task::spawn_blocking(move || server.join().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client() {
+        replay_to_client("unary", |client| async move {
+            let res = echo_call(&client, 1, "hello").await.unwrap();
+            assert_eq!(res, EchoPayload::new(2, "hello"));
+            let res = echo_call(&client, 41, "ttrpc").await.unwrap();
+            assert_eq!(res, EchoPayload::new(42, "ttrpc"));
+        })
+        .await;
+
+        replay_to_client("server-streaming", |client| async move {
+            let req = request(SERVICE, "DivideStream", Sum { sum: 7, num: 3 }.encode());
+            let mut s = client.new_stream(req, false, true).await.unwrap();
+            for add in [2, 2, 3] {
+                assert_eq!(Part::decode(&s.recv().await.unwrap()), Part { add });
+            }
+            assert!(matches!(s.recv().await, Err(Error::Eof)));
+        })
+        .await;
+
+        replay_to_client("client-streaming", |client| async move {
+            let req = request(SERVICE, "SumStream", Vec::new());
+            let mut s = client.new_stream(req, true, false).await.unwrap();
+            for add in [1, 2, 4] {
+                s.send(Part { add }.encode()).await.unwrap();
+            }
+            s.close_send().await.unwrap();
+            let res = Sum::decode(&s.recv().await.unwrap());
+            assert_eq!(res, Sum { sum: 7, num: 3 });
+        })
+        .await;
+
+        replay_to_client("duplex", |client| async move {
+            let req = request(SERVICE, "EchoStream", Vec::new());
+            let mut s = client.new_stream(req, true, true).await.unwrap();
+            for (seq, msg) in [(1, "a"), (3, "b")] {
+                s.send(EchoPayload::new(seq, msg).encode()).await.unwrap();
+                let res = EchoPayload::decode(&s.recv().await.unwrap());
+                assert_eq!(res, EchoPayload::new(seq + 1, msg));
+            }
+            s.close_send().await.unwrap();
+            assert!(matches!(s.recv().await, Err(Error::Eof)));
+        })
+        .await;
+
+        replay_to_client("errors", |client| async move {
+            let payload = EchoPayload::new(1, "hello").encode();
+            let req = request("ttrpc.interop.Missing", "Echo", payload.clone());
+            assert_code(client.request(req).await, Code::UNIMPLEMENTED);
+            assert_code(call(&client, "Missing", payload).await, Code::UNIMPLEMENTED);
+            assert_code(echo_call(&client, 1, "").await, Code::INVALID_ARGUMENT);
+            let req = request(SERVICE, "DivideStream", Sum { sum: 7, num: 0 }.encode());
+            let mut s = client.new_stream(req, false, true).await.unwrap();
+            assert_code(s.recv().await, Code::INVALID_ARGUMENT);
+        })
+        .await;
+
+        replay_to_client("oversize-response", |client| async move {
+            let res = echo_call(&client, 1, "hello").await;
+            assert_code(res, Code::RESOURCE_EXHAUSTED);
+            let res = echo_call(&client, 41, "ttrpc").await.unwrap();
+            assert_eq!(res, EchoPayload::new(42, "ttrpc"));
+        })
+        .await;
+
+        replay_to_client("unknown-type-response", |client| async move {
+            let res = echo_call(&client, 1, "hello").await.unwrap();
+            assert_eq!(res, EchoPayload::new(2, "hello"));
+            assert!(echo_call(&client, 41, "ttrpc").await.is_err());
+        })
+        .await;
+    }
+}
@@ -84,8 +84,10 @@ fn wait_with_output(name: &str, cmd: Child) {
 #[test]
 fn run_examples() -> Result<(), Box<dyn std::error::Error>> {
     run_example("server", "client")?;
+    run_example("stream-server", "stream-client")?;
     run_example("async-server", "async-client")?;
     run_example("async-stream-server", "async-stream-client")?;
+    run_example("prost-server", "prost-client")?;
 
     Ok(())
 }
@@ -31,21 +31,13 @@
 pub use protobuf_codegen::{
     Customize as ProtobufCustomize, CustomizeCallback as ProtobufCustomizeCallback,
 };
-use std::collections::HashMap;
-use std::error::Error;
-use std::fmt;
-use std::fs;
 use std::io;
-use std::io::Read;
 use std::path::Path;
 use std::path::PathBuf;
+#[doc(hidden)]
+pub use ttrpc_compiler::parse::{parse_and_typecheck, ParsedAndTypechecked};
 pub use ttrpc_compiler::Customize;
 
-mod convert;
-mod model;
-mod parser;
-mod str_lit;
-
 /// Invoke pure rust codegen.
 #[derive(Debug, Default)]
 pub struct Codegen {
@@ -57,6 +49,8 @@ pub struct Codegen {
     inputs: Vec<PathBuf>,
     /// Generate rust-protobuf files along with rust-gprc
     rust_protobuf: bool,
+    /// Generate prost messages and ttrpc services on top of them
+    prost: bool,
     /// rust protobuf codegen
     rust_protobuf_codegen: protobuf_codegen::Codegen,
     /// Customize code generation
@@ -109,6 +103,18 @@ impl Codegen {
         self
     }
 
+    /// Generate prost messages with ttrpc services instead of rust-protobuf code.
+    ///
+    /// The services are written into the prost file of their package, this
+    /// takes precedence over [`rust_protobuf`](Self::rust_protobuf) and
+    /// `Customize::gen_mod` is not supported. Like the other paths, this needs
+    /// no `protoc`, so imported files such as `google/protobuf/empty.proto`
+    /// must be found in the include directories.
+    pub fn prost(&mut self) -> &mut Self {
+        self.prost = true;
+        self
+    }
+
     /// Customize code generated by rust-protobuf-codegen.
     pub fn rust_protobuf_customize(&mut self, customize: ProtobufCustomize) -> &mut Self {
         self.rust_protobuf_codegen.customize(customize);
@@ -135,7 +141,6 @@ impl Codegen {
     pub fn run(&mut self) -> io::Result<()> {
         let includes: Vec<&Path> = self.includes.iter().map(|p| p.as_path()).collect();
         let inputs: Vec<&Path> = self.inputs.iter().map(|p| p.as_path()).collect();
-        let p = parse_and_typecheck(&includes, &inputs)?;
         // If out_dir is none ,dst_path will be setting in path_dir
         let dst_path = self.out_dir.clone().unwrap_or_else(|| {
             // Add default path from env OUT_DIR, if no OUT_DIR env ,that's will be current path
@@ -145,6 +150,17 @@ impl Codegen {
             )
         });
 
+        let p = parse_and_typecheck(&includes, &inputs)?;
+
+        if self.prost {
+            ttrpc_compiler::prost_codegen::compile_file_descriptors(
+                &p.file_descriptors,
+                &dst_path.to_string_lossy(),
+                &self.customize,
+            )?;
+            return Ok(());
+        }
+
         if self.rust_protobuf {
             self.rust_protobuf_codegen
                 .pure()
@@ -163,240 +179,3 @@ impl Codegen {
         )
     }
 }
-
-/// Convert OS path to protobuf path (with slashes)
-/// Function is `pub(crate)` for test.
-pub(crate) fn relative_path_to_protobuf_path(path: &Path) -> String {
-    assert!(path.is_relative());
-    let path = path.to_str().expect("not a valid UTF-8 name");
-    if cfg!(windows) {
-        path.replace('\\', "/")
-    } else {
-        path.to_owned()
-    }
-}
-
-#[derive(Clone)]
-struct FileDescriptorPair {
-    parsed: model::FileDescriptor,
-    descriptor: protobuf::descriptor::FileDescriptorProto,
-}
-
-#[derive(Debug)]
-enum CodegenError {
-    ParserErrorWithLocation(parser::ParserErrorWithLocation),
-    ConvertError(convert::ConvertError),
-}
-
-impl From<parser::ParserErrorWithLocation> for CodegenError {
-    fn from(e: parser::ParserErrorWithLocation) -> Self {
-        CodegenError::ParserErrorWithLocation(e)
-    }
-}
-
-impl From<convert::ConvertError> for CodegenError {
-    fn from(e: convert::ConvertError) -> Self {
-        CodegenError::ConvertError(e)
-    }
-}
-
-#[derive(Debug)]
-struct WithFileError {
-    file: String,
-    error: CodegenError,
-}
-
-impl fmt::Display for WithFileError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "WithFileError(file: {:?}, error: {:?})",
-            &self.file, &self.error
-        )
-    }
-}
-
-impl Error for WithFileError {
-    fn description(&self) -> &str {
-        "WithFileError"
-    }
-}
-
-struct Run<'a> {
-    parsed_files: HashMap<String, FileDescriptorPair>,
-    includes: &'a [&'a Path],
-}
-
-impl<'a> Run<'a> {
-    fn get_file_and_all_deps_already_parsed(
-        &self,
-        protobuf_path: &str,
-        result: &mut HashMap<String, FileDescriptorPair>,
-    ) {
-        if result.contains_key(protobuf_path) {
-            return;
-        }
-
-        let pair = self
-            .parsed_files
-            .get(protobuf_path)
-            .expect("must be already parsed");
-        result.insert(protobuf_path.to_owned(), pair.clone());
-
-        self.get_all_deps_already_parsed(&pair.parsed, result);
-    }
-
-    fn get_all_deps_already_parsed(
-        &self,
-        parsed: &model::FileDescriptor,
-        result: &mut HashMap<String, FileDescriptorPair>,
-    ) {
-        for import in &parsed.import_paths {
-            self.get_file_and_all_deps_already_parsed(import, result);
-        }
-    }
-
-    fn add_file(&mut self, protobuf_path: &str, fs_path: &Path) -> io::Result<()> {
-        if self.parsed_files.contains_key(protobuf_path) {
-            return Ok(());
-        }
-
-        let mut content = String::new();
-        fs::File::open(fs_path)?.read_to_string(&mut content)?;
-
-        let parsed = model::FileDescriptor::parse(content).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                WithFileError {
-                    file: format!("{}", fs_path.display()),
-                    error: e.into(),
-                },
-            )
-        })?;
-
-        for import_path in &parsed.import_paths {
-            self.add_imported_file(import_path)?;
-        }
-
-        let mut this_file_deps = HashMap::new();
-        self.get_all_deps_already_parsed(&parsed, &mut this_file_deps);
-
-        let this_file_deps: Vec<_> = this_file_deps.into_values().map(|v| v.parsed).collect();
-
-        let descriptor =
-            convert::file_descriptor(protobuf_path.to_owned(), &parsed, &this_file_deps).map_err(
-                |e| {
-                    io::Error::new(
-                        io::ErrorKind::Other,
-                        WithFileError {
-                            file: format!("{}", fs_path.display()),
-                            error: e.into(),
-                        },
-                    )
-                },
-            )?;
-
-        self.parsed_files.insert(
-            protobuf_path.to_owned(),
-            FileDescriptorPair { parsed, descriptor },
-        );
-
-        Ok(())
-    }
-
-    fn add_imported_file(&mut self, protobuf_path: &str) -> io::Result<()> {
-        for include_dir in self.includes {
-            let fs_path = Path::new(include_dir).join(protobuf_path);
-            if fs_path.exists() {
-                return self.add_file(protobuf_path, &fs_path);
-            }
-        }
-
-        Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!(
-                "protobuf path {:?} is not found in import path {:?}",
-                protobuf_path, self.includes
-            ),
-        ))
-    }
-
-    fn add_fs_file(&mut self, fs_path: &Path) -> io::Result<String> {
-        let relative_path = self
-            .includes
-            .iter()
-            .filter_map(|include_dir| fs_path.strip_prefix(include_dir).ok())
-            .next();
-
-        match relative_path {
-            Some(relative_path) => {
-                let protobuf_path = relative_path_to_protobuf_path(relative_path);
-                self.add_file(&protobuf_path, fs_path)?;
-                Ok(protobuf_path)
-            }
-            None => Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!(
-                    "file {:?} must reside in include path {:?}",
-                    fs_path, self.includes
-                ),
-            )),
-        }
-    }
-}
-
-#[doc(hidden)]
-pub struct ParsedAndTypechecked {
-    pub relative_paths: Vec<String>,
-    pub file_descriptors: Vec<protobuf::descriptor::FileDescriptorProto>,
-}
-
-#[doc(hidden)]
-pub fn parse_and_typecheck(
-    includes: &[&Path],
-    input: &[&Path],
-) -> io::Result<ParsedAndTypechecked> {
-    let mut run = Run {
-        parsed_files: HashMap::new(),
-        includes,
-    };
-
-    let mut relative_paths = Vec::new();
-
-    for input in input {
-        relative_paths.push(run.add_fs_file(Path::new(input))?);
-    }
-
-    let file_descriptors: Vec<_> = run
-        .parsed_files
-        .into_values()
-        .map(|v| v.descriptor)
-        .collect();
-
-    Ok(ParsedAndTypechecked {
-        relative_paths,
-        file_descriptors,
-    })
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    #[cfg(windows)]
-    #[test]
-    fn test_relative_path_to_protobuf_path_windows() {
-        assert_eq!(
-            "foo/bar.proto",
-            relative_path_to_protobuf_path(Path::new("foo\\bar.proto"))
-        );
-    }
-
-    #[test]
-    fn test_relative_path_to_protobuf_path() {
-        assert_eq!(
-            "foo/bar.proto",
-            relative_path_to_protobuf_path(Path::new("foo/bar.proto"))
-        );
-    }
-}
@@ -0,0 +1,261 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Find the symbols declared in `FileDescriptorProto`s and print them as in `.proto` files.
+
+use protobuf::descriptor::field_descriptor_proto::{Label, Type};
+use protobuf::descriptor::{
+    DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+    MethodDescriptorProto, ServiceDescriptorProto,
+};
+
+/// The full names of the services declared in `files`, sorted.
+pub fn services(files: &[FileDescriptorProto]) -> Vec<String> {
+    let mut services: Vec<_> = files
+        .iter()
+        .flat_map(|file| {
+            file.service
+                .iter()
+                .map(move |service| full_name(file.package(), service.name()))
+        })
+        .collect();
+    services.sort();
+    services
+}
+
+/// The full names of the methods of `service`, `None` if it isn't declared in `files`.
+pub fn methods(files: &[FileDescriptorProto], service: &str) -> Option<Vec<String>> {
+    let (file, service) = find_service(files, service)?;
+    let service_name = full_name(file.package(), service.name());
+    Some(
+        service
+            .method
+            .iter()
+            .map(|method| format!("{}.{}", service_name, method.name()))
+            .collect(),
+    )
+}
+
+/// Describe the service, method, message or enum of full name `symbol`, `None` if it isn't
+/// declared in `files`.
+pub fn describe(files: &[FileDescriptorProto], symbol: &str) -> Option<String> {
+    let symbol = symbol.trim_start_matches('.');
+    if let Some((_, service)) = find_service(files, symbol) {
+        return Some(format!(
+            "{} is a service:\n{}",
+            symbol,
+            print_service(service)
+        ));
+    }
+    if let Some((service, method)) = symbol.rsplit_once('.') {
+        if let Some((_, service)) = find_service(files, service) {
+            if let Some(method) = service.method.iter().find(|m| m.name() == method) {
+                return Some(format!("{} is a method:\n{}", symbol, print_method(method)));
+            }
+        }
+    }
+
+    for file in files {
+        let path = match relative_name(file.package(), symbol) {
+            Some(path) => path,
+            None => continue,
+        };
+        let proto3 = file.syntax() == "proto3";
+        let mut out = String::new();
+        match find_type(&file.message_type, &file.enum_type, path) {
+            Some(Declared::Message(message)) => {
+                print_message(message, proto3, "", &mut out);
+                return Some(format!("{} is a message:\n{}", symbol, out.trim_end()));
+            }
+            Some(Declared::Enum(e)) => {
+                print_enum(e, "", &mut out);
+                return Some(format!("{} is an enum:\n{}", symbol, out.trim_end()));
+            }
+            None => {}
+        }
+    }
+    None
+}
+
+enum Declared<'a> {
+    Message(&'a DescriptorProto),
+    Enum(&'a EnumDescriptorProto),
+}
+
+fn full_name(package: &str, name: &str) -> String {
+    if package.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", package, name)
+    }
+}
+
+// The name of `name` relative to `package`, if it is declared in it.
+fn relative_name<'a>(package: &str, name: &'a str) -> Option<&'a str> {
+    if package.is_empty() {
+        return Some(name);
+    }
+    name.strip_prefix(package)?.strip_prefix('.')
+}
+
+fn find_service<'a>(
+    files: &'a [FileDescriptorProto],
+    name: &str,
+) -> Option<(&'a FileDescriptorProto, &'a ServiceDescriptorProto)> {
+    let name = name.trim_start_matches('.');
+    files.iter().find_map(|file| {
+        let name = relative_name(file.package(), name)?;
+        let service = file.service.iter().find(|s| s.name() == name)?;
+        Some((file, service))
+    })
+}
+
+// Find the message or enum of `path`, relative to the scope declaring `messages` and `enums`.
+fn find_type<'a>(
+    messages: &'a [DescriptorProto],
+    enums: &'a [EnumDescriptorProto],
+    path: &str,
+) -> Option<Declared<'a>> {
+    let (name, rest) = match path.split_once('.') {
+        Some((name, rest)) => (name, Some(rest)),
+        None => (path, None),
+    };
+    if rest.is_none() {
+        if let Some(e) = enums.iter().find(|e| e.name() == name) {
+            return Some(Declared::Enum(e));
+        }
+    }
+    let message = messages.iter().find(|m| m.name() == name)?;
+    match rest {
+        Some(rest) => find_type(&message.nested_type, &message.enum_type, rest),
+        None => Some(Declared::Message(message)),
+    }
+}
+
+fn print_service(service: &ServiceDescriptorProto) -> String {
+    let mut out = format!("service {} {{\n", service.name());
+    for method in &service.method {
+        out.push_str(&format!("  {}\n", print_method(method)));
+    }
+    out.push('}');
+    out
+}
+
+fn print_method(method: &MethodDescriptorProto) -> String {
+    let stream = |streaming| if streaming { "stream " } else { "" };
+    format!(
+        "rpc {} ( {}{} ) returns ( {}{} );",
+        method.name(),
+        stream(method.client_streaming()),
+        method.input_type(),
+        stream(method.server_streaming()),
+        method.output_type()
+    )
+}
+
+fn print_message(message: &DescriptorProto, proto3: bool, indent: &str, out: &mut String) {
+    out.push_str(&format!("{}message {} {{\n", indent, message.name()));
+    let inner = format!("{}  ", indent);
+    for nested in &message.nested_type {
+        if !nested.options.map_entry() {
+            print_message(nested, proto3, &inner, out);
+        }
+    }
+    for e in &message.enum_type {
+        print_enum(e, &inner, out);
+    }
+
+    let mut oneofs = Vec::new();
+    for field in &message.field {
+        if !field.has_oneof_index() || field.proto3_optional() {
+            let field = print_field(message, field, Some(proto3));
+            out.push_str(&format!("{}{}\n", inner, field));
+            continue;
+        }
+        let index = field.oneof_index();
+        if oneofs.contains(&index) {
+            continue;
+        }
+        oneofs.push(index);
+        let name = message.oneof_decl[index as usize].name();
+        out.push_str(&format!("{}oneof {} {{\n", inner, name));
+        for field in &message.field {
+            if field.has_oneof_index() && field.oneof_index() == index {
+                let field = print_field(message, field, None);
+                out.push_str(&format!("{}  {}\n", inner, field));
+            }
+        }
+        out.push_str(&format!("{}}}\n", inner));
+    }
+    out.push_str(&format!("{}}}\n", indent));
+}
+
+fn print_enum(e: &EnumDescriptorProto, indent: &str, out: &mut String) {
+    out.push_str(&format!("{}enum {} {{\n", indent, e.name()));
+    for value in &e.value {
+        out.push_str(&format!(
+            "{}  {} = {};\n",
+            indent,
+            value.name(),
+            value.number()
+        ));
+    }
+    out.push_str(&format!("{}}}\n", indent));
+}
+
+// A field of `message`, labelled as in a file of syntax proto3 or not unless in a oneof.
+fn print_field(
+    message: &DescriptorProto,
+    field: &FieldDescriptorProto,
+    proto3: Option<bool>,
+) -> String {
+    let entry = message.nested_type.iter().find(|nested| {
+        nested.options.map_entry() && field.type_name().ends_with(&format!(".{}", nested.name()))
+    });
+    let (label, type_name) = match (entry, field.label()) {
+        (Some(entry), Label::LABEL_REPEATED) if entry.field.len() == 2 => {
+            let key = type_name(&entry.field[0]);
+            let value = type_name(&entry.field[1]);
+            ("", format!("map<{}, {}>", key, value))
+        }
+        (_, Label::LABEL_REPEATED) => ("repeated ", type_name(field)),
+        (_, Label::LABEL_REQUIRED) => ("required ", type_name(field)),
+        (_, Label::LABEL_OPTIONAL) => match proto3 {
+            Some(false) => ("optional ", type_name(field)),
+            Some(true) if field.proto3_optional() => ("optional ", type_name(field)),
+            _ => ("", type_name(field)),
+        },
+    };
+    let label = if proto3.is_some() { label } else { "" };
+    format!(
+        "{}{} {} = {};",
+        label,
+        type_name,
+        field.name(),
+        field.number()
+    )
+}
+
+fn type_name(field: &FieldDescriptorProto) -> String {
+    let name = match field.type_() {
+        Type::TYPE_MESSAGE | Type::TYPE_ENUM | Type::TYPE_GROUP => {
+            return field.type_name().to_string()
+        }
+        Type::TYPE_DOUBLE => "double",
+        Type::TYPE_FLOAT => "float",
+        Type::TYPE_INT64 => "int64",
+        Type::TYPE_UINT64 => "uint64",
+        Type::TYPE_INT32 => "int32",
+        Type::TYPE_FIXED64 => "fixed64",
+        Type::TYPE_FIXED32 => "fixed32",
+        Type::TYPE_BOOL => "bool",
+        Type::TYPE_STRING => "string",
+        Type::TYPE_BYTES => "bytes",
+        Type::TYPE_UINT32 => "uint32",
+        Type::TYPE_SFIXED32 => "sfixed32",
+        Type::TYPE_SFIXED64 => "sfixed64",
+        Type::TYPE_SINT32 => "sint32",
+        Type::TYPE_SINT64 => "sint64",
+    };
+    name.to_string()
+}
@@ -0,0 +1,537 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! `ttrpcurl` calls the methods of ttrpc servers from the command line, as `grpcurl` does for
+//! gRPC. The services are found by the reflection service of the server, or in `.proto` files
+//! parsed by `ttrpc-codegen`, without protoc.
+
+mod describe;
+
+use std::io::Read as _;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use protobuf::descriptor::FileDescriptorProto;
+use protobuf::Message as _;
+use ttrpc::context::Context;
+use ttrpc::proto::Code;
+use ttrpc::r#async::dynamic::{self, DynamicClient};
+use ttrpc::r#async::Client;
+use ttrpc::reflection::r#async::ReflectionClient;
+use ttrpc::{Error, Result};
+
+const USAGE: &str = "\
+Usage:
+    ttrpcurl [flags] [address] list [service]
+    ttrpcurl [flags] [address] describe [symbol]
+    ttrpcurl [flags] address method
+
+The address is unix:///path, unix://@abstract or vsock://cid:port. The method is
+package.Service/Method or package.Service.Method. Without -proto, the services are found
+by the reflection service of the server.
+
+Flags:
+    -proto FILE         Use the .proto FILE, relative to the import paths, instead of
+                        reflection. May be repeated.
+    -import-path DIR    Look for the .proto files and their imports in DIR, the current
+                        directory by default. May be repeated.
+    -d DATA             The requests in JSON, read from stdin if DATA is @. Unary and
+                        server streaming methods get an empty request by default.
+    -H 'KEY: VALUE'     Add metadata to the calls, in base64 for the keys ending
+                        with -bin. May be repeated.
+    -max-time SECONDS   The timeout of the calls.
+    -h, -help           Print this help.
+";
+
+struct Args {
+    protos: Vec<String>,
+    import_paths: Vec<PathBuf>,
+    data: Option<String>,
+    metadata: Vec<(String, String)>,
+    max_time: Option<Duration>,
+    address: Option<String>,
+    command: Command,
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    List(Option<String>),
+    Describe(Option<String>),
+    Call(String),
+}
+
+// Parse the arguments, `None` asks for the help.
+fn parse_args(mut args: impl Iterator<Item = String>) -> std::result::Result<Option<Args>, String> {
+    let mut protos = Vec::new();
+    let mut import_paths = Vec::new();
+    let mut data = None;
+    let mut metadata = Vec::new();
+    let mut max_time = None;
+
+    // The flags come first, as `-flag value` or `-flag=value`, with one dash or two.
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        if !positional.is_empty() || !arg.starts_with('-') {
+            positional.push(arg);
+            continue;
+        }
+        let flag = arg.trim_start_matches('-');
+        let (flag, value) = match flag.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_string())),
+            None => (flag, None),
+        };
+        if flag == "h" || flag == "help" {
+            return Ok(None);
+        }
+        let value = value
+            .or_else(|| args.next())
+            .ok_or_else(|| format!("flag -{} needs a value", flag))?;
+        match flag {
+            "proto" => protos.push(value),
+            "import-path" => import_paths.push(PathBuf::from(value)),
+            "d" => data = Some(value),
+            "H" => {
+                let (key, value) = value
+                    .split_once(':')
+                    .ok_or_else(|| format!("invalid metadata {:?}, not 'KEY: VALUE'", value))?;
+                metadata.push((key.trim().to_string(), value.trim().to_string()));
+            }
+            "max-time" => {
+                let seconds = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|s| s.is_finite() && *s > 0.0)
+                    .ok_or_else(|| format!("invalid max time {:?}", value))?;
+                max_time = Some(Duration::from_secs_f64(seconds));
+            }
+            _ => return Err(format!("unknown flag -{}", flag)),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let first = positional.next().ok_or("too few arguments")?;
+    let (address, verb) = match first.as_str() {
+        "list" | "describe" => (None, first),
+        _ => (Some(first), positional.next().ok_or("too few arguments")?),
+    };
+    let command = match verb.as_str() {
+        "list" => Command::List(positional.next()),
+        "describe" => Command::Describe(positional.next()),
+        _ => Command::Call(verb),
+    };
+    if positional.next().is_some() {
+        return Err("too many arguments".to_string());
+    }
+    if address.is_none() && protos.is_empty() {
+        return Err("the address is needed without -proto".to_string());
+    }
+
+    Ok(Some(Args {
+        protos,
+        import_paths,
+        data,
+        metadata,
+        max_time,
+        address,
+        command,
+    }))
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(args).await {
+        match e {
+            Error::RpcStatus(s) => {
+                eprintln!("ERROR:\n  Code: {:?}\n  Message: {}", s.code(), s.message())
+            }
+            Error::Others(e) => eprintln!("ERROR: {}", e),
+            e => eprintln!("ERROR: {}", e),
+        }
+        std::process::exit(1);
+    }
+}
+
+async fn run(args: Args) -> Result<()> {
+    let mut ctx = Context::default();
+    if let Some(max_time) = args.max_time {
+        ctx.timeout_nano = max_time.as_nanos() as i64;
+    }
+    for (key, value) in args.metadata {
+        ctx.metadata.append_encoded(&key, value)?;
+    }
+
+    let files = if args.protos.is_empty() {
+        None
+    } else {
+        Some(parse_protos(&args.protos, &args.import_paths)?)
+    };
+    let client = match &args.address {
+        Some(address) => Some(Client::connect(address).await?),
+        None => None,
+    };
+
+    match args.command {
+        Command::List(service) => list(ctx, client, files, service).await,
+        Command::Describe(symbol) => describe(ctx, client, files, symbol).await,
+        // The address is required to call a method.
+        Command::Call(method) => call(ctx, client.unwrap(), files, &method, args.data).await,
+    }
+}
+
+// Parse the `.proto` files, found in the import paths, and the files they import.
+fn parse_protos(protos: &[String], import_paths: &[PathBuf]) -> Result<Vec<FileDescriptorProto>> {
+    let import_paths = match import_paths {
+        [] => vec![PathBuf::from(".")],
+        paths => paths.to_vec(),
+    };
+    let inputs = protos
+        .iter()
+        .map(|proto| {
+            import_paths
+                .iter()
+                .map(|dir| dir.join(proto))
+                .find(|path| path.exists())
+                .ok_or_else(|| Error::Others(format!("{} is not in the import paths", proto)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let includes: Vec<&Path> = import_paths.iter().map(PathBuf::as_path).collect();
+    let inputs: Vec<&Path> = inputs.iter().map(PathBuf::as_path).collect();
+    ttrpc_codegen::parse_and_typecheck(&includes, &inputs)
+        .map(|parsed| parsed.file_descriptors)
+        .map_err(|e| Error::Others(e.to_string()))
+}
+
+async fn list(
+    ctx: Context,
+    client: Option<Client>,
+    files: Option<Vec<FileDescriptorProto>>,
+    service: Option<String>,
+) -> Result<()> {
+    let unknown = |service: &str| Error::Others(format!("unknown service {:?}", service));
+    let names = match files {
+        Some(files) => match service {
+            Some(service) => {
+                describe::methods(&files, &service).ok_or_else(|| unknown(&service))?
+            }
+            None => describe::services(&files),
+        },
+        None => {
+            let client = ReflectionClient::new(client.unwrap());
+            let services = client.list_services(ctx).await?;
+            match service {
+                Some(service) => {
+                    let service = service.trim_start_matches('.');
+                    let info = services.services.iter().find(|s| s.name == service);
+                    let info = info.ok_or_else(|| unknown(service))?;
+                    let methods = info.methods.iter();
+                    methods.map(|m| format!("{}.{}", service, m.name)).collect()
+                }
+                None => services.services.into_iter().map(|s| s.name).collect(),
+            }
+        }
+    };
+    for name in names {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+async fn describe(
+    ctx: Context,
+    client: Option<Client>,
+    files: Option<Vec<FileDescriptorProto>>,
+    symbol: Option<String>,
+) -> Result<()> {
+    if let Some(files) = files {
+        let symbols = match symbol {
+            Some(symbol) => vec![symbol],
+            None => describe::services(&files),
+        };
+        for symbol in symbols {
+            println!("{}", describe_symbol(&files, &symbol)?);
+        }
+        return Ok(());
+    }
+
+    // Get the files declaring each symbol from the reflection service, the services without
+    // descriptors are only named when describing them all.
+    let client = ReflectionClient::new(client.unwrap());
+    let all = symbol.is_none();
+    let symbols = match symbol {
+        Some(symbol) => vec![symbol.trim_start_matches('/').replace('/', ".")],
+        None => {
+            let services = client.list_services(ctx.clone()).await?.services;
+            services.into_iter().map(|s| s.name).collect()
+        }
+    };
+    for symbol in symbols {
+        let files = match client.file_containing_symbol(ctx.clone(), &symbol).await {
+            Ok(res) => res
+                .file_descriptor_proto
+                .iter()
+                .map(|file| {
+                    FileDescriptorProto::parse_from_bytes(file)
+                        .map_err(|e| Error::Others(format!("invalid file descriptor: {}", e)))
+                })
+                .collect::<Result<Vec<_>>>()?,
+            Err(Error::RpcStatus(s)) if s.code() == Code::NOT_FOUND && all => {
+                println!("{} is a service without descriptor", symbol);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        println!("{}", describe_symbol(&files, &symbol)?);
+    }
+    Ok(())
+}
+
+fn describe_symbol(files: &[FileDescriptorProto], symbol: &str) -> Result<String> {
+    describe::describe(files, &symbol.trim_start_matches('/').replace('/', "."))
+        .ok_or_else(|| Error::Others(format!("unknown symbol {:?}", symbol)))
+}
+
+async fn call(
+    ctx: Context,
+    client: Client,
+    files: Option<Vec<FileDescriptorProto>>,
+    name: &str,
+    data: Option<String>,
+) -> Result<()> {
+    let client = DynamicClient::new(client);
+    let client = match files {
+        Some(files) => client.add_file_descriptor_protos(files)?,
+        None => {
+            let symbol = name.trim_start_matches('/').replace('/', ".");
+            client.add_from_reflection(ctx.clone(), &symbol).await?
+        }
+    };
+    let method = client.method(name)?;
+
+    let data = match data.as_deref() {
+        Some("@") => {
+            let mut data = String::new();
+            std::io::stdin()
+                .read_to_string(&mut data)
+                .map_err(|e| Error::Others(format!("read stdin: {}", e)))?;
+            data
+        }
+        Some(data) => data.to_string(),
+        None => String::new(),
+    };
+    let mut reqs = split_json(&data)?
+        .into_iter()
+        .map(|json| dynamic::parse_json(&method.input_type(), json))
+        .collect::<Result<Vec<_>>>()?;
+    if !method.client_streaming() {
+        if reqs.is_empty() {
+            reqs.push(method.input_type().new_instance());
+        }
+        if reqs.len() > 1 {
+            return Err(Error::Others(format!(
+                "{} takes one request, not {}",
+                name,
+                reqs.len()
+            )));
+        }
+    }
+
+    let mut stream = match (method.client_streaming(), method.server_streaming()) {
+        (false, false) => {
+            let res = client.call(ctx, name, &*reqs[0]).await?;
+            println!("{}", dynamic::print_json(&*res)?);
+            return Ok(());
+        }
+        (false, true) => client.server_stream(ctx, name, &*reqs[0]).await?,
+        (true, _) => {
+            let stream = client.stream(ctx, name).await?;
+            for req in &reqs {
+                stream.send(&**req).await?;
+            }
+            stream.close_send().await?;
+            stream
+        }
+    };
+    while let Some(res) = stream.recv().await? {
+        println!("{}", dynamic::print_json(&*res)?);
+    }
+    Ok(())
+}
+
+// Split `data` into the JSON objects it is a sequence of.
+fn split_json(data: &str) -> Result<Vec<&str>> {
+    let mut values = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in data.char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            '{' | '[' => {
+                if depth == 0 {
+                    start = i;
+                }
+                depth += 1;
+            }
+            '}' | ']' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    values.push(&data[start..=i]);
+                }
+            }
+            '"' if depth > 0 => in_string = true,
+            c if depth == 0 && !c.is_whitespace() => {
+                return Err(Error::Others(format!("invalid JSON at {:?}", &data[i..])));
+            }
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return Err(Error::Others(format!(
+            "unterminated JSON {:?}",
+            &data[start..]
+        )));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &str) -> std::result::Result<Option<Args>, String> {
+        parse_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let a = args("-proto a.proto --import-path=protos -H user:me -max-time 1.5 list")
+            .unwrap()
+            .unwrap();
+        assert_eq!(a.protos, ["a.proto"]);
+        assert_eq!(a.import_paths, [PathBuf::from("protos")]);
+        assert_eq!(a.metadata, [("user".to_string(), "me".to_string())]);
+        assert_eq!(a.max_time, Some(Duration::from_millis(1500)));
+        assert_eq!(a.address, None);
+        assert_eq!(a.command, Command::List(None));
+
+        let a = args("-d {} unix://@ttrpc describe agent.Agent")
+            .unwrap()
+            .unwrap();
+        assert_eq!(a.data.as_deref(), Some("{}"));
+        assert_eq!(a.address.as_deref(), Some("unix://@ttrpc"));
+        assert_eq!(
+            a.command,
+            Command::Describe(Some("agent.Agent".to_string()))
+        );
+
+        let a = args("unix://@ttrpc agent.Agent/Start").unwrap().unwrap();
+        assert_eq!(a.command, Command::Call("agent.Agent/Start".to_string()));
+
+        assert!(args("-h list").unwrap().is_none());
+        for invalid in [
+            "list",
+            "unix://@ttrpc",
+            "unix://@ttrpc list a b",
+            "-H user unix://@ttrpc list",
+            "-max-time -1 unix://@ttrpc list",
+            "-unknown 1 unix://@ttrpc list",
+        ] {
+            assert!(args(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_split_json() {
+        let data = r#" {"a": "}{\""} [1]{"b": {"c": []}} "#;
+        assert_eq!(
+            split_json(data).unwrap(),
+            [r#"{"a": "}{\""}"#, "[1]", r#"{"b": {"c": []}}"#]
+        );
+        assert!(split_json("  ").unwrap().is_empty());
+        assert!(split_json(r#"{"a": 1"#).is_err());
+        assert!(split_json(r#"{} 1"#).is_err());
+        assert!(split_json(r#"}"#).is_err());
+    }
+
+    #[test]
+    fn test_describe() {
+        let dir = std::env::temp_dir().join(format!("ttrpcurl-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("test.proto"),
+            r#"
+syntax = "proto3";
+package test;
+
+service Test {
+    rpc Get(Request) returns (stream Request);
+}
+
+message Request {
+    message Nested {}
+    map<string, int64> sizes = 1;
+    int32 count = 2;
+    repeated string names = 3;
+    oneof value {
+        bytes data = 4;
+        Nested nested = 5;
+    }
+}
+"#,
+        )
+        .unwrap();
+        let files = parse_protos(&["test.proto".to_string()], &[dir.clone()]).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(describe::services(&files), ["test.Test"]);
+        assert_eq!(
+            describe::methods(&files, "test.Test").unwrap(),
+            ["test.Test.Get"]
+        );
+        assert_eq!(
+            describe_symbol(&files, "test.Test/Get").unwrap(),
+            "test.Test.Get is a method:\n\
+             rpc Get ( .test.Request ) returns ( stream .test.Request );"
+        );
+        assert_eq!(
+            describe_symbol(&files, ".test.Request").unwrap(),
+            "test.Request is a message:
+message Request {
+  message Nested {
+  }
+  map<string, int64> sizes = 1;
+  int32 count = 2;
+  repeated string names = 3;
+  oneof value {
+    bytes data = 4;
+    .test.Request.Nested nested = 5;
+  }
+}"
+        );
+        assert!(describe_symbol(&files, "test.Request.Nested").is_ok());
+        assert!(describe_symbol(&files, "test.Unknown").is_err());
+    }
+}